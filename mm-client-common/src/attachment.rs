@@ -8,12 +8,13 @@ use async_mutex::Mutex as AsyncMutex;
 use futures::{channel::oneshot, future, FutureExt as _};
 use mm_protocol as protocol;
 pub use protocol::audio_channels::Channel as AudioChannel;
-use tracing::error;
+use tracing::{debug, error};
 
 use crate::{
     codec, conn, display_params, input,
     packet::{self, PacketRing},
-    ClientError, ClientState,
+    timing::{self, ClockSync},
+    window, ClientError, ClientState,
 };
 
 #[derive(Debug, Clone, uniffi::Record)]
@@ -23,6 +24,15 @@ pub struct AttachmentConfig {
     /// The height of the video stream.
     pub height: u32,
 
+    /// The output to attach to. Zero is the primary output, and N is the Nth
+    /// additional output.
+    pub output: u32,
+
+    /// A toplevel window to stream on its own, instead of an output, by the id
+    /// from [AttachmentDelegate::windows_changed]. The window is resized to
+    /// match the width and height.
+    pub window_id: Option<u64>,
+
     /// The codec to use for the video stream. Leaving it empty allows the
     /// server to decide.
     pub video_codec: Option<codec::VideoCodec>,
@@ -58,6 +68,16 @@ pub struct AttachmentConfig {
     /// way to ensure sequence numbers stay monotonic, even across individual
     /// attachment streams.
     pub audio_stream_seq_offset: u64,
+
+    /// Requests per-frame timing information from the server. See
+    /// [packet::Packet::timing].
+    pub frame_timing: bool,
+
+    /// Indicates that the client can handle a new streaming resolution
+    /// mid-attachment, as a new video stream. Otherwise, the attachment ends
+    /// whenever the streaming resolution changes, and the client must
+    /// reattach. Required for [Attachment::update_streaming_resolution].
+    pub resize_in_place: bool,
 }
 
 /// The settled video stream params, after the server has applied its defaults.
@@ -128,6 +148,8 @@ impl Attachment {
             prev_audio_stream_seq: None,
             audio_stream_seq_offset: 0,
 
+            clock_sync: ClockSync::default(),
+
             notify_detached: Some(detached_tx),
             reattach_required: false,
         };
@@ -199,9 +221,19 @@ pub trait AttachmentDelegate: Send + Sync + std::fmt::Debug {
     /// The pointer should be released.
     fn release_pointer(&self);
 
-    /// The remote session display params were changed. This usually requires
-    /// the client to reattach. If reattach_required is true, the attachment
-    /// should be considered ended. [attachment_ended] will not be called.
+    /// The list of windows in the remote session changed. The list is in
+    /// stacking order, from bottom to top.
+    fn windows_changed(&self, windows: Vec<window::Window>);
+
+    /// The focused window in the remote session changed, or its title or app
+    /// ID changed. Both are empty if no window has focus.
+    fn window_metadata_changed(&self, title: String, app_id: String);
+
+    /// The remote session display params were changed. If reattach_required
+    /// is true, the attachment should be considered ended, and
+    /// [attachment_ended] will not be called. Otherwise, the attachment
+    /// continues, and a resize is followed by a new video stream at the new
+    /// resolution.
     fn display_params_changed(
         &self,
         params: display_params::DisplayParams,
@@ -343,6 +375,43 @@ impl Attachment {
         )
     }
 
+    /// Sends a clock synchronization request to the server. Once the response
+    /// arrives, packets include [packet::Packet::timing] on the local clock.
+    /// Clocks drift, so this should be called regularly (for example, once a
+    /// second) while timing information is needed.
+    pub fn sync_clock(&self) {
+        self.send(
+            protocol::SyncClock {
+                client_timestamp: timing::timestamp_micros(),
+            },
+            false,
+        )
+    }
+
+    /// Requests that the server focus, raise, or close a window from the
+    /// most recent [AttachmentDelegate::windows_changed] call.
+    pub fn window_action(&self, window_id: u64, action: window::WindowAction) {
+        self.send(
+            protocol::WindowAction {
+                window_id,
+                action: action.into(),
+            },
+            false,
+        )
+    }
+
+    /// Requests a new streaming resolution for a window attachment. The window
+    /// is resized to match, and a new video stream starts at the new
+    /// resolution.
+    pub fn update_streaming_resolution(&self, width: u32, height: u32) {
+        self.send(
+            protocol::UpdateStreamingResolution {
+                streaming_resolution: Some(protocol::Size { width, height }),
+            },
+            false,
+        )
+    }
+
     /// Ends the attachment.
     pub async fn detach(&self) -> Result<(), ClientError> {
         self.send(protocol::Detach {}, true);
@@ -370,6 +439,8 @@ pub(crate) struct AttachmentState {
     prev_audio_stream_seq: Option<u64>,
     audio_stream_seq_offset: u64,
 
+    clock_sync: ClockSync,
+
     // A future representing the end of the attachment.
     notify_detached: Option<oneshot::Sender<()>>,
 }
@@ -421,6 +492,7 @@ impl AttachmentState {
                         .flat_map(Result::ok)
                     {
                         packet.stream_seq += self.video_stream_seq_offset;
+                        packet.timing = self.local_timing(&packet);
                         self.delegate.video_packet(Arc::new(packet));
                     }
                 }
@@ -433,6 +505,7 @@ impl AttachmentState {
                         match res {
                             Ok(mut packet) => {
                                 packet.stream_seq += self.video_stream_seq_offset;
+                                packet.timing = self.local_timing(&packet);
                                 self.delegate.video_packet(Arc::new(packet));
                             }
                             Err(mut dropped) => {
@@ -512,7 +585,32 @@ impl AttachmentState {
                 self.delegate.lock_pointer(msg.x, msg.y);
             }
             protocol::MessageType::ReleasePointer(_) => self.delegate.release_pointer(),
+            protocol::MessageType::WindowList(msg) => {
+                let windows = match msg
+                    .list
+                    .into_iter()
+                    .map(window::Window::try_from)
+                    .collect::<Result<Vec<_>, _>>()
+                {
+                    Ok(windows) => windows,
+                    Err(err) => {
+                        error!(?err, "invalid window list from server");
+                        return;
+                    }
+                };
+
+                self.delegate.windows_changed(windows);
+            }
+            protocol::MessageType::WindowMetadataChanged(msg) => {
+                self.delegate.window_metadata_changed(msg.title, msg.app_id);
+            }
             protocol::MessageType::SessionParametersChanged(msg) => {
+                // The stream continues at a new resolution. Subsequent
+                // streams are reported with the new size.
+                if let Some(res) = msg.streaming_resolution {
+                    self.attached_msg.streaming_resolution = Some(res);
+                }
+
                 let Some(params) = msg.display_params.and_then(|p| p.try_into().ok()) else {
                     error!(?msg, "invalid display params from server");
                     return;
@@ -524,8 +622,16 @@ impl AttachmentState {
                 // Mute the attachment_ended callback once.
                 self.reattach_required = msg.reattach_required;
             }
-            protocol::MessageType::SessionEnded(_) => {
+            protocol::MessageType::ClockSynced(msg) => {
+                self.clock_sync.record(
+                    msg.client_timestamp,
+                    msg.server_timestamp,
+                    timing::timestamp_micros(),
+                );
+            }
+            protocol::MessageType::SessionEnded(msg) => {
                 // We just check for the fin on the attachment stream.
+                debug!(exit_status = msg.exit_status, "session ended");
             }
             protocol::MessageType::Error(error) => {
                 self.server_error = Some(error.clone());
@@ -535,6 +641,12 @@ impl AttachmentState {
         }
     }
 
+    fn local_timing(&self, packet: &packet::Packet) -> Option<timing::FrameTiming> {
+        let server_timing = packet.server_timing.as_ref()?;
+        self.clock_sync
+            .frame_timing(server_timing, timing::timestamp_micros())
+    }
+
     pub(crate) fn handle_close(mut self, err: Option<ClientError>) {
         if let Some(tx) = self.notify_detached.take() {
             let _ = tx.send(());
@@ -12,6 +12,21 @@ pub struct DisplayParams {
     pub height: u32,
     pub framerate: u32,
     pub ui_scale: PixelScale,
+
+    /// Additional outputs, beyond the primary one. These can't be changed
+    /// after the session is launched.
+    pub additional_outputs: Vec<VirtualOutput>,
+}
+
+/// An additional virtual output. The position is in the same pixel space as
+/// the primary output, which is always at the origin.
+#[derive(Debug, Clone, PartialEq, Eq, uniffi::Record)]
+pub struct VirtualOutput {
+    pub width: u32,
+    pub height: u32,
+    pub ui_scale: PixelScale,
+    pub x: u32,
+    pub y: u32,
 }
 
 impl TryFrom<protocol::VirtualDisplayParameters> for DisplayParams {
@@ -25,6 +40,27 @@ impl TryFrom<protocol::VirtualDisplayParameters> for DisplayParams {
             height: res.height,
             framerate: msg.framerate_hz,
             ui_scale: required_field!(msg.ui_scale)?.try_into()?,
+            additional_outputs: msg
+                .additional_outputs
+                .into_iter()
+                .map(TryInto::try_into)
+                .collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+impl TryFrom<protocol::VirtualOutput> for VirtualOutput {
+    type Error = ValidationError;
+
+    fn try_from(msg: protocol::VirtualOutput) -> Result<Self, Self::Error> {
+        let res = required_field!(msg.resolution)?;
+
+        Ok(VirtualOutput {
+            width: res.width,
+            height: res.height,
+            ui_scale: required_field!(msg.ui_scale)?.try_into()?,
+            x: msg.x,
+            y: msg.y,
         })
     }
 }
@@ -38,6 +74,25 @@ impl From<DisplayParams> for protocol::VirtualDisplayParameters {
             }),
             framerate_hz: value.framerate,
             ui_scale: Some(value.ui_scale.into()),
+            additional_outputs: value
+                .additional_outputs
+                .into_iter()
+                .map(Into::into)
+                .collect(),
+        }
+    }
+}
+
+impl From<VirtualOutput> for protocol::VirtualOutput {
+    fn from(value: VirtualOutput) -> Self {
+        protocol::VirtualOutput {
+            resolution: Some(protocol::Size {
+                width: value.width,
+                height: value.height,
+            }),
+            ui_scale: Some(value.ui_scale.into()),
+            x: value.x,
+            y: value.y,
         }
     }
 }
@@ -19,17 +19,20 @@ mod logging;
 mod packet;
 mod session;
 mod stats;
+mod timing;
 mod validation;
 
 pub mod codec;
 pub mod display_params;
 pub mod input;
 pub mod pixel_scale;
+pub mod window;
 
 pub use attachment::*;
 pub use logging::*;
 pub use packet::*;
 pub use session::*;
+pub use timing::*;
 
 uniffi::setup_scaffolding!();
 
@@ -367,6 +370,7 @@ impl Client {
             start: time::SystemTime::now(),
             application_id,
             display_params,
+            resource_usage: None,
         })
     }
 
@@ -432,6 +436,11 @@ impl Client {
             audio_codec: config.audio_codec.unwrap_or_default().into(),
             sample_rate_hz: config.sample_rate.unwrap_or_default(),
             channels: channel_conf,
+
+            frame_timing: config.frame_timing,
+            resize_in_place: config.resize_in_place,
+            output: config.output,
+            window_id: config.window_id.unwrap_or_default(),
         };
 
         let (sid, res) = self.initiate_stream(attach, false, Some(timeout)).await?;
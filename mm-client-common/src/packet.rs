@@ -5,14 +5,19 @@
 mod ring;
 use std::collections::VecDeque;
 
+use mm_protocol as protocol;
 pub(crate) use ring::*;
 
+use crate::timing::FrameTiming;
+
 #[derive(Debug, Clone, uniffi::Object)]
 pub struct Packet {
     pub(crate) pts: u64,
     pub(crate) seq: u64,
     pub(crate) stream_seq: u64,
     pub(crate) hierarchical_layer: u32,
+    pub(crate) server_timing: Option<protocol::FrameTiming>,
+    pub(crate) timing: Option<FrameTiming>,
     data: VecDeque<bytes::Bytes>,
 }
 
@@ -42,6 +47,12 @@ impl Packet {
         self.hierarchical_layer
     }
 
+    /// Timing information for the packet, if frame timing was requested and
+    /// the clock has been synchronized with the server.
+    pub fn timing(&self) -> Option<FrameTiming> {
+        self.timing
+    }
+
     pub fn data(&self) -> Vec<u8> {
         if self.data.len() == 1 {
             self.data[0].to_vec()
@@ -20,6 +20,7 @@ pub(crate) trait Chunk {
     fn pts(&self) -> u64;
     fn hierarchical_layer(&self) -> u32;
     fn fec_metadata(&self) -> Option<protocol::FecMetadata>;
+    fn timing(&self) -> Option<protocol::FrameTiming>;
 }
 
 impl Chunk for protocol::VideoChunk {
@@ -54,6 +55,10 @@ impl Chunk for protocol::VideoChunk {
     fn fec_metadata(&self) -> Option<mm_protocol::FecMetadata> {
         self.fec_metadata.clone()
     }
+
+    fn timing(&self) -> Option<protocol::FrameTiming> {
+        self.timing
+    }
 }
 
 impl Chunk for protocol::AudioChunk {
@@ -88,6 +93,10 @@ impl Chunk for protocol::AudioChunk {
     fn fec_metadata(&self) -> Option<mm_protocol::FecMetadata> {
         self.fec_metadata.clone()
     }
+
+    fn timing(&self) -> Option<protocol::FrameTiming> {
+        None
+    }
 }
 
 #[derive(Debug)]
@@ -105,6 +114,7 @@ struct WipPacket {
     seq: u64,
     pts: u64,
     hierarchical_layer: u32,
+    timing: Option<protocol::FrameTiming>,
     decoder: FECDecoder,
 }
 
@@ -136,6 +146,7 @@ impl WipPacket {
             seq: incoming.seq(),
             pts: incoming.pts(),
             hierarchical_layer: incoming.hierarchical_layer(),
+            timing: incoming.timing(),
             decoder,
         };
 
@@ -218,6 +229,8 @@ impl WipPacket {
             seq: self.seq,
             stream_seq: self.stream_seq,
             hierarchical_layer: self.hierarchical_layer,
+            server_timing: self.timing,
+            timing: None,
             data,
         }
     }
@@ -469,6 +482,7 @@ mod tests {
                 timestamp: 0,
                 hierarchical_layer: 0,
                 fec_metadata: None,
+                timing: None,
             })
             .collect()
     }
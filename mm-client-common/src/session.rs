@@ -50,6 +50,25 @@ pub struct Session {
     pub application_id: String,
     pub start: time::SystemTime,
     pub display_params: display_params::DisplayParams,
+    pub resource_usage: Option<ResourceUsage>,
+}
+
+/// The current resource usage of a session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, uniffi::Record)]
+pub struct ResourceUsage {
+    pub memory_bytes: u64,
+    pub cpu_time: time::Duration,
+    pub processes: u32,
+}
+
+impl From<protocol::ResourceUsage> for ResourceUsage {
+    fn from(msg: protocol::ResourceUsage) -> Self {
+        ResourceUsage {
+            memory_bytes: msg.memory_bytes,
+            cpu_time: time::Duration::from_micros(msg.cpu_time_usec),
+            processes: msg.processes,
+        }
+    }
 }
 
 impl TryFrom<protocol::session_list::Session> for Session {
@@ -68,6 +87,7 @@ impl TryFrom<protocol::session_list::Session> for Session {
             application_id: msg.application_id,
             start,
             display_params: required_field!(msg.display_params)?.try_into()?,
+            resource_usage: msg.resource_usage.map(Into::into),
         })
     }
 }
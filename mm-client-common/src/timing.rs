@@ -0,0 +1,142 @@
+// Copyright 2024 Colin Marc <hi@colinmarc.com>
+//
+// SPDX-License-Identifier: MIT
+
+use std::{collections::VecDeque, sync::LazyLock, time};
+
+use mm_protocol as protocol;
+
+static EPOCH: LazyLock<time::Instant> = LazyLock::new(time::Instant::now);
+
+// How many clock sync samples to keep around.
+const CLOCK_SYNC_SAMPLES: usize = 8;
+
+/// Returns the current time in microseconds, on the same clock used for
+/// [FrameTiming].
+#[uniffi::export]
+pub fn timestamp_micros() -> u64 {
+    EPOCH.elapsed().as_micros() as u64
+}
+
+/// Records when a video frame passed through each stage of the pipeline, as
+/// microsecond timestamps on the local clock (see [timestamp_micros]).
+///
+/// The server-side stages are only set once the clock has been synchronized
+/// with [crate::Attachment::sync_clock], and if the server included the stage
+/// for the frame.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, uniffi::Record)]
+pub struct FrameTiming {
+    /// The first app commit that contributed to the frame.
+    pub app_commit: Option<u64>,
+    /// When the server started compositing the frame.
+    pub composite_start: Option<u64>,
+    /// When the server submitted the composite to the GPU.
+    pub composite_end: Option<u64>,
+    /// When the server submitted the frame to the encoder.
+    pub encode_submit: Option<u64>,
+    /// When the encoded packet was ready on the server.
+    pub encode_complete: Option<u64>,
+    /// When the server started sending the packet.
+    pub send: Option<u64>,
+    /// When the packet was fully received by the client.
+    pub recv: u64,
+}
+
+/// Estimates the offset between the local clock and the server clock from
+/// `SyncClock` round trips, in the style of NTP. Samples with a shorter round
+/// trip are more accurate, so we use the best of the last few.
+#[derive(Debug, Default)]
+pub(crate) struct ClockSync {
+    // (rtt, offset) in microseconds.
+    samples: VecDeque<(u64, i64)>,
+}
+
+impl ClockSync {
+    /// Records a sample. `client_ts` is the time the request was sent, and
+    /// `now` the time the response was received, both on the local clock.
+    pub(crate) fn record(&mut self, client_ts: u64, server_ts: u64, now: u64) {
+        let rtt = now.saturating_sub(client_ts);
+        let offset = server_ts as i64 - (client_ts + rtt / 2) as i64;
+
+        if self.samples.len() == CLOCK_SYNC_SAMPLES {
+            self.samples.pop_front();
+        }
+
+        self.samples.push_back((rtt, offset));
+    }
+
+    /// The current estimate of the server clock minus the local clock.
+    pub(crate) fn offset(&self) -> Option<i64> {
+        self.samples
+            .iter()
+            .min_by_key(|(rtt, _)| *rtt)
+            .map(|(_, offset)| *offset)
+    }
+
+    /// Converts the server timing for a frame to the local clock. Returns
+    /// None if the clock hasn't been synchronized yet.
+    pub(crate) fn frame_timing(
+        &self,
+        timing: &protocol::FrameTiming,
+        recv: u64,
+    ) -> Option<FrameTiming> {
+        let offset = self.offset()?;
+        let local = |ts: u64| match ts {
+            0 => None,
+            ts => Some((ts as i64 - offset).max(0) as u64),
+        };
+
+        Some(FrameTiming {
+            app_commit: local(timing.app_commit),
+            composite_start: local(timing.composite_start),
+            composite_end: local(timing.composite_end),
+            encode_submit: local(timing.encode_submit),
+            encode_complete: local(timing.encode_complete),
+            send: local(timing.send),
+            recv,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clock_sync() {
+        let mut sync = ClockSync::default();
+        assert_eq!(sync.offset(), None);
+
+        // The server clock is 1000us ahead, with a symmetric path.
+        sync.record(100, 1150, 200);
+        assert_eq!(sync.offset(), Some(1000));
+
+        // A sample with a longer (asymmetric) round trip is ignored.
+        sync.record(300, 1500, 500);
+        assert_eq!(sync.offset(), Some(1000));
+
+        let timing = protocol::FrameTiming {
+            composite_start: 2000,
+            send: 2500,
+            ..Default::default()
+        };
+
+        let local = sync.frame_timing(&timing, 1600).unwrap();
+        assert_eq!(local.app_commit, None);
+        assert_eq!(local.composite_start, Some(1000));
+        assert_eq!(local.send, Some(1500));
+        assert_eq!(local.recv, 1600);
+    }
+
+    #[test]
+    fn test_clock_sync_window() {
+        let mut sync = ClockSync::default();
+        sync.record(0, 1000, 10);
+        for i in 1..=CLOCK_SYNC_SAMPLES as u64 {
+            sync.record(i * 100, i * 100 + 2000, i * 100 + 50);
+        }
+
+        // The first sample fell out of the window.
+        assert_eq!(sync.offset(), Some(1975));
+    }
+}
@@ -0,0 +1,36 @@
+// Copyright 2024 Colin Marc <hi@colinmarc.com>
+//
+// SPDX-License-Identifier: MIT
+
+use mm_protocol as protocol;
+pub use protocol::window_action::Action as WindowAction;
+
+use crate::validation::*;
+
+/// A toplevel window in the remote session.
+#[derive(Debug, Clone, PartialEq, Eq, uniffi::Record)]
+pub struct Window {
+    pub id: u64,
+    pub title: String,
+    pub app_id: String,
+    pub width: u32,
+    pub height: u32,
+    pub focused: bool,
+}
+
+impl TryFrom<protocol::window_list::Window> for Window {
+    type Error = ValidationError;
+
+    fn try_from(msg: protocol::window_list::Window) -> Result<Self, Self::Error> {
+        let size = required_field!(msg.size)?;
+
+        Ok(Window {
+            id: msg.id,
+            title: msg.title,
+            app_id: msg.app_id,
+            width: size.width,
+            height: size.height,
+            focused: msg.focused,
+        })
+    }
+}
@@ -9,6 +9,7 @@ use ash::vk;
 use clap::Parser;
 use mm_client::{
     delegate::{AttachmentEvent, AttachmentProxy},
+    stats::{latency_breakdown, LATENCY_STAGES},
     video::*,
     vulkan::*,
 };
@@ -95,9 +96,15 @@ struct LatencyTest {
 
     next_block: usize,
     block_started: time::Instant,
+    input_sent: u64,
     num_tests: usize,
     histogram: histo::Histogram,
 
+    // Sums of the time spent in each stage, starting with the time from input
+    // to app commit, followed by the LATENCY_STAGES.
+    breakdown_totals: [f64; LATENCY_STAGES.len() + 1],
+    breakdown_samples: usize,
+
     first_frame_recvd: Option<time::Instant>,
     total_video_bytes: usize,
 
@@ -138,6 +145,20 @@ fn main() -> anyhow::Result<()> {
 
         println!("{}", win.histogram);
 
+        if win.breakdown_samples > 0 {
+            println!("latency breakdown (average):");
+            for (stage, total) in std::iter::once("input")
+                .chain(LATENCY_STAGES)
+                .zip(win.breakdown_totals)
+            {
+                println!(
+                    "  {:>10}: {:.2}ms",
+                    stage,
+                    total / win.breakdown_samples as f64
+                );
+            }
+        }
+
         if let Some(first_frame_recvd) = win.first_frame_recvd {
             println!(
                 "transfer rate: {:.2} mpbs ({:.2}kb per frame)",
@@ -245,9 +266,15 @@ impl LatencyTest {
                 self.video_texture = Some(tex);
             }
             AppEvent::VideoFrameAvailable => {
-                if self.stream.prepare_frame()?.is_some() {
+                if let Some(info) = self.stream.prepare_frame()? {
                     self.frames_recvd += 1;
 
+                    // Keep the clock in sync with the server, so that we can
+                    // measure the latency breakdown.
+                    if self.frames_recvd % 10 == 0 {
+                        self.attachment.sync_clock();
+                    }
+
                     match self.frames_recvd.cmp(&100) {
                         std::cmp::Ordering::Less => (),
                         std::cmp::Ordering::Equal => {
@@ -257,7 +284,7 @@ impl LatencyTest {
                             self.next_block = 0;
                         }
                         std::cmp::Ordering::Greater => {
-                            self.check_frame()?;
+                            self.check_frame(&info)?;
                             if self.next_block >= self.num_tests {
                                 return Ok(false);
                             }
@@ -272,6 +299,7 @@ impl LatencyTest {
 
     fn send_space(&mut self) {
         debug!("sending space");
+        self.input_sent = client::timestamp_micros();
 
         self.attachment.keyboard_input(
             client::input::Key::Space,
@@ -286,7 +314,7 @@ impl LatencyTest {
         );
     }
 
-    fn check_frame(&mut self) -> anyhow::Result<()> {
+    fn check_frame(&mut self, info: &FrameMetadata) -> anyhow::Result<()> {
         unsafe {
             self.submit_copy()?;
         }
@@ -299,6 +327,7 @@ impl LatencyTest {
             let elapsed = self.block_started.elapsed();
             debug!("block {} took {}ms", self.next_block, elapsed.as_millis());
             self.histogram.add(elapsed.as_millis() as u64);
+            self.record_breakdown(info);
 
             // Start the next one.
             // Sleep 10-100ms.
@@ -320,6 +349,27 @@ impl LatencyTest {
         Ok(())
     }
 
+    fn record_breakdown(&mut self, info: &FrameMetadata) {
+        let Some(breakdown) = latency_breakdown(info, client::timestamp_micros()) else {
+            return;
+        };
+
+        let Some(app_commit) = info.timing.and_then(|t| t.app_commit) else {
+            return;
+        };
+
+        let input = app_commit.saturating_sub(self.input_sent) as f32 / 1000.0;
+        for (total, sample) in self
+            .breakdown_totals
+            .iter_mut()
+            .zip(std::iter::once(input).chain(breakdown))
+        {
+            *total += sample as f64;
+        }
+
+        self.breakdown_samples += 1;
+    }
+
     fn check_block(&mut self, idx: usize) -> bool {
         let data =
             unsafe { std::slice::from_raw_parts(self.copy_buffer.access as *mut u8, 256 * 256) };
@@ -427,6 +477,7 @@ fn start_test(
                 height: APP_DIMENSION,
                 framerate: args.framerate.unwrap_or(60),
                 ui_scale: client::pixel_scale::PixelScale::ONE,
+                additional_outputs: vec![],
             },
             vec![],
             DEFAULT_TIMEOUT,
@@ -437,6 +488,8 @@ fn start_test(
     let config = client::AttachmentConfig {
         width: APP_DIMENSION,
         height: APP_DIMENSION,
+        output: 0,
+        window_id: None,
         video_codec: codec.into(),
         video_profile: None,
         quality_preset: Some(6),
@@ -445,6 +498,8 @@ fn start_test(
         channels: vec![],
         video_stream_seq_offset: 0,
         audio_stream_seq_offset: 0,
+        frame_timing: true,
+        resize_in_place: false,
     };
 
     let delegate = Arc::new(AttachmentProxy::new(proxy.clone()));
@@ -478,9 +533,13 @@ fn start_test(
 
         next_block: 0,
         block_started: time::Instant::now(),
+        input_sent: 0,
         num_tests: args.samples.unwrap_or(256),
         histogram: histo::Histogram::with_buckets(10),
 
+        breakdown_totals: Default::default(),
+        breakdown_samples: 0,
+
         first_frame_recvd: None,
         total_video_bytes: 0,
 
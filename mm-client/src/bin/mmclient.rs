@@ -31,7 +31,7 @@ const DEFAULT_CONNECT_TIMEOUT: time::Duration = time::Duration::from_secs(1);
 const DEFAULT_REQUEST_TIMEOUT: time::Duration = time::Duration::from_secs(30);
 
 const MAX_FRAME_TIME: time::Duration = time::Duration::from_nanos(1_000_000_000 / 24);
-const RESIZE_COOLDOWN: time::Duration = time::Duration::from_millis(500);
+const RESIZE_COOLDOWN: time::Duration = time::Duration::from_millis(100);
 
 #[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
 enum Resolution {
@@ -56,9 +56,41 @@ impl From<&str> for Resolution {
     }
 }
 
+/// An additional output, specified as WIDTHxHEIGHT+X+Y.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+struct OutputGeometry {
+    width: u32,
+    height: u32,
+    x: u32,
+    y: u32,
+}
+
+impl From<&str> for OutputGeometry {
+    fn from(s: &str) -> Self {
+        let mut parts = s.split('+');
+        let (w, h) = parts
+            .next()
+            .and_then(|size| size.split_once('x'))
+            .expect("invalid output size");
+        let x = parts.next().unwrap_or("0");
+        let y = parts.next().unwrap_or("0");
+
+        OutputGeometry {
+            width: w.parse().expect("invalid output width"),
+            height: h.parse().expect("invalid output height"),
+            x: x.parse().expect("invalid output position"),
+            y: y.parse().expect("invalid output position"),
+        }
+    }
+}
+
 #[derive(Debug, Parser)]
 #[command(name = "mmclient")]
 #[command(about = "The Magic Mirror reference client", long_about = None)]
+#[command(after_help = "Keyboard shortcuts:
+  Ctrl+D          Detach from the session and exit
+  Ctrl+Shift+N    Focus the next window in the session
+  Ctrl+Shift+Q    Close the focused window in the session")]
 struct Cli {
     /// The server to connect to.
     #[arg(value_name = "HOST[:PORT]")]
@@ -112,6 +144,18 @@ struct Cli {
     /// Enable the overlay, which shows various stats.
     #[arg(long)]
     overlay: bool,
+    /// Add an output to a newly launched session, in the form WxH+X+Y. The
+    /// primary output is always at the origin. May be specified multiple times.
+    #[arg(long = "add-output", value_name = "WxH+X+Y")]
+    additional_outputs: Vec<OutputGeometry>,
+    /// Attach to the Nth additional output, instead of the primary one. The
+    /// window size doesn't affect the size of additional outputs.
+    #[arg(long, default_value = "0")]
+    output: u32,
+    /// Stream a single remote window, by ID, instead of the whole display. The
+    /// remote window is resized to match the local one.
+    #[arg(long, conflicts_with = "output")]
+    window: Option<u64>,
 }
 
 struct AttachmentWindow {
@@ -145,10 +189,14 @@ struct AttachmentWindow {
     cursor_modifiers: winit::keyboard::ModifiersState,
     cursor_pos: Option<(f64, f64)>,
 
+    // The toplevel windows in the remote session, from bottom to top.
+    remote_windows: Vec<client::window::Window>,
+
     flash: Flash,
     overlay: Option<Overlay>,
 
     stats_timer: time::Instant,
+    clock_sync_timer: time::Instant,
 
     _vk: Arc<vulkan::VkContext>,
 }
@@ -374,11 +422,28 @@ impl AttachmentWindow {
                     },
                 ..
             } => {
+                let shortcut = state == ElementState::Pressed
+                    && self.cursor_modifiers.control_key()
+                    && self.cursor_modifiers.shift_key();
+
                 if state == ElementState::Pressed
                     && logical_key == winit::keyboard::Key::Character("d".into())
                     && self.cursor_modifiers.control_key()
                 {
                     return Ok(false);
+                } else if shortcut && code == winit::keyboard::KeyCode::KeyN {
+                    // Cycle through remote windows by raising the bottom one.
+                    if let Some(w) = self.remote_windows.first().filter(|w| !w.focused) {
+                        debug!(id = w.id, title = w.title, "focusing remote window");
+                        self.attachment
+                            .window_action(w.id, client::window::WindowAction::Focus);
+                    }
+                } else if shortcut && code == winit::keyboard::KeyCode::KeyQ {
+                    if let Some(w) = self.remote_windows.iter().find(|w| w.focused) {
+                        debug!(id = w.id, title = w.title, "closing remote window");
+                        self.attachment
+                            .window_action(w.id, client::window::WindowAction::Close);
+                    }
                 } else {
                     let char = match logical_key {
                         winit::keyboard::Key::Character(text) => text.chars().next(),
@@ -604,14 +669,48 @@ impl AttachmentWindow {
                     self.window
                         .set_cursor_grab(winit::window::CursorGrabMode::None)?;
                 }
+                WindowsChanged(windows) => {
+                    for w in &windows {
+                        debug!(
+                            id = w.id,
+                            title = w.title,
+                            app_id = w.app_id,
+                            focused = w.focused,
+                            "remote window"
+                        );
+
+                        // If we're streaming a single window, use its title.
+                        if self.attachment_config.window_id == Some(w.id) {
+                            self.window.set_title(&window_title(
+                                &w.title,
+                                &w.app_id,
+                                &self.session,
+                            ));
+                        }
+                    }
+
+                    self.remote_windows = windows;
+                }
+                WindowMetadataChanged { title, app_id } => {
+                    if self.attachment_config.window_id.is_none() {
+                        self.window
+                            .set_title(&window_title(&title, &app_id, &self.session));
+                    }
+                }
                 DisplayParamsChanged {
                     params,
                     reattach_required,
                 } => {
-                    if reattach_required {
+                    // The stream follows the size of the primary output.
+                    if self.attachment_config.output == 0
+                        && self.attachment_config.window_id.is_none()
+                    {
                         self.attachment_config.width = params.width;
                         self.attachment_config.height = params.height;
+                    }
 
+                    // Older servers restart the attachment on resize.
+                    if reattach_required {
                         // TODO: this blocks the app, which is not ideal.
                         // We could spawn a thread for this, or reuse one.
                         debug!("reattaching to session after resize");
@@ -662,9 +761,19 @@ impl AttachmentWindow {
         }
 
         if self.stats_timer.elapsed() > time::Duration::from_millis(100) {
+            self.stats_timer = time::Instant::now();
             STATS.set_connection_rtt(client.stats().rtt)
         }
 
+        // Keep the clock in sync with the server, for the latency breakdown in
+        // the overlay.
+        if self.attachment_config.frame_timing
+            && self.clock_sync_timer.elapsed() > time::Duration::from_secs(1)
+        {
+            self.clock_sync_timer = time::Instant::now();
+            self.attachment.sync_clock();
+        }
+
         let last_frame = self.last_frame_received.elapsed();
         if last_frame > time::Duration::from_secs(1) {
             if last_frame > DEFAULT_REQUEST_TIMEOUT {
@@ -713,20 +822,34 @@ impl AttachmentWindow {
                     height: desired_height,
                     ui_scale: desired_ui_scale,
                     framerate: self.configured_framerate,
+                    additional_outputs: self.session.display_params.additional_outputs.clone(),
                 };
 
                 // Update the session to match our desired resolution or
                 // scale. Note that this is skipped if there is no
                 // current attachment (and `current_streaming_res` is
-                // None).
-                if desired_params != self.session.display_params {
+                // None). Additional outputs can't be resized, and a
+                // streamed window is resized along with its stream.
+                if self.attachment_config.window_id.is_some() {
+                    if (desired_width, desired_height)
+                        != (self.attachment_config.width, self.attachment_config.height)
+                    {
+                        debug!(desired_width, desired_height, "resizing remote window");
+                        self.flash.set_message("resizing...");
+
+                        self.attachment_config.width = desired_width;
+                        self.attachment_config.height = desired_height;
+                        self.attachment
+                            .update_streaming_resolution(desired_width, desired_height);
+                    }
+                } else if self.attachment_config.output == 0
+                    && desired_params != self.session.display_params
+                {
                     debug!(
                         "resizing session to {}x{}@{} (scale: {})",
                         desired_width, desired_height, self.configured_framerate, desired_ui_scale,
                     );
 
-                    self.flash.set_message("resizing...");
-
                     // TODO: this blocks the app.
                     client
                         .update_session_display_params(
@@ -912,17 +1035,37 @@ fn init_window(
     let (width, height) =
         determine_resolution(args.resolution, window_size.width, window_size.height);
 
+    let ui_scale = determine_ui_scale(args.ui_scale.unwrap_or(window_ui_scale));
+    let additional_outputs = match &session {
+        // Outputs can't be changed on a running session.
+        Some(session) => session.display_params.additional_outputs.clone(),
+        None => args
+            .additional_outputs
+            .iter()
+            .map(|o| client::display_params::VirtualOutput {
+                width: o.width,
+                height: o.height,
+                ui_scale,
+                x: o.x,
+                y: o.y,
+            })
+            .collect(),
+    };
+
     let desired_params = client::display_params::DisplayParams {
         width,
         height,
         framerate: args.framerate,
-        ui_scale: determine_ui_scale(args.ui_scale.unwrap_or(window_ui_scale)),
+        ui_scale,
+        additional_outputs,
     };
 
     let initial_gamepads = spawn_gamepad_monitor(proxy.clone())?;
 
     let session_id = if let Some(session) = session {
-        if session.display_params != desired_params {
+        // Attaching to an additional output or a single window leaves the
+        // primary output alone.
+        if args.output == 0 && args.window.is_none() && session.display_params != desired_params {
             debug!("updating session params to {:?}", desired_params);
             client
                 .update_session_display_params(session.id, desired_params, DEFAULT_REQUEST_TIMEOUT)
@@ -955,6 +1098,9 @@ fn init_window(
         .find(|s| s.id == session_id)
         .ok_or(anyhow!("new session not found in session list"))?;
 
+    // Updated once the server tells us which window has focus.
+    window.set_title(&window_title("", "", &session));
+
     let now = time::Instant::now();
 
     let mut flash = Flash::new();
@@ -972,9 +1118,23 @@ fn init_window(
     let video_stream = video::VideoStream::new(vk.clone(), proxy.clone());
     spawn_gamepad_monitor(proxy.clone())?;
 
+    let (width, height) = match args.output {
+        // A streamed window is sized to match the local window.
+        _ if args.window.is_some() => (width, height),
+        0 => (session.display_params.width, session.display_params.height),
+        n => session
+            .display_params
+            .additional_outputs
+            .get(n as usize - 1)
+            .map(|o| (o.width, o.height))
+            .ok_or(anyhow!("session has no output {n}"))?,
+    };
+
     let attachment_config = client::AttachmentConfig {
-        width: session.display_params.width,
-        height: session.display_params.height,
+        width,
+        height,
+        output: args.output,
+        window_id: args.window,
         video_codec: Some(configured_codec),
         video_profile: Some(configured_profile),
         quality_preset: Some(args.preset + 1),
@@ -983,6 +1143,8 @@ fn init_window(
         channels: Vec::new(),
         video_stream_seq_offset: 0,
         audio_stream_seq_offset: 0,
+        frame_timing: args.overlay,
+        resize_in_place: true,
     };
 
     debug!(session_id = session.id, "attaching to session");
@@ -1024,12 +1186,14 @@ fn init_window(
         refresh_cooldown: None,
 
         cursor_modifiers: winit::keyboard::ModifiersState::default(),
+        remote_windows: Vec::new(),
         cursor_pos: None,
 
         flash,
         overlay,
 
         stats_timer: now,
+        clock_sync_timer: now,
 
         _vk: vk,
     })
@@ -1115,6 +1279,20 @@ fn determine_resolution(resolution: Resolution, width: u32, height: u32) -> (u32
     }
 }
 
+/// Formats the local window title from the focused remote window, falling
+/// back to the session's application ID.
+fn window_title(title: &str, app_id: &str, session: &client::Session) -> String {
+    let name = if !title.is_empty() {
+        title
+    } else if !app_id.is_empty() {
+        app_id
+    } else {
+        &session.application_id
+    };
+
+    format!("{name} - mmclient")
+}
+
 fn filter_sessions(sessions: Vec<client::Session>, app: &str) -> Vec<client::Session> {
     if let Ok(id) = app.parse::<u64>() {
         return match sessions.into_iter().find(|s| s.id == id) {
@@ -38,6 +38,11 @@ pub enum AttachmentEvent {
     },
     LockPointer(f64, f64),
     ReleasePointer,
+    WindowsChanged(Vec<client::window::Window>),
+    WindowMetadataChanged {
+        title: String,
+        app_id: String,
+    },
     DisplayParamsChanged {
         params: client::display_params::DisplayParams,
         reattach_required: bool,
@@ -77,6 +82,12 @@ impl std::fmt::Debug for AttachmentEvent {
             AttachmentEvent::ReleasePointer => {
                 write!(f, "ReleasePointer()")
             }
+            AttachmentEvent::WindowsChanged(windows) => {
+                write!(f, "WindowsChanged(len={})", windows.len())
+            }
+            AttachmentEvent::WindowMetadataChanged { title, app_id } => {
+                write!(f, "WindowMetadataChanged({:?}, {:?})", title, app_id)
+            }
             AttachmentEvent::DisplayParamsChanged {
                 reattach_required, ..
             } => {
@@ -135,6 +146,14 @@ impl<T: From<AttachmentEvent> + std::fmt::Debug + Send + 'static> client::Attach
         self.proxy(AttachmentEvent::ReleasePointer)
     }
 
+    fn windows_changed(&self, windows: Vec<client::window::Window>) {
+        self.proxy(AttachmentEvent::WindowsChanged(windows))
+    }
+
+    fn window_metadata_changed(&self, title: String, app_id: String) {
+        self.proxy(AttachmentEvent::WindowMetadataChanged { title, app_id })
+    }
+
     fn display_params_changed(
         &self,
         params: client::display_params::DisplayParams,
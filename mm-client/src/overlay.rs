@@ -6,7 +6,7 @@ use std::{collections::VecDeque, vec};
 
 use mm_protocol as protocol;
 
-use crate::stats::STATS;
+use crate::stats::{LATENCY_STAGES, STATS};
 
 pub struct Overlay {
     streaming_width: u32,
@@ -107,6 +107,12 @@ impl Overlay {
                     "bitrate:",
                     format!("{:.1} mbps", STATS.video_bitrate() / 1_000_000.0),
                 );
+
+                if let Some(breakdown) = STATS.video_latency_breakdown() {
+                    for (stage, ms) in LATENCY_STAGES.iter().zip(breakdown) {
+                        stat_row(ui, format!("{stage}:"), format!("{ms:.1} ms"));
+                    }
+                }
             }
 
             let [width, height] = ui.window_size();
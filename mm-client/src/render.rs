@@ -255,6 +255,32 @@ impl Renderer {
             ),
         };
 
+        // Pass the stream's static HDR metadata through to the display.
+        if let (Some(loader), Some(metadata), vk::ColorSpaceKHR::HDR10_ST2084_EXT) = (
+            self.vk.hdr_metadata_loader.as_ref(),
+            video_params.hdr_metadata,
+            surface_format.color_space,
+        ) {
+            let xy = |(x, y): (u16, u16)| vk::XYColorEXT {
+                x: x as f32 * 0.00002,
+                y: y as f32 * 0.00002,
+            };
+
+            let [r, g, b] = metadata.primaries;
+            let vk_metadata = vk::HdrMetadataEXT::default()
+                .display_primary_red(xy(r))
+                .display_primary_green(xy(g))
+                .display_primary_blue(xy(b))
+                .white_point(xy(metadata.white_point))
+                .max_luminance(metadata.max_luminance as f32)
+                .min_luminance(metadata.min_luminance as f32 * 0.0001)
+                .max_content_light_level(metadata.max_cll as f32)
+                .max_frame_average_light_level(metadata.max_fall as f32);
+
+            debug!(?metadata, "setting swapchain HDR metadata");
+            loader.set_hdr_metadata(&[swapchain], &[vk_metadata]);
+        }
+
         let sampler_conversion =
             create_ycbcr_sampler_conversion(device, video_texture_format, &video_params)?;
 
@@ -9,12 +9,26 @@ use std::{
 };
 
 use lazy_static::lazy_static;
+use mm_client_common as client;
 use simple_moving_average::{SingleSumSMA, SMA as _};
 
+use crate::video::FrameMetadata;
+
 lazy_static! {
     pub static ref STATS: Arc<Stats> = Arc::new(Stats::default());
 }
 
+/// The stages of a frame's trip from the app to the screen, as measured by
+/// [latency_breakdown].
+pub const LATENCY_STAGES: [&str; 6] = [
+    "composite",
+    "encode",
+    "send",
+    "network",
+    "decode",
+    "present",
+];
+
 #[derive(Default)]
 pub struct Stats {
     inner: RwLock<Inner>,
@@ -31,6 +45,7 @@ struct Inner {
 
     connection_rtt: time::Duration,
     video_latency: SingleSumSMA<u64, u64, 60>,
+    video_latency_breakdown: Option<[SingleSumSMA<f32, f32, 60>; LATENCY_STAGES.len()]>,
 }
 
 impl Stats {
@@ -53,7 +68,8 @@ impl Stats {
 
     /// Tracks the total frame time. Should be called right before the frame is
     /// rendered.
-    pub fn frame_rendered(&self, stream_seq: u64, seq: u64) {
+    pub fn frame_rendered(&self, info: &FrameMetadata) {
+        let (stream_seq, seq) = (info.stream_seq, info.seq);
         let now = time::Instant::now();
         let mut inner = self.inner.write().unwrap();
 
@@ -71,6 +87,17 @@ impl Stats {
                 .video_latency
                 .add_sample((now - frame.0).as_nanos() as u64)
         }
+
+        // If we have timing information from the server, we can break it down
+        // further.
+        if let Some(breakdown) = latency_breakdown(info, client::timestamp_micros()) {
+            let smas = inner
+                .video_latency_breakdown
+                .get_or_insert_with(|| std::array::from_fn(|_| SingleSumSMA::new()));
+            for (sma, sample) in smas.iter_mut().zip(breakdown) {
+                sma.add_sample(sample);
+            }
+        }
     }
 
     pub fn frame_discarded(&self, stream_seq: u64, seq: u64) {
@@ -93,6 +120,46 @@ impl Stats {
         let avg = inner.video_latency.get_average() + inner.connection_rtt.as_nanos() as u64;
         avg as f32 / 1_000_000.0
     }
+
+    /// Returns the average time spent in each of the [LATENCY_STAGES], in
+    /// milliseconds, if the server is sending frame timing.
+    pub fn video_latency_breakdown(&self) -> Option<[f32; LATENCY_STAGES.len()]> {
+        let inner = self.inner.read().unwrap();
+        let smas = inner.video_latency_breakdown.as_ref()?;
+        Some(std::array::from_fn(|i| smas[i].get_average()))
+    }
+}
+
+/// Splits the latency of a frame into [LATENCY_STAGES], in milliseconds.
+/// `presented` is the time the frame was displayed (or otherwise consumed),
+/// as returned by [client::timestamp_micros].
+///
+/// The first stage starts with the app commit, so the time it takes for the
+/// app to react to input isn't included.
+pub fn latency_breakdown(
+    info: &FrameMetadata,
+    presented: u64,
+) -> Option<[f32; LATENCY_STAGES.len()]> {
+    let timing = info.timing.as_ref()?;
+    let decoded = info.decoded?;
+
+    let composite_end = timing.composite_end?;
+    let encode_complete = timing.encode_complete?;
+    let send = timing.send?;
+    let start = timing
+        .app_commit
+        .or(timing.composite_start)
+        .unwrap_or(composite_end);
+
+    let ms = |from: u64, to: u64| to.saturating_sub(from) as f32 / 1000.0;
+    Some([
+        ms(start, composite_end),
+        ms(composite_end, encode_complete),
+        ms(encode_complete, send),
+        ms(send, timing.recv),
+        ms(timing.recv, decoded),
+        ms(decoded, presented),
+    ])
 }
 
 impl Default for Inner {
@@ -106,6 +173,7 @@ impl Default for Inner {
 
             connection_rtt: time::Duration::ZERO,
             video_latency: SingleSumSMA::new(),
+            video_latency_breakdown: None,
         }
     }
 }
@@ -27,6 +27,11 @@ pub struct FrameMetadata {
     pub stream_seq: u64,
     pub seq: u64,
     pub pts: u64,
+
+    /// Timing information from the server, if requested.
+    pub timing: Option<client::FrameTiming>,
+    /// When the frame finished decoding, on the same clock as `timing`.
+    pub decoded: Option<u64>,
 }
 
 #[derive(Debug, Clone)]
@@ -48,6 +53,7 @@ pub struct VideoStreamParams {
     pub height: u32,
     pub color_space: ColorSpace,
     pub color_full_range: bool,
+    pub hdr_metadata: Option<HdrMetadata>,
 }
 
 impl Default for VideoStreamParams {
@@ -57,10 +63,73 @@ impl Default for VideoStreamParams {
             height: 0,
             color_space: ColorSpace::Bt709,
             color_full_range: false,
+            hdr_metadata: None,
         }
     }
 }
 
+/// Static HDR metadata, as sent in the bitstream. Chromaticity coordinates are
+/// in units of 0.00002, and luminance values in cd/m².
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HdrMetadata {
+    pub primaries: [(u16, u16); 3], // R, G, B
+    pub white_point: (u16, u16),
+    pub min_luminance: u32, // In units of 0.0001 cd/m².
+    pub max_luminance: u32,
+    pub max_cll: u32,
+    pub max_fall: u32,
+}
+
+impl HdrMetadata {
+    /// Reads mastering display and content light level metadata from a
+    /// decoded frame, if present.
+    fn from_frame(frame: &ffmpeg::frame::Video) -> Option<Self> {
+        use ffmpeg::frame::side_data::Type;
+
+        let mdcv = frame.side_data(Type::MasteringDisplayMetadata)?;
+        let mdcv = unsafe {
+            (mdcv.data().as_ptr() as *const ffmpeg_sys::AVMasteringDisplayMetadata).read_unaligned()
+        };
+
+        if mdcv.has_primaries == 0 || mdcv.has_luminance == 0 {
+            return None;
+        }
+
+        let q = |v: ffmpeg_sys::AVRational| {
+            if v.den == 0 {
+                0.0
+            } else {
+                v.num as f64 / v.den as f64
+            }
+        };
+
+        let xy = |[x, y]: [ffmpeg_sys::AVRational; 2]| {
+            ((q(x) * 50000.0) as u16, (q(y) * 50000.0) as u16)
+        };
+
+        let (max_cll, max_fall) = match frame.side_data(Type::ContentLightLevel) {
+            Some(cll) => {
+                let cll = unsafe {
+                    (cll.data().as_ptr() as *const ffmpeg_sys::AVContentLightMetadata)
+                        .read_unaligned()
+                };
+
+                (cll.MaxCLL, cll.MaxFALL)
+            }
+            None => (0, 0),
+        };
+
+        Some(Self {
+            primaries: mdcv.display_primaries.map(xy),
+            white_point: xy(mdcv.white_point),
+            min_luminance: (q(mdcv.min_luminance) * 10000.0) as u32,
+            max_luminance: q(mdcv.max_luminance) as u32,
+            max_cll,
+            max_fall,
+        })
+    }
+}
+
 pub enum VideoStreamEvent {
     VideoStreamReady(Arc<VkImage>, VideoStreamParams),
     VideoFrameAvailable,
@@ -340,6 +409,8 @@ impl DecoderInit {
             stream_seq: self.stream_seq,
             seq: buf.seq(),
             pts: buf.pts(),
+            timing: buf.timing(),
+            decoded: None,
         };
 
         if self.started.elapsed() > DECODER_INIT_TIMEOUT {
@@ -470,14 +541,21 @@ impl DecoderInit {
         debug_assert_eq!(frame.width(), width);
         debug_assert_eq!(frame.height(), height);
 
+        let hdr_metadata = HdrMetadata::from_frame(&frame);
+        if let Some(metadata) = &hdr_metadata {
+            debug!(?metadata, "video stream has HDR metadata");
+        }
+
+        // The stream may have been resized before we heard about it, in which
+        // case the dimensions in the bitstream win.
         if width != self.width || height != self.height {
-            return Err(anyhow!(
-                "unexpected video stream dimensions: {}x{} (expected {}x{})",
+            debug!(
                 width,
                 height,
-                self.width,
-                self.height
-            ));
+                expected_width = self.width,
+                expected_height = self.height,
+                "unexpected video stream dimensions"
+            );
         }
 
         let mut intermediate_frame =
@@ -590,6 +668,8 @@ impl DecoderInit {
                         stream_seq,
                         seq: buf.seq(),
                         pts: buf.pts(),
+                        timing: buf.timing(),
+                        decoded: None,
                     };
 
                     copy_packet(&mut packet, buf)?;
@@ -606,6 +686,11 @@ impl DecoderInit {
                     loop {
                         match receive_frame(&mut decoder, &mut frame, hw_frame.as_mut()) {
                             Ok(()) => {
+                                let info = FrameMetadata {
+                                    decoded: Some(client::timestamp_micros()),
+                                    ..info
+                                };
+
                                 let pic = copy_frame(
                                     &mut frame,
                                     intermediate_frame.as_mut(),
@@ -669,6 +754,7 @@ impl DecoderInit {
             height,
             color_space,
             color_full_range,
+            hdr_metadata,
         };
 
         Ok((dec, video_texture, params))
@@ -729,7 +815,7 @@ impl CPUDecoder {
 
     pub fn mark_frame_rendered(&mut self) {
         if let Some(info) = self.prepared_frame_info.take() {
-            STATS.frame_rendered(info.stream_seq, info.seq);
+            STATS.frame_rendered(&info);
         }
     }
 
@@ -11,7 +11,7 @@ use std::{
 
 use anyhow::{anyhow, bail, Context};
 use ash::{
-    ext::debug_utils,
+    ext::{debug_utils, hdr_metadata},
     khr::{
         dynamic_rendering, surface, swapchain, video_decode_av1, video_decode_h264,
         video_decode_h265, video_decode_queue, video_queue,
@@ -43,6 +43,7 @@ pub struct VkDeviceInfo {
     pub supports_h264: bool,
     pub supports_h265: bool,
     pub supports_av1: bool,
+    pub supports_hdr_metadata: bool,
     pub memory_props: vk::PhysicalDeviceMemoryProperties,
     pub host_visible_mem_type_index: u32,
     pub host_mem_is_cached: bool,
@@ -55,6 +56,7 @@ pub struct VkContext {
     pub swapchain_loader: swapchain::Device,
     pub surface_loader: surface::Instance,
     pub dynamic_rendering_loader: dynamic_rendering::Device,
+    pub hdr_metadata_loader: Option<hdr_metadata::Device>,
 
     pub surface: vk::SurfaceKHR,
     pub pdevice: vk::PhysicalDevice,
@@ -168,6 +170,11 @@ impl VkDeviceInfo {
             }
         }
 
+        let supports_hdr_metadata = contains(&available_extensions, hdr_metadata::NAME);
+        if supports_hdr_metadata {
+            selected_extensions.push(hdr_metadata::NAME.to_owned());
+        }
+
         // We want HOST_CACHED | HOST_COHERENT, but we can make do with just
         // HOST_VISIBLE.
         let memory_props = unsafe { instance.get_physical_device_memory_properties(device) };
@@ -207,6 +214,7 @@ impl VkDeviceInfo {
             supports_h264,
             supports_h265,
             supports_av1,
+            supports_hdr_metadata,
             memory_props,
             host_visible_mem_type_index,
             host_mem_is_cached,
@@ -473,6 +481,9 @@ impl VkContext {
 
         let swapchain_loader = swapchain::Device::new(&instance, &device);
         let dynamic_rendering_loader = dynamic_rendering::Device::new(&instance, &device);
+        let hdr_metadata_loader = device_info
+            .supports_hdr_metadata
+            .then(|| hdr_metadata::Device::new(&instance, &device));
 
         let tracy_context = tracy_client::Client::running().and_then(|client| {
             match init_tracy_context(&device, &device_info, &present_queue, client) {
@@ -490,6 +501,7 @@ impl VkContext {
             swapchain_loader,
             surface_loader,
             dynamic_rendering_loader,
+            hdr_metadata_loader,
 
             surface,
             pdevice,
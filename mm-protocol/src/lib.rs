@@ -117,7 +117,13 @@ message_types! {
     31 => Attached,
     32 => KeepAlive,
     33 => SessionParametersChanged,
+    34 => UpdateStreamingResolution,
     35 => Detach,
+    36 => SyncClock,
+    37 => ClockSynced,
+    38 => WindowList,
+    39 => WindowAction,
+    40 => WindowMetadataChanged,
     51 => VideoChunk,
     52 => RequestVideoRefresh,
     56 => AudioChunk,
@@ -0,0 +1,213 @@
+// Copyright 2024 Colin Marc <hi@colinmarc.com>
+//
+// SPDX-License-Identifier: BUSL-1.1
+
+//! A local control socket for inspecting and managing the server, used by
+//! `mmctl`. Access is controlled by the file permissions on the socket.
+
+use std::{
+    io::{BufRead as _, BufReader, Write as _},
+    os::unix::{
+        fs::DirBuilderExt as _,
+        net::{UnixListener, UnixStream},
+    },
+    path::Path,
+    time,
+};
+
+use anyhow::{bail, Context as _};
+use tracing::{debug, error, info};
+
+use crate::state::SharedState;
+
+mod api;
+
+pub use api::default_socket_path;
+use api::{AttachmentInfo, Request, Response, SessionInfo};
+
+/// Binds the admin socket and starts serving requests in the background.
+pub fn spawn(path: &Path, state: SharedState) -> anyhow::Result<()> {
+    if path.exists() {
+        // Clean up after a previous instance, but don't steal the socket from
+        // a running one.
+        if UnixStream::connect(path).is_ok() {
+            bail!("{} is in use by another server", path.display());
+        }
+
+        std::fs::remove_file(path).context("removing stale socket")?;
+    } else if let Some(parent) = path.parent() {
+        std::fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(parent)?;
+    }
+
+    let listener = bind(path).context("binding admin socket")?;
+
+    info!("admin socket listening on {}", path.display());
+
+    std::thread::Builder::new()
+        .name("admin".to_string())
+        .spawn(move || {
+            for conn in listener.incoming() {
+                let conn = match conn {
+                    Ok(conn) => conn,
+                    Err(e) => {
+                        error!("failed to accept admin connection: {:#}", e);
+                        continue;
+                    }
+                };
+
+                let state = state.clone();
+                std::thread::spawn(move || {
+                    if let Err(e) = handle_conn(conn, state) {
+                        debug!("admin connection ended with error: {:#}", e);
+                    }
+                });
+            }
+        })?;
+
+    Ok(())
+}
+
+/// Binds the socket, and restricts it to the current user before listening.
+/// Until then, connection attempts are refused, so the socket is never
+/// accessible to other users.
+fn bind(path: &Path) -> anyhow::Result<UnixListener> {
+    use rustix::net::*;
+
+    let sock = socket_with(
+        AddressFamily::UNIX,
+        SocketType::STREAM,
+        SocketFlags::CLOEXEC,
+        None,
+    )?;
+
+    rustix::net::bind(&sock, &SocketAddrUnix::new(path)?)?;
+    rustix::fs::chmod(path, rustix::fs::Mode::from_raw_mode(0o600))?;
+    listen(&sock, 128)?;
+
+    Ok(sock.into())
+}
+
+fn handle_conn(conn: UnixStream, state: SharedState) -> anyhow::Result<()> {
+    let mut w = conn.try_clone()?;
+    for line in BufReader::new(conn).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let resp = match serde_json::from_str(&line) {
+            Ok(req) => {
+                debug!(?req, "admin request");
+                handle_request(&state, req)
+            }
+            Err(e) => Response::Error {
+                message: format!("invalid request: {}", e),
+            },
+        };
+
+        serde_json::to_writer(&mut w, &resp)?;
+        w.write_all(b"\n")?;
+    }
+
+    Ok(())
+}
+
+fn handle_request(state: &SharedState, req: Request) -> Response {
+    let res = match req {
+        Request::ListSessions => return list_sessions(state),
+        Request::EndSession { session_id } => end_session(state, session_id),
+        Request::KickSession { session_id } => kick_session(state, session_id),
+        Request::Detach {
+            session_id,
+            attachment_id,
+        } => detach(state, session_id, attachment_id),
+        Request::ReloadConfig => crate::state::reload_config(state),
+    };
+
+    match res {
+        Ok(()) => Response::Ok,
+        Err(e) => Response::Error {
+            message: format!("{:#}", e),
+        },
+    }
+}
+
+fn list_sessions(state: &SharedState) -> Response {
+    let now = time::SystemTime::now();
+    let since = |t: time::SystemTime| now.duration_since(t).unwrap_or_default().as_secs();
+
+    let mut sessions = state
+        .lock()
+        .sessions
+        .values()
+        .map(|s| {
+            let usage = s.resource_usage();
+            SessionInfo {
+                session_id: s.id,
+                application_id: s.application_id.clone(),
+                started: s
+                    .started
+                    .duration_since(time::UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs(),
+                uptime_secs: since(s.started),
+                detached_secs: s.detached_since.map(|t| t.elapsed().as_secs()),
+                defunct: s.defunct,
+                width: s.display_params.width,
+                height: s.display_params.height,
+                framerate: s.display_params.framerate,
+                memory_bytes: usage.map(|u| u.memory_bytes),
+                cpu_secs: usage.map(|u| u.cpu_time.as_secs_f64()),
+                attachments: s
+                    .attachments()
+                    .map(|a| AttachmentInfo {
+                        attachment_id: a.id,
+                        duration_secs: since(a.attached),
+                        bytes_sent: a.bytes_sent(),
+                        bitrate_mbps: a.bitrate_mbps(),
+                    })
+                    .collect(),
+            }
+        })
+        .collect::<Vec<_>>();
+
+    sessions.sort_by_key(|s| s.started);
+    Response::Sessions { sessions }
+}
+
+fn end_session(state: &SharedState, session_id: u64) -> anyhow::Result<()> {
+    if !state.lock().end_session(session_id) {
+        bail!("session not found");
+    }
+
+    info!(session_id, "ending session from admin socket");
+    Ok(())
+}
+
+fn kick_session(state: &SharedState, session_id: u64) -> anyhow::Result<()> {
+    let mut guard = state.lock();
+    let Some(session) = guard.sessions.get_mut(&session_id) else {
+        bail!("session not found");
+    };
+
+    let ids = session.attachments().map(|a| a.id).collect::<Vec<_>>();
+    for id in ids {
+        info!(session_id, attachment_id = id, "kicking client");
+        session.kick(id)?;
+    }
+
+    Ok(())
+}
+
+fn detach(state: &SharedState, session_id: u64, attachment_id: u64) -> anyhow::Result<()> {
+    let mut guard = state.lock();
+    let Some(session) = guard.sessions.get_mut(&session_id) else {
+        bail!("session not found");
+    };
+
+    info!(session_id, attachment_id, "detaching client");
+    session.kick(attachment_id)
+}
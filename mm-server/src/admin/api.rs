@@ -0,0 +1,98 @@
+// Copyright 2024 Colin Marc <hi@colinmarc.com>
+//
+// SPDX-License-Identifier: BUSL-1.1
+
+//! The wire format for the admin socket. Each request and response is a single
+//! line of JSON.
+//!
+//! This module is shared with `mmctl`, so it shouldn't depend on anything else
+//! in the crate.
+
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+/// The socket path used if `admin_socket` isn't configured.
+pub fn default_socket_path() -> Option<PathBuf> {
+    let dir = std::env::var_os("XDG_RUNTIME_DIR")?;
+    Some(Path::new(&dir).join("mmserver-admin.sock"))
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum Request {
+    ListSessions,
+    /// Stops a session and the app running in it.
+    EndSession {
+        session_id: u64,
+    },
+    /// Detaches all clients from a session, leaving it running.
+    KickSession {
+        session_id: u64,
+    },
+    /// Detaches a single client from a session.
+    Detach {
+        session_id: u64,
+        attachment_id: u64,
+    },
+    /// Re-reads the config file and app definitions.
+    ReloadConfig,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "result", rename_all = "snake_case")]
+pub enum Response {
+    Sessions { sessions: Vec<SessionInfo> },
+    Ok,
+    Error { message: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionInfo {
+    pub session_id: u64,
+    pub application_id: String,
+    /// Seconds since the unix epoch.
+    pub started: u64,
+    pub uptime_secs: u64,
+    /// How long the session has been idle, if nothing is attached.
+    pub detached_secs: Option<u64>,
+    pub defunct: bool,
+    pub width: u32,
+    pub height: u32,
+    pub framerate: u32,
+    /// Only available if the session is running in a cgroup.
+    pub memory_bytes: Option<u64>,
+    pub cpu_secs: Option<f64>,
+    pub attachments: Vec<AttachmentInfo>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AttachmentInfo {
+    pub attachment_id: u64,
+    pub duration_secs: u64,
+    pub bytes_sent: u64,
+    pub bitrate_mbps: f64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn request_format() {
+        let req = Request::Detach {
+            session_id: 1,
+            attachment_id: 2,
+        };
+
+        let json = serde_json::to_string(&req).unwrap();
+        assert_eq!(
+            json,
+            r#"{"command":"detach","session_id":1,"attachment_id":2}"#
+        );
+        assert_eq!(serde_json::from_str::<Request>(&json).unwrap(), req);
+
+        let json = serde_json::to_string(&Request::ListSessions).unwrap();
+        assert_eq!(json, r#"{"command":"list_sessions"}"#);
+    }
+}
@@ -0,0 +1,149 @@
+// Copyright 2024 Colin Marc <hi@colinmarc.com>
+//
+// SPDX-License-Identifier: BUSL-1.1
+
+use std::{
+    io::{BufRead as _, BufReader, Write as _},
+    os::unix::net::UnixStream,
+    path::PathBuf,
+};
+
+use anyhow::{anyhow, bail, Context as _, Result};
+use clap::{Parser, Subcommand};
+
+#[path = "../admin/api.rs"]
+mod api;
+
+use api::{Request, Response};
+
+#[derive(Debug, Parser)]
+#[command(name = "mmctl")]
+#[command(about = "Manage a running Magic Mirror server", long_about = None)]
+struct Cli {
+    /// The path to the admin socket. Defaults to
+    /// $XDG_RUNTIME_DIR/mmserver-admin.sock.
+    #[arg(short, long, value_name = "PATH")]
+    socket: Option<PathBuf>,
+    /// Print the raw JSON response.
+    #[arg(long)]
+    json: bool,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// List running sessions and their attachments.
+    #[command(alias = "ls")]
+    List,
+    /// End a session, stopping the app.
+    End { session_id: u64 },
+    /// Detach all clients from a session, leaving it running.
+    Kick { session_id: u64 },
+    /// Detach a single client from a session.
+    Detach { session_id: u64, attachment_id: u64 },
+    /// Reload the server configuration.
+    Reload,
+}
+
+fn main() -> Result<()> {
+    let args = Cli::parse();
+
+    let path = args
+        .socket
+        .or_else(api::default_socket_path)
+        .ok_or(anyhow!("$XDG_RUNTIME_DIR not set; use --socket"))?;
+
+    let req = match args.command {
+        Command::List => Request::ListSessions,
+        Command::End { session_id } => Request::EndSession { session_id },
+        Command::Kick { session_id } => Request::KickSession { session_id },
+        Command::Detach {
+            session_id,
+            attachment_id,
+        } => Request::Detach {
+            session_id,
+            attachment_id,
+        },
+        Command::Reload => Request::ReloadConfig,
+    };
+
+    let mut conn =
+        UnixStream::connect(&path).context(format!("failed to connect to {}", path.display()))?;
+
+    serde_json::to_writer(&mut conn, &req)?;
+    conn.write_all(b"\n")?;
+
+    let mut line = String::new();
+    BufReader::new(conn).read_line(&mut line)?;
+    if args.json {
+        print!("{}", line);
+        return Ok(());
+    }
+
+    match serde_json::from_str(&line).context("invalid response")? {
+        Response::Sessions { sessions } => print_sessions(&sessions),
+        Response::Ok => (),
+        Response::Error { message } => bail!(message),
+    }
+
+    Ok(())
+}
+
+fn print_sessions(sessions: &[api::SessionInfo]) {
+    if sessions.is_empty() {
+        println!("no running sessions");
+        return;
+    }
+
+    println!(
+        "{:<10} {:<24} {:<16} {:<10} {:<10} STATUS",
+        "SESSION", "APP", "DISPLAY", "UPTIME", "MEMORY"
+    );
+
+    for s in sessions {
+        let status = if s.defunct {
+            "defunct".to_string()
+        } else if let Some(secs) = s.detached_secs {
+            format!("detached for {}", format_duration(secs))
+        } else {
+            "attached".to_string()
+        };
+
+        let memory = match s.memory_bytes {
+            Some(b) => format!("{:.1}G", b as f64 / (1024.0 * 1024.0 * 1024.0)),
+            None => "-".to_string(),
+        };
+
+        println!(
+            "{:<10} {:<24} {:<16} {:<10} {:<10} {}",
+            s.session_id,
+            s.application_id,
+            format!("{}x{}@{}", s.width, s.height, s.framerate),
+            format_duration(s.uptime_secs),
+            memory,
+            status,
+        );
+
+        for a in &s.attachments {
+            println!(
+                "  attachment {:<10} {:>10} {:>8.2} Mbps {:>10.2} MB sent",
+                a.attachment_id,
+                format_duration(a.duration_secs),
+                a.bitrate_mbps,
+                a.bytes_sent as f64 / (1024.0 * 1024.0),
+            );
+        }
+    }
+}
+
+fn format_duration(secs: u64) -> String {
+    let (h, m, s) = (secs / 3600, (secs / 60) % 60, secs % 60);
+    if h > 0 {
+        format!("{}h{:02}m", h, m)
+    } else if m > 0 {
+        format!("{}m{:02}s", m, s)
+    } else {
+        format!("{}s", s)
+    }
+}
@@ -65,6 +65,41 @@ impl From<VideoProfile> for protocol::VideoProfile {
     }
 }
 
+/// Static HDR metadata, describing the display the content was mastered on
+/// (SMPTE ST 2086) and the light levels of the content itself (CTA-861.3).
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct HdrMetadata {
+    /// The red, green, and blue primaries of the mastering display, as CIE
+    /// 1931 xy coordinates multiplied by 1,000,000.
+    pub mastering_primaries: [(u32, u32); 3],
+    /// The white point of the mastering display, in the same units as the
+    /// primaries.
+    pub mastering_white_point: (u32, u32),
+    /// The minimum luminance of the mastering display, in units of 0.0001
+    /// cd/m².
+    pub min_mastering_luminance: u32,
+    /// The maximum luminance of the mastering display, in cd/m².
+    pub max_mastering_luminance: u32,
+    /// The maximum content light level, in cd/m².
+    pub max_cll: u32,
+    /// The maximum frame-average light level, in cd/m².
+    pub max_fall: u32,
+}
+
+impl Default for HdrMetadata {
+    /// Describes a typical 1000-nit HDR10 display with BT.2020 primaries.
+    fn default() -> Self {
+        Self {
+            mastering_primaries: [(708_000, 292_000), (170_000, 797_000), (131_000, 46_000)],
+            mastering_white_point: (312_700, 329_000),
+            min_mastering_luminance: 50,
+            max_mastering_luminance: 1000,
+            max_cll: 1000,
+            max_fall: 400,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub enum TransferFunction {
     Linear,
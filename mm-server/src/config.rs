@@ -14,6 +14,7 @@ use std::{
 use anyhow::{bail, Context};
 use lazy_static::lazy_static;
 use regex::Regex;
+use syscalls::Sysno;
 use tracing::trace;
 
 lazy_static! {
@@ -94,6 +95,8 @@ mod parsed {
         pub(super) mdns_hostname: Option<String>,
         pub(super) mdns_instance_name: Option<String>,
         pub(super) video_fec_ratios: Option<Vec<f32>>,
+        pub(super) admin_socket: Option<PathBuf>,
+        pub(super) metrics_bind: Option<String>,
     }
 
     #[derive(Debug, Clone, PartialEq, Deserialize, Converge)]
@@ -101,9 +104,23 @@ mod parsed {
     pub(super) struct DefaultAppSettings {
         pub(super) xwayland: Option<bool>,
         pub(super) force_1x_scale: Option<bool>,
+        pub(super) variable_refresh: Option<bool>,
+        pub(super) record: Option<bool>,
         pub(super) session_timeout: Option<NonZeroOrInf>,
+        pub(super) idle_timeout: Option<NonZeroOrInf>,
+        pub(super) idle_action: Option<IdleAction>,
+        pub(super) stop_timeout: Option<u64>,
         pub(super) isolate_home: Option<bool>,
         pub(super) tmp_home: Option<bool>,
+        pub(super) memory_limit: Option<ByteSize>,
+        pub(super) cpu_limit: Option<f32>,
+        pub(super) cpu_weight: Option<u32>,
+        pub(super) io_weight: Option<u32>,
+        pub(super) pids_limit: Option<u32>,
+        pub(super) network: Option<NetworkMode>,
+        pub(super) seccomp: Option<bool>,
+        pub(super) deny_syscalls: Option<Vec<String>>,
+        pub(super) session_bus: Option<bool>,
     }
 
     #[derive(Debug, Clone, PartialEq, Deserialize)]
@@ -116,10 +133,84 @@ mod parsed {
         pub(super) environment: Option<BTreeMap<String, String>>,
         pub(super) xwayland: Option<bool>,
         pub(super) force_1x_scale: Option<bool>,
+        pub(super) variable_refresh: Option<bool>,
+        pub(super) record: Option<bool>,
         pub(super) session_timeout: Option<NonZeroOrInf>,
+        pub(super) idle_timeout: Option<NonZeroOrInf>,
+        pub(super) idle_action: Option<IdleAction>,
+        pub(super) stop_timeout: Option<u64>,
         pub(super) isolate_home: Option<bool>,
         pub(super) shared_home_name: Option<String>,
         pub(super) tmp_home: Option<bool>,
+        pub(super) memory_limit: Option<ByteSize>,
+        pub(super) cpu_limit: Option<f32>,
+        pub(super) cpu_weight: Option<u32>,
+        pub(super) io_weight: Option<u32>,
+        pub(super) pids_limit: Option<u32>,
+        pub(super) network: Option<NetworkMode>,
+        pub(super) seccomp: Option<bool>,
+        pub(super) deny_syscalls: Option<Vec<String>>,
+        pub(super) allow_syscalls: Option<Vec<String>>,
+        pub(super) bind_mounts: Option<Vec<BindMount>>,
+        pub(super) tmpfs_paths: Option<Vec<PathBuf>>,
+        pub(super) masked_paths: Option<Vec<PathBuf>>,
+        pub(super) rootfs: Option<PathBuf>,
+        pub(super) rootfs_overlay: Option<bool>,
+        pub(super) session_bus: Option<bool>,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Deserialize)]
+    #[serde(deny_unknown_fields)]
+    pub(super) struct BindMount {
+        pub(super) source: PathBuf,
+        pub(super) destination: Option<PathBuf>,
+        pub(super) read_only: Option<bool>,
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+    #[serde(rename_all = "lowercase")]
+    pub(super) enum IdleAction {
+        End,
+        Suspend,
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+    #[serde(rename_all = "lowercase")]
+    pub(super) enum NetworkMode {
+        Host,
+        None,
+        Nat,
+    }
+
+    /// A size in bytes, either as an integer or a string with a suffix like
+    /// "512M" or "8G".
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub(super) struct ByteSize(pub(super) u64);
+
+    impl<'de> Deserialize<'de> for ByteSize {
+        fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+        where
+            D: serde::Deserializer<'de>,
+        {
+            #[derive(Deserialize)]
+            #[serde(untagged, expecting = "a size in bytes, like 1024 or \"8G\"")]
+            enum Variant {
+                Int(u64),
+                Str(String),
+            }
+
+            match Deserialize::deserialize(deserializer)? {
+                Variant::Int(n) => Ok(ByteSize(n)),
+                Variant::Str(s) => {
+                    super::parse_byte_size(&s)
+                        .map(ByteSize)
+                        .ok_or(serde::de::Error::invalid_value(
+                            serde::de::Unexpected::Str(&s),
+                            &"a size in bytes, like 1024 or \"8G\"",
+                        ))
+                }
+            }
+        }
     }
 }
 
@@ -144,6 +235,8 @@ pub struct ServerConfig {
     pub mdns_hostname: Option<String>,
     pub mdns_instance_name: Option<String>,
     pub video_fec_ratios: Vec<f32>,
+    pub admin_socket: Option<PathBuf>,
+    pub metrics_bind: Option<String>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -155,8 +248,86 @@ pub struct AppConfig {
     pub env: BTreeMap<OsString, OsString>,
     pub xwayland: bool,
     pub force_1x_scale: bool,
+    pub variable_refresh: bool,
+    pub recording_dir: Option<PathBuf>,
     pub session_timeout: Option<time::Duration>,
+    /// How long an attached session can go without input before
+    /// `idle_action` is taken.
+    pub idle_timeout: Option<time::Duration>,
+    pub idle_action: IdleAction,
+    /// How long to wait for the app to exit after SIGTERM, before killing it.
+    pub stop_timeout: time::Duration,
     pub home_isolation_mode: HomeIsolationMode,
+    pub resource_limits: ResourceLimits,
+    pub network_mode: NetworkMode,
+    /// Syscalls blocked with seccomp. If empty, no filter is installed.
+    pub denied_syscalls: Vec<Sysno>,
+    pub bind_mounts: Vec<BindMount>,
+    /// Paths inside the container covered with an empty, writable tmpfs.
+    pub tmpfs_paths: Vec<PathBuf>,
+    /// Paths inside the container hidden behind an empty, read-only mount.
+    pub masked_paths: Vec<PathBuf>,
+    pub rootfs: Option<Rootfs>,
+    /// Whether to start a private D-Bus session bus for the app.
+    pub session_bus: bool,
+}
+
+/// A root filesystem to run the app in, instead of the host's.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rootfs {
+    pub path: PathBuf,
+    /// If set, the rootfs is mounted read-only as the lower layer of an
+    /// overlayfs, with the upper layer stored here. Only one container can
+    /// use it at a time. Without it, mount points are created in the rootfs
+    /// directly.
+    pub overlay: Option<PathBuf>,
+}
+
+/// A host path to make available inside the container.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BindMount {
+    pub source: PathBuf,
+    pub destination: PathBuf,
+    pub read_only: bool,
+}
+
+/// Limits applied to the cgroup for each session.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ResourceLimits {
+    pub memory_max: Option<u64>,
+    /// A fraction of CPUs, e.g. 2.5 to allow two and a half cores.
+    pub cpu_max: Option<f32>,
+    pub cpu_weight: Option<u32>,
+    pub io_weight: Option<u32>,
+    pub pids_max: Option<u32>,
+}
+
+impl ResourceLimits {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// What to do with an attached session once it's been idle for
+/// `idle_timeout`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdleAction {
+    /// End the session.
+    End,
+    /// Detach all clients, so that the session stops rendering. It can be
+    /// reattached until `session_timeout` runs out.
+    Suspend,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NetworkMode {
+    /// Share the host network stack.
+    Host,
+    /// An empty network namespace, with only loopback.
+    None,
+    /// A separate network namespace, with outbound access through a
+    /// user-mode network stack (slirp4netns).
+    Nat,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -241,6 +412,10 @@ impl Config {
                 mdns_hostname: server.mdns_hostname,
                 mdns_instance_name: server.mdns_instance_name,
                 video_fec_ratios: server.video_fec_ratios.unwrap(),
+                admin_socket: server
+                    .admin_socket
+                    .or_else(crate::admin::default_socket_path),
+                metrics_bind: server.metrics_bind,
             },
             data_home: data_home.clone(),
             apps: BTreeMap::new(), // Handled below.
@@ -319,6 +494,19 @@ impl Config {
     }
 }
 
+/// Where the config was loaded from, so that it can be reloaded later.
+#[derive(Debug, Clone, Default)]
+pub struct ConfigSource {
+    pub path: Option<PathBuf>,
+    pub includes: Vec<PathBuf>,
+}
+
+impl ConfigSource {
+    pub fn load(&self) -> anyhow::Result<Config> {
+        Config::new(self.path.as_ref(), &self.includes)
+    }
+}
+
 impl Default for Config {
     fn default() -> Self {
         Config::build(None, &[]).expect("failed to build default config")
@@ -426,6 +614,16 @@ fn validate_app(
         parsed::NonZeroOrInf::Infinity => None,
     };
 
+    let idle_timeout = match app.idle_timeout.or(defaults.idle_timeout.clone()).unwrap() {
+        parsed::NonZeroOrInf::Value(v) => Some(time::Duration::from_secs(v.get() as u64)),
+        parsed::NonZeroOrInf::Infinity => None,
+    };
+
+    let idle_action = match app.idle_action.or(defaults.idle_action).unwrap() {
+        parsed::IdleAction::End => IdleAction::End,
+        parsed::IdleAction::Suspend => IdleAction::Suspend,
+    };
+
     let isolate_home = app.isolate_home.or(defaults.isolate_home).unwrap();
     let tmp_home = app.tmp_home.or(defaults.tmp_home).unwrap();
     let home_isolation_mode = match (isolate_home, tmp_home) {
@@ -445,6 +643,124 @@ fn validate_app(
         }
     };
 
+    let resource_limits = ResourceLimits {
+        memory_max: app.memory_limit.or(defaults.memory_limit).map(|b| b.0),
+        cpu_max: app.cpu_limit.or(defaults.cpu_limit),
+        cpu_weight: app.cpu_weight.or(defaults.cpu_weight),
+        io_weight: app.io_weight.or(defaults.io_weight),
+        pids_max: app.pids_limit.or(defaults.pids_limit),
+    };
+
+    if resource_limits.cpu_max.is_some_and(|v| v <= 0.0) {
+        bail!("cpu_limit must be positive");
+    }
+
+    for (name, weight) in [
+        ("cpu_weight", resource_limits.cpu_weight),
+        ("io_weight", resource_limits.io_weight),
+    ] {
+        if weight.is_some_and(|w| !(1..=10000).contains(&w)) {
+            bail!("{name} must be between 1 and 10000");
+        }
+    }
+
+    let network_mode = match app.network.or(defaults.network).unwrap() {
+        parsed::NetworkMode::Host => NetworkMode::Host,
+        parsed::NetworkMode::None => NetworkMode::None,
+        parsed::NetworkMode::Nat => NetworkMode::Nat,
+    };
+
+    let denied_syscalls = if app.seccomp.or(defaults.seccomp).unwrap() {
+        let allowed = resolve_syscalls(app.allow_syscalls.as_deref().unwrap_or_default())
+            .context("invalid allow_syscalls")?;
+
+        let mut denied = resolve_syscalls(defaults.deny_syscalls.as_deref().unwrap_or_default())
+            .context("invalid default deny_syscalls")?;
+        for nr in resolve_syscalls(app.deny_syscalls.as_deref().unwrap_or_default())
+            .context("invalid deny_syscalls")?
+        {
+            if !denied.contains(&nr) {
+                denied.push(nr);
+            }
+        }
+
+        denied.retain(|nr| !allowed.contains(nr));
+        denied
+    } else {
+        Vec::new()
+    };
+
+    let bind_mounts = app
+        .bind_mounts
+        .unwrap_or_default()
+        .into_iter()
+        .map(|m| {
+            let source = expand_home(&m.source);
+            if !source.is_absolute() {
+                bail!("bind mount source must be absolute: {}", m.source.display());
+            } else if !source.exists() {
+                bail!("bind mount source does not exist: {}", source.display());
+            }
+
+            let destination = match m.destination {
+                Some(p) => validate_container_path(&p).context("invalid bind mount destination")?,
+                None => validate_container_path(&source).context("invalid bind mount source")?,
+            };
+
+            Ok(BindMount {
+                source,
+                destination,
+                read_only: m.read_only.unwrap_or_default(),
+            })
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let tmpfs_paths = app
+        .tmpfs_paths
+        .unwrap_or_default()
+        .iter()
+        .map(|p| validate_container_path(p).context("invalid tmpfs_paths"))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let masked_paths = app
+        .masked_paths
+        .unwrap_or_default()
+        .iter()
+        .map(|p| validate_container_path(p).context("invalid masked_paths"))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let rootfs = match app.rootfs {
+        Some(p) => {
+            if !p.is_absolute() {
+                bail!("rootfs must be absolute: {}", p.display());
+            } else if !p.is_dir() {
+                bail!("rootfs is not a directory: {}", p.display());
+            }
+
+            // Unpacked OCI bundles have the filesystem in a subdirectory.
+            let path = if p.join("config.json").is_file() && p.join("rootfs").is_dir() {
+                p.join("rootfs")
+            } else {
+                p
+            };
+
+            let overlay = app
+                .rootfs_overlay
+                .unwrap_or_default()
+                .then(|| data_home.join("overlays").join(id));
+
+            Some(Rootfs { path, overlay })
+        }
+        None if app.rootfs_overlay.is_some() => bail!("rootfs_overlay requires rootfs"),
+        None => None,
+    };
+
+    let recording_dir = app
+        .record
+        .or(defaults.record)
+        .unwrap()
+        .then(|| data_home.join("recordings").join(id));
+
     Ok(AppConfig {
         path,
         description: app.description,
@@ -458,11 +774,78 @@ fn validate_app(
             .collect(),
         xwayland: app.xwayland.or(defaults.xwayland).unwrap(),
         force_1x_scale: app.force_1x_scale.or(defaults.force_1x_scale).unwrap(),
+        variable_refresh: app.variable_refresh.or(defaults.variable_refresh).unwrap(),
+        recording_dir,
         session_timeout,
+        idle_timeout,
+        idle_action,
+        stop_timeout: time::Duration::from_secs(
+            app.stop_timeout.or(defaults.stop_timeout).unwrap(),
+        ),
         home_isolation_mode,
+        resource_limits,
+        network_mode,
+        denied_syscalls,
+        bind_mounts,
+        tmpfs_paths,
+        masked_paths,
+        rootfs,
+        session_bus: app.session_bus.or(defaults.session_bus).unwrap(),
     })
 }
 
+/// Expands a leading `~` to `$HOME`, which is also `$HOME` inside the
+/// container.
+fn expand_home(p: &Path) -> PathBuf {
+    match (p.strip_prefix("~"), std::env::var_os("HOME")) {
+        (Ok(rest), Some(home)) => Path::new(&home).join(rest),
+        _ => p.to_owned(),
+    }
+}
+
+/// Validates a mount point inside the container.
+fn validate_container_path(p: &Path) -> anyhow::Result<PathBuf> {
+    let p = expand_home(p);
+    if !p.is_absolute() {
+        bail!("path must be absolute: {}", p.display());
+    } else if p.components().any(|c| matches!(c, Component::ParentDir)) {
+        bail!("path must not contain \"..\": {}", p.display());
+    } else if p.parent().is_none() {
+        bail!("can't mount over /");
+    }
+
+    for reserved in ["/proc", "/dev"] {
+        if p.starts_with(reserved) {
+            bail!("path is reserved: {}", p.display());
+        }
+    }
+
+    Ok(p)
+}
+
+fn resolve_syscalls(names: &[String]) -> anyhow::Result<Vec<Sysno>> {
+    names
+        .iter()
+        .map(|name| {
+            name.parse::<Sysno>()
+                .map_err(|_| anyhow::anyhow!("unknown syscall: {name}"))
+        })
+        .collect()
+}
+
+fn parse_byte_size(s: &str) -> Option<u64> {
+    let s = s.trim();
+    let (digits, mult) = match s.char_indices().last()? {
+        (i, 'K' | 'k') => (&s[..i], 1 << 10),
+        (i, 'M' | 'm') => (&s[..i], 1 << 20),
+        (i, 'G' | 'g') => (&s[..i], 1 << 30),
+        (i, 'T' | 't') => (&s[..i], 1 << 40),
+        _ => (s, 1),
+    };
+
+    digits.trim().parse::<u64>().ok()?.checked_mul(mult)
+}
+
 fn validate_app_path(p: String) -> anyhow::Result<Vec<String>> {
     let components = Path::new(&p).components();
     let mut out = Vec::new();
@@ -511,11 +894,34 @@ mod test {
             env: Default::default(),
             xwayland: true,
             force_1x_scale: false,
+            variable_refresh: false,
+            recording_dir: None,
             session_timeout: Some(time::Duration::from_secs(3600)),
+            idle_timeout: None,
+            idle_action: IdleAction::Suspend,
+            stop_timeout: time::Duration::from_secs(10),
             home_isolation_mode: HomeIsolationMode::Unisolated,
+            resource_limits: Default::default(),
+            network_mode: NetworkMode::Host,
+            denied_syscalls: default_denied_syscalls(),
+            bind_mounts: Vec::new(),
+            tmpfs_paths: Vec::new(),
+            masked_paths: Vec::new(),
+            rootfs: None,
+            session_bus: true,
         };
     }
 
+    fn default_denied_syscalls() -> Vec<Sysno> {
+        let names = DEFAULT_CFG
+            .default_app_settings
+            .as_ref()
+            .and_then(|s| s.deny_syscalls.as_ref())
+            .unwrap();
+
+        resolve_syscalls(names).unwrap()
+    }
+
     fn config_from_str(s: &str) -> anyhow::Result<Config> {
         let input: parsed::Config = toml::from_str(s)?;
         Config::build(Some(input), &[])
@@ -618,6 +1024,233 @@ mod test {
             .expect("TLS not required for shared NAT address");
     }
 
+    #[test]
+    fn resource_limits() {
+        let config = config_from_str(
+            r#"
+            [default_app_settings]
+            pids_limit = 4096
+            [apps.example]
+            command = ["echo", "hello"]
+            memory_limit = "8G"
+            cpu_limit = 2.5
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.apps["example"].resource_limits,
+            ResourceLimits {
+                memory_max: Some(8 * 1024 * 1024 * 1024),
+                cpu_max: Some(2.5),
+                pids_max: Some(4096),
+                ..Default::default()
+            }
+        );
+
+        assert!(config_from_str(
+            r#"
+            [apps.example]
+            command = ["echo", "hello"]
+            cpu_weight = 0
+            "#,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn network_mode() {
+        let config = config_from_str(
+            r#"
+            [apps.example]
+            command = ["echo", "hello"]
+            network = "none"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.apps["example"].network_mode, NetworkMode::None);
+
+        assert!(config_from_str(
+            r#"
+            [apps.example]
+            command = ["echo", "hello"]
+            network = "bridge"
+            "#,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn idle_policy() {
+        let config = config_from_str(
+            r#"
+            [default_app_settings]
+            idle_action = "end"
+            [apps.example]
+            command = ["echo", "hello"]
+            idle_timeout = 600
+            [apps.defaults]
+            command = ["echo", "hello"]
+            [apps.suspended]
+            command = ["echo", "hello"]
+            idle_timeout = inf
+            idle_action = "suspend"
+            "#,
+        )
+        .unwrap();
+
+        let app = &config.apps["example"];
+        assert_eq!(app.idle_timeout, Some(time::Duration::from_secs(600)));
+        assert_eq!(app.idle_action, IdleAction::End);
+
+        let app = &config.apps["defaults"];
+        assert_eq!(app.idle_timeout, None);
+        assert_eq!(app.idle_action, IdleAction::End);
+
+        let app = &config.apps["suspended"];
+        assert_eq!(app.idle_timeout, None);
+        assert_eq!(app.idle_action, IdleAction::Suspend);
+
+        for invalid in [r#"idle_timeout = 0"#, r#"idle_action = "hibernate""#] {
+            let s = format!("[apps.example]\ncommand = [\"echo\"]\n{invalid}");
+            assert!(config_from_str(&s).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn syscall_filter() {
+        let config = config_from_str(
+            r#"
+            [apps.example]
+            command = ["echo", "hello"]
+            deny_syscalls = ["userfaultfd"]
+            allow_syscalls = ["ptrace"]
+
+            [apps.defaults]
+            command = ["echo", "hello"]
+
+            [apps.unfiltered]
+            command = ["echo", "hello"]
+            seccomp = false
+            "#,
+        )
+        .unwrap();
+
+        let denied = &config.apps["example"].denied_syscalls;
+        assert!(denied.contains(&Sysno::userfaultfd));
+        assert!(denied.contains(&Sysno::kexec_load));
+        assert!(!denied.contains(&Sysno::ptrace));
+
+        let denied = &config.apps["defaults"].denied_syscalls;
+        assert!(denied.contains(&Sysno::ptrace));
+        assert!(denied.contains(&Sysno::bpf));
+
+        assert!(config.apps["unfiltered"].denied_syscalls.is_empty());
+
+        assert!(config_from_str(
+            r#"
+            [apps.example]
+            command = ["echo", "hello"]
+            deny_syscalls = ["not_a_syscall"]
+            "#,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn mounts() {
+        let home = PathBuf::from(std::env::var_os("HOME").unwrap());
+        let config = config_from_str(
+            r#"
+            [apps.example]
+            command = ["echo", "hello"]
+            bind_mounts = [
+                { source = "/tmp", destination = "/mnt/games", read_only = true },
+                { source = "/tmp" },
+            ]
+            tmpfs_paths = ["~/.ssh"]
+            masked_paths = ["/etc/ssh"]
+            "#,
+        )
+        .unwrap();
+
+        let app = &config.apps["example"];
+        assert_eq!(
+            app.bind_mounts,
+            vec![
+                BindMount {
+                    source: "/tmp".into(),
+                    destination: "/mnt/games".into(),
+                    read_only: true,
+                },
+                BindMount {
+                    source: "/tmp".into(),
+                    destination: "/tmp".into(),
+                    read_only: false,
+                }
+            ]
+        );
+        assert_eq!(app.tmpfs_paths, vec![home.join(".ssh")]);
+        assert_eq!(app.masked_paths, vec![PathBuf::from("/etc/ssh")]);
+
+        for invalid in [
+            r#"bind_mounts = [{ source = "/does/not/exist" }]"#,
+            r#"bind_mounts = [{ source = "/tmp", destination = "relative" }]"#,
+            r#"tmpfs_paths = ["/foo/../etc"]"#,
+            r#"masked_paths = ["/proc/self"]"#,
+            r#"masked_paths = ["/"]"#,
+        ] {
+            let s = format!("[apps.example]\ncommand = [\"echo\"]\n{invalid}");
+            assert!(config_from_str(&s).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn rootfs() {
+        let bundle = mktemp::Temp::new_dir().unwrap();
+        std::fs::create_dir(bundle.join("rootfs")).unwrap();
+        std::fs::write(bundle.join("config.json"), "{}").unwrap();
+
+        let config = config_from_str(&format!(
+            r#"
+            data_home = "/data"
+            [apps.example]
+            command = ["/bin/true"]
+            rootfs = {:?}
+            rootfs_overlay = true
+            "#,
+            bundle.as_path(),
+        ))
+        .unwrap();
+
+        assert_eq!(
+            config.apps["example"].rootfs,
+            Some(Rootfs {
+                path: bundle.join("rootfs"),
+                overlay: Some("/data/overlays/example".into()),
+            })
+        );
+
+        assert!(config_from_str(
+            r#"
+            [apps.example]
+            command = ["echo", "hello"]
+            rootfs = "/does/not/exist"
+            "#,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn byte_sizes() {
+        assert_eq!(parse_byte_size("1024"), Some(1024));
+        assert_eq!(parse_byte_size("512M"), Some(512 * 1024 * 1024));
+        assert_eq!(parse_byte_size("2g"), Some(2 * 1024 * 1024 * 1024));
+        assert_eq!(parse_byte_size("G"), None);
+        assert_eq!(parse_byte_size("-1K"), None);
+    }
+
     #[test]
     fn app_paths() {
         assert!(validate_app_path("foo!".into()).is_err());
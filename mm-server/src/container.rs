@@ -6,17 +6,21 @@ use std::{
     ffi::CStr,
     os::fd::{AsFd, BorrowedFd, OwnedFd},
     path::{Path, PathBuf},
+    sync::Arc,
 };
 
-use anyhow::{bail, Context as _};
+use anyhow::Context as _;
 use rustix::{
     mount::MountAttrFlags,
     process::{Pid, Signal, WaitId, WaitIdOptions},
 };
 use tracing::{debug, info};
 
+mod cgroup;
 mod ipc;
 mod runtime;
+mod seccomp;
+pub use cgroup::{Cgroup, ResourceUsage};
 pub use runtime::Container;
 
 /// A handle to a running container.
@@ -25,6 +29,9 @@ pub struct ContainerHandle {
     pidfd: OwnedFd,
 
     run_path: PathBuf,
+    _cgroup: Option<Arc<Cgroup>>,
+    _network: Option<runtime::UserNetwork>,
+    _overlay_lock: Option<OwnedFd>,
 }
 
 impl AsFd for ContainerHandle {
@@ -49,19 +56,22 @@ impl ContainerHandle {
         Ok(())
     }
 
-    pub fn wait(&mut self) -> anyhow::Result<()> {
-        let exit_status =
-            rustix::process::waitid(WaitId::PidFd(self.as_fd()), WaitIdOptions::EXITED)
-                .context("waitid")?
-                .and_then(|x| x.exit_status())
-                .unwrap_or_default();
+    /// Waits for the app to exit, and returns its exit status. If the app was
+    /// killed by a signal, the status is 128 plus the signal number, as in a
+    /// shell.
+    pub fn wait(&mut self) -> anyhow::Result<i32> {
+        let status = rustix::process::waitid(WaitId::PidFd(self.as_fd()), WaitIdOptions::EXITED)
+            .context("waitid")?;
 
-        info!(exit_status, "child process exited");
-        if exit_status != 0 {
-            bail!("child process exited with status: {exit_status}");
-        }
+        // If init itself was killed, the app went with it.
+        let exit_status = match status {
+            Some(s) if s.exited() => s.exit_status().unwrap_or_default() as i32,
+            Some(s) if s.killed() => 128 + s.terminating_signal().unwrap_or_default() as i32,
+            _ => 0,
+        };
 
-        Ok(())
+        info!(exit_status, "child process exited");
+        Ok(exit_status)
     }
 
     /// Mounts a named filesystem inside the container at the given path.
@@ -0,0 +1,205 @@
+// Copyright 2024 Colin Marc <hi@colinmarc.com>
+//
+// SPDX-License-Identifier: BUSL-1.1
+
+use std::{
+    path::{Path, PathBuf},
+    sync::OnceLock,
+    time,
+};
+
+use anyhow::{anyhow, bail, Context as _};
+use rustix::process::Pid;
+use tracing::{debug, warn};
+
+use crate::config::ResourceLimits;
+
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+const CONTROLLERS: &[&str] = &["cpu", "memory", "io", "pids"];
+
+// The scheduling period used for cpu.max.
+const CPU_PERIOD_USEC: u64 = 100_000;
+
+static DELEGATED_ROOT: OnceLock<Result<PathBuf, String>> = OnceLock::new();
+
+/// A cgroup (v2) for a single container. It's removed on drop, killing any
+/// remaining processes.
+#[derive(Debug)]
+pub struct Cgroup {
+    path: PathBuf,
+}
+
+/// Current resource usage of a cgroup.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ResourceUsage {
+    pub memory_bytes: u64,
+    pub cpu_time: time::Duration,
+    pub pids: u64,
+}
+
+impl Cgroup {
+    /// Creates a new cgroup with the given limits, under the cgroup that
+    /// mmserver was started in.
+    pub fn new(name: &str, limits: &ResourceLimits) -> anyhow::Result<Self> {
+        let root = DELEGATED_ROOT
+            .get_or_init(|| delegate().map_err(|e| format!("{:#}", e)))
+            .as_ref()
+            .map_err(|e| anyhow!("cgroups unavailable: {e}"))?;
+
+        let path = root.join(name);
+        std::fs::create_dir(&path)
+            .context(format!("failed to create cgroup {}", path.display()))?;
+
+        let cgroup = Self { path };
+        cgroup.apply_limits(limits)?;
+
+        debug!(path = ?cgroup.path, ?limits, "created cgroup");
+        Ok(cgroup)
+    }
+
+    /// Moves a process into the cgroup.
+    pub fn add_process(&self, pid: Pid) -> anyhow::Result<()> {
+        write_file(
+            self.path.join("cgroup.procs"),
+            pid.as_raw_nonzero().to_string(),
+        )
+    }
+
+    pub fn usage(&self) -> anyhow::Result<ResourceUsage> {
+        let memory_bytes = read_u64(self.path.join("memory.current")).unwrap_or_default();
+        let pids = read_u64(self.path.join("pids.current")).unwrap_or_default();
+
+        let cpu_stat = std::fs::read_to_string(self.path.join("cpu.stat"))?;
+        let usage_usec = cpu_stat
+            .lines()
+            .find_map(|l| l.strip_prefix("usage_usec "))
+            .and_then(|v| v.trim().parse().ok())
+            .unwrap_or_default();
+
+        Ok(ResourceUsage {
+            memory_bytes,
+            cpu_time: time::Duration::from_micros(usage_usec),
+            pids,
+        })
+    }
+
+    fn apply_limits(&self, limits: &ResourceLimits) -> anyhow::Result<()> {
+        if let Some(max) = limits.memory_max {
+            write_file(self.path.join("memory.max"), max.to_string())?;
+            // Don't let the app push the rest of the host into swap, either.
+            write_file(self.path.join("memory.swap.max"), "0").ok();
+        }
+
+        if let Some(cpus) = limits.cpu_max {
+            let quota = (cpus as f64 * CPU_PERIOD_USEC as f64).round() as u64;
+            write_file(
+                self.path.join("cpu.max"),
+                format!("{} {}", quota.max(1000), CPU_PERIOD_USEC),
+            )?;
+        }
+
+        if let Some(weight) = limits.cpu_weight {
+            write_file(self.path.join("cpu.weight"), weight.to_string())?;
+        }
+
+        if let Some(weight) = limits.io_weight {
+            write_file(self.path.join("io.weight"), format!("default {weight}"))?;
+        }
+
+        if let Some(max) = limits.pids_max {
+            write_file(self.path.join("pids.max"), max.to_string())?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for Cgroup {
+    fn drop(&mut self) {
+        // cgroup.kill is only available on newer kernels. The PID namespace
+        // should have taken care of things anyway.
+        let _ = write_file(self.path.join("cgroup.kill"), "1");
+
+        match std::fs::remove_dir(&self.path) {
+            Ok(()) => (),
+            Err(e) if e.raw_os_error() == Some(libc::EBUSY) => {
+                // Killed processes take a moment to disappear. Retry in the
+                // background, rather than blocking whoever dropped us.
+                let path = std::mem::take(&mut self.path);
+                let res = std::thread::Builder::new()
+                    .name("cgroup cleanup".to_string())
+                    .spawn(move || remove_eventually(&path));
+                if let Err(e) = res {
+                    warn!("failed to spawn cgroup cleanup thread: {}", e);
+                }
+            }
+            Err(e) => warn!(path = ?self.path, "failed to remove cgroup: {}", e),
+        }
+    }
+}
+
+fn remove_eventually(path: &Path) {
+    for _ in 0..100 {
+        std::thread::sleep(time::Duration::from_millis(10));
+        match std::fs::remove_dir(path) {
+            Ok(()) => return,
+            Err(e) if e.raw_os_error() == Some(libc::EBUSY) => continue,
+            Err(e) => {
+                warn!(?path, "failed to remove cgroup: {}", e);
+                return;
+            }
+        }
+    }
+
+    warn!(?path, "timed out removing cgroup");
+}
+
+/// Sets up the cgroup we were started in for creating child cgroups. Because
+/// of the "no internal processes" rule, mmserver itself has to move to a leaf
+/// cgroup before controllers can be enabled for the others.
+fn delegate() -> anyhow::Result<PathBuf> {
+    let own = std::fs::read_to_string("/proc/self/cgroup").context("reading /proc/self/cgroup")?;
+    let Some(rel) = own.lines().find_map(|l| l.strip_prefix("0::")) else {
+        bail!("cgroups v2 not mounted");
+    };
+
+    let root = Path::new(CGROUP_ROOT).join(rel.trim().trim_start_matches('/'));
+    let leaf = root.join("mmserver");
+    if !leaf.exists() {
+        std::fs::create_dir(&leaf).context(format!(
+            "failed to create {} (is the cgroup delegated?)",
+            leaf.display()
+        ))?;
+    }
+
+    write_file(leaf.join("cgroup.procs"), "0").context("failed to move mmserver to a leaf")?;
+
+    let available = std::fs::read_to_string(root.join("cgroup.controllers"))?;
+    let enable = CONTROLLERS
+        .iter()
+        .filter(|c| available.split_whitespace().any(|a| a == **c))
+        .map(|c| format!("+{c}"))
+        .collect::<Vec<_>>();
+
+    if enable.len() < CONTROLLERS.len() {
+        warn!(
+            available = available.trim(),
+            "not all cgroup controllers are delegated; some limits won't be enforced"
+        );
+    }
+
+    write_file(root.join("cgroup.subtree_control"), enable.join(" "))
+        .context("failed to enable cgroup controllers")?;
+
+    debug!(root = ?root, "using delegated cgroup");
+    Ok(root)
+}
+
+fn write_file(path: impl AsRef<Path>, contents: impl AsRef<[u8]>) -> anyhow::Result<()> {
+    let path = path.as_ref();
+    std::fs::write(path, contents).context(format!("failed to write {}", path.display()))
+}
+
+fn read_u64(path: impl AsRef<Path>) -> anyhow::Result<u64> {
+    Ok(std::fs::read_to_string(path)?.trim().parse()?)
+}
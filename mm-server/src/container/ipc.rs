@@ -88,6 +88,27 @@ impl FdReceiver {
     }
 }
 
+/// Waits for a readiness notification on a pipe, as used by e.g. slirp4netns's
+/// `--ready-fd`.
+pub fn wait_ready(fd: impl AsFd, timeout: time::Duration) -> rustix::io::Result<()> {
+    let mut pollfd = [PollFd::new(&fd, PollFlags::IN)];
+    let mut buf = [0; 1];
+    let timespec = timeout.try_into().expect("invalid duration");
+    loop {
+        match poll(&mut pollfd, Some(&timespec)) {
+            Ok(0) => return Err(Errno::TIMEDOUT),
+            Ok(_) => match read(&fd, &mut buf) {
+                Ok(0) => return Err(Errno::PIPE), // Exited before becoming ready.
+                Ok(_) => return Ok(()),
+                Err(Errno::INTR) => continue,
+                Err(e) => return Err(e),
+            },
+            Err(Errno::INTR) => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
 fn signal_eventfd(fd: impl AsFd) -> rustix::io::Result<()> {
     loop {
         match write(&fd, &1_u64.to_ne_bytes()).map(|_| ()) {
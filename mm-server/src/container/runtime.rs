@@ -8,11 +8,12 @@ use std::{
     io,
     os::{
         fd::{AsFd, AsRawFd as _, BorrowedFd, FromRawFd as _, OwnedFd},
-        unix::process::CommandExt as _,
+        unix::{ffi::OsStrExt as _, process::CommandExt as _},
     },
     path::{Path, PathBuf},
     process::Command,
     str::FromStr as _,
+    sync::Arc,
     time,
 };
 
@@ -31,8 +32,8 @@ use rustix::{
 };
 use tracing::debug;
 
-use super::ipc;
-use crate::config::HomeIsolationMode;
+use super::{ipc, seccomp::SeccompFilter, Cgroup};
+use crate::config::{HomeIsolationMode, NetworkMode, Rootfs};
 
 // In CPU-constrained testing environments, we sometimes need to wait
 // to get scheduled.
@@ -42,6 +43,9 @@ const SYNC_TIMEOUT: time::Duration = time::Duration::from_secs(5);
 #[cfg(not(test))]
 const SYNC_TIMEOUT: time::Duration = time::Duration::from_secs(1);
 
+// How long to wait for slirp4netns to configure the network.
+const NETWORK_TIMEOUT: time::Duration = time::Duration::from_secs(5);
+
 #[derive(Debug, Clone, Copy)]
 struct DevBindMount {
     path: &'static str,
@@ -162,19 +166,35 @@ macro_rules! must {
         let res = $n( $($args)* );
         _must(stringify!($n), res)
     }};
+    ($recv:ident.$n:ident( $($args:tt)* )) => {{
+        let res = $recv.$n( $($args)* );
+        _must(stringify!($n), res)
+    }};
 }
 
 type SetupHook = Box<dyn FnOnce(&mut super::ContainerHandle) -> anyhow::Result<()>>;
 
+// The PATH used to find commands in a separate rootfs.
+const ROOTFS_PATH: &str = "/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin";
+
 /// A lightweight linux container. Currently we use the following namespaces:
 ///  - A mount namespace, to mount tmpfs on /dev, /tmp, /run, etc, and
-///    potentially to isolate home as well. We don't pivot_root/chroot.
-///  - A PID namespace, so that processes get cleaned up when a session ends.
-///    Note that we currently don't use a "stub init" process to handle
-///    reparenting or reaping, since we don't expect to spawn lots of grandchild
-///    processes.
+///    potentially to isolate home as well. By default, the container shares the
+///    host's root filesystem; alternatively, we can pivot_root into a separate
+///    one (see [Container::with_rootfs]).
+///  - A PID namespace, so that processes get cleaned up when a session ends. A
+///    stub init process runs as PID 1, reaping orphaned processes and
+///    forwarding signals to the app. When the app exits, init exits with the
+///    same status, which tears down the rest of the namespace.
 ///  - A user namespace, to enable the above. We just map the current user to
 ///    itself.
+///  - Optionally, a network namespace, either empty or with outbound access
+///    through slirp4netns (see [NetworkMode]).
+///
+/// A seccomp filter can also be installed to block dangerous syscalls.
+///
+/// Optionally, the container can be placed in a cgroup to enforce resource
+/// limits (see [Cgroup]).
 ///
 /// IMPORTANT: This container is not a secure container. Under NO CIRCUMSTANCES
 /// should you use it to run untrusted code. Any security benefits are purely
@@ -195,26 +215,73 @@ pub struct Container {
     intern_run_path: PathBuf,
     extern_run_path: PathBuf,
 
-    additional_bind_mounts: Vec<(PathBuf, PathBuf)>,
+    rootfs: Option<RootfsMount>,
+    // Held for as long as the container runs, so that only one container uses
+    // an overlay at a time.
+    overlay_lock: Option<OwnedFd>,
+
+    additional_bind_mounts: Vec<(PathBuf, PathBuf, bool)>,
     internal_bind_mounts: Vec<(PathBuf, PathBuf, bool)>,
+    tmpfs_mounts: Vec<CString>,
+    masked_paths: Vec<CString>,
 
     // Stores a closure to run before unfreeze.
     setup_hooks: Vec<SetupHook>,
 
+    cgroup: Option<Arc<Cgroup>>,
+    network_mode: NetworkMode,
+    seccomp_filter: Option<SeccompFilter>,
+
     uid: Uid,
     gid: Gid,
 }
 
+/// The root filesystem, prepared for mounting after fork.
+struct RootfsMount {
+    path: CString,
+    // The upper and work dirs for overlayfs.
+    overlay: Option<(CString, CString)>,
+}
+
 impl Container {
     pub fn new(
+        args: Vec<OsString>,
+        home_isolation_mode: HomeIsolationMode,
+    ) -> anyhow::Result<Self> {
+        Self::build(args, home_isolation_mode, None)
+    }
+
+    /// Creates a container that runs in a separate root filesystem, for
+    /// example an unpacked OCI image. The command is resolved inside the
+    /// rootfs. Device nodes, /sys, XDG_RUNTIME_DIR and home are still bound in
+    /// from the host.
+    pub fn with_rootfs(
+        args: Vec<OsString>,
+        home_isolation_mode: HomeIsolationMode,
+        rootfs: &Rootfs,
+    ) -> anyhow::Result<Self> {
+        Self::build(args, home_isolation_mode, Some(rootfs))
+    }
+
+    fn build(
         mut args: Vec<OsString>,
         home_isolation_mode: HomeIsolationMode,
+        rootfs: Option<&Rootfs>,
     ) -> anyhow::Result<Self> {
-        let exe_path = validate_exe(args.remove(0))?;
+        let exe_path = match rootfs {
+            Some(rootfs) => validate_exe_in_rootfs(args.remove(0), &rootfs.path)?,
+            None => validate_exe(args.remove(0))?,
+        };
+
         let mut envs = Vec::new();
 
+        if rootfs.is_some() {
+            envs.push(make_putenv("PATH", ROOTFS_PATH));
+        } else if let Some(path) = std::env::var_os("PATH") {
+            envs.push(make_putenv("PATH", path));
+        }
+
         for key in [
-            "PATH",
             "USER",
             "SHELL",
             "EDITOR",
@@ -252,6 +319,10 @@ impl Container {
 
         debug!(home_mode = ?home_isolation_mode, "using home mode");
         let (extern_home_path, clear_home) = match home_isolation_mode {
+            // With a separate rootfs, we have to bind-mount the host home.
+            HomeIsolationMode::Unisolated if rootfs.is_some() => {
+                (Some(PathBuf::from(&intern_home_path)), true)
+            }
             HomeIsolationMode::Unisolated => (None, false),
             HomeIsolationMode::Tmpfs => (None, true),
             HomeIsolationMode::Permanent(path) => {
@@ -264,7 +335,7 @@ impl Container {
             }
         };
 
-        if clear_home && exe_path.starts_with(&intern_home_path) {
+        if rootfs.is_none() && clear_home && exe_path.starts_with(&intern_home_path) {
             bail!(
                 "command {:?} will be unavailable in container (set isolate_home = false to avoid \
                  this error)",
@@ -272,6 +343,48 @@ impl Container {
             );
         }
 
+        let mut overlay_lock = None;
+        let rootfs = match rootfs {
+            Some(rootfs) => {
+                debug!(?rootfs, "using separate rootfs");
+
+                let overlay = match &rootfs.overlay {
+                    Some(dir) => {
+                        let (upper, work) = (dir.join("upper"), dir.join("work"));
+                        std::fs::create_dir_all(&upper)?;
+                        std::fs::create_dir_all(&work)?;
+
+                        // Overlayfs doesn't support mounting the same upper
+                        // dir twice.
+                        let lock = OpenOptions::new()
+                            .create(true)
+                            .truncate(false)
+                            .write(true)
+                            .open(dir.join("lock"))
+                            .context("failed to open overlay lock")?;
+                        rustix::fs::flock(
+                            &lock,
+                            rustix::fs::FlockOperation::NonBlockingLockExclusive,
+                        )
+                        .map_err(|_| {
+                            anyhow!("overlay {} is in use by another session", dir.display())
+                        })?;
+
+                        overlay_lock = Some(lock.into());
+
+                        Some((path_to_cstring(&upper)?, path_to_cstring(&work)?))
+                    }
+                    None => None,
+                };
+
+                Some(RootfsMount {
+                    path: path_to_cstring(&rootfs.path)?,
+                    overlay,
+                })
+            }
+            None => None,
+        };
+
         let mut child_cmd = Command::new(exe_path);
         child_cmd.current_dir("/");
         child_cmd.args(args);
@@ -287,11 +400,20 @@ impl Container {
             intern_run_path: intern_run_path.into(),
             extern_run_path,
 
+            rootfs,
+            overlay_lock,
+
             additional_bind_mounts: Vec::new(),
             internal_bind_mounts: Vec::new(),
+            tmpfs_mounts: Vec::new(),
+            masked_paths: Vec::new(),
 
             setup_hooks: Vec::new(),
 
+            cgroup: None,
+            network_mode: NetworkMode::Host,
+            seccomp_filter: None,
+
             uid,
             gid,
         })
@@ -307,7 +429,29 @@ impl Container {
 
     pub fn bind_mount(&mut self, src: impl AsRef<Path>, dst: impl AsRef<Path>) {
         self.additional_bind_mounts
-            .push((src.as_ref().to_owned(), dst.as_ref().to_owned()));
+            .push((src.as_ref().to_owned(), dst.as_ref().to_owned(), false));
+    }
+
+    pub fn bind_mount_readonly(&mut self, src: impl AsRef<Path>, dst: impl AsRef<Path>) {
+        self.additional_bind_mounts
+            .push((src.as_ref().to_owned(), dst.as_ref().to_owned(), true));
+    }
+
+    /// Mounts an empty tmpfs at the given path inside the container, hiding
+    /// anything underneath.
+    pub fn mount_tmpfs(&mut self, dst: impl AsRef<Path>) -> anyhow::Result<()> {
+        self.tmpfs_mounts
+            .push(CString::new(dst.as_ref().as_os_str().as_bytes())?);
+        Ok(())
+    }
+
+    /// Hides a file or directory inside the container behind an empty,
+    /// read-only mount. Paths that don't exist inside the container are
+    /// ignored.
+    pub fn mask_path(&mut self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        self.masked_paths
+            .push(CString::new(path.as_ref().as_os_str().as_bytes())?);
+        Ok(())
     }
 
     pub fn internal_bind_mount(&mut self, src: impl AsRef<Path>, dst: impl AsRef<Path>) {
@@ -315,6 +459,21 @@ impl Container {
             .push((src.as_ref().to_owned(), dst.as_ref().to_owned(), true));
     }
 
+    /// Runs the container in the given cgroup.
+    pub fn set_cgroup(&mut self, cgroup: Arc<Cgroup>) {
+        self.cgroup = Some(cgroup);
+    }
+
+    pub fn set_network_mode(&mut self, mode: NetworkMode) {
+        self.network_mode = mode;
+    }
+
+    /// Blocks the given syscalls inside the container, using seccomp. The
+    /// filter is installed after all mounts are set up, just before exec.
+    pub fn set_denied_syscalls(&mut self, denied: &[syscalls::Sysno]) {
+        self.seccomp_filter = (!denied.is_empty()).then(|| SeccompFilter::new(denied));
+    }
+
     pub fn setup_hook(
         &mut self,
         f: impl FnOnce(&mut super::ContainerHandle) -> anyhow::Result<()> + 'static,
@@ -360,17 +519,24 @@ impl Container {
                     PathBuf::from_str(m.path).unwrap(),
                     PathBuf::from_str(m.path).unwrap(),
                     m.is_dir,
+                    false,
                     None,
                 ))
             })
             .collect::<anyhow::Result<Vec<_>>>()?;
 
-        for (src, dst) in self.additional_bind_mounts.drain(..) {
+        // With a separate rootfs, we need the host's /sys for device
+        // discovery. We can't mount a new one without a network namespace.
+        if self.rootfs.is_some() {
+            mounts.push(("/sys".into(), "/sys".into(), true, false, None));
+        }
+
+        for (src, dst, read_only) in self.additional_bind_mounts.drain(..) {
             let is_dir = std::fs::metadata(&src)
                 .context("failed to stat bind mount")?
                 .is_dir();
 
-            mounts.push((src, dst, is_dir, None))
+            mounts.push((src, dst, is_dir, read_only, None))
         }
 
         let mut child_pidfd = -1;
@@ -381,6 +547,10 @@ impl Container {
             .flag_newns()
             .flag_newpid();
 
+        if self.network_mode != NetworkMode::Host {
+            args.flag_newnet();
+        }
+
         debug!(cmd = ?self.child_cmd, "spawning child process");
 
         let (barrier, child_barrier) = ipc::EventfdBarrier::new()?;
@@ -399,15 +569,30 @@ impl Container {
 
         set_uid_map(child_pid, self.uid, self.gid).context("failed to set uid/gid map")?;
 
+        // The child is waiting at the barrier, so this takes effect before
+        // the app starts.
+        if let Some(cgroup) = &self.cgroup {
+            cgroup.add_process(Pid::from_raw(child_pid).unwrap())?;
+        }
+
         // Wait for the child to signal that it's ready.
         barrier
             .sync(SYNC_TIMEOUT)
             .context("timed out waiting for forked child (phase 1)")?;
 
+        let network = if self.network_mode == NetworkMode::Nat {
+            Some(UserNetwork::start(child_pid).context("failed to set up container network")?)
+        } else {
+            None
+        };
+
         let mut handle = super::ContainerHandle {
             pid: Pid::from_raw(child_pid).unwrap(),
             pidfd: child_pidfd,
             run_path: self.extern_run_path,
+            _cgroup: self.cgroup.take(),
+            _network: network,
+            _overlay_lock: self.overlay_lock.take(),
         };
 
         for hook in self.setup_hooks.drain(..) {
@@ -428,7 +613,7 @@ impl Container {
         mut self,
         stderr: Option<FD>,
         barrier: ipc::EventfdBarrier,
-        bind_mounts: &mut [(PathBuf, PathBuf, bool, Option<OwnedFd>)],
+        bind_mounts: &mut [(PathBuf, PathBuf, bool, bool, Option<OwnedFd>)],
     ) -> !
     where
         FD: AsFd,
@@ -447,23 +632,35 @@ impl Container {
 
         preexec_debug!("starting container setup");
 
-        // Mount /proc first.
-        must!(mount_fs(
-            c"proc",
-            c"/proc",
-            MountAttrFlags::MOUNT_ATTR_NOEXEC
-                | MountAttrFlags::MOUNT_ATTR_NOSUID
-                | MountAttrFlags::MOUNT_ATTR_NODEV,
-            &[],
-        ));
+        // Mount /proc first. If we're switching to a new rootfs, that happens
+        // below instead.
+        if self.rootfs.is_none() {
+            must!(mount_fs(
+                c"proc",
+                c"/proc",
+                MountAttrFlags::MOUNT_ATTR_NOEXEC
+                    | MountAttrFlags::MOUNT_ATTR_NOSUID
+                    | MountAttrFlags::MOUNT_ATTR_NODEV,
+                &[],
+            ));
+        }
+
+        // Bring up loopback in the new network namespace.
+        if self.network_mode != NetworkMode::Host {
+            must!(loopback_up());
+        }
 
         // Collect detached mounts we want to bind-mount later. We can't
         // allocate a vec, so we fill in the Options in the passed-in vec
         // instead.
         preexec_debug!("collecting detached bind mounts");
-        for (src_path, _, _, ref mut device_fd) in bind_mounts.iter_mut() {
+        for (src_path, _, _, read_only, ref mut device_fd) in bind_mounts.iter_mut() {
             if src_path.exists() {
                 let fd = must!(detach_mount(src_path,));
+                if *read_only {
+                    must!(set_mount_readonly(&fd));
+                }
+
                 *device_fd = Some(fd)
             }
         }
@@ -478,6 +675,12 @@ impl Container {
             .as_ref()
             .map(|p| must!(detach_mount(p)));
 
+        // Switch to the new rootfs, now that we have everything we need from
+        // the host.
+        if let Some(rootfs) = &self.rootfs {
+            must!(pivot_into_rootfs(rootfs));
+        }
+
         // Mount /dev and a few other filesystems.
         must!(mount_fs(
             c"tmpfs",
@@ -586,7 +789,7 @@ impl Container {
         }
 
         // Attach detached bind mounts, now that the filesystem is prepared.
-        for (_src_path, dst_path, is_dir, mount_fd) in bind_mounts {
+        for (_src_path, dst_path, is_dir, _, mount_fd) in bind_mounts {
             if let Some(detached_mount_fd) = mount_fd.take() {
                 preexec_debug!(
                     "bind-mounting {} (outside) to {} (inside)",
@@ -604,6 +807,45 @@ impl Container {
             }
         }
 
+        // Cover up paths we want to hide from the app. This happens after
+        // bind mounts, so that paths inside them can be hidden as well.
+        for dst in &self.tmpfs_mounts {
+            must!(mount_fs(
+                c"tmpfs",
+                dst,
+                MountAttrFlags::MOUNT_ATTR_NOSUID | MountAttrFlags::MOUNT_ATTR_NODEV,
+                &[(c"mode", c"0700")],
+            ));
+        }
+
+        for path in &self.masked_paths {
+            // This is checked inside the container, since the home directory
+            // or rootfs may not match the host.
+            let st = match rustix::fs::stat(path.as_c_str()) {
+                Err(Errno::NOENT) => continue,
+                res => _must("stat", res),
+            };
+
+            if FileType::from_raw_mode(st.st_mode) == FileType::Directory {
+                must!(mount_fs(
+                    c"tmpfs",
+                    path,
+                    MountAttrFlags::MOUNT_ATTR_RDONLY
+                        | MountAttrFlags::MOUNT_ATTR_NOEXEC
+                        | MountAttrFlags::MOUNT_ATTR_NOSUID
+                        | MountAttrFlags::MOUNT_ATTR_NODEV,
+                    &[(c"mode", c"0000"), (c"size", c"4k")],
+                ));
+            } else {
+                let path = Path::new(OsStr::from_bytes(path.to_bytes()));
+                preexec_debug!("masking {}", path.display());
+
+                let fd = must!(detach_mount("/dev/null"));
+                must!(set_mount_readonly(&fd));
+                must!(reattach_mount(fd, path));
+            }
+        }
+
         preexec_debug!("finished initial setup, waiting for mmserver");
 
         // Sync with mmserver.
@@ -630,7 +872,10 @@ impl Container {
             must!(reattach_mount(fd, dst_path));
         }
 
-        // TODO: Install seccomp handlers here.
+        if let Some(filter) = &self.seccomp_filter {
+            preexec_debug!("installing seccomp filter");
+            must!(filter.install());
+        }
 
         // We don't trust std::os::Command's env handling, because sometimes
         // it allocates.
@@ -639,6 +884,25 @@ impl Container {
             libc::putenv(v.as_ptr() as *mut _);
         }
 
+        // Block all signals, so that init can receive them with
+        // sigwaitinfo(2). Signals for PID 1 without a handler are otherwise
+        // dropped.
+        let mut sigset: libc::sigset_t = std::mem::zeroed();
+        libc::sigfillset(&mut sigset);
+        libc::sigprocmask(libc::SIG_SETMASK, &sigset, std::ptr::null_mut());
+
+        let app_pid = libc::fork();
+        if app_pid < 0 {
+            preexec_debug!("fork failed: {}", io::Error::last_os_error());
+            libc::_exit(1);
+        } else if app_pid > 0 {
+            run_init(app_pid, &sigset);
+        }
+
+        let mut empty: libc::sigset_t = std::mem::zeroed();
+        libc::sigemptyset(&mut empty);
+        libc::sigprocmask(libc::SIG_SETMASK, &empty, std::ptr::null_mut());
+
         // If successful, this never returns.
         let _e = self.child_cmd.exec();
 
@@ -647,6 +911,109 @@ impl Container {
     }
 }
 
+/// The main loop for the stub init process, which runs as PID 1 inside the
+/// container. Like the rest of the child setup, this must not allocate.
+unsafe fn run_init(app_pid: libc::pid_t, sigset: &libc::sigset_t) -> ! {
+    // We don't need anything but stdio.
+    libc::syscall(libc::SYS_close_range, 3, u32::MAX, 0);
+
+    loop {
+        let mut info: libc::siginfo_t = std::mem::zeroed();
+        let sig = libc::sigwaitinfo(sigset, &mut info);
+        if sig < 0 {
+            continue;
+        } else if sig != libc::SIGCHLD {
+            // Forward everything else (notably SIGTERM) to the app.
+            libc::kill(app_pid, sig);
+            continue;
+        }
+
+        // Reap the app and any orphans reparented to us.
+        loop {
+            let mut status = 0;
+            let pid = libc::waitpid(-1, &mut status, libc::WNOHANG);
+            if pid <= 0 {
+                break;
+            } else if pid != app_pid {
+                continue;
+            }
+
+            let code = if libc::WIFSIGNALED(status) {
+                128 + libc::WTERMSIG(status)
+            } else {
+                libc::WEXITSTATUS(status)
+            };
+
+            preexec_debug!("app exited with status {code}");
+            libc::_exit(code);
+        }
+    }
+}
+
+/// A user-mode network stack for a container, provided by slirp4netns.
+pub(super) struct UserNetwork {
+    child: std::process::Child,
+    // slirp4netns exits when this is closed, even if we crash.
+    exit_fd: Option<OwnedFd>,
+}
+
+impl UserNetwork {
+    fn start(child_pid: i32) -> anyhow::Result<Self> {
+        let exe = find_executable_in_path("slirp4netns")
+            .ok_or(anyhow!("slirp4netns is required for network = \"nat\""))?;
+
+        let (ready_rx, ready_tx) = rustix::pipe::pipe_with(rustix::pipe::PipeFlags::CLOEXEC)?;
+        let (exit_rx, exit_tx) = rustix::pipe::pipe_with(rustix::pipe::PipeFlags::CLOEXEC)?;
+
+        let mut cmd = Command::new(exe);
+        cmd.arg("--configure")
+            .arg("--mtu=65520")
+            .arg("--disable-host-loopback")
+            .arg(format!("--ready-fd={}", ready_tx.as_raw_fd()))
+            .arg(format!("--exit-fd={}", exit_rx.as_raw_fd()))
+            .arg(child_pid.to_string())
+            .arg("tap0")
+            .stdin(std::process::Stdio::null())
+            .stdout(std::process::Stdio::null());
+
+        // Let the child inherit the pipes.
+        let (ready_raw, exit_raw) = (ready_tx.as_raw_fd(), exit_rx.as_raw_fd());
+        unsafe {
+            cmd.pre_exec(move || {
+                for fd in [ready_raw, exit_raw] {
+                    let fd = BorrowedFd::borrow_raw(fd);
+                    rustix::io::fcntl_setfd(fd, rustix::io::FdFlags::empty())?;
+                }
+
+                Ok(())
+            });
+        }
+
+        debug!(cmd = ?cmd, "starting slirp4netns");
+        let mut child = cmd.spawn().context("failed to spawn slirp4netns")?;
+        drop(ready_tx);
+        drop(exit_rx);
+
+        if let Err(e) = ipc::wait_ready(&ready_rx, NETWORK_TIMEOUT) {
+            let _ = child.kill();
+            let _ = child.wait();
+            bail!("slirp4netns failed to start: {e}");
+        }
+
+        Ok(Self {
+            child,
+            exit_fd: Some(exit_tx),
+        })
+    }
+}
+
+impl Drop for UserNetwork {
+    fn drop(&mut self) {
+        self.exit_fd.take();
+        let _ = self.child.wait();
+    }
+}
+
 fn set_uid_map(child_pid: i32, uid: rustix::fs::Uid, gid: rustix::fs::Gid) -> anyhow::Result<()> {
     let uid = uid.as_raw();
     let gid = gid.as_raw();
@@ -824,6 +1191,31 @@ pub(super) fn fuse_mount_into(
     fd_rx.recv_timeout(SYNC_TIMEOUT)
 }
 
+fn loopback_up() -> rustix::io::Result<()> {
+    let sock = rustix::net::socket(
+        rustix::net::AddressFamily::INET,
+        rustix::net::SocketType::DGRAM,
+        None,
+    )?;
+
+    unsafe {
+        let mut ifr: libc::ifreq = std::mem::zeroed();
+        ifr.ifr_name[0] = b'l' as _;
+        ifr.ifr_name[1] = b'o' as _;
+
+        if libc::ioctl(sock.as_raw_fd(), libc::SIOCGIFFLAGS as _, &mut ifr) < 0 {
+            return Err(Errno::from_io_error(&io::Error::last_os_error()).unwrap_or(Errno::IO));
+        }
+
+        ifr.ifr_ifru.ifru_flags |= libc::IFF_UP as libc::c_short;
+        if libc::ioctl(sock.as_raw_fd(), libc::SIOCSIFFLAGS as _, &ifr) < 0 {
+            return Err(Errno::from_io_error(&io::Error::last_os_error()).unwrap_or(Errno::IO));
+        }
+    }
+
+    Ok(())
+}
+
 fn touch(path: impl AsRef<Path>, mode: impl Into<Mode>) -> rustix::io::Result<()> {
     let _ = openat(
         AT_FDCWD,
@@ -855,6 +1247,115 @@ fn reattach_mount(fd: OwnedFd, path: impl AsRef<Path>) -> rustix::io::Result<()>
     )
 }
 
+fn pivot_into_rootfs(rootfs: &RootfsMount) -> rustix::io::Result<()> {
+    preexec_debug!("switching to rootfs {:?}", rootfs.path);
+
+    // pivot_root doesn't work with shared mounts, and we don't want to
+    // propagate anything to the host anyway.
+    rustix::mount::mount_change(
+        c"/",
+        rustix::mount::MountPropagationFlags::PRIVATE | rustix::mount::MountPropagationFlags::REC,
+    )?;
+
+    let new_root = match &rootfs.overlay {
+        Some((upper, work)) => {
+            let fsfd = fsopen(c"overlay", FsOpenFlags::FSOPEN_CLOEXEC)?;
+            fsconfig_set_string(fsfd.as_fd(), c"lowerdir", &*rootfs.path)?;
+            fsconfig_set_string(fsfd.as_fd(), c"upperdir", &**upper)?;
+            fsconfig_set_string(fsfd.as_fd(), c"workdir", &**work)?;
+            // Required for overlayfs in a user namespace.
+            fsconfig_set_flag(fsfd.as_fd(), c"userxattr")?;
+            fsconfig_create(fsfd.as_fd())?;
+            fsmount(
+                fsfd.as_fd(),
+                FsMountFlags::FSMOUNT_CLOEXEC,
+                MountAttrFlags::empty(),
+            )?
+        }
+        None => detach_mount(Path::new(OsStr::from_bytes(rootfs.path.to_bytes())))?,
+    };
+
+    // Mount the new root over the original directory, so that it's a mount
+    // point, as pivot_root requires.
+    move_mount(
+        new_root.as_fd(),
+        c"",
+        AT_FDCWD,
+        &*rootfs.path,
+        MoveMountFlags::MOVE_MOUNT_F_EMPTY_PATH,
+    )?;
+
+    let old_root_fd = openat(
+        AT_FDCWD,
+        c"/",
+        OFlags::DIRECTORY | OFlags::RDONLY | OFlags::CLOEXEC,
+        Mode::empty(),
+    )?;
+
+    let new_root_fd = openat(
+        AT_FDCWD,
+        &*rootfs.path,
+        OFlags::DIRECTORY | OFlags::RDONLY | OFlags::CLOEXEC,
+        Mode::empty(),
+    )?;
+
+    // Mount /proc while the host's is still visible, because the kernel
+    // doesn't allow a new procfs mount in a user namespace otherwise.
+    rustix::process::fchdir(&new_root_fd)?;
+    mount_fs(
+        c"proc",
+        c"proc",
+        MountAttrFlags::MOUNT_ATTR_NOEXEC
+            | MountAttrFlags::MOUNT_ATTR_NOSUID
+            | MountAttrFlags::MOUNT_ATTR_NODEV,
+        &[],
+    )?;
+
+    // This stacks the old root on top of the new one, which we then detach.
+    // See pivot_root(2).
+    rustix::process::pivot_root(c".", c".")?;
+    rustix::process::fchdir(&old_root_fd)?;
+    rustix::mount::unmount(c".", rustix::mount::UnmountFlags::DETACH)?;
+    rustix::process::chdir(c"/")?;
+
+    Ok(())
+}
+
+fn set_mount_readonly(fd: impl AsFd) -> rustix::io::Result<()> {
+    // struct mount_attr, which isn't in libc yet.
+    #[repr(C)]
+    struct MountAttr {
+        attr_set: u64,
+        attr_clr: u64,
+        propagation: u64,
+        userns_fd: u64,
+    }
+
+    let attr = MountAttr {
+        attr_set: MountAttrFlags::MOUNT_ATTR_RDONLY.bits() as u64,
+        attr_clr: 0,
+        propagation: 0,
+        userns_fd: 0,
+    };
+
+    let res = unsafe {
+        libc::syscall(
+            libc::SYS_mount_setattr,
+            fd.as_fd().as_raw_fd(),
+            c"".as_ptr(),
+            libc::AT_EMPTY_PATH | libc::AT_RECURSIVE,
+            &attr as *const MountAttr,
+            std::mem::size_of::<MountAttr>(),
+        )
+    };
+
+    if res < 0 {
+        return Err(Errno::from_io_error(&io::Error::last_os_error()).unwrap_or(Errno::IO));
+    }
+
+    Ok(())
+}
+
 fn mount_fs(
     fstype: &CStr,
     dst: &CStr,
@@ -903,6 +1404,40 @@ fn make_putenv(k: impl AsRef<OsStr>, v: impl AsRef<OsStr>) -> CString {
     .unwrap()
 }
 
+fn path_to_cstring(p: &Path) -> anyhow::Result<CString> {
+    CString::new(p.as_os_str().as_bytes()).context(format!("invalid path: {}", p.display()))
+}
+
+/// Validates an executable path inside a rootfs, and returns the path as it
+/// will be seen inside the container.
+fn validate_exe_in_rootfs(p: impl AsRef<Path>, rootfs: &Path) -> anyhow::Result<PathBuf> {
+    let p = p.as_ref();
+    let candidates = if p.components().count() == 1 {
+        std::env::split_paths(ROOTFS_PATH)
+            .map(|d| d.join(p))
+            .collect()
+    } else if p.is_absolute() {
+        vec![p.to_owned()]
+    } else {
+        bail!("path {:?} must be absolute", p.display());
+    };
+
+    // Note that this doesn't follow absolute symlinks correctly, which is
+    // fine for a sanity check.
+    for candidate in candidates {
+        let outside = rootfs.join(candidate.strip_prefix("/").unwrap());
+        if outside.exists() && is_executable(&outside)? {
+            return Ok(candidate);
+        }
+    }
+
+    bail!(
+        "command {:?} not found in {}",
+        p.display(),
+        rootfs.display()
+    )
+}
+
 /// Validates an executable path, and returns the canonical version.
 fn validate_exe(p: impl AsRef<Path>) -> anyhow::Result<PathBuf> {
     let p = p.as_ref();
@@ -951,6 +1486,18 @@ mod test {
         Ok(())
     }
 
+    #[test_log::test]
+    fn exit_status() -> anyhow::Result<()> {
+        let container = Container::new(
+            vec!["sh".into(), "-c".into(), "sleep 10 & exit 3".into()],
+            HomeIsolationMode::Tmpfs,
+        )?;
+
+        let mut child = container.spawn()?;
+        pretty_assertions::assert_eq!(child.wait()?, 3);
+        Ok(())
+    }
+
     #[test]
     fn test_validate_exe() {
         let cat = pathsearch::find_executable_in_path("cat").unwrap();
@@ -0,0 +1,227 @@
+// Copyright 2024 Colin Marc <hi@colinmarc.com>
+//
+// SPDX-License-Identifier: BUSL-1.1
+
+use syscalls::Sysno;
+
+#[cfg(target_arch = "x86_64")]
+const AUDIT_ARCH: u32 = 0xc000_003e; // AUDIT_ARCH_X86_64
+
+#[cfg(target_arch = "aarch64")]
+const AUDIT_ARCH: u32 = 0xc000_00b7; // AUDIT_ARCH_AARCH64
+
+// 32-bit x86 binaries (Steam, 32-bit Wine, etc) run on x86_64 hosts, and use
+// a different syscall table.
+#[cfg(target_arch = "x86_64")]
+const AUDIT_ARCH_I386: u32 = 0x4000_0003;
+
+// Offsets into struct seccomp_data.
+const DATA_NR: u32 = 0;
+const DATA_ARCH: u32 = 4;
+
+// Syscalls with this bit set use the x32 ABI, which has its own syscall
+// numbers.
+#[cfg(target_arch = "x86_64")]
+const X32_SYSCALL_BIT: u32 = 0x4000_0000;
+
+const RET_ERRNO_EPERM: u32 = libc::SECCOMP_RET_ERRNO | libc::EPERM as u32;
+
+/// A compiled seccomp-BPF program that blocks a list of syscalls, returning
+/// `EPERM`.
+#[derive(Debug, Clone)]
+pub struct SeccompFilter {
+    prog: Vec<libc::sock_filter>,
+}
+
+impl SeccompFilter {
+    pub fn new(denied: &[Sysno]) -> Self {
+        let mut prog = vec![stmt(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, DATA_ARCH)];
+
+        // On x86_64, apply the same list to i386 syscalls, using the i386
+        // numbering. The block always returns, so we jump over it for other
+        // architectures.
+        #[cfg(target_arch = "x86_64")]
+        {
+            let mut compat = vec![stmt(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, DATA_NR)];
+            compat.extend(deny_list(denied.iter().filter_map(|nr| {
+                nr.name()
+                    .parse::<syscalls::x86::Sysno>()
+                    .ok()
+                    .map(|nr| nr.id() as u32)
+            })));
+
+            prog.extend([
+                jump(
+                    libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K,
+                    AUDIT_ARCH_I386,
+                    1,
+                    0,
+                ),
+                stmt(libc::BPF_JMP | libc::BPF_JA, compat.len() as u32),
+            ]);
+            prog.extend(compat);
+        }
+
+        prog.extend([
+            // Kill the process if the syscall is from any other architecture,
+            // since the numbers won't match.
+            jump(
+                libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K,
+                AUDIT_ARCH,
+                1,
+                0,
+            ),
+            stmt(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_KILL_PROCESS),
+            stmt(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, DATA_NR),
+        ]);
+
+        #[cfg(target_arch = "x86_64")]
+        prog.extend([
+            jump(
+                libc::BPF_JMP | libc::BPF_JGE | libc::BPF_K,
+                X32_SYSCALL_BIT,
+                0,
+                1,
+            ),
+            stmt(libc::BPF_RET | libc::BPF_K, RET_ERRNO_EPERM),
+        ]);
+
+        prog.extend(deny_list(denied.iter().map(|nr| nr.id() as u32)));
+        Self { prog }
+    }
+
+    /// Installs the filter for the current process. This doesn't allocate, so
+    /// it's safe to call after fork.
+    pub fn install(&self) -> rustix::io::Result<()> {
+        rustix::thread::set_no_new_privs(true)?;
+
+        let fprog = libc::sock_fprog {
+            len: self.prog.len() as u16,
+            filter: self.prog.as_ptr() as *mut _,
+        };
+
+        let res = unsafe {
+            libc::syscall(
+                libc::SYS_seccomp,
+                libc::SECCOMP_SET_MODE_FILTER,
+                0,
+                &fprog as *const libc::sock_fprog,
+            )
+        };
+
+        if res < 0 {
+            return Err(
+                rustix::io::Errno::from_io_error(&std::io::Error::last_os_error())
+                    .unwrap_or(rustix::io::Errno::IO),
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// Builds a block that returns `EPERM` for any of the given syscall numbers
+/// (which must already be loaded), and allows everything else.
+fn deny_list(denied: impl IntoIterator<Item = u32>) -> Vec<libc::sock_filter> {
+    let mut block = Vec::new();
+    for nr in denied {
+        block.extend([
+            jump(libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K, nr, 0, 1),
+            stmt(libc::BPF_RET | libc::BPF_K, RET_ERRNO_EPERM),
+        ]);
+    }
+
+    block.push(stmt(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_ALLOW));
+    block
+}
+
+fn stmt(code: u32, k: u32) -> libc::sock_filter {
+    libc::sock_filter {
+        code: code as u16,
+        jt: 0,
+        jf: 0,
+        k,
+    }
+}
+
+fn jump(code: u32, k: u32, jt: u8, jf: u8) -> libc::sock_filter {
+    libc::sock_filter {
+        code: code as u16,
+        jt,
+        jf,
+        k,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Evaluates the program against a syscall, supporting just the
+    /// instructions we emit.
+    fn run(filter: &SeccompFilter, arch: u32, nr: u32) -> u32 {
+        let mut acc = 0;
+        let mut pc = 0;
+        loop {
+            let insn = filter.prog[pc];
+            pc += 1;
+
+            let code = insn.code as u32;
+            if code == libc::BPF_LD | libc::BPF_W | libc::BPF_ABS {
+                acc = match insn.k {
+                    DATA_NR => nr,
+                    DATA_ARCH => arch,
+                    k => panic!("unexpected load offset {k}"),
+                };
+            } else if code == libc::BPF_JMP | libc::BPF_JA {
+                pc += insn.k as usize;
+            } else if code == libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K {
+                pc += if acc == insn.k { insn.jt } else { insn.jf } as usize;
+            } else if code == libc::BPF_JMP | libc::BPF_JGE | libc::BPF_K {
+                pc += if acc >= insn.k { insn.jt } else { insn.jf } as usize;
+            } else if code == libc::BPF_RET | libc::BPF_K {
+                return insn.k;
+            } else {
+                panic!("unexpected instruction {code:#x}");
+            }
+        }
+    }
+
+    #[test]
+    fn blocks_denied_syscalls() {
+        let filter = SeccompFilter::new(&[Sysno::reboot, Sysno::bpf]);
+
+        let reboot = Sysno::reboot.id() as u32;
+        let getpid = Sysno::getpid.id() as u32;
+
+        assert_eq!(run(&filter, AUDIT_ARCH, reboot), RET_ERRNO_EPERM);
+        assert_eq!(
+            run(&filter, AUDIT_ARCH, Sysno::bpf.id() as u32),
+            RET_ERRNO_EPERM
+        );
+        assert_eq!(run(&filter, AUDIT_ARCH, getpid), libc::SECCOMP_RET_ALLOW);
+        assert_eq!(
+            run(&filter, 0xdead_beef, getpid),
+            libc::SECCOMP_RET_KILL_PROCESS
+        );
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn blocks_denied_compat_syscalls() {
+        let filter = SeccompFilter::new(&[Sysno::reboot]);
+
+        assert_eq!(
+            run(&filter, AUDIT_ARCH, X32_SYSCALL_BIT | 39),
+            RET_ERRNO_EPERM
+        );
+
+        let reboot = syscalls::x86::Sysno::reboot.id() as u32;
+        let getpid = syscalls::x86::Sysno::getpid.id() as u32;
+        assert_eq!(run(&filter, AUDIT_ARCH_I386, reboot), RET_ERRNO_EPERM);
+        assert_eq!(
+            run(&filter, AUDIT_ARCH_I386, getpid),
+            libc::SECCOMP_RET_ALLOW
+        );
+    }
+}
@@ -16,7 +16,8 @@ use tracing::{debug, error, instrument, trace, trace_span};
 
 use self::gop_structure::HierarchicalP;
 use crate::codec::VideoCodec;
-use crate::session::control::VideoStreamParams;
+use crate::color::HdrMetadata;
+use crate::session::control::{ContentType, VideoStreamParams};
 use crate::vulkan::video::VideoQueueExt;
 use crate::vulkan::*;
 
@@ -55,10 +56,11 @@ impl Encoder {
         image: &VkImage,
         acquire: VkTimelinePoint,
         release: VkTimelinePoint,
+        timing: FrameTiming,
     ) -> anyhow::Result<()> {
         match self {
-            Self::H264(encoder) => encoder.submit_encode(image, acquire, release),
-            Self::H265(encoder) => encoder.submit_encode(image, acquire, release),
+            Self::H264(encoder) => encoder.submit_encode(image, acquire, release, timing),
+            Self::H265(encoder) => encoder.submit_encode(image, acquire, release, timing),
         }
     }
 
@@ -82,6 +84,34 @@ impl Encoder {
             Encoder::H265(encoder) => encoder.request_refresh(),
         }
     }
+
+    /// Changes the resolution of the stream, reusing the video session. The
+    /// next frame is an IDR, preceded by the new parameter sets. Input images
+    /// created before the resize must be recreated.
+    pub fn resize(&mut self, width: u32, height: u32) -> anyhow::Result<()> {
+        match self {
+            Encoder::H264(encoder) => encoder.resize(width, height),
+            Encoder::H265(encoder) => encoder.resize(width, height),
+        }
+    }
+
+    pub fn set_hdr_metadata(&mut self, metadata: Option<HdrMetadata>) {
+        match self {
+            // TODO: H.264 supports the same SEI messages.
+            Encoder::H264(_) => (),
+            Encoder::H265(encoder) => encoder.set_hdr_metadata(metadata),
+        }
+    }
+
+    /// Retunes the encoder for a new content type, by updating the rate
+    /// control. Returns false if that's not enough, and the encoder needs to
+    /// be recreated.
+    pub fn set_content_type(&mut self, content_type: ContentType) -> bool {
+        match self {
+            Encoder::H264(encoder) => encoder.set_content_type(content_type),
+            Encoder::H265(encoder) => encoder.set_content_type(content_type),
+        }
+    }
 }
 
 struct EncoderInner {
@@ -93,6 +123,9 @@ struct EncoderInner {
     writer_thread_handle: Option<std::thread::JoinHandle<anyhow::Result<()>>>,
     submitted_frames: Option<crossbeam::Sender<EncoderOutputFrame>>,
     done_frames: crossbeam::Receiver<EncoderOutputFrame>,
+    // Frames collected by a resize, which are used before waiting on
+    // done_frames.
+    idle_frames: Vec<EncoderOutputFrame>,
 
     dpb: dpb::DpbPool,
 
@@ -100,12 +133,16 @@ struct EncoderInner {
     height: u32,
     framerate: u32,
     input_format: vk::Format,
+    buffer_size_alignment: usize,
 
     stats: stats::EncodeStats,
 
     vk: Arc<VkContext>,
 }
 
+/// The number of output frames in flight.
+const OUTPUT_FRAMES: usize = 2;
+
 impl EncoderInner {
     pub fn new(
         vk: Arc<VkContext>,
@@ -125,15 +162,7 @@ impl EncoderInner {
         let (video_loader, _encode_loader) = vk.video_apis.as_ref().unwrap();
         let encode_family = vk.device_info.encode_family.unwrap();
 
-        if capabilities.max_coded_extent.width < width
-            || capabilities.max_coded_extent.height < height
-        {
-            bail!(
-                "video resolution too large: (max {}x{})",
-                capabilities.max_coded_extent.width,
-                capabilities.max_coded_extent.height
-            );
-        }
+        check_extent(&capabilities, width, height)?;
 
         let format_info = list_format_props(
             video_loader,
@@ -177,51 +206,23 @@ impl EncoderInner {
         let session_memory =
             bind_session_memory(video_loader, &vk.device, &vk.device_info, session)?;
 
-        let session_params = {
-            let create_info = vk::VideoSessionParametersCreateInfoKHR::default()
-                .video_session(session)
-                .push_next(session_params);
-
-            unsafe {
-                video_loader
-                    .create_video_session_parameters(&create_info, None)
-                    .context("vkCreateVideoSessionParametersKHR")?
-            }
-        };
-
-        let dpb = if capabilities
-            .flags
-            .contains(vk::VideoCapabilityFlagsKHR::SEPARATE_REFERENCE_IMAGES)
-        {
-            trace!("using separate images for DPB pool");
-
-            dpb::DpbPool::new_separate_images(
-                vk.clone(),
-                input_format,
-                width.next_multiple_of(capabilities.picture_access_granularity.width),
-                height.next_multiple_of(capabilities.picture_access_granularity.height),
-                profile,
-                required_dpb_size,
-            )?
-        } else {
-            trace!("using shared image for DPB pool");
-
-            dpb::DpbPool::new(
-                vk.clone(),
-                input_format,
-                width.next_multiple_of(capabilities.picture_access_granularity.width),
-                height.next_multiple_of(capabilities.picture_access_granularity.height),
-                profile,
-                required_dpb_size,
-            )?
-        };
+        let session_params = create_session_params(video_loader, session, session_params)?;
+        let dpb = create_dpb(
+            vk.clone(),
+            input_format,
+            width,
+            height,
+            profile,
+            &capabilities,
+            required_dpb_size,
+        )?;
 
         let stats = stats::EncodeStats::default();
 
         let (submitted_frames_tx, submitted_frames_rx) = crossbeam::bounded(1);
         let (done_frames_tx, done_frames_rx) = crossbeam::unbounded();
 
-        for _frame in 0..2 {
+        for _frame in 0..OUTPUT_FRAMES {
             // We need a frame name for each swapframe.
             #[cfg(feature = "tracy")]
             let frame_name = [
@@ -264,6 +265,7 @@ impl EncoderInner {
             writer_thread_handle: Some(handle),
             submitted_frames: Some(submitted_frames_tx),
             done_frames: done_frames_rx,
+            idle_frames: Vec::new(),
 
             dpb,
 
@@ -271,6 +273,7 @@ impl EncoderInner {
             height,
             framerate,
             input_format,
+            buffer_size_alignment,
 
             stats,
 
@@ -278,6 +281,57 @@ impl EncoderInner {
         })
     }
 
+    /// Changes the resolution, replacing the session parameters and DPB. The
+    /// caller is responsible for starting a new GOP afterwards.
+    pub fn resize(
+        &mut self,
+        width: u32,
+        height: u32,
+        required_dpb_size: usize,
+        profile: &mut vk::VideoProfileInfoKHR,
+        capabilities: vk::VideoCapabilitiesKHR,
+        session_params: &mut impl vk::ExtendsVideoSessionParametersCreateInfoKHR,
+    ) -> anyhow::Result<()> {
+        check_extent(&capabilities, width, height)?;
+
+        let (video_loader, _) = self.vk.video_apis.as_ref().unwrap();
+
+        // Wait for all frames in flight to be written out, so that nothing
+        // references the resources we're about to replace.
+        let mut frames = std::mem::take(&mut self.idle_frames);
+        while frames.len() < OUTPUT_FRAMES {
+            match self.done_frames.recv() {
+                Ok(frame) => frames.push(frame),
+                Err(_) => bail!("copy thread died"),
+            }
+        }
+
+        let new_params = create_session_params(video_loader, self.session, session_params)?;
+        unsafe { video_loader.destroy_video_session_parameters(self.session_params, None) };
+        self.session_params = new_params;
+
+        self.dpb = create_dpb(
+            self.vk.clone(),
+            self.input_format,
+            width,
+            height,
+            profile,
+            &capabilities,
+            required_dpb_size,
+        )?;
+
+        for frame in &mut frames {
+            frame.resize_buffer(width, height, self.buffer_size_alignment, profile)?;
+        }
+
+        self.idle_frames = frames;
+
+        trace!(width, height, "resized encoder");
+        self.width = width;
+        self.height = height;
+        Ok(())
+    }
+
     fn create_input_image(&self, profile: &mut vk::VideoProfileInfoKHR) -> anyhow::Result<VkImage> {
         let image = {
             let mut profile_list_info = single_profile_list_info(profile);
@@ -353,11 +407,14 @@ impl EncoderInner {
         tp_acquire: VkTimelinePoint,
         tp_release: VkTimelinePoint,
         frame_state: &gop_structure::GopFrame,
-        rc_info: &mut (impl vk::ExtendsVideoBeginCodingInfoKHR + vk::ExtendsVideoCodingControlInfoKHR),
+        rc_info: &mut vk::VideoEncodeRateControlInfoKHR,
+        // Set if the rate control changed since the last frame.
+        prev_rc_info: Option<&mut vk::VideoEncodeRateControlInfoKHR>,
         codec_pic_info: &mut impl vk::ExtendsVideoEncodeInfoKHR,
         codec_setup_info: &mut impl vk::ExtendsVideoReferenceSlotInfoKHR,
         codec_ref_info: &mut [impl vk::ExtendsVideoReferenceSlotInfoKHR],
         insert: Option<Bytes>,
+        mut timing: FrameTiming,
     ) -> anyhow::Result<()> {
         use ash::vk::Handle;
         if self.session_params.is_null() {
@@ -369,7 +426,10 @@ impl EncoderInner {
 
         // "Acquire" a buffer to copy to. This provides backpressure if the
         // encoder can't keep up.
-        let res = trace_span!("wait_prev_frame").in_scope(|| self.done_frames.recv());
+        let res = match self.idle_frames.pop() {
+            Some(frame) => Ok(frame),
+            None => trace_span!("wait_prev_frame").in_scope(|| self.done_frames.recv()),
+        };
         let mut frame = match res {
             Ok(frame) => frame,
             Err(_) => {
@@ -460,6 +520,8 @@ impl EncoderInner {
             "encoding frame"
         );
 
+        let rc_changed = prev_rc_info.is_some();
+
         // Bind the session.
         {
             let mut begin_info = vk::VideoBeginCodingInfoKHR::default()
@@ -471,7 +533,10 @@ impl EncoderInner {
             // Vulkan wants us to inform it of the current rate control, which
             // is unset on the first frame.
             if frame_state.stream_position != 0 {
-                begin_info = begin_info.push_next(rc_info)
+                begin_info = match prev_rc_info {
+                    Some(prev) => begin_info.push_next(prev),
+                    None => begin_info.push_next(&mut *rc_info),
+                };
             }
 
             unsafe {
@@ -479,13 +544,15 @@ impl EncoderInner {
             };
         }
 
-        // Reset on keyframes.
-        if frame_state.is_keyframe {
+        // Reset on keyframes, and update the rate control if it changed.
+        if frame_state.is_keyframe || rc_changed {
+            let mut flags = vk::VideoCodingControlFlagsKHR::ENCODE_RATE_CONTROL;
+            if frame_state.is_keyframe {
+                flags |= vk::VideoCodingControlFlagsKHR::RESET;
+            }
+
             let ctrl_info = vk::VideoCodingControlInfoKHR::default()
-                .flags(
-                    vk::VideoCodingControlFlagsKHR::RESET
-                        | vk::VideoCodingControlFlagsKHR::ENCODE_RATE_CONTROL,
-                )
+                .flags(flags)
                 .push_next(rc_info);
 
             unsafe {
@@ -687,8 +754,11 @@ impl EncoderInner {
                 .context("vkQueueSubmit")?;
         }
 
+        timing.encode_submit = Some(time::Instant::now());
+
         frame.hierarchical_layer = frame_state.id;
         frame.is_keyframe = frame_state.is_keyframe;
+        frame.timing = timing;
         if let Some(submitted_frames) = &self.submitted_frames {
             // Tell the other thread to copy out the finished packet when it's
             // finished. Optionally insert headers.
@@ -748,6 +818,7 @@ struct EncoderOutputFrame {
     hierarchical_layer: u32,
     is_keyframe: bool,
     headers: Option<bytes::Bytes>,
+    timing: FrameTiming,
 
     timeline: VkTimelineSemaphore,
     tp_encoded: VkTimelinePoint,
@@ -776,37 +847,8 @@ impl EncoderOutputFrame {
         profile: &mut vk::VideoProfileInfoKHR,
         #[cfg(feature = "tracy")] frame_name: tracy_client::FrameName,
     ) -> anyhow::Result<Self> {
-        let buffer_size = (width * height * 3).next_multiple_of(buffer_size_alignment as u32);
-
-        let mut profile_list_info = single_profile_list_info(profile);
-
-        let copy_buffer = {
-            let buf = {
-                let create_info = vk::BufferCreateInfo::default()
-                    .size(buffer_size as u64)
-                    .sharing_mode(vk::SharingMode::EXCLUSIVE)
-                    .usage(vk::BufferUsageFlags::VIDEO_ENCODE_DST_KHR)
-                    .push_next(&mut profile_list_info);
-
-                unsafe { vk.device.create_buffer(&create_info, None)? }
-            };
-
-            let requirements = unsafe { vk.device.get_buffer_memory_requirements(buf) };
-
-            let alloc_info = vk::MemoryAllocateInfo::default()
-                .allocation_size(requirements.size)
-                .memory_type_index(vk.device_info.host_visible_mem_type_index);
-
-            let memory = unsafe { vk.device.allocate_memory(&alloc_info, None)? };
-
-            unsafe {
-                vk.device
-                    .bind_buffer_memory(buf, memory, 0)
-                    .context("vkBindBufferMemory")?
-            };
-
-            VkHostBuffer::wrap(vk.clone(), buf, memory, buffer_size as usize)
-        };
+        let copy_buffer =
+            create_copy_buffer(vk.clone(), width, height, buffer_size_alignment, profile)?;
 
         let encode_queue = vk.encode_queue.as_ref().unwrap();
         let encode_cb = allocate_command_buffer(&vk.device, encode_queue.command_pool)?;
@@ -857,6 +899,7 @@ impl EncoderOutputFrame {
             hierarchical_layer: 0,
             is_keyframe: false,
             headers: None,
+            timing: FrameTiming::default(),
 
             tp_encoded: timeline.new_point(0),
             tp_copied: timeline.new_point(0),
@@ -873,6 +916,28 @@ impl EncoderOutputFrame {
             vk,
         })
     }
+
+    /// Replaces the output buffer if it's too small for the given resolution.
+    fn resize_buffer(
+        &mut self,
+        width: u32,
+        height: u32,
+        buffer_size_alignment: usize,
+        profile: &mut vk::VideoProfileInfoKHR,
+    ) -> anyhow::Result<()> {
+        let buffer_size = (width * height * 3).next_multiple_of(buffer_size_alignment as u32);
+        if self.copy_buffer.len < buffer_size as usize {
+            self.copy_buffer = create_copy_buffer(
+                self.vk.clone(),
+                width,
+                height,
+                buffer_size_alignment,
+                profile,
+            )?;
+        }
+
+        Ok(())
+    }
 }
 
 impl Drop for EncoderOutputFrame {
@@ -896,6 +961,22 @@ impl Drop for EncoderOutputFrame {
 // SAFETY: the contained pointers are nothing fancy.
 unsafe impl Send for EncoderOutputFrame {}
 
+/// Records when a frame passed through each stage of the pipeline, for
+/// latency measurement. Stages that were skipped are left unset.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FrameTiming {
+    /// The first app commit that contributed to the frame.
+    pub app_commit: Option<time::Instant>,
+    /// When the compositor started recording the frame.
+    pub composite_start: Option<time::Instant>,
+    /// When the composite (and conversion) was submitted to the GPU.
+    pub composite_end: Option<time::Instant>,
+    /// When the frame was submitted to the encode queue.
+    pub encode_submit: Option<time::Instant>,
+    /// When the encoded packet was ready on the CPU.
+    pub encode_complete: Option<time::Instant>,
+}
+
 /// Allows the caller to decide where to sink the frames.
 pub trait Sink: Send + 'static {
     fn write_frame(
@@ -904,6 +985,7 @@ pub trait Sink: Send + 'static {
         frame: Bytes,
         hierarchical_layer: u32,
         is_keyframe: bool,
+        timing: FrameTiming,
     );
 }
 
@@ -938,6 +1020,13 @@ fn writer_thread(
             frame.tp_encoded.wait()?;
         }
 
+        let mut timing = std::mem::take(&mut frame.timing);
+        let now = time::Instant::now();
+        timing.encode_complete = Some(now);
+        if let Some(submitted) = timing.encode_submit {
+            stats.record_encode_latency(now - submitted);
+        }
+
         #[cfg(feature = "tracy")]
         {
             frame.tracy_context.frame.take();
@@ -1001,6 +1090,7 @@ fn writer_thread(
             data,
             frame.hierarchical_layer,
             frame.is_keyframe,
+            timing,
         );
         done.send(frame).ok();
     }
@@ -1008,6 +1098,101 @@ fn writer_thread(
     Ok(())
 }
 
+fn create_copy_buffer(
+    vk: Arc<VkContext>,
+    width: u32,
+    height: u32,
+    buffer_size_alignment: usize,
+    profile: &mut vk::VideoProfileInfoKHR,
+) -> anyhow::Result<VkHostBuffer> {
+    let buffer_size = (width * height * 3).next_multiple_of(buffer_size_alignment as u32);
+
+    let mut profile_list_info = single_profile_list_info(profile);
+
+    let buf = {
+        let create_info = vk::BufferCreateInfo::default()
+            .size(buffer_size as u64)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE)
+            .usage(vk::BufferUsageFlags::VIDEO_ENCODE_DST_KHR)
+            .push_next(&mut profile_list_info);
+
+        unsafe { vk.device.create_buffer(&create_info, None)? }
+    };
+
+    let requirements = unsafe { vk.device.get_buffer_memory_requirements(buf) };
+
+    let alloc_info = vk::MemoryAllocateInfo::default()
+        .allocation_size(requirements.size)
+        .memory_type_index(vk.device_info.host_visible_mem_type_index);
+
+    let memory = unsafe { vk.device.allocate_memory(&alloc_info, None)? };
+
+    unsafe {
+        vk.device
+            .bind_buffer_memory(buf, memory, 0)
+            .context("vkBindBufferMemory")?
+    };
+
+    Ok(VkHostBuffer::wrap(vk, buf, memory, buffer_size as usize))
+}
+
+fn check_extent(
+    capabilities: &vk::VideoCapabilitiesKHR,
+    width: u32,
+    height: u32,
+) -> anyhow::Result<()> {
+    if capabilities.max_coded_extent.width < width || capabilities.max_coded_extent.height < height
+    {
+        bail!(
+            "video resolution too large: (max {}x{})",
+            capabilities.max_coded_extent.width,
+            capabilities.max_coded_extent.height
+        );
+    }
+
+    Ok(())
+}
+
+fn create_session_params(
+    video_loader: &VideoQueueExt,
+    session: vk::VideoSessionKHR,
+    session_params: &mut impl vk::ExtendsVideoSessionParametersCreateInfoKHR,
+) -> anyhow::Result<vk::VideoSessionParametersKHR> {
+    let create_info = vk::VideoSessionParametersCreateInfoKHR::default()
+        .video_session(session)
+        .push_next(session_params);
+
+    unsafe {
+        video_loader
+            .create_video_session_parameters(&create_info, None)
+            .context("vkCreateVideoSessionParametersKHR")
+    }
+}
+
+fn create_dpb(
+    vk: Arc<VkContext>,
+    format: vk::Format,
+    width: u32,
+    height: u32,
+    profile: &mut vk::VideoProfileInfoKHR,
+    capabilities: &vk::VideoCapabilitiesKHR,
+    size: usize,
+) -> anyhow::Result<dpb::DpbPool> {
+    let width = width.next_multiple_of(capabilities.picture_access_granularity.width);
+    let height = height.next_multiple_of(capabilities.picture_access_granularity.height);
+
+    if capabilities
+        .flags
+        .contains(vk::VideoCapabilityFlagsKHR::SEPARATE_REFERENCE_IMAGES)
+    {
+        trace!("using separate images for DPB pool");
+        dpb::DpbPool::new_separate_images(vk, format, width, height, profile, size)
+    } else {
+        trace!("using shared image for DPB pool");
+        dpb::DpbPool::new(vk, format, width, height, profile, size)
+    }
+}
+
 fn list_format_props<'a>(
     video_loader: &'a VideoQueueExt,
     pdevice: vk::PhysicalDevice,
@@ -1103,17 +1288,28 @@ fn default_hdr10_profile(op: vk::VideoCodecOperationFlagsKHR) -> vk::VideoProfil
         .luma_bit_depth(vk::VideoComponentBitDepthFlagsKHR::TYPE_10)
 }
 
-fn default_encode_usage(driver_version: DriverVersion) -> vk::VideoEncodeUsageInfoKHR<'static> {
-    // Nvidia chokes on "ULTRA LOW" for some reason.
-    let tuning_mode = if matches!(driver_version, DriverVersion::NvidiaProprietary { .. }) {
+fn default_encode_usage(
+    driver_version: DriverVersion,
+    content_type: ContentType,
+) -> vk::VideoEncodeUsageInfoKHR<'static> {
+    // Video can tolerate some extra latency, which gives the driver room to
+    // look ahead. Nvidia chokes on "ULTRA LOW" for some reason.
+    let tuning_mode = if content_type == ContentType::Video {
+        vk::VideoEncodeTuningModeKHR::HIGH_QUALITY
+    } else if matches!(driver_version, DriverVersion::NvidiaProprietary { .. }) {
         vk::VideoEncodeTuningModeKHR::LOW_LATENCY
     } else {
         vk::VideoEncodeTuningModeKHR::ULTRA_LOW_LATENCY
     };
 
+    let content_hints = match content_type {
+        ContentType::Photo | ContentType::Video => vk::VideoEncodeContentFlagsKHR::CAMERA,
+        _ => vk::VideoEncodeContentFlagsKHR::RENDERED,
+    };
+
     vk::VideoEncodeUsageInfoKHR::default()
         .video_usage_hints(vk::VideoEncodeUsageFlagsKHR::STREAMING)
-        .video_content_hints(vk::VideoEncodeContentFlagsKHR::RENDERED)
+        .video_content_hints(content_hints)
         .tuning_mode(tuning_mode)
 }
 
@@ -1127,8 +1323,27 @@ fn single_profile_list_info<'a>(
     }
 }
 
+/// Returns true if the encoder can switch between two content types by just
+/// updating the rate control, because the session and GOP structure would be
+/// the same.
+fn can_retune(driver_version: DriverVersion, from: ContentType, to: ContentType) -> bool {
+    let a = default_encode_usage(driver_version.clone(), from);
+    let b = default_encode_usage(driver_version, to);
+
+    a.tuning_mode == b.tuning_mode
+        && a.video_content_hints == b.video_content_hints
+        && uses_temporal_layers(from) == uses_temporal_layers(to)
+}
+
+/// Temporal layers let clients drop frames to keep latency down, which matters
+/// for games, but costs quality for video and photos.
+fn uses_temporal_layers(content_type: ContentType) -> bool {
+    !matches!(content_type, ContentType::Video | ContentType::Photo)
+}
+
 fn default_structure(
     codec: VideoCodec,
+    content_type: ContentType,
     max_codec_layers: u32,
     max_dpb_slots: u32,
 ) -> anyhow::Result<HierarchicalP> {
@@ -1136,7 +1351,7 @@ fn default_structure(
     const DEFAULT_GOP_SIZE: u32 = 256;
 
     // Disable hierarchical coding on H264, because it's broken.
-    let mut layers = if codec == VideoCodec::H264 {
+    let mut layers = if codec == VideoCodec::H264 || !uses_temporal_layers(content_type) {
         1
     } else {
         std::cmp::min(MAX_LAYERS, max_codec_layers)
@@ -1154,3 +1369,60 @@ fn default_structure(
 
     Ok(structure)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_can_retune() {
+        let radv = DriverVersion::MesaRadv {
+            major: 24,
+            minor: 1,
+            patch: 0,
+        };
+
+        assert!(can_retune(
+            radv.clone(),
+            ContentType::None,
+            ContentType::Game
+        ));
+        assert!(can_retune(
+            radv.clone(),
+            ContentType::Game,
+            ContentType::None
+        ));
+        assert!(can_retune(
+            radv.clone(),
+            ContentType::Video,
+            ContentType::Video
+        ));
+
+        // The tuning mode changes.
+        assert!(!can_retune(
+            radv.clone(),
+            ContentType::None,
+            ContentType::Video
+        ));
+        assert!(!can_retune(
+            radv.clone(),
+            ContentType::Photo,
+            ContentType::Video
+        ));
+
+        // The content hints and GOP structure change.
+        assert!(!can_retune(radv, ContentType::Game, ContentType::Photo));
+
+        let nvidia = DriverVersion::NvidiaProprietary {
+            major: 565,
+            minor: 57,
+        };
+
+        assert!(can_retune(
+            nvidia.clone(),
+            ContentType::None,
+            ContentType::Game
+        ));
+        assert!(!can_retune(nvidia, ContentType::Game, ContentType::Video));
+    }
+}
@@ -109,6 +109,14 @@ impl HierarchicalP {
         self.needs_refresh = true
     }
 
+    /// Starts a new GOP with the next frame, for example because the
+    /// reference pictures are no longer valid. Unlike a refresh, this doesn't
+    /// wait for the current mini-GOP to finish.
+    pub fn restart(&mut self) {
+        self.frame_num = self.frame_num.next_multiple_of(self.mini_gop_size as u64);
+        self.needs_refresh = true;
+    }
+
     pub fn required_dpb_size(&self) -> usize {
         // We should have one slot for each layer.
         std::cmp::max(self.layers as usize, 2)
@@ -273,4 +281,29 @@ mod test {
             assert_eq!(structure.next_frame(), *frame, "Frame {}", i);
         }
     }
+
+    #[test]
+    fn test_restart() {
+        let mut structure = HierarchicalP::new(3, 60);
+        for _ in 0..6 {
+            structure.next_frame();
+        }
+
+        structure.restart();
+        assert_eq!(
+            structure.next_frame(),
+            GopFrame {
+                stream_position: 8,
+                gop_position: 0,
+                id: 0,
+                ref_ids: vec![],
+                is_keyframe: true,
+                forward_ref_count: 3,
+            }
+        );
+
+        let frame = structure.next_frame();
+        assert_eq!(frame.gop_position, 1);
+        assert_eq!(frame.ref_ids, vec![0]);
+    }
 }
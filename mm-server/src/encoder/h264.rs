@@ -16,8 +16,13 @@ use tracing::{debug, trace};
 
 use super::gop_structure::HierarchicalP;
 use super::rate_control::{self, RateControlMode};
+use super::FrameTiming;
 use crate::codec::VideoCodec;
-use crate::{color::VideoProfile, session::control::VideoStreamParams, vulkan::*};
+use crate::{
+    color::VideoProfile,
+    session::control::{ContentType, VideoStreamParams},
+    vulkan::*,
+};
 
 vk_chain! {
     pub struct H264EncodeProfile<'a> {
@@ -48,10 +53,18 @@ struct H264Metadata {
     pic_order_cnt: i32,
 }
 
+// TODO autoselect level
+const LEVEL_IDC: vk::native::StdVideoH264LevelIdc =
+    vk::native::StdVideoH264LevelIdc_STD_VIDEO_H264_LEVEL_IDC_5_2;
+
 pub struct H264Encoder {
     inner: super::EncoderInner,
     profile: H264EncodeProfile,
+    caps: H264EncodeCapabilities,
+    params: VideoStreamParams,
     rc_mode: RateControlMode,
+    // Set if the rate control changed, and the change hasn't been submitted.
+    prev_rc_mode: Option<RateControlMode>,
 
     structure: HierarchicalP,
     pic_metadata: Vec<H264Metadata>, // Indexed by layer.
@@ -68,7 +81,7 @@ impl H264Encoder {
         framerate: u32,
         sink: impl super::Sink,
     ) -> anyhow::Result<Self> {
-        let (video_loader, encode_loader) = vk.video_apis.as_ref().unwrap();
+        let (video_loader, _) = vk.video_apis.as_ref().unwrap();
 
         let op = vk::VideoCodecOperationFlagsKHR::ENCODE_H264_EXT;
         let (profile, profile_idc) = match params.profile {
@@ -81,7 +94,7 @@ impl H264Encoder {
 
         let mut profile = H264EncodeProfile::new(
             profile,
-            super::default_encode_usage(vk.device_info.driver_version.clone()),
+            super::default_encode_usage(vk.device_info.driver_version.clone(), params.content_type),
             h264_profile_info,
         );
 
@@ -122,174 +135,51 @@ impl H264Encoder {
 
         let structure = super::default_structure(
             VideoCodec::H264,
+            params.content_type,
             caps.h264_caps
                 .max_temporal_layer_count
                 .min(caps.encode_caps.max_rate_control_layers),
             caps.video_caps.max_dpb_slots,
         )?;
 
-        let rc_mode = rate_control::select_rc_mode(
-            params,
-            &caps.encode_caps,
-            caps.h264_caps.min_qp.try_into().unwrap_or(17),
-            caps.h264_caps.max_qp.try_into().unwrap_or(50),
-            &structure,
-        );
+        let rc_mode = select_rc_mode(&caps, params, &structure);
         debug!(?rc_mode, "selected rate control mode");
 
         // TODO check more caps
-        // TODO autoselect level
-        let level_idc = vk::native::StdVideoH264LevelIdc_STD_VIDEO_H264_LEVEL_IDC_5_2;
-        if caps.h264_caps.max_level_idc != 0 && caps.h264_caps.max_level_idc < level_idc {
+        if caps.h264_caps.max_level_idc != 0 && caps.h264_caps.max_level_idc < LEVEL_IDC {
             bail!("video resolution too large for hardware");
         }
 
-        assert_eq!(
-            caps.video_caps.picture_access_granularity.width,
-            caps.video_caps.picture_access_granularity.height
-        );
-
-        let mb_width = caps.video_caps.picture_access_granularity.width;
-        let mb_height = caps.video_caps.picture_access_granularity.height;
-        trace!("mb size: {mb_width}x{mb_height}");
-
-        let aligned_width = params.width.next_multiple_of(mb_width);
-        let aligned_height = params.height.next_multiple_of(mb_height);
-
-        trace!(
-            "aligned width: {}, height: {}",
-            aligned_width,
-            aligned_height
-        );
-
-        // Divide by two because of chroma subsampling, I guess?
-        let crop_right = (aligned_width - params.width) / 2;
-        let crop_bottom = (aligned_height - params.height) / 2;
-
-        trace!("crop right: {}, bottom: {}", crop_right, crop_bottom);
-
-        let (colour_primaries, transfer_characteristics, matrix_coefficients) = match params.profile
-        {
-            VideoProfile::Hd => (1, 1, 1),
-            VideoProfile::Hdr10 => (9, 16, 9),
-        };
-
-        let mut vui = StdVideoH264SequenceParameterSetVui {
-            colour_primaries,
-            transfer_characteristics,
-            matrix_coefficients,
-            // Unspecified.
-            video_format: 5,
-            ..unsafe { std::mem::zeroed() }
-        };
-
-        vui.flags.set_video_signal_type_present_flag(1);
-        vui.flags.set_video_full_range_flag(0); // Narrow range.
-        vui.flags.set_color_description_present_flag(1);
-
-        let log2_max_frame_num_minus4 = structure
-            .gop_size
-            .next_power_of_two()
-            .ilog2()
-            .saturating_sub(4) as u8;
-
-        let bit_depth = match params.profile {
-            VideoProfile::Hd => 8,
-            VideoProfile::Hdr10 => 10,
-        };
-
-        let mut sps = StdVideoH264SequenceParameterSet {
-            profile_idc,
-            level_idc,
-            chroma_format_idc: StdVideoH264ChromaFormatIdc_STD_VIDEO_H264_CHROMA_FORMAT_IDC_420,
-
-            bit_depth_chroma_minus8: bit_depth - 8,
-            bit_depth_luma_minus8: bit_depth - 8,
-
-            max_num_ref_frames: 1,
-            pic_order_cnt_type: StdVideoH264PocType_STD_VIDEO_H264_POC_TYPE_0,
-            log2_max_pic_order_cnt_lsb_minus4: log2_max_frame_num_minus4,
-            log2_max_frame_num_minus4,
-            pic_width_in_mbs_minus1: (aligned_width / mb_width) - 1,
-            pic_height_in_map_units_minus1: (aligned_height / mb_height) - 1,
-            frame_crop_right_offset: crop_right,
-            frame_crop_bottom_offset: crop_bottom,
-
-            pSequenceParameterSetVui: <*const _>::cast(&vui),
-            ..unsafe { std::mem::zeroed() }
-        };
-
-        sps.flags.set_vui_parameters_present_flag(1);
-        sps.flags.set_frame_mbs_only_flag(1);
-        if crop_right > 0 || crop_bottom > 0 {
-            sps.flags.set_frame_cropping_flag(1);
-        }
-
-        let pps = StdVideoH264PictureParameterSet {
-            ..unsafe { std::mem::zeroed() }
-        };
-
-        let sps = [sps];
-        let pps = [pps];
-
-        let h264_add_info = vk::VideoEncodeH264SessionParametersAddInfoEXT::default()
-            .std_sp_ss(&sps)
-            .std_pp_ss(&pps);
-        let mut session_params = vk::VideoEncodeH264SessionParametersCreateInfoEXT::default()
-            .parameters_add_info(&h264_add_info)
-            .max_std_pps_count(1)
-            .max_std_sps_count(1);
-
-        let inner = super::EncoderInner::new(
-            vk.clone(),
-            params.width,
-            params.height,
-            framerate,
-            structure.required_dpb_size(),
-            profile.as_mut(),
-            caps.video_caps,
-            &mut session_params,
-            sink,
-        )?;
-
-        // Generate encoded stream headers.
-        let headers = unsafe {
-            let mut h264_get_info = vk::VideoEncodeH264SessionParametersGetInfoEXT::default()
-                .write_std_sps(true)
-                .write_std_pps(true);
-
-            let mut h264_feedback_info =
-                vk::VideoEncodeH264SessionParametersFeedbackInfoEXT::default();
-
-            let mut feedback_info = vk::VideoEncodeSessionParametersFeedbackInfoKHR::default()
-                .push_next(&mut h264_feedback_info);
-
-            let get_info = vk::VideoEncodeSessionParametersGetInfoKHR::default()
-                .video_session_parameters(inner.session_params)
-                .push_next(&mut h264_get_info);
-
-            encode_loader
-                .get_encoded_video_session_parameters(&get_info, &mut feedback_info)
-                .context("vkGetEncodedVideoSessionParametersKHR")?
-        };
-
-        if headers.is_empty() {
-            bail!("failed to generate sps/pps");
-        } else {
-            trace!("generated {} bytes of h264 headers", headers.len());
-        }
+        let inner =
+            with_parameter_sets(&caps, params, profile_idc, &structure, |session_params| {
+                super::EncoderInner::new(
+                    vk.clone(),
+                    params.width,
+                    params.height,
+                    framerate,
+                    structure.required_dpb_size(),
+                    profile.as_mut(),
+                    caps.video_caps,
+                    session_params,
+                    sink,
+                )
+            })?;
 
+        let headers = encoded_headers(&vk, inner.session_params)?;
         let pic_metadata = vec![H264Metadata::default(); structure.layers as usize];
 
         Ok(Self {
             inner,
             profile,
+            caps,
+            params,
             rc_mode,
+            prev_rc_mode: None,
             structure,
             pic_metadata,
             idr_num: 0,
             frame_num: 0,
-            headers: Bytes::copy_from_slice(&headers),
+            headers,
         })
     }
 
@@ -298,6 +188,7 @@ impl H264Encoder {
         input: &VkImage,
         tp_acquire: VkTimelinePoint,
         tp_release: VkTimelinePoint,
+        timing: FrameTiming,
     ) -> anyhow::Result<()> {
         let frame_state = self.structure.next_frame();
         if frame_state.is_keyframe {
@@ -308,82 +199,6 @@ impl H264Encoder {
             self.frame_num = 0;
         }
 
-        let pattern = if self.structure.layers > 1 {
-            vk::VideoEncodeH264RateControlFlagsEXT::TEMPORAL_LAYER_PATTERN_DYADIC
-        } else {
-            vk::VideoEncodeH264RateControlFlagsEXT::REFERENCE_PATTERN_FLAT
-        };
-
-        let mut h264_rc_layers = Vec::new();
-        let mut rc_layers = Vec::new();
-
-        if let RateControlMode::Vbr(vbr) = self.rc_mode {
-            let layer_settings = (0..self.structure.layers)
-                .map(|layer| vbr.layer(layer))
-                .collect::<Vec<_>>();
-
-            for settings in &layer_settings {
-                h264_rc_layers.push(
-                    vk::VideoEncodeH264RateControlLayerInfoEXT::default()
-                        .use_min_qp(true)
-                        .use_max_qp(true)
-                        .min_qp(vk::VideoEncodeH264QpEXT {
-                            qp_i: settings.min_qp as i32,
-                            qp_p: settings.min_qp as i32,
-                            qp_b: settings.min_qp as i32,
-                        })
-                        .max_qp(vk::VideoEncodeH264QpEXT {
-                            qp_i: settings.max_qp as i32,
-                            qp_p: settings.max_qp as i32,
-                            qp_b: settings.max_qp as i32,
-                        }),
-                );
-            }
-
-            // We can't do this in one step because the borrow checker doesn't
-            // like the way push_next borrows.
-            // TODO: Ash 0.39 may make this easier.
-            for (layer, (settings, h264)) in layer_settings
-                .iter()
-                .zip(h264_rc_layers.iter_mut())
-                .enumerate()
-            {
-                let (fps_numerator, fps_denominator) = self
-                    .structure
-                    .layer_framerate(layer as u32, self.inner.framerate);
-
-                rc_layers.push(
-                    vk::VideoEncodeRateControlLayerInfoKHR::default()
-                        .max_bitrate(settings.peak_bitrate)
-                        .average_bitrate(settings.average_bitrate)
-                        .frame_rate_numerator(fps_numerator)
-                        .frame_rate_denominator(fps_denominator)
-                        .push_next(h264),
-                );
-            }
-        }
-
-        let mut h264_rc_info = vk::VideoEncodeH264RateControlInfoEXT::default()
-            .gop_frame_count(self.structure.gop_size)
-            .idr_period(self.structure.gop_size)
-            .consecutive_b_frame_count(0)
-            .temporal_layer_count(rc_layers.len() as u32)
-            .flags(vk::VideoEncodeH264RateControlFlagsEXT::REGULAR_GOP | pattern);
-
-        let vbv_size = match self.rc_mode {
-            RateControlMode::Vbr(vbr) => vbr.vbv_size_ms,
-            _ => 0,
-        };
-
-        let mut rc_info = vk::VideoEncodeRateControlInfoKHR::default()
-            .rate_control_mode(self.rc_mode.as_vk_flags())
-            .virtual_buffer_size_in_ms(vbv_size)
-            .layers(&rc_layers);
-
-        // Doesn't have a push_next method, because we're supposed to call it on the
-        // parent struct.
-        rc_info.p_next = <*mut _>::cast(&mut h264_rc_info);
-
         let weight_table: vk::native::StdVideoEncodeH264WeightTable = std::mem::zeroed();
 
         let slice_type = if frame_state.is_keyframe {
@@ -500,17 +315,44 @@ impl H264Encoder {
             None
         };
 
-        self.inner.submit_encode(
-            input,
-            tp_acquire,
-            tp_release,
-            &frame_state,
-            &mut rc_info,
-            &mut h264_pic_info,
-            &mut setup_info,
-            &mut ref_info,
-            insert,
-        )?;
+        // If the rate control changed, Vulkan needs the old one as well.
+        let framerate = self.inner.framerate;
+        match self.prev_rc_mode.take() {
+            Some(prev_rc_mode) => {
+                with_rate_control(&prev_rc_mode, &self.structure, framerate, |prev_rc_info| {
+                    with_rate_control(&self.rc_mode, &self.structure, framerate, |rc_info| {
+                        self.inner.submit_encode(
+                            input,
+                            tp_acquire,
+                            tp_release,
+                            &frame_state,
+                            rc_info,
+                            Some(prev_rc_info),
+                            &mut h264_pic_info,
+                            &mut setup_info,
+                            &mut ref_info,
+                            insert,
+                            timing,
+                        )
+                    })
+                })?
+            }
+            None => with_rate_control(&self.rc_mode, &self.structure, framerate, |rc_info| {
+                self.inner.submit_encode(
+                    input,
+                    tp_acquire,
+                    tp_release,
+                    &frame_state,
+                    rc_info,
+                    None,
+                    &mut h264_pic_info,
+                    &mut setup_info,
+                    &mut ref_info,
+                    insert,
+                    timing,
+                )
+            })?,
+        }
 
         // Save the reference info for the DPB slot we just wrote.
         self.pic_metadata[frame_state.id as usize] = H264Metadata {
@@ -537,4 +379,304 @@ impl H264Encoder {
     pub fn request_refresh(&mut self) {
         self.structure.request_refresh()
     }
+
+    pub fn set_content_type(&mut self, content_type: ContentType) -> bool {
+        if !super::can_retune(
+            self.inner.vk.device_info.driver_version.clone(),
+            self.params.content_type,
+            content_type,
+        ) {
+            return false;
+        }
+
+        self.params.content_type = content_type;
+        let rc_mode = select_rc_mode(&self.caps, self.params, &self.structure);
+        debug!(?rc_mode, "updating rate control mode");
+
+        // Keep the mode Vulkan knows about, if we haven't submitted a frame
+        // since the last change.
+        let prev_rc_mode = std::mem::replace(&mut self.rc_mode, rc_mode);
+        self.prev_rc_mode.get_or_insert(prev_rc_mode);
+        true
+    }
+
+    pub fn resize(&mut self, width: u32, height: u32) -> anyhow::Result<()> {
+        self.params.width = width;
+        self.params.height = height;
+
+        let profile_idc = self.profile.h264_profile.std_profile_idc;
+        let required_dpb_size = self.structure.required_dpb_size();
+        with_parameter_sets(
+            &self.caps,
+            self.params,
+            profile_idc,
+            &self.structure,
+            |session_params| {
+                self.inner.resize(
+                    width,
+                    height,
+                    required_dpb_size,
+                    self.profile.as_mut(),
+                    self.caps.video_caps,
+                    session_params,
+                )
+            },
+        )?;
+
+        self.headers = encoded_headers(&self.inner.vk, self.inner.session_params)?;
+
+        // The bitrate depends on the resolution.
+        let rc_mode = select_rc_mode(&self.caps, self.params, &self.structure);
+        debug!(?rc_mode, "updating rate control mode");
+
+        // Keep the mode Vulkan knows about, if we haven't submitted a frame
+        // since the last change.
+        let prev_rc_mode = std::mem::replace(&mut self.rc_mode, rc_mode);
+        self.prev_rc_mode.get_or_insert(prev_rc_mode);
+
+        // The old references are gone, so the next frame has to be an IDR.
+        self.structure.restart();
+        Ok(())
+    }
+}
+
+/// Builds the SPS and PPS for the stream, and passes the resulting session
+/// parameters to `f`. The parameter sets borrow from each other, so they can't
+/// be returned.
+fn with_parameter_sets<T>(
+    caps: &H264EncodeCapabilities,
+    params: VideoStreamParams,
+    profile_idc: vk::native::StdVideoH264ProfileIdc,
+    structure: &HierarchicalP,
+    f: impl FnOnce(&mut vk::VideoEncodeH264SessionParametersCreateInfoEXT) -> anyhow::Result<T>,
+) -> anyhow::Result<T> {
+    assert_eq!(
+        caps.video_caps.picture_access_granularity.width,
+        caps.video_caps.picture_access_granularity.height
+    );
+
+    let mb_width = caps.video_caps.picture_access_granularity.width;
+    let mb_height = caps.video_caps.picture_access_granularity.height;
+    trace!("mb size: {mb_width}x{mb_height}");
+
+    let aligned_width = params.width.next_multiple_of(mb_width);
+    let aligned_height = params.height.next_multiple_of(mb_height);
+
+    trace!(
+        "aligned width: {}, height: {}",
+        aligned_width,
+        aligned_height
+    );
+
+    // Divide by two because of chroma subsampling, I guess?
+    let crop_right = (aligned_width - params.width) / 2;
+    let crop_bottom = (aligned_height - params.height) / 2;
+
+    trace!("crop right: {}, bottom: {}", crop_right, crop_bottom);
+
+    let (colour_primaries, transfer_characteristics, matrix_coefficients) = match params.profile {
+        VideoProfile::Hd => (1, 1, 1),
+        VideoProfile::Hdr10 => (9, 16, 9),
+    };
+
+    let mut vui = StdVideoH264SequenceParameterSetVui {
+        colour_primaries,
+        transfer_characteristics,
+        matrix_coefficients,
+        // Unspecified.
+        video_format: 5,
+        ..unsafe { std::mem::zeroed() }
+    };
+
+    vui.flags.set_video_signal_type_present_flag(1);
+    vui.flags.set_video_full_range_flag(0); // Narrow range.
+    vui.flags.set_color_description_present_flag(1);
+
+    let log2_max_frame_num_minus4 = structure
+        .gop_size
+        .next_power_of_two()
+        .ilog2()
+        .saturating_sub(4) as u8;
+
+    let bit_depth = match params.profile {
+        VideoProfile::Hd => 8,
+        VideoProfile::Hdr10 => 10,
+    };
+
+    let mut sps = StdVideoH264SequenceParameterSet {
+        profile_idc,
+        level_idc: LEVEL_IDC,
+        chroma_format_idc: StdVideoH264ChromaFormatIdc_STD_VIDEO_H264_CHROMA_FORMAT_IDC_420,
+
+        bit_depth_chroma_minus8: bit_depth - 8,
+        bit_depth_luma_minus8: bit_depth - 8,
+
+        max_num_ref_frames: 1,
+        pic_order_cnt_type: StdVideoH264PocType_STD_VIDEO_H264_POC_TYPE_0,
+        log2_max_pic_order_cnt_lsb_minus4: log2_max_frame_num_minus4,
+        log2_max_frame_num_minus4,
+        pic_width_in_mbs_minus1: (aligned_width / mb_width) - 1,
+        pic_height_in_map_units_minus1: (aligned_height / mb_height) - 1,
+        frame_crop_right_offset: crop_right,
+        frame_crop_bottom_offset: crop_bottom,
+
+        pSequenceParameterSetVui: <*const _>::cast(&vui),
+        ..unsafe { std::mem::zeroed() }
+    };
+
+    sps.flags.set_vui_parameters_present_flag(1);
+    sps.flags.set_frame_mbs_only_flag(1);
+    if crop_right > 0 || crop_bottom > 0 {
+        sps.flags.set_frame_cropping_flag(1);
+    }
+
+    let pps = StdVideoH264PictureParameterSet {
+        ..unsafe { std::mem::zeroed() }
+    };
+
+    let sps = [sps];
+    let pps = [pps];
+
+    let h264_add_info = vk::VideoEncodeH264SessionParametersAddInfoEXT::default()
+        .std_sp_ss(&sps)
+        .std_pp_ss(&pps);
+    let mut session_params = vk::VideoEncodeH264SessionParametersCreateInfoEXT::default()
+        .parameters_add_info(&h264_add_info)
+        .max_std_pps_count(1)
+        .max_std_sps_count(1);
+
+    f(&mut session_params)
+}
+
+/// Fetches the encoded SPS and PPS, to be inserted before keyframes.
+fn encoded_headers(
+    vk: &VkContext,
+    session_params: vk::VideoSessionParametersKHR,
+) -> anyhow::Result<Bytes> {
+    let (_, encode_loader) = vk.video_apis.as_ref().unwrap();
+    let headers = unsafe {
+        let mut h264_get_info = vk::VideoEncodeH264SessionParametersGetInfoEXT::default()
+            .write_std_sps(true)
+            .write_std_pps(true);
+
+        let mut h264_feedback_info = vk::VideoEncodeH264SessionParametersFeedbackInfoEXT::default();
+
+        let mut feedback_info = vk::VideoEncodeSessionParametersFeedbackInfoKHR::default()
+            .push_next(&mut h264_feedback_info);
+
+        let get_info = vk::VideoEncodeSessionParametersGetInfoKHR::default()
+            .video_session_parameters(session_params)
+            .push_next(&mut h264_get_info);
+
+        encode_loader
+            .get_encoded_video_session_parameters(&get_info, &mut feedback_info)
+            .context("vkGetEncodedVideoSessionParametersKHR")?
+    };
+
+    if headers.is_empty() {
+        bail!("failed to generate sps/pps");
+    } else {
+        trace!("generated {} bytes of h264 headers", headers.len());
+    }
+
+    Ok(Bytes::copy_from_slice(&headers))
+}
+
+fn select_rc_mode(
+    caps: &H264EncodeCapabilities,
+    params: VideoStreamParams,
+    structure: &HierarchicalP,
+) -> RateControlMode {
+    rate_control::select_rc_mode(
+        params,
+        caps.encode_caps.rate_control_modes,
+        caps.h264_caps.min_qp.try_into().unwrap_or(17),
+        caps.h264_caps.max_qp.try_into().unwrap_or(50),
+        structure,
+    )
+}
+/// Builds the rate control info for the given mode, and passes it to `f`. The
+/// info borrows from a chain of structs, so it can't be returned.
+fn with_rate_control<T>(
+    rc_mode: &RateControlMode,
+    structure: &HierarchicalP,
+    framerate: u32,
+    f: impl FnOnce(&mut vk::VideoEncodeRateControlInfoKHR) -> T,
+) -> T {
+    let pattern = if structure.layers > 1 {
+        vk::VideoEncodeH264RateControlFlagsEXT::TEMPORAL_LAYER_PATTERN_DYADIC
+    } else {
+        vk::VideoEncodeH264RateControlFlagsEXT::REFERENCE_PATTERN_FLAT
+    };
+
+    let mut h264_rc_layers = Vec::new();
+    let mut rc_layers = Vec::new();
+
+    if let RateControlMode::Vbr(vbr) = *rc_mode {
+        let layer_settings = (0..structure.layers)
+            .map(|layer| vbr.layer(layer))
+            .collect::<Vec<_>>();
+
+        for settings in &layer_settings {
+            h264_rc_layers.push(
+                vk::VideoEncodeH264RateControlLayerInfoEXT::default()
+                    .use_min_qp(true)
+                    .use_max_qp(true)
+                    .min_qp(vk::VideoEncodeH264QpEXT {
+                        qp_i: settings.min_qp as i32,
+                        qp_p: settings.min_qp as i32,
+                        qp_b: settings.min_qp as i32,
+                    })
+                    .max_qp(vk::VideoEncodeH264QpEXT {
+                        qp_i: settings.max_qp as i32,
+                        qp_p: settings.max_qp as i32,
+                        qp_b: settings.max_qp as i32,
+                    }),
+            );
+        }
+
+        // We can't do this in one step because the borrow checker doesn't
+        // like the way push_next borrows.
+        // TODO: Ash 0.39 may make this easier.
+        for (layer, (settings, h264)) in layer_settings
+            .iter()
+            .zip(h264_rc_layers.iter_mut())
+            .enumerate()
+        {
+            let (fps_numerator, fps_denominator) =
+                structure.layer_framerate(layer as u32, framerate);
+
+            rc_layers.push(
+                vk::VideoEncodeRateControlLayerInfoKHR::default()
+                    .max_bitrate(settings.peak_bitrate)
+                    .average_bitrate(settings.average_bitrate)
+                    .frame_rate_numerator(fps_numerator)
+                    .frame_rate_denominator(fps_denominator)
+                    .push_next(h264),
+            );
+        }
+    }
+
+    let mut h264_rc_info = vk::VideoEncodeH264RateControlInfoEXT::default()
+        .gop_frame_count(structure.gop_size)
+        .idr_period(structure.gop_size)
+        .consecutive_b_frame_count(0)
+        .temporal_layer_count(rc_layers.len() as u32)
+        .flags(vk::VideoEncodeH264RateControlFlagsEXT::REGULAR_GOP | pattern);
+
+    let vbv_size = match *rc_mode {
+        RateControlMode::Vbr(vbr) => vbr.vbv_size_ms,
+        _ => 0,
+    };
+
+    let mut rc_info = vk::VideoEncodeRateControlInfoKHR::default()
+        .rate_control_mode(rc_mode.as_vk_flags())
+        .virtual_buffer_size_in_ms(vbv_size)
+        .layers(&rc_layers);
+
+    // Doesn't have a push_next method, because we're supposed to call it on the
+    // parent struct.
+    rc_info.p_next = <*mut _>::cast(&mut h264_rc_info);
+
+    f(&mut rc_info)
 }
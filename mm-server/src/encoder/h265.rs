@@ -6,14 +6,18 @@ use std::sync::Arc;
 
 use anyhow::{bail, Context};
 use ash::vk;
-use bytes::Bytes;
+use bytes::{BufMut as _, Bytes, BytesMut};
 use tracing::{debug, trace};
 
 use super::gop_structure::HierarchicalP;
 use super::rate_control::{self, RateControlMode};
+use super::FrameTiming;
 use crate::codec::VideoCodec;
-use crate::color::VideoProfile;
-use crate::{session::control::VideoStreamParams, vulkan::*};
+use crate::color::{HdrMetadata, VideoProfile};
+use crate::{
+    session::control::{ContentType, VideoStreamParams},
+    vulkan::*,
+};
 
 vk_chain! {
     pub struct H265EncodeProfile<'a> {
@@ -45,10 +49,18 @@ struct H265Metadata {
     ref_count: u32,
 }
 
+// TODO autoselect level
+const LEVEL_IDC: vk::native::StdVideoH265LevelIdc =
+    vk::native::StdVideoH265LevelIdc_STD_VIDEO_H265_LEVEL_IDC_5_2;
+
 pub struct H265Encoder {
     inner: super::EncoderInner,
     profile: H265EncodeProfile,
-    rc_mode: super::rate_control::RateControlMode,
+    caps: H265EncodeCapabilities,
+    params: VideoStreamParams,
+    rc_mode: RateControlMode,
+    // Set if the rate control changed, and the change hasn't been submitted.
+    prev_rc_mode: Option<RateControlMode>,
 
     structure: HierarchicalP,
     pic_metadata: Vec<H265Metadata>, // Indexed by layer.
@@ -56,6 +68,8 @@ pub struct H265Encoder {
     frame_num: u32,
 
     headers: Bytes,
+    hdr: bool,
+    hdr_sei: Option<Bytes>,
 }
 
 impl H265Encoder {
@@ -78,7 +92,7 @@ impl H265Encoder {
 
         let mut profile = H265EncodeProfile::new(
             profile,
-            super::default_encode_usage(vk.device_info.driver_version.clone()),
+            super::default_encode_usage(vk.device_info.driver_version.clone(), params.content_type),
             h265_profile_info,
         );
 
@@ -121,261 +135,53 @@ impl H265Encoder {
 
         let structure = super::default_structure(
             VideoCodec::H265,
+            params.content_type,
             caps.h265_caps
                 .max_sub_layer_count
                 .min(caps.encode_caps.max_rate_control_layers),
             caps.video_caps.max_dpb_slots,
         )?;
 
-        let rc_mode = rate_control::select_rc_mode(
-            params,
-            &caps.encode_caps,
-            caps.h265_caps.min_qp.try_into().unwrap_or(17),
-            caps.h265_caps.max_qp.try_into().unwrap_or(50),
-            &structure,
-        );
-
+        let rc_mode = select_rc_mode(&caps, params, &structure);
         debug!(?rc_mode, "selected rate control mode");
 
         // TODO check more caps
-        // TODO autoselect level
-        let level_idc = vk::native::StdVideoH265LevelIdc_STD_VIDEO_H265_LEVEL_IDC_5_2;
-        if caps.h265_caps.max_level_idc != 0 && caps.h265_caps.max_level_idc < level_idc {
+        if caps.h265_caps.max_level_idc != 0 && caps.h265_caps.max_level_idc < LEVEL_IDC {
             bail!("video resolution too large for hardware");
         }
 
-        const CTB_SIZES: [(vk::VideoEncodeH265CtbSizeFlagsEXT, usize); 3] = [
-            (vk::VideoEncodeH265CtbSizeFlagsEXT::TYPE_16, 16),
-            (vk::VideoEncodeH265CtbSizeFlagsEXT::TYPE_32, 32),
-            (vk::VideoEncodeH265CtbSizeFlagsEXT::TYPE_64, 64),
-        ];
-
-        let min_ctb = CTB_SIZES
-            .iter()
-            .filter(|(flag, _)| caps.h265_caps.ctb_sizes.contains(*flag))
-            .map(|(_, size)| *size)
-            .min()
-            .expect("no ctb size found");
-
-        let max_ctb = CTB_SIZES
-            .iter()
-            .filter(|(flag, _)| caps.h265_caps.ctb_sizes.contains(*flag))
-            .map(|(_, size)| *size)
-            .max()
-            .expect("no ctb size found");
-
-        const TBS_SIZES: [(vk::VideoEncodeH265TransformBlockSizeFlagsEXT, usize); 4] = [
-            (vk::VideoEncodeH265TransformBlockSizeFlagsEXT::TYPE_4, 4),
-            (vk::VideoEncodeH265TransformBlockSizeFlagsEXT::TYPE_8, 8),
-            (vk::VideoEncodeH265TransformBlockSizeFlagsEXT::TYPE_16, 16),
-            (vk::VideoEncodeH265TransformBlockSizeFlagsEXT::TYPE_32, 32),
-        ];
-
-        let min_tbs = TBS_SIZES
-            .iter()
-            .filter(|(flag, _)| caps.h265_caps.transform_block_sizes.contains(*flag))
-            .map(|(_, size)| *size)
-            .min()
-            .expect("no tbs size found");
-
-        let max_tbs = TBS_SIZES
-            .iter()
-            .filter(|(flag, _)| caps.h265_caps.transform_block_sizes.contains(*flag))
-            .map(|(_, size)| *size)
-            .max()
-            .expect("no tbs size found");
-
-        let aligned_width = params
-            .width
-            .next_multiple_of(caps.encode_caps.encode_input_picture_granularity.width);
-        let aligned_height = params
-            .height
-            .next_multiple_of(caps.encode_caps.encode_input_picture_granularity.height);
-
-        trace!(
-            min_ctb,
-            max_ctb,
-            min_tbs,
-            max_tbs,
-            aligned_width,
-            aligned_height,
-            "block sizes",
-        );
-
-        let crop_right = (aligned_width - params.width) / 2;
-        let crop_bottom = (aligned_height - params.height) / 2;
-
-        trace!("crop right: {}, bottom: {}", crop_right, crop_bottom);
-
-        let (colour_primaries, transfer_characteristics, matrix_coeffs) = match params.profile {
-            VideoProfile::Hd => (1, 1, 1),
-            VideoProfile::Hdr10 => (9, 16, 9),
-        };
-
-        let mut vui = vk::native::StdVideoH265SequenceParameterSetVui {
-            colour_primaries,
-            transfer_characteristics,
-            matrix_coeffs,
-            // Unspecified.
-            video_format: 5,
-            ..unsafe { std::mem::zeroed() }
-        };
-
-        vui.flags.set_video_signal_type_present_flag(1);
-        vui.flags.set_colour_description_present_flag(1);
-        vui.flags.set_video_full_range_flag(0); // Narrow range.
-
-        let ptl = vk::native::StdVideoH265ProfileTierLevel {
-            general_profile_idc: profile_idc,
-            general_level_idc: level_idc,
-            ..unsafe { std::mem::zeroed() }
-        };
-
-        // ptl.flags.set_general_progressive_source_flag(1);
-        // ptl.flags.set_general_interlaced_source_flag(0);
-
-        let layers_minus_1 = (structure.layers - 1) as u8;
-        let mut pbm: vk::native::StdVideoH265DecPicBufMgr = unsafe { std::mem::zeroed() };
-        pbm.max_dec_pic_buffering_minus1[layers_minus_1 as usize] =
-            (structure.required_dpb_size() - 1) as u8;
-        // No picture reordering.
-        pbm.max_num_reorder_pics[layers_minus_1 as usize] = 0;
-        pbm.max_latency_increase_plus1[layers_minus_1 as usize] = 0;
-
-        let mut vps = vk::native::StdVideoH265VideoParameterSet {
-            vps_max_sub_layers_minus1: layers_minus_1,
-            pDecPicBufMgr: &pbm,
-            pHrdParameters: std::ptr::null(),
-            pProfileTierLevel: &ptl,
-            ..unsafe { std::mem::zeroed() }
-        };
-
-        vps.flags.set_vps_sub_layer_ordering_info_present_flag(1);
-        vps.flags.set_vps_temporal_id_nesting_flag(1);
-
-        let min_cb = 8_u8;
-        let max_cb = max_ctb;
-
-        let max_transform_hierarchy_depth = (max_ctb.ilog2() - min_tbs.ilog2()) as u8;
-
-        let bit_depth = match params.profile {
-            VideoProfile::Hd => 8,
-            VideoProfile::Hdr10 => 10,
-        };
-
-        let mut sps = vk::native::StdVideoH265SequenceParameterSet {
-            chroma_format_idc:
-                vk::native::StdVideoH265ChromaFormatIdc_STD_VIDEO_H265_CHROMA_FORMAT_IDC_420,
-            pic_width_in_luma_samples: aligned_width,
-            pic_height_in_luma_samples: aligned_height,
-            sps_max_sub_layers_minus1: layers_minus_1,
-            bit_depth_luma_minus8: bit_depth - 8,
-            bit_depth_chroma_minus8: bit_depth - 8,
-            log2_max_pic_order_cnt_lsb_minus4: 4,
-            log2_min_luma_coding_block_size_minus3: (min_cb.ilog2() - 3) as u8,
-            log2_diff_max_min_luma_coding_block_size: (max_cb.ilog2() - min_cb.ilog2()) as u8,
-            log2_min_luma_transform_block_size_minus2: (min_tbs.ilog2() - 2) as u8,
-            log2_diff_max_min_luma_transform_block_size: (max_tbs.ilog2() - min_tbs.ilog2()) as u8,
-            max_transform_hierarchy_depth_inter: max_transform_hierarchy_depth,
-            max_transform_hierarchy_depth_intra: max_transform_hierarchy_depth,
-            conf_win_right_offset: crop_right,
-            conf_win_bottom_offset: crop_bottom,
-            pProfileTierLevel: &ptl,
-            pDecPicBufMgr: &pbm,
-            pSequenceParameterSetVui: &vui,
-            ..unsafe { std::mem::zeroed() }
-        };
-
-        sps.flags.set_conformance_window_flag(1);
-        sps.flags.set_vui_parameters_present_flag(1);
-        sps.flags.set_sps_temporal_id_nesting_flag(1);
-        sps.flags.set_sps_sub_layer_ordering_info_present_flag(1);
-
-        if caps
-            .h265_caps
-            .std_syntax_flags
-            .contains(vk::VideoEncodeH265StdFlagsEXT::SAMPLE_ADAPTIVE_OFFSET_ENABLED_FLAG_SET)
-        {
-            sps.flags.set_sample_adaptive_offset_enabled_flag(1);
-        }
-
-        if caps
-            .h265_caps
-            .std_syntax_flags
-            .contains(vk::VideoEncodeH265StdFlagsEXT::TRANSFORM_SKIP_ENABLED_FLAG_SET)
-        {
-            sps.flags.set_transform_skip_context_enabled_flag(1);
-        }
-
-        let pps = vk::native::StdVideoH265PictureParameterSet {
-            ..unsafe { std::mem::zeroed() }
-        };
-
-        let sps = [sps];
-        let pps = [pps];
-        let vps = [vps];
-
-        let h265_add_info = vk::VideoEncodeH265SessionParametersAddInfoEXT::default()
-            .std_vp_ss(&vps)
-            .std_sp_ss(&sps)
-            .std_pp_ss(&pps);
-        let mut session_params = vk::VideoEncodeH265SessionParametersCreateInfoEXT::default()
-            .parameters_add_info(&h265_add_info)
-            .max_std_vps_count(1)
-            .max_std_pps_count(1)
-            .max_std_sps_count(1);
-
-        let inner = super::EncoderInner::new(
-            vk.clone(),
-            params.width,
-            params.height,
-            framerate,
-            structure.required_dpb_size(),
-            profile.as_mut(),
-            caps.video_caps,
-            &mut session_params,
-            sink,
-        )?;
-
-        // Generate encoded stream headers.
-        let headers = unsafe {
-            let mut h265_get_info = vk::VideoEncodeH265SessionParametersGetInfoEXT::default()
-                .write_std_vps(true)
-                .write_std_sps(true)
-                .write_std_pps(true);
-
-            let mut h265_feedback_info =
-                vk::VideoEncodeH265SessionParametersFeedbackInfoEXT::default();
-
-            let mut feedback_info = vk::VideoEncodeSessionParametersFeedbackInfoKHR::default()
-                .push_next(&mut h265_feedback_info);
-
-            let get_info = vk::VideoEncodeSessionParametersGetInfoKHR::default()
-                .video_session_parameters(inner.session_params)
-                .push_next(&mut h265_get_info);
-
-            encode_loader
-                .get_encoded_video_session_parameters(&get_info, &mut feedback_info)
-                .context("vkGetEncodedVideoSessionParametersKHR")?
-        };
-
-        if headers.is_empty() {
-            bail!("failed to generate sps/pps/vps");
-        } else {
-            trace!("generated {} bytes of h265 headers", headers.len());
-        }
+        let inner =
+            with_parameter_sets(&caps, params, profile_idc, &structure, |session_params| {
+                super::EncoderInner::new(
+                    vk.clone(),
+                    params.width,
+                    params.height,
+                    framerate,
+                    structure.required_dpb_size(),
+                    profile.as_mut(),
+                    caps.video_caps,
+                    session_params,
+                    sink,
+                )
+            })?;
 
+        let headers = encoded_headers(&vk, inner.session_params)?;
         let pic_metadata = vec![H265Metadata::default(); structure.layers as usize];
 
         Ok(Self {
             inner,
             profile,
+            caps,
+            params,
             rc_mode,
+            prev_rc_mode: None,
             structure,
             pic_metadata,
             idr_num: 0,
             frame_num: 0,
-            headers: Bytes::copy_from_slice(&headers),
+            headers,
+            hdr: params.profile == VideoProfile::Hdr10,
+            hdr_sei: None,
         })
     }
 
@@ -384,6 +190,7 @@ impl H265Encoder {
         input: &VkImage,
         tp_acquire: VkTimelinePoint,
         tp_release: VkTimelinePoint,
+        timing: FrameTiming,
     ) -> anyhow::Result<()> {
         let frame_state = self.structure.next_frame();
         if frame_state.is_keyframe {
@@ -391,82 +198,6 @@ impl H265Encoder {
             self.frame_num = 0;
         }
 
-        let pattern = if self.structure.layers > 1 {
-            vk::VideoEncodeH265RateControlFlagsEXT::TEMPORAL_SUB_LAYER_PATTERN_DYADIC
-        } else {
-            vk::VideoEncodeH265RateControlFlagsEXT::REFERENCE_PATTERN_FLAT
-        };
-
-        let mut h265_rc_layers = Vec::new();
-        let mut rc_layers = Vec::new();
-
-        if let RateControlMode::Vbr(vbr) = self.rc_mode {
-            let layer_settings = (0..self.structure.layers)
-                .map(|layer| vbr.layer(layer))
-                .collect::<Vec<_>>();
-
-            for settings in &layer_settings {
-                h265_rc_layers.push(
-                    vk::VideoEncodeH265RateControlLayerInfoEXT::default()
-                        .use_min_qp(true)
-                        .use_max_qp(true)
-                        .min_qp(vk::VideoEncodeH265QpEXT {
-                            qp_i: settings.min_qp as i32,
-                            qp_p: settings.min_qp as i32,
-                            qp_b: settings.min_qp as i32,
-                        })
-                        .max_qp(vk::VideoEncodeH265QpEXT {
-                            qp_i: settings.max_qp as i32,
-                            qp_p: settings.max_qp as i32,
-                            qp_b: settings.max_qp as i32,
-                        }),
-                );
-            }
-
-            for (layer, (settings, h265_rc_layer)) in layer_settings
-                .iter()
-                .zip(h265_rc_layers.iter_mut())
-                .enumerate()
-            {
-                let (fps_numerator, fps_denominator) = self
-                    .structure
-                    .layer_framerate(layer as u32, self.inner.framerate);
-
-                rc_layers.push(
-                    vk::VideoEncodeRateControlLayerInfoKHR::default()
-                        .max_bitrate(settings.peak_bitrate)
-                        .average_bitrate(settings.average_bitrate)
-                        .frame_rate_numerator(fps_numerator)
-                        .frame_rate_denominator(fps_denominator)
-                        .push_next(h265_rc_layer),
-                );
-            }
-        }
-
-        let mut h265_rc_info = vk::VideoEncodeH265RateControlInfoEXT::default()
-            .gop_frame_count(self.structure.gop_size)
-            .idr_period(self.structure.gop_size)
-            .consecutive_b_frame_count(0)
-            .sub_layer_count(rc_layers.len() as u32)
-            .flags(vk::VideoEncodeH265RateControlFlagsEXT::REGULAR_GOP | pattern);
-
-        let vbv_size = match self.rc_mode {
-            RateControlMode::Vbr(settings) => settings.vbv_size_ms,
-            _ => 0,
-        };
-
-        let mut rc_info = vk::VideoEncodeRateControlInfoKHR::default()
-            .rate_control_mode(self.rc_mode.as_vk_flags())
-            .virtual_buffer_size_in_ms(vbv_size);
-
-        if !rc_layers.is_empty() {
-            rc_info = rc_info.layers(&rc_layers);
-        }
-
-        // Doesn't have a push_next method, because we're supposed to call it on
-        // the parent struct.
-        rc_info.p_next = <*mut _>::cast(&mut h265_rc_info);
-
         let weight_table: vk::native::StdVideoEncodeH265WeightTable = std::mem::zeroed();
 
         let slice_type = if frame_state.is_keyframe {
@@ -628,22 +359,57 @@ impl H265Encoder {
             vk::VideoEncodeH265DpbSlotInfoEXT::default().std_reference_info(&setup_std_ref_info);
 
         let insert = if frame_state.is_keyframe {
-            Some(self.headers.clone())
+            match &self.hdr_sei {
+                Some(sei) => {
+                    let mut buf = BytesMut::with_capacity(self.headers.len() + sei.len());
+                    buf.put_slice(&self.headers);
+                    buf.put_slice(sei);
+                    Some(buf.freeze())
+                }
+                None => Some(self.headers.clone()),
+            }
         } else {
             None
         };
 
-        self.inner.submit_encode(
-            input,
-            tp_acquire,
-            tp_release,
-            &frame_state,
-            &mut rc_info,
-            &mut h265_pic_info,
-            &mut setup_info,
-            &mut ref_info,
-            insert,
-        )?;
+        // If the rate control changed, Vulkan needs the old one as well.
+        let framerate = self.inner.framerate;
+        match self.prev_rc_mode.take() {
+            Some(prev_rc_mode) => {
+                with_rate_control(&prev_rc_mode, &self.structure, framerate, |prev_rc_info| {
+                    with_rate_control(&self.rc_mode, &self.structure, framerate, |rc_info| {
+                        self.inner.submit_encode(
+                            input,
+                            tp_acquire,
+                            tp_release,
+                            &frame_state,
+                            rc_info,
+                            Some(prev_rc_info),
+                            &mut h265_pic_info,
+                            &mut setup_info,
+                            &mut ref_info,
+                            insert,
+                            timing,
+                        )
+                    })
+                })?
+            }
+            None => with_rate_control(&self.rc_mode, &self.structure, framerate, |rc_info| {
+                self.inner.submit_encode(
+                    input,
+                    tp_acquire,
+                    tp_release,
+                    &frame_state,
+                    rc_info,
+                    None,
+                    &mut h265_pic_info,
+                    &mut setup_info,
+                    &mut ref_info,
+                    insert,
+                    timing,
+                )
+            })?,
+        }
 
         // Save the reference info for the DPB slot we just wrote.
         self.pic_metadata[frame_state.id as usize] = H265Metadata {
@@ -671,4 +437,478 @@ impl H265Encoder {
     pub fn request_refresh(&mut self) {
         self.structure.request_refresh()
     }
+
+    pub fn set_content_type(&mut self, content_type: ContentType) -> bool {
+        if !super::can_retune(
+            self.inner.vk.device_info.driver_version.clone(),
+            self.params.content_type,
+            content_type,
+        ) {
+            return false;
+        }
+
+        self.params.content_type = content_type;
+        let rc_mode = select_rc_mode(&self.caps, self.params, &self.structure);
+        debug!(?rc_mode, "updating rate control mode");
+
+        // Keep the mode Vulkan knows about, if we haven't submitted a frame
+        // since the last change.
+        let prev_rc_mode = std::mem::replace(&mut self.rc_mode, rc_mode);
+        self.prev_rc_mode.get_or_insert(prev_rc_mode);
+        true
+    }
+
+    pub fn resize(&mut self, width: u32, height: u32) -> anyhow::Result<()> {
+        self.params.width = width;
+        self.params.height = height;
+
+        let profile_idc = self.profile.h265_profile.std_profile_idc;
+        let required_dpb_size = self.structure.required_dpb_size();
+        with_parameter_sets(
+            &self.caps,
+            self.params,
+            profile_idc,
+            &self.structure,
+            |session_params| {
+                self.inner.resize(
+                    width,
+                    height,
+                    required_dpb_size,
+                    self.profile.as_mut(),
+                    self.caps.video_caps,
+                    session_params,
+                )
+            },
+        )?;
+
+        self.headers = encoded_headers(&self.inner.vk, self.inner.session_params)?;
+
+        // The bitrate depends on the resolution.
+        let rc_mode = select_rc_mode(&self.caps, self.params, &self.structure);
+        debug!(?rc_mode, "updating rate control mode");
+
+        // Keep the mode Vulkan knows about, if we haven't submitted a frame
+        // since the last change.
+        let prev_rc_mode = std::mem::replace(&mut self.rc_mode, rc_mode);
+        self.prev_rc_mode.get_or_insert(prev_rc_mode);
+
+        // The old references are gone, so the next frame has to be an IDR.
+        self.structure.restart();
+        Ok(())
+    }
+
+    /// Sets the static HDR metadata, which is sent as SEI messages along with
+    /// the headers on every keyframe.
+    pub fn set_hdr_metadata(&mut self, metadata: Option<HdrMetadata>) {
+        if self.hdr {
+            self.hdr_sei = metadata.map(|m| hdr_sei(&m));
+        }
+    }
+}
+
+/// Builds the VPS, SPS and PPS for the stream, and passes the resulting
+/// session parameters to `f`. The parameter sets borrow from a number of
+/// structs, so they can't be returned.
+fn with_parameter_sets<T>(
+    caps: &H265EncodeCapabilities,
+    params: VideoStreamParams,
+    profile_idc: vk::native::StdVideoH265ProfileIdc,
+    structure: &HierarchicalP,
+    f: impl FnOnce(&mut vk::VideoEncodeH265SessionParametersCreateInfoEXT) -> anyhow::Result<T>,
+) -> anyhow::Result<T> {
+    const CTB_SIZES: [(vk::VideoEncodeH265CtbSizeFlagsEXT, usize); 3] = [
+        (vk::VideoEncodeH265CtbSizeFlagsEXT::TYPE_16, 16),
+        (vk::VideoEncodeH265CtbSizeFlagsEXT::TYPE_32, 32),
+        (vk::VideoEncodeH265CtbSizeFlagsEXT::TYPE_64, 64),
+    ];
+
+    let min_ctb = CTB_SIZES
+        .iter()
+        .filter(|(flag, _)| caps.h265_caps.ctb_sizes.contains(*flag))
+        .map(|(_, size)| *size)
+        .min()
+        .expect("no ctb size found");
+
+    let max_ctb = CTB_SIZES
+        .iter()
+        .filter(|(flag, _)| caps.h265_caps.ctb_sizes.contains(*flag))
+        .map(|(_, size)| *size)
+        .max()
+        .expect("no ctb size found");
+
+    const TBS_SIZES: [(vk::VideoEncodeH265TransformBlockSizeFlagsEXT, usize); 4] = [
+        (vk::VideoEncodeH265TransformBlockSizeFlagsEXT::TYPE_4, 4),
+        (vk::VideoEncodeH265TransformBlockSizeFlagsEXT::TYPE_8, 8),
+        (vk::VideoEncodeH265TransformBlockSizeFlagsEXT::TYPE_16, 16),
+        (vk::VideoEncodeH265TransformBlockSizeFlagsEXT::TYPE_32, 32),
+    ];
+
+    let min_tbs = TBS_SIZES
+        .iter()
+        .filter(|(flag, _)| caps.h265_caps.transform_block_sizes.contains(*flag))
+        .map(|(_, size)| *size)
+        .min()
+        .expect("no tbs size found");
+
+    let max_tbs = TBS_SIZES
+        .iter()
+        .filter(|(flag, _)| caps.h265_caps.transform_block_sizes.contains(*flag))
+        .map(|(_, size)| *size)
+        .max()
+        .expect("no tbs size found");
+
+    let aligned_width = params
+        .width
+        .next_multiple_of(caps.encode_caps.encode_input_picture_granularity.width);
+    let aligned_height = params
+        .height
+        .next_multiple_of(caps.encode_caps.encode_input_picture_granularity.height);
+
+    trace!(
+        min_ctb,
+        max_ctb,
+        min_tbs,
+        max_tbs,
+        aligned_width,
+        aligned_height,
+        "block sizes",
+    );
+
+    let crop_right = (aligned_width - params.width) / 2;
+    let crop_bottom = (aligned_height - params.height) / 2;
+
+    trace!("crop right: {}, bottom: {}", crop_right, crop_bottom);
+
+    let (colour_primaries, transfer_characteristics, matrix_coeffs) = match params.profile {
+        VideoProfile::Hd => (1, 1, 1),
+        VideoProfile::Hdr10 => (9, 16, 9),
+    };
+
+    let mut vui = vk::native::StdVideoH265SequenceParameterSetVui {
+        colour_primaries,
+        transfer_characteristics,
+        matrix_coeffs,
+        // Unspecified.
+        video_format: 5,
+        ..unsafe { std::mem::zeroed() }
+    };
+
+    vui.flags.set_video_signal_type_present_flag(1);
+    vui.flags.set_colour_description_present_flag(1);
+    vui.flags.set_video_full_range_flag(0); // Narrow range.
+
+    let ptl = vk::native::StdVideoH265ProfileTierLevel {
+        general_profile_idc: profile_idc,
+        general_level_idc: LEVEL_IDC,
+        ..unsafe { std::mem::zeroed() }
+    };
+
+    // ptl.flags.set_general_progressive_source_flag(1);
+    // ptl.flags.set_general_interlaced_source_flag(0);
+
+    let layers_minus_1 = (structure.layers - 1) as u8;
+    let mut pbm: vk::native::StdVideoH265DecPicBufMgr = unsafe { std::mem::zeroed() };
+    pbm.max_dec_pic_buffering_minus1[layers_minus_1 as usize] =
+        (structure.required_dpb_size() - 1) as u8;
+    // No picture reordering.
+    pbm.max_num_reorder_pics[layers_minus_1 as usize] = 0;
+    pbm.max_latency_increase_plus1[layers_minus_1 as usize] = 0;
+
+    let mut vps = vk::native::StdVideoH265VideoParameterSet {
+        vps_max_sub_layers_minus1: layers_minus_1,
+        pDecPicBufMgr: &pbm,
+        pHrdParameters: std::ptr::null(),
+        pProfileTierLevel: &ptl,
+        ..unsafe { std::mem::zeroed() }
+    };
+
+    vps.flags.set_vps_sub_layer_ordering_info_present_flag(1);
+    vps.flags.set_vps_temporal_id_nesting_flag(1);
+
+    let min_cb = 8_u8;
+    let max_cb = max_ctb;
+
+    let max_transform_hierarchy_depth = (max_ctb.ilog2() - min_tbs.ilog2()) as u8;
+
+    let bit_depth = match params.profile {
+        VideoProfile::Hd => 8,
+        VideoProfile::Hdr10 => 10,
+    };
+
+    let mut sps = vk::native::StdVideoH265SequenceParameterSet {
+        chroma_format_idc:
+            vk::native::StdVideoH265ChromaFormatIdc_STD_VIDEO_H265_CHROMA_FORMAT_IDC_420,
+        pic_width_in_luma_samples: aligned_width,
+        pic_height_in_luma_samples: aligned_height,
+        sps_max_sub_layers_minus1: layers_minus_1,
+        bit_depth_luma_minus8: bit_depth - 8,
+        bit_depth_chroma_minus8: bit_depth - 8,
+        log2_max_pic_order_cnt_lsb_minus4: 4,
+        log2_min_luma_coding_block_size_minus3: (min_cb.ilog2() - 3) as u8,
+        log2_diff_max_min_luma_coding_block_size: (max_cb.ilog2() - min_cb.ilog2()) as u8,
+        log2_min_luma_transform_block_size_minus2: (min_tbs.ilog2() - 2) as u8,
+        log2_diff_max_min_luma_transform_block_size: (max_tbs.ilog2() - min_tbs.ilog2()) as u8,
+        max_transform_hierarchy_depth_inter: max_transform_hierarchy_depth,
+        max_transform_hierarchy_depth_intra: max_transform_hierarchy_depth,
+        conf_win_right_offset: crop_right,
+        conf_win_bottom_offset: crop_bottom,
+        pProfileTierLevel: &ptl,
+        pDecPicBufMgr: &pbm,
+        pSequenceParameterSetVui: &vui,
+        ..unsafe { std::mem::zeroed() }
+    };
+
+    sps.flags.set_conformance_window_flag(1);
+    sps.flags.set_vui_parameters_present_flag(1);
+    sps.flags.set_sps_temporal_id_nesting_flag(1);
+    sps.flags.set_sps_sub_layer_ordering_info_present_flag(1);
+
+    if caps
+        .h265_caps
+        .std_syntax_flags
+        .contains(vk::VideoEncodeH265StdFlagsEXT::SAMPLE_ADAPTIVE_OFFSET_ENABLED_FLAG_SET)
+    {
+        sps.flags.set_sample_adaptive_offset_enabled_flag(1);
+    }
+
+    if caps
+        .h265_caps
+        .std_syntax_flags
+        .contains(vk::VideoEncodeH265StdFlagsEXT::TRANSFORM_SKIP_ENABLED_FLAG_SET)
+    {
+        sps.flags.set_transform_skip_context_enabled_flag(1);
+    }
+
+    let pps = vk::native::StdVideoH265PictureParameterSet {
+        ..unsafe { std::mem::zeroed() }
+    };
+
+    let sps = [sps];
+    let pps = [pps];
+    let vps = [vps];
+
+    let h265_add_info = vk::VideoEncodeH265SessionParametersAddInfoEXT::default()
+        .std_vp_ss(&vps)
+        .std_sp_ss(&sps)
+        .std_pp_ss(&pps);
+    let mut session_params = vk::VideoEncodeH265SessionParametersCreateInfoEXT::default()
+        .parameters_add_info(&h265_add_info)
+        .max_std_vps_count(1)
+        .max_std_pps_count(1)
+        .max_std_sps_count(1);
+
+    f(&mut session_params)
+}
+
+/// Fetches the encoded VPS, SPS and PPS, to be inserted before keyframes.
+fn encoded_headers(
+    vk: &VkContext,
+    session_params: vk::VideoSessionParametersKHR,
+) -> anyhow::Result<Bytes> {
+    // Generate encoded stream headers.
+    let (_, encode_loader) = vk.video_apis.as_ref().unwrap();
+    let headers = unsafe {
+        let mut h265_get_info = vk::VideoEncodeH265SessionParametersGetInfoEXT::default()
+            .write_std_vps(true)
+            .write_std_sps(true)
+            .write_std_pps(true);
+
+        let mut h265_feedback_info = vk::VideoEncodeH265SessionParametersFeedbackInfoEXT::default();
+
+        let mut feedback_info = vk::VideoEncodeSessionParametersFeedbackInfoKHR::default()
+            .push_next(&mut h265_feedback_info);
+
+        let get_info = vk::VideoEncodeSessionParametersGetInfoKHR::default()
+            .video_session_parameters(session_params)
+            .push_next(&mut h265_get_info);
+
+        encode_loader
+            .get_encoded_video_session_parameters(&get_info, &mut feedback_info)
+            .context("vkGetEncodedVideoSessionParametersKHR")?
+    };
+
+    if headers.is_empty() {
+        bail!("failed to generate sps/pps/vps");
+    } else {
+        trace!("generated {} bytes of h265 headers", headers.len());
+    }
+
+    Ok(Bytes::copy_from_slice(&headers))
+}
+
+fn select_rc_mode(
+    caps: &H265EncodeCapabilities,
+    params: VideoStreamParams,
+    structure: &HierarchicalP,
+) -> RateControlMode {
+    rate_control::select_rc_mode(
+        params,
+        caps.encode_caps.rate_control_modes,
+        caps.h265_caps.min_qp.try_into().unwrap_or(17),
+        caps.h265_caps.max_qp.try_into().unwrap_or(50),
+        structure,
+    )
+}
+
+/// Generates a prefix SEI NAL containing mastering display colour volume and
+/// content light level information messages.
+fn hdr_sei(metadata: &HdrMetadata) -> Bytes {
+    let mut rbsp = BytesMut::new();
+
+    // mastering_display_colour_volume, with primaries in G, B, R order and
+    // coordinates in increments of 0.00002.
+    rbsp.put_u8(137);
+    rbsp.put_u8(24);
+    let [r, g, b] = metadata.mastering_primaries;
+    for (x, y) in [g, b, r, metadata.mastering_white_point] {
+        rbsp.put_u16((x / 20).min(50000) as u16);
+        rbsp.put_u16((y / 20).min(50000) as u16);
+    }
+
+    // Both luminance values are in increments of 0.0001 cd/m².
+    rbsp.put_u32(metadata.max_mastering_luminance.saturating_mul(10000));
+    rbsp.put_u32(metadata.min_mastering_luminance);
+
+    // content_light_level_info.
+    rbsp.put_u8(144);
+    rbsp.put_u8(4);
+    rbsp.put_u16(metadata.max_cll.min(u16::MAX as u32) as u16);
+    rbsp.put_u16(metadata.max_fall.min(u16::MAX as u32) as u16);
+
+    // rbsp_trailing_bits.
+    rbsp.put_u8(0x80);
+
+    // Start code and NAL header, with nal_unit_type = PREFIX_SEI_NUT (39) and
+    // nuh_temporal_id_plus1 = 1.
+    let mut nal = BytesMut::with_capacity(rbsp.len() + 8);
+    nal.put_slice(&[0, 0, 0, 1, 39 << 1, 1]);
+
+    // Insert emulation prevention bytes.
+    let mut zeroes = 0;
+    for b in rbsp {
+        if zeroes >= 2 && b <= 3 {
+            nal.put_u8(3);
+            zeroes = 0;
+        }
+
+        nal.put_u8(b);
+        zeroes = if b == 0 { zeroes + 1 } else { 0 };
+    }
+
+    nal.freeze()
+}
+
+/// Builds the rate control info for the given mode, and passes it to `f`. The
+/// info borrows from a chain of structs, so it can't be returned.
+fn with_rate_control<T>(
+    rc_mode: &RateControlMode,
+    structure: &HierarchicalP,
+    framerate: u32,
+    f: impl FnOnce(&mut vk::VideoEncodeRateControlInfoKHR) -> T,
+) -> T {
+    let pattern = if structure.layers > 1 {
+        vk::VideoEncodeH265RateControlFlagsEXT::TEMPORAL_SUB_LAYER_PATTERN_DYADIC
+    } else {
+        vk::VideoEncodeH265RateControlFlagsEXT::REFERENCE_PATTERN_FLAT
+    };
+
+    let mut h265_rc_layers = Vec::new();
+    let mut rc_layers = Vec::new();
+
+    if let RateControlMode::Vbr(vbr) = *rc_mode {
+        let layer_settings = (0..structure.layers)
+            .map(|layer| vbr.layer(layer))
+            .collect::<Vec<_>>();
+
+        for settings in &layer_settings {
+            h265_rc_layers.push(
+                vk::VideoEncodeH265RateControlLayerInfoEXT::default()
+                    .use_min_qp(true)
+                    .use_max_qp(true)
+                    .min_qp(vk::VideoEncodeH265QpEXT {
+                        qp_i: settings.min_qp as i32,
+                        qp_p: settings.min_qp as i32,
+                        qp_b: settings.min_qp as i32,
+                    })
+                    .max_qp(vk::VideoEncodeH265QpEXT {
+                        qp_i: settings.max_qp as i32,
+                        qp_p: settings.max_qp as i32,
+                        qp_b: settings.max_qp as i32,
+                    }),
+            );
+        }
+
+        for (layer, (settings, h265_rc_layer)) in layer_settings
+            .iter()
+            .zip(h265_rc_layers.iter_mut())
+            .enumerate()
+        {
+            let (fps_numerator, fps_denominator) =
+                structure.layer_framerate(layer as u32, framerate);
+
+            rc_layers.push(
+                vk::VideoEncodeRateControlLayerInfoKHR::default()
+                    .max_bitrate(settings.peak_bitrate)
+                    .average_bitrate(settings.average_bitrate)
+                    .frame_rate_numerator(fps_numerator)
+                    .frame_rate_denominator(fps_denominator)
+                    .push_next(h265_rc_layer),
+            );
+        }
+    }
+
+    let mut h265_rc_info = vk::VideoEncodeH265RateControlInfoEXT::default()
+        .gop_frame_count(structure.gop_size)
+        .idr_period(structure.gop_size)
+        .consecutive_b_frame_count(0)
+        .sub_layer_count(rc_layers.len() as u32)
+        .flags(vk::VideoEncodeH265RateControlFlagsEXT::REGULAR_GOP | pattern);
+
+    let vbv_size = match *rc_mode {
+        RateControlMode::Vbr(settings) => settings.vbv_size_ms,
+        _ => 0,
+    };
+
+    let mut rc_info = vk::VideoEncodeRateControlInfoKHR::default()
+        .rate_control_mode(rc_mode.as_vk_flags())
+        .virtual_buffer_size_in_ms(vbv_size);
+
+    if !rc_layers.is_empty() {
+        rc_info = rc_info.layers(&rc_layers);
+    }
+
+    // Doesn't have a push_next method, because we're supposed to call it on
+    // the parent struct.
+    rc_info.p_next = <*mut _>::cast(&mut h265_rc_info);
+
+    f(&mut rc_info)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_hdr_sei() {
+        let sei = hdr_sei(&HdrMetadata {
+            mastering_primaries: [(680_000, 320_000), (265_000, 690_000), (150_000, 60_000)],
+            mastering_white_point: (312_700, 329_000),
+            min_mastering_luminance: 1,
+            max_mastering_luminance: 1000,
+            max_cll: 0,
+            max_fall: 0,
+        });
+
+        assert_eq!(&sei[..6], &[0, 0, 0, 1, 0x4e, 0x01]);
+        assert_eq!(&sei[6..8], &[137, 24]);
+
+        // G primary, x = 0.265.
+        assert_eq!(&sei[8..10], &13250_u16.to_be_bytes());
+
+        // The min luminance and cll require emulation prevention.
+        assert_eq!(
+            &sei[sei.len() - 13..],
+            &[0, 0, 3, 0, 1, 144, 4, 0, 0, 3, 0, 0, 0x80]
+        );
+    }
 }
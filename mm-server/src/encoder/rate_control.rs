@@ -5,7 +5,7 @@
 use ash::vk;
 use tracing::warn;
 
-use crate::session::control::VideoStreamParams;
+use crate::session::control::{ContentType, VideoStreamParams};
 
 // Bitrate is defined here in terms of 1080p, and scaled nonlinearly to the
 // target resolution. Values are indexed by quality preset. Values 7/8/9 are
@@ -16,6 +16,14 @@ const BASELINE_PEAK_BITRATE_MBPS: [f32; 10] =
 const BASELINE_DIMS: f32 = 1920.0 * 1080.0;
 const VBV_SIZE: u32 = 2500;
 
+// Games get a smaller buffer, to keep latency down, and video a larger one,
+// to smooth out quality.
+const GAME_VBV_SIZE: u32 = 1000;
+const VIDEO_VBV_SIZE: u32 = 5000;
+
+// Photos are mostly static, so we can afford to spend more bits on detail.
+const PHOTO_QP_OFFSET: u32 = 4;
+
 #[derive(Debug, Clone)]
 pub enum RateControlMode {
     ConstantQp(CascadingQp),
@@ -81,7 +89,7 @@ impl LayeredVbr {
 
 pub fn select_rc_mode(
     params: VideoStreamParams,
-    caps: &vk::VideoEncodeCapabilitiesKHR,
+    supported_modes: vk::VideoEncodeRateControlModeFlagsKHR,
     min_qp: u32,
     max_qp: u32,
     structure: &super::gop_structure::HierarchicalP,
@@ -89,16 +97,23 @@ pub fn select_rc_mode(
     assert!(params.preset <= 9);
 
     let min_qp = 17.max(min_qp);
-    let target_qp = 40 - (2 * params.preset); // 22 - 40;
+    let mut target_qp = 40 - (2 * params.preset); // 22 - 40;
+    if params.content_type == ContentType::Photo {
+        target_qp -= PHOTO_QP_OFFSET;
+    }
+
+    let supports_crf = supported_modes.contains(vk::VideoEncodeRateControlModeFlagsKHR::DISABLED);
+    let supports_vbr = supported_modes.contains(vk::VideoEncodeRateControlModeFlagsKHR::VBR);
 
-    let supports_crf = caps
-        .rate_control_modes
-        .contains(vk::VideoEncodeRateControlModeFlagsKHR::DISABLED);
-    let supports_vbr = caps
-        .rate_control_modes
-        .contains(vk::VideoEncodeRateControlModeFlagsKHR::VBR);
+    // Constant QP is spiky, which isn't a problem for (mostly static) photos,
+    // but is for video.
+    let prefer_crf = match params.content_type {
+        ContentType::Photo => true,
+        ContentType::Video => false,
+        _ => params.preset >= 7,
+    };
 
-    if params.preset >= 7 && supports_crf {
+    if prefer_crf && supports_crf {
         // Presets 7/8/9 use a very low constant QP.
         RateControlMode::ConstantQp(CascadingQp {
             target: target_qp.clamp(min_qp, max_qp),
@@ -115,8 +130,14 @@ pub fn select_rc_mode(
         let peak_bitrate =
             (BASELINE_PEAK_BITRATE_MBPS[params.preset as usize] * MBPS * scale).round() as u64;
 
+        let vbv_size_ms = match params.content_type {
+            ContentType::Game => GAME_VBV_SIZE,
+            ContentType::Video => VIDEO_VBV_SIZE,
+            _ => VBV_SIZE,
+        };
+
         RateControlMode::Vbr(LayeredVbr {
-            vbv_size_ms: VBV_SIZE,
+            vbv_size_ms,
             base: VbrSettings {
                 average_bitrate,
                 peak_bitrate,
@@ -143,3 +164,76 @@ fn layer_qp(target_qp: u32, layer: u32) -> u32 {
     //   22, 27, 29, 31...
     target_qp + (3 * layer.min(1)) + (layer * 2)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{codec::VideoCodec, color::VideoProfile, encoder::gop_structure::HierarchicalP};
+
+    fn select(preset: u32, content_type: ContentType) -> RateControlMode {
+        let params = VideoStreamParams {
+            width: 1920,
+            height: 1080,
+            codec: VideoCodec::H265,
+            preset,
+            profile: VideoProfile::Hd,
+            content_type,
+        };
+
+        let modes = vk::VideoEncodeRateControlModeFlagsKHR::DISABLED
+            | vk::VideoEncodeRateControlModeFlagsKHR::VBR;
+        select_rc_mode(params, modes, 0, 51, &HierarchicalP::new(1, 256))
+    }
+
+    #[test]
+    fn test_photo_qp_offset() {
+        let RateControlMode::ConstantQp(qp) = select(8, ContentType::None) else {
+            panic!("expected constant QP");
+        };
+        assert_eq!(qp.target, 24);
+
+        let RateControlMode::ConstantQp(qp) = select(8, ContentType::Photo) else {
+            panic!("expected constant QP");
+        };
+        assert_eq!(qp.target, 24 - PHOTO_QP_OFFSET);
+    }
+
+    #[test]
+    fn test_prefer_crf() {
+        assert!(matches!(
+            select(4, ContentType::None),
+            RateControlMode::Vbr(_)
+        ));
+        assert!(matches!(
+            select(4, ContentType::Game),
+            RateControlMode::Vbr(_)
+        ));
+        assert!(matches!(
+            select(4, ContentType::Photo),
+            RateControlMode::ConstantQp(_)
+        ));
+
+        assert!(matches!(
+            select(8, ContentType::None),
+            RateControlMode::ConstantQp(_)
+        ));
+        assert!(matches!(
+            select(8, ContentType::Video),
+            RateControlMode::Vbr(_)
+        ));
+    }
+
+    #[test]
+    fn test_vbv_size() {
+        for (content_type, expected) in [
+            (ContentType::None, VBV_SIZE),
+            (ContentType::Game, GAME_VBV_SIZE),
+            (ContentType::Video, VIDEO_VBV_SIZE),
+        ] {
+            let RateControlMode::Vbr(vbr) = select(4, content_type) else {
+                panic!("expected VBR for {content_type:?}");
+            };
+            assert_eq!(vbr.vbv_size_ms, expected, "{content_type:?}");
+        }
+    }
+}
@@ -77,6 +77,8 @@ impl std::fmt::Debug for LayerStats {
 
 impl EncodeStats {
     pub fn record_frame_size(&self, is_keyframe: bool, layer: u32, len: usize) {
+        crate::metrics::record_video_frame(is_keyframe, layer, len);
+
         let mut inner = self.inner.lock();
 
         inner.stream_stats.record_frame_size(len);
@@ -94,6 +96,10 @@ impl EncodeStats {
             inner.layer_stats[layer].record_frame_size(len);
         }
     }
+
+    pub fn record_encode_latency(&self, dur: time::Duration) {
+        crate::metrics::record_encode_latency(dur);
+    }
 }
 
 impl std::fmt::Debug for EncodeStats {
@@ -2,11 +2,13 @@
 //
 // SPDX-License-Identifier: BUSL-1.1
 
+mod admin;
 mod codec;
 mod color;
 mod config;
 mod container;
 mod encoder;
+mod metrics;
 mod pixel_scale;
 mod server;
 mod session;
@@ -24,7 +26,7 @@ use std::{
 use anyhow::{bail, Context, Result};
 use clap::Parser;
 use parking_lot::Mutex;
-use tracing::{debug, info, warn};
+use tracing::{debug, error, info, warn};
 use tracing_subscriber::{util::SubscriberInitExt, EnvFilter, Layer};
 
 #[derive(Debug, Parser)]
@@ -91,8 +93,12 @@ fn main() -> Result<()> {
     warn!("tracing enabled!");
 
     // Load config.
-    let mut cfg = config::Config::new(args.config.as_ref(), &args.include_apps)
-        .context("failed to read config")?;
+    let cfg_source = config::ConfigSource {
+        path: args.config.clone(),
+        includes: args.include_apps.clone(),
+    };
+
+    let mut cfg = cfg_source.load().context("failed to read config")?;
 
     let vk = Arc::new(vulkan::VkContext::new(cfg!(debug_assertions))?);
 
@@ -118,8 +124,42 @@ fn main() -> Result<()> {
         std::net::UdpSocket::bind(&cfg.server.bind).context("binding server socket")?
     };
 
-    let state = Arc::new(Mutex::new(state::ServerState::new(vk, cfg.clone())));
-    let mut srv = server::Server::new(sock, cfg.server.clone(), state)?;
+    let state = Arc::new(Mutex::new(state::ServerState::new(
+        vk,
+        cfg.clone(),
+        cfg_source,
+    )?));
+
+    // Another server may already be using the socket, and that shouldn't stop
+    // us from starting.
+    let admin_socket =
+        cfg.server
+            .admin_socket
+            .as_ref()
+            .filter(|path| match admin::spawn(path, state.clone()) {
+                Ok(()) => true,
+                Err(e) => {
+                    warn!("failed to start admin socket: {:#}", e);
+                    false
+                }
+            });
+
+    if let Some(bind) = &cfg.server.metrics_bind {
+        metrics::spawn(bind, state.clone()).context("failed to start metrics listener")?;
+    }
+
+    let mut srv = server::Server::new(sock, cfg.server.clone(), state.clone())?;
+
+    let reload_state = state.clone();
+    let mut signals = signal_hook::iterator::Signals::new([signal_hook::consts::SIGHUP])?;
+    std::thread::spawn(move || {
+        for _ in signals.forever() {
+            info!("received SIGHUP, reloading config");
+            if let Err(e) = state::reload_config(&reload_state) {
+                error!("failed to reload config: {:#}", e);
+            }
+        }
+    });
 
     let closer = srv.closer();
     ctrlc::set_handler(move || {
@@ -130,6 +170,10 @@ fn main() -> Result<()> {
     info!("listening on {:?}", srv.local_addr()?);
     srv.run().context("server exited")?;
 
+    if let Some(path) = admin_socket {
+        let _ = std::fs::remove_file(path);
+    }
+
     if let Some(dir) = &bug_report_dir {
         save_vulkaninfo(dir);
         info!("bug report files saved to: {:?}", dir);
@@ -0,0 +1,313 @@
+// Copyright 2024 Colin Marc <hi@colinmarc.com>
+//
+// SPDX-License-Identifier: BUSL-1.1
+
+//! Prometheus metrics, served over plain HTTP if `metrics_bind` is set.
+
+use std::{
+    io::{BufRead as _, BufReader, Write as _},
+    net::{SocketAddr, TcpListener, TcpStream},
+    time,
+};
+
+use anyhow::Context as _;
+use lazy_static::lazy_static;
+use mm_protocol::error::ErrorCode;
+use prometheus::{
+    exponential_buckets, register_histogram, register_histogram_vec, register_int_counter,
+    register_int_counter_vec, register_int_gauge, register_int_gauge_vec, Encoder as _, Histogram,
+    HistogramVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec, TextEncoder,
+};
+use tracing::{debug, error, info};
+
+use crate::state::SharedState;
+
+// Label values for VIDEO_FRAME_SIZE, by hierarchical layer. The encoder uses
+// at most five layers.
+const LAYER_LABELS: [&str; 5] = ["0", "1", "2", "3", "4"];
+
+lazy_static! {
+    static ref ACTIVE_SESSIONS: IntGauge =
+        register_int_gauge!("mm_active_sessions", "Number of running sessions.").unwrap();
+    static ref ACTIVE_ATTACHMENTS: IntGauge = register_int_gauge!(
+        "mm_active_attachments",
+        "Number of clients attached to sessions."
+    )
+    .unwrap();
+    static ref ATTACHMENT_BITRATE: IntGaugeVec = register_int_gauge_vec!(
+        "mm_attachment_bitrate_kbps",
+        "Current bitrate for each attachment, averaged over recent frames.",
+        &["session_id", "attachment_id", "app"]
+    )
+    .unwrap();
+    static ref ATTACHMENT_BYTES_SENT: IntGaugeVec = register_int_gauge_vec!(
+        "mm_attachment_sent_bytes",
+        "Total audio and video bytes sent to each attachment.",
+        &["session_id", "attachment_id", "app"]
+    )
+    .unwrap();
+    static ref VIDEO_FRAME_SIZE: HistogramVec = register_histogram_vec!(
+        "mm_video_frame_size_bytes",
+        "Size of encoded video frames, by hierarchical layer. Keyframes use the layer \"idr\".",
+        &["layer"],
+        exponential_buckets(1024.0, 2.0, 12).unwrap()
+    )
+    .unwrap();
+    static ref VIDEO_KEYFRAMES: IntCounter =
+        register_int_counter!("mm_video_keyframes_total", "Number of keyframes encoded.").unwrap();
+    static ref ENCODE_LATENCY: Histogram = register_histogram!(
+        "mm_video_encode_latency_seconds",
+        "Time between submitting a frame to the encoder and the encoded packet being ready.",
+        exponential_buckets(0.0005, 2.0, 10).unwrap()
+    )
+    .unwrap();
+    static ref QUIC_RTT: IntGaugeVec = register_int_gauge_vec!(
+        "mm_quic_rtt_microseconds",
+        "Estimated round-trip time for each client connection.",
+        &["remote_addr"]
+    )
+    .unwrap();
+    static ref QUIC_CWND: IntGaugeVec = register_int_gauge_vec!(
+        "mm_quic_cwnd_bytes",
+        "Congestion window for each client connection.",
+        &["remote_addr"]
+    )
+    .unwrap();
+    static ref QUIC_PACKETS_LOST: IntCounterVec = register_int_counter_vec!(
+        "mm_quic_lost_packets_total",
+        "Packets lost on each client connection.",
+        &["remote_addr"]
+    )
+    .unwrap();
+    static ref AUDIO_UNDERRUNS: IntCounter = register_int_counter!(
+        "mm_audio_underruns_total",
+        "Number of times an application audio stream ran out of buffered data."
+    )
+    .unwrap();
+    static ref SESSION_LAUNCH_FAILURES: IntCounterVec = register_int_counter_vec!(
+        "mm_session_launch_failures_total",
+        "Failed session launches, by error code.",
+        &["code"]
+    )
+    .unwrap();
+}
+
+pub fn record_launch_failure(code: ErrorCode) {
+    SESSION_LAUNCH_FAILURES
+        .with_label_values(&[code.as_str_name()])
+        .inc();
+}
+
+pub fn record_video_frame(is_keyframe: bool, layer: u32, len: usize) {
+    if is_keyframe {
+        VIDEO_KEYFRAMES.inc();
+        VIDEO_FRAME_SIZE
+            .with_label_values(&["idr"])
+            .observe(len as f64);
+    } else {
+        let label = LAYER_LABELS.get(layer as usize).copied().unwrap_or("other");
+        VIDEO_FRAME_SIZE
+            .with_label_values(&[label])
+            .observe(len as f64);
+    }
+}
+
+pub fn record_encode_latency(dur: time::Duration) {
+    ENCODE_LATENCY.observe(dur.as_secs_f64());
+}
+
+pub fn record_audio_underrun() {
+    AUDIO_UNDERRUNS.inc();
+}
+
+pub fn record_connection_stats(remote_addr: SocketAddr, conn: &quiche::Connection) {
+    let addr = remote_addr.to_string();
+    if let Some(path) = conn.path_stats().next() {
+        QUIC_RTT
+            .with_label_values(&[&addr])
+            .set(path.rtt.as_micros() as i64);
+        QUIC_CWND.with_label_values(&[&addr]).set(path.cwnd as i64);
+    }
+
+    // Counters can only go up, so add the difference.
+    let lost = QUIC_PACKETS_LOST.with_label_values(&[&addr]);
+    lost.inc_by((conn.stats().lost as u64).saturating_sub(lost.get()));
+}
+
+pub fn remove_connection(remote_addr: SocketAddr) {
+    let addr = remote_addr.to_string();
+    let _ = QUIC_RTT.remove_label_values(&[&addr]);
+    let _ = QUIC_CWND.remove_label_values(&[&addr]);
+    let _ = QUIC_PACKETS_LOST.remove_label_values(&[&addr]);
+}
+
+/// Starts serving metrics over HTTP in the background.
+pub fn spawn(bind: &str, state: SharedState) -> anyhow::Result<()> {
+    let listener = TcpListener::bind(bind).context("binding metrics socket")?;
+    info!("serving metrics on {:?}", listener.local_addr()?);
+
+    std::thread::Builder::new()
+        .name("metrics".to_string())
+        .spawn(move || {
+            for conn in listener.incoming() {
+                let conn = match conn {
+                    Ok(conn) => conn,
+                    Err(e) => {
+                        debug!("failed to accept metrics connection: {:#}", e);
+                        continue;
+                    }
+                };
+
+                // Handle each request on its own thread, so that a slow
+                // scraper can't hold up the others.
+                let state = state.clone();
+                std::thread::spawn(move || {
+                    if let Err(e) = handle_conn(conn, || gather(&state)) {
+                        debug!("metrics request failed: {:#}", e);
+                    }
+                });
+            }
+        })?;
+
+    Ok(())
+}
+
+fn handle_conn(
+    mut conn: TcpStream,
+    gather: impl FnOnce() -> anyhow::Result<Vec<u8>>,
+) -> anyhow::Result<()> {
+    conn.set_read_timeout(Some(time::Duration::from_secs(5)))?;
+
+    // We only care about the request line, but we have to read the headers.
+    let mut request_line = String::new();
+    let mut r = BufReader::new(conn.try_clone()?);
+    r.read_line(&mut request_line)?;
+    loop {
+        let mut line = String::new();
+        if r.read_line(&mut line)? == 0 || line.trim().is_empty() {
+            break;
+        }
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let (status, content_type, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => match gather() {
+            Ok(body) => ("200 OK", prometheus::TEXT_FORMAT, body),
+            Err(e) => {
+                error!("failed to encode metrics: {:#}", e);
+                ("500 Internal Server Error", "text/plain", Vec::new())
+            }
+        },
+        _ => ("404 Not Found", "text/plain", Vec::new()),
+    };
+
+    write!(
+        conn,
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: \
+         close\r\n\r\n",
+        body.len()
+    )?;
+    conn.write_all(&body)?;
+
+    Ok(())
+}
+
+/// Updates the per-session metrics, then renders everything in the text
+/// exposition format.
+fn gather(state: &SharedState) -> anyhow::Result<Vec<u8>> {
+    // Session metrics are collected at scrape time, so that ended sessions
+    // disappear.
+    ATTACHMENT_BITRATE.reset();
+    ATTACHMENT_BYTES_SENT.reset();
+
+    let guard = state.lock();
+    let mut attachments = 0;
+    for session in guard.sessions.values() {
+        for info in session.attachments() {
+            attachments += 1;
+
+            let session_id = session.id.to_string();
+            let attachment_id = info.id.to_string();
+            let labels = [
+                session_id.as_str(),
+                attachment_id.as_str(),
+                session.application_id.as_str(),
+            ];
+
+            ATTACHMENT_BITRATE
+                .with_label_values(&labels)
+                .set((info.bitrate_mbps() * 1024.0) as i64);
+            ATTACHMENT_BYTES_SENT
+                .with_label_values(&labels)
+                .set(info.bytes_sent() as i64);
+        }
+    }
+
+    ACTIVE_SESSIONS.set(guard.sessions.len() as i64);
+    ACTIVE_ATTACHMENTS.set(attachments);
+    drop(guard);
+
+    render()
+}
+
+fn render() -> anyhow::Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    TextEncoder::new().encode(&prometheus::gather(), &mut buf)?;
+    Ok(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read as _, Write as _};
+
+    use super::*;
+
+    #[test]
+    fn render_exposition_format() {
+        record_video_frame(true, 0, 1500);
+        record_video_frame(false, 2, 1500);
+        record_video_frame(false, 7, 1500);
+        record_launch_failure(ErrorCode::ErrorSessionLaunchFailed);
+
+        let text = String::from_utf8(render().unwrap()).unwrap();
+
+        assert!(text.contains("# TYPE mm_video_keyframes_total counter\n"));
+        assert!(text.contains("# TYPE mm_video_frame_size_bytes histogram\n"));
+        assert!(text.contains("mm_video_frame_size_bytes_bucket{layer=\"idr\",le=\"2048\"}"));
+        assert!(text.contains("mm_video_frame_size_bytes_bucket{layer=\"2\",le=\"1024\"} 0\n"));
+        assert!(text.contains("mm_video_frame_size_bytes_count{layer=\"other\"}"));
+        assert!(
+            text.contains("mm_session_launch_failures_total{code=\"ERROR_SESSION_LAUNCH_FAILED\"}")
+        );
+    }
+
+    fn request(req: &str, gather: impl FnOnce() -> anyhow::Result<Vec<u8>>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        client.write_all(req.as_bytes()).unwrap();
+
+        let (conn, _) = listener.accept().unwrap();
+        handle_conn(conn, gather).unwrap();
+
+        let mut resp = String::new();
+        client.read_to_string(&mut resp).unwrap();
+        resp
+    }
+
+    #[test]
+    fn serve_metrics() {
+        let resp = request("GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n", || {
+            Ok(b"mm_foo 1\n".to_vec())
+        });
+
+        assert!(resp.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(resp.contains("Content-Length: 9\r\n"));
+        assert!(resp.ends_with("\r\n\r\nmm_foo 1\n"));
+    }
+
+    #[test]
+    fn serve_not_found() {
+        let resp = request("GET / HTTP/1.1\r\n\r\n", || unreachable!());
+        assert!(resp.starts_with("HTTP/1.1 404 Not Found\r\n"));
+    }
+}
@@ -33,6 +33,8 @@ use crate::waking_sender::WakingSender;
 
 const MAX_QUIC_PACKET_SIZE: usize = 1350;
 
+const METRICS_INTERVAL: time::Duration = time::Duration::from_secs(1);
+
 const SOCKET: mio::Token = mio::Token(0);
 const WAKER: mio::Token = mio::Token(1);
 
@@ -56,6 +58,7 @@ pub struct Server {
 
     _mdns: Option<mdns::MdnsService>,
     shutting_down: bool,
+    last_metrics_update: time::Instant,
 }
 
 struct Outgoing {
@@ -187,6 +190,7 @@ impl Server {
 
             _mdns: mdns,
             shutting_down: false,
+            last_metrics_update: time::Instant::now(),
         })
     }
 
@@ -272,6 +276,7 @@ impl Server {
             self.clients.retain(|_, c| {
                 if c.conn.is_closed() {
                     debug!(conn_id = ?c.conn_id, remote_addr = ?c.remote_addr, "client disconnected");
+                    crate::metrics::remove_connection(c.remote_addr);
                     false
                 } else if c.conn.is_draining() {
                     // Drop the workers, which drops the send/recv channels,
@@ -283,6 +288,13 @@ impl Server {
                 }
             });
 
+            if self.last_metrics_update.elapsed() > METRICS_INTERVAL {
+                self.last_metrics_update = time::Instant::now();
+                for client in self.clients.values() {
+                    crate::metrics::record_connection_stats(client.remote_addr, &client.conn);
+                }
+            }
+
             if self.shutting_down && self.clients.is_empty() {
                 return Ok(());
             } else if self.shutting_down {
@@ -12,6 +12,7 @@ use protocol::error::ErrorCode;
 use tracing::{debug, debug_span, error, trace};
 
 use crate::{
+    metrics,
     session::{control::DisplayParams, Session},
     state::SharedState,
     waking_sender::{WakingOneshot, WakingSender},
@@ -86,7 +87,14 @@ pub fn dispatch(
     match initial {
         protocol::MessageType::ListApplications(msg) => roundtrip(list_applications, &ctx, msg),
         protocol::MessageType::FetchApplicationImage(msg) => roundtrip(fetch_img, &ctx, msg),
-        protocol::MessageType::LaunchSession(msg) => roundtrip(launch_session, &ctx, msg),
+        protocol::MessageType::LaunchSession(msg) => roundtrip(
+            |ctx, msg| {
+                launch_session(ctx, msg)
+                    .inspect_err(|ServerError(code, _)| metrics::record_launch_failure(*code))
+            },
+            &ctx,
+            msg,
+        ),
         protocol::MessageType::ListSessions(msg) => roundtrip(list_sessions, &ctx, msg),
         protocol::MessageType::UpdateSession(msg) => roundtrip(update_session, &ctx, msg),
         protocol::MessageType::EndSession(msg) => roundtrip(end_session, &ctx, msg),
@@ -198,12 +206,22 @@ fn launch_session(
     ctx: &Context,
     msg: protocol::LaunchSession,
 ) -> Result<protocol::SessionLaunched> {
-    let display_params = validate_display_params(msg.display_params).map_err(|err| match err {
-        ValidationError::Unsupported(text) => {
-            ServerError(ErrorCode::ErrorSessionParamsNotSupported, Some(text))
-        }
-        ValidationError::Invalid(text) => ServerError(ErrorCode::ErrorProtocol, Some(text)),
-    })?;
+    let requested_outputs = msg
+        .display_params
+        .as_ref()
+        .map(|p| p.additional_outputs.clone())
+        .unwrap_or_default();
+    let (display_params, additional_outputs) = validate_display_params(msg.display_params)
+        .and_then(|params| {
+            let outputs = validate_additional_outputs(&params, requested_outputs)?;
+            Ok((params, outputs))
+        })
+        .map_err(|err| match err {
+            ValidationError::Unsupported(text) => {
+                ServerError(ErrorCode::ErrorSessionParamsNotSupported, Some(text))
+            }
+            ValidationError::Invalid(text) => ServerError(ErrorCode::ErrorProtocol, Some(text)),
+        })?;
 
     // Tracy gets confused if we have multiple sessions going.
     let mut guard = ctx.state.lock();
@@ -249,6 +267,7 @@ fn launch_session(
         &msg.application_id,
         &application_config,
         display_params,
+        additional_outputs,
         msg.permanent_gamepads,
         bug_report_dir,
     ) {
@@ -280,9 +299,17 @@ fn list_sessions(ctx: &Context, _msg: protocol::ListSessions) -> Result<protocol
             application_id: s.application_id.clone(),
             session_id: s.id,
             session_start: Some(s.started.into()),
-            display_params: Some(s.display_params.into()),
+            display_params: Some(protocol::VirtualDisplayParameters {
+                additional_outputs: s.additional_outputs.iter().map(|o| (*o).into()).collect(),
+                ..s.display_params.into()
+            }),
             supported_streaming_resolutions: generate_streaming_res(&s.display_params),
             permanent_gamepads: s.permanent_gamepads.clone(),
+            resource_usage: s.resource_usage().map(|u| protocol::ResourceUsage {
+                memory_bytes: u.memory_bytes,
+                cpu_time_usec: u.cpu_time.as_micros() as u64,
+                processes: u.pids as u32,
+            }),
         })
         .collect();
 
@@ -290,18 +317,46 @@ fn list_sessions(ctx: &Context, _msg: protocol::ListSessions) -> Result<protocol
 }
 
 fn update_session(ctx: &Context, msg: protocol::UpdateSession) -> Result<protocol::SessionUpdated> {
-    let display_params = validate_display_params(msg.display_params).map_err(|err| match err {
-        ValidationError::Unsupported(text) => {
-            ServerError(ErrorCode::ErrorSessionParamsNotSupported, Some(text))
-        }
-        ValidationError::Invalid(text) => ServerError(ErrorCode::ErrorProtocol, Some(text)),
-    })?;
+    let requested_outputs = msg
+        .display_params
+        .as_ref()
+        .map(|p| p.additional_outputs.clone())
+        .unwrap_or_default();
+    let (display_params, additional_outputs) = validate_display_params(msg.display_params)
+        .and_then(|params| {
+            let outputs = validate_additional_outputs(&params, requested_outputs)?;
+            Ok((params, outputs))
+        })
+        .map_err(|err| match err {
+            ValidationError::Unsupported(text) => {
+                ServerError(ErrorCode::ErrorSessionParamsNotSupported, Some(text))
+            }
+            ValidationError::Invalid(text) => ServerError(ErrorCode::ErrorProtocol, Some(text)),
+        })?;
 
     let mut state = ctx.state.lock();
     let Some(session) = state.sessions.get_mut(&msg.session_id) else {
         return Err(ServerError(ErrorCode::ErrorSessionNotFound, None));
     };
 
+    // Outputs are fixed for the lifetime of the session. An empty list means
+    // "keep the existing outputs".
+    if !additional_outputs.is_empty() && additional_outputs != session.additional_outputs {
+        return Err(ServerError(
+            ErrorCode::ErrorSessionParamsNotSupported,
+            Some("outputs can't be changed on a running session".to_string()),
+        ));
+    } else if session
+        .additional_outputs
+        .iter()
+        .any(|output| output.overlaps(&display_params.into()))
+    {
+        return Err(ServerError(
+            ErrorCode::ErrorSessionParamsNotSupported,
+            Some("primary output would overlap another output".to_string()),
+        ));
+    }
+
     trace!(?session.display_params, ?display_params, "update_session");
     if session.display_params != display_params {
         if let Err(err) = session.update_display_params(display_params) {
@@ -319,15 +374,11 @@ fn update_session(ctx: &Context, msg: protocol::UpdateSession) -> Result<protoco
 }
 
 fn end_session(ctx: &Context, msg: protocol::EndSession) -> Result<protocol::SessionEnded> {
-    let Some(session) = ctx.state.lock().sessions.remove(&msg.session_id) else {
+    if !ctx.state.lock().end_session(msg.session_id) {
         return Err(ServerError(ErrorCode::ErrorSessionNotFound, None));
-    };
-
-    if let Err(e) = session.stop() {
-        error!("failed to gracefully stop session: {}", e)
-    };
+    }
 
-    Ok(protocol::SessionEnded {})
+    Ok(protocol::SessionEnded::default())
 }
 
 fn generate_streaming_res(display_params: &DisplayParams) -> Vec<protocol::Size> {
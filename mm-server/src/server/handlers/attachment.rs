@@ -9,13 +9,15 @@ use tracing::{debug, debug_span, error, trace};
 
 mod stats;
 
-use super::{validate_attachment, validate_gamepad, ServerError, ValidationError};
+use super::{
+    validate_attachment, validate_gamepad, validate_resolution, ServerError, ValidationError,
+};
 use crate::{
     server::stream::StreamWriter,
     session::{
         compositor,
-        control::{ControlMessage, DisplayParams, SessionEvent},
-        Attachment,
+        control::{ControlMessage, DisplayParams, OutputParams, SessionEvent, StreamTarget},
+        Attachment, EPOCH,
     },
 };
 
@@ -28,6 +30,21 @@ impl From<DisplayParams> for protocol::VirtualDisplayParameters {
             }),
             framerate_hz: params.framerate,
             ui_scale: Some(params.ui_scale.into()),
+            additional_outputs: Vec::new(),
+        }
+    }
+}
+
+impl From<OutputParams> for protocol::VirtualOutput {
+    fn from(params: OutputParams) -> Self {
+        protocol::VirtualOutput {
+            resolution: Some(protocol::Size {
+                width: params.width,
+                height: params.height,
+            }),
+            ui_scale: Some(params.ui_scale.into()),
+            x: params.x,
+            y: params.y,
         }
     }
 }
@@ -37,8 +54,18 @@ struct AttachmentHandler<'a> {
     handle: Attachment,
 
     session_display_params: DisplayParams,
+    additional_outputs: Vec<OutputParams>,
+    // The output or window we're streaming, and its position. Pointer
+    // coordinates are relative to it.
+    target: StreamTarget,
+    origin: (f64, f64),
+    // Whether the streamed window has focus, if we're streaming a window.
+    window_focused: bool,
     attached: protocol::Attached,
     superscale: f64,
+    // Whether the client can handle a new streaming resolution without
+    // reattaching.
+    resize_in_place: bool,
 
     // Keep track of the pointer lock, and debounce session events for it.
     pointer_lock: Option<(f64, f64)>,
@@ -84,6 +111,12 @@ impl<'a> AttachmentHandler<'a> {
         }
 
         let session_id = msg.session_id;
+        let target = match msg.window_id {
+            0 => StreamTarget::Output(msg.output as usize),
+            id => StreamTarget::Window(id),
+        };
+        let frame_timing = msg.frame_timing;
+        let resize_in_place = msg.resize_in_place;
         let (video_params, audio_params) = validate_attachment(msg).map_err(|err| match err {
             ValidationError::Unsupported(text) => {
                 ServerError(ErrorCode::ErrorAttachmentParamsNotSupported, Some(text))
@@ -99,7 +132,7 @@ impl<'a> AttachmentHandler<'a> {
             return Err(ServerError(ErrorCode::ErrorSessionNotFound, None));
         };
 
-        if !session.supports_stream(video_params) {
+        if !session.supports_stream(video_params, target) {
             return Err(ServerError(
                 ErrorCode::ErrorAttachmentParamsNotSupported,
                 Some("unsupported streaming resolution or codec".to_string()),
@@ -112,11 +145,13 @@ impl<'a> AttachmentHandler<'a> {
             &server_config,
             ctx.outgoing_dgrams.clone(),
             ctx.max_dgram_len,
+            frame_timing,
         );
 
         let handle = match session.attach(
             attachment_id,
             true,
+            target,
             video_params,
             audio_params,
             stream_writer,
@@ -132,18 +167,31 @@ impl<'a> AttachmentHandler<'a> {
         };
 
         let app_id = session.application_id.clone();
+        let info = handle.info.clone();
         let display_params = session.display_params;
+        let additional_outputs = session.additional_outputs.clone();
+        let output_params = match target {
+            StreamTarget::Output(idx) => session.output(idx),
+            StreamTarget::Window(_) => None,
+        };
         let bug_report_dir = session.bug_report_dir.clone();
         drop(guard);
 
-        let superscale = display_params.height as f64 / video_params.height as f64;
-        assert_eq!(display_params.height % video_params.height, 0);
-        assert_eq!(
-            display_params.width as f64 / video_params.width as f64,
-            superscale
-        );
+        // Windows are sized to match the stream, and their position is
+        // updated from the window list.
+        let (superscale, origin) = match output_params {
+            Some(params) => {
+                let superscale = params.height as f64 / video_params.height as f64;
+                assert_eq!(params.height % video_params.height, 0);
+                assert_eq!(params.width as f64 / video_params.width as f64, superscale);
+
+                (superscale, (params.x as f64, params.y as f64))
+            }
+            None => (1.0, (0.0, 0.0)),
+        };
 
         debug!(
+            ?target,
             ?video_params,
             ?audio_params,
             ?superscale,
@@ -156,6 +204,14 @@ impl<'a> AttachmentHandler<'a> {
         let attached = protocol::Attached {
             session_id,
             attachment_id: handle.attachment_id,
+            output: match target {
+                StreamTarget::Output(idx) => idx as u32,
+                StreamTarget::Window(_) => 0,
+            },
+            window_id: match target {
+                StreamTarget::Output(_) => 0,
+                StreamTarget::Window(id) => id,
+            },
 
             video_codec: video_codec.into(),
             streaming_resolution: Some(protocol::Size {
@@ -174,6 +230,8 @@ impl<'a> AttachmentHandler<'a> {
                     audio_params.channels as usize
                 ],
             }),
+
+            frame_timing,
         };
 
         let pointer_lock = None;
@@ -185,8 +243,13 @@ impl<'a> AttachmentHandler<'a> {
             handle,
 
             session_display_params: display_params,
+            additional_outputs,
+            target,
+            origin,
+            window_focused: false,
             attached,
             superscale,
+            resize_in_place,
 
             pointer_lock,
 
@@ -196,7 +259,7 @@ impl<'a> AttachmentHandler<'a> {
 
             bug_report: bug_report_dir.map(|dir| (dir, BTreeMap::default())),
 
-            stats: stats::AttachmentStats::new(app_id),
+            stats: stats::AttachmentStats::new(app_id, info),
         })
     }
 
@@ -269,9 +332,20 @@ impl<'a> AttachmentHandler<'a> {
         match msg {
             protocol::MessageType::KeepAlive(_) => {}
             protocol::MessageType::Detach(_) => return Err(AttachmentError::Finished),
+            protocol::MessageType::SyncClock(ev) => {
+                let msg = protocol::ClockSynced {
+                    client_timestamp: ev.client_timestamp,
+                    server_timestamp: EPOCH.elapsed().as_micros() as u64,
+                };
+
+                let _ = self.ctx.outgoing.send(msg.into());
+            }
             protocol::MessageType::RequestVideoRefresh(ev) => {
                 if ev.stream_seq == self.current_video_stream_seq {
-                    let _ = self.handle.control.send(ControlMessage::RefreshVideo);
+                    let _ = self
+                        .handle
+                        .control
+                        .send(ControlMessage::RefreshVideo(self.target));
                 } else {
                     debug!(
                         current = self.current_video_stream_seq,
@@ -280,6 +354,56 @@ impl<'a> AttachmentHandler<'a> {
                     );
                 }
             }
+            protocol::MessageType::UpdateStreamingResolution(ev) => {
+                let StreamTarget::Window(window_id) = self.target else {
+                    return Err(AttachmentError::ServerError(
+                        ErrorCode::ErrorProtocol,
+                        Some("only window streams can be resized".to_string()),
+                    ));
+                };
+
+                if !self.resize_in_place {
+                    return Err(AttachmentError::ServerError(
+                        ErrorCode::ErrorProtocol,
+                        Some("resize_in_place not set".to_string()),
+                    ));
+                }
+
+                let (width, height) =
+                    validate_resolution(ev.streaming_resolution).map_err(|err| match err {
+                        ValidationError::Unsupported(text) | ValidationError::Invalid(text) => {
+                            AttachmentError::ServerError(ErrorCode::ErrorProtocol, Some(text))
+                        }
+                    })?;
+
+                debug!(window_id, width, height, "resizing window stream");
+                self.handle
+                    .control
+                    .send(ControlMessage::ResizeWindowStream {
+                        window_id,
+                        width,
+                        height,
+                    })
+                    .ok();
+
+                let streaming_resolution = protocol::Size { width, height };
+                self.attached.streaming_resolution = Some(streaming_resolution);
+
+                let params = self.session_display_params;
+                self.send(protocol::SessionParametersChanged {
+                    display_params: Some(protocol::VirtualDisplayParameters {
+                        additional_outputs: self
+                            .additional_outputs
+                            .iter()
+                            .map(|o| (*o).into())
+                            .collect(),
+                        ..params.into()
+                    }),
+                    supported_streaming_resolutions: super::generate_streaming_res(&params),
+                    streaming_resolution: Some(streaming_resolution),
+                    reattach_required: false,
+                });
+            }
             protocol::MessageType::KeyboardInput(ev) => {
                 use protocol::keyboard_input::KeyState;
 
@@ -333,8 +457,8 @@ impl<'a> AttachmentHandler<'a> {
                     .ok();
             }
             protocol::MessageType::PointerMotion(ev) => {
-                let x = ev.x * self.superscale;
-                let y = ev.y * self.superscale;
+                let x = ev.x * self.superscale + self.origin.0;
+                let y = ev.y * self.superscale + self.origin.1;
                 self.handle
                     .control
                     .send(ControlMessage::PointerMotion(x, y))
@@ -349,6 +473,7 @@ impl<'a> AttachmentHandler<'a> {
                     .ok();
             }
             protocol::MessageType::PointerEntered(_) => {
+                self.focus_window();
                 self.handle
                     .control
                     .send(ControlMessage::PointerEntered)
@@ -392,11 +517,15 @@ impl<'a> AttachmentHandler<'a> {
                     "sending cursor input event",
                 );
 
+                if state == compositor::ButtonState::Pressed {
+                    self.focus_window();
+                }
+
                 self.handle
                     .control
                     .send(ControlMessage::PointerInput {
-                        x: ev.x,
-                        y: ev.y,
+                        x: ev.x + self.origin.0,
+                        y: ev.y + self.origin.1,
                         button_code,
                         state,
                     })
@@ -513,6 +642,29 @@ impl<'a> AttachmentHandler<'a> {
                     })
                     .ok();
             }
+            protocol::MessageType::WindowAction(ev) => {
+                use protocol::window_action::Action;
+
+                let action = match ev.action.try_into() {
+                    Ok(Action::Focus) => compositor::WindowAction::Focus,
+                    Ok(Action::Raise) => compositor::WindowAction::Raise,
+                    Ok(Action::Close) => compositor::WindowAction::Close,
+                    _ => {
+                        return Err(AttachmentError::ServerError(
+                            ErrorCode::ErrorProtocol,
+                            Some("invalid window action".to_string()),
+                        ));
+                    }
+                };
+
+                self.handle
+                    .control
+                    .send(ControlMessage::WindowAction {
+                        id: ev.window_id,
+                        action,
+                    })
+                    .ok();
+            }
             protocol::MessageType::Error(ev) => {
                 error!(
                     "received error from client: {}: {}",
@@ -534,27 +686,73 @@ impl<'a> AttachmentHandler<'a> {
 
     fn handle_session_event(&mut self, event: SessionEvent) -> Result<(), AttachmentError> {
         match event {
-            SessionEvent::Shutdown => {
+            SessionEvent::Shutdown { exit_status } => {
                 // The session ended, probably because the app exited.
-                self.ctx
-                    .state
-                    .lock()
-                    .sessions
-                    .remove(&self.handle.session_id);
+                self.ctx.state.lock().end_session(self.handle.session_id);
 
-                self.send(protocol::SessionEnded {});
+                self.send(protocol::SessionEnded {
+                    exit_status: exit_status.unwrap_or_default(),
+                });
                 return Err(AttachmentError::Finished);
             }
-            SessionEvent::DisplayParamsChanged { params, reattach } => {
+            SessionEvent::Kicked => {
+                debug!("attachment kicked");
+                return Err(AttachmentError::ServerError(
+                    ErrorCode::ErrorAttachmentKicked,
+                    Some("detached by administrator".to_string()),
+                ));
+            }
+            SessionEvent::Suspended => {
+                debug!("attachment suspended");
+                return Err(AttachmentError::ServerError(
+                    ErrorCode::ErrorAttachmentSuspended,
+                    Some("detached after inactivity".to_string()),
+                ));
+            }
+            SessionEvent::DisplayParamsChanged { params } => {
+                // If we're streaming the primary output, the stream is
+                // restarted at the new resolution. Clients that can't handle
+                // that have to reattach.
+                let streaming_resolution = (self.target == StreamTarget::PRIMARY
+                    && (params.width, params.height)
+                        != (
+                            self.session_display_params.width,
+                            self.session_display_params.height,
+                        ))
+                    .then_some(protocol::Size {
+                        width: params.width,
+                        height: params.height,
+                    });
+
+                let reattach_required = streaming_resolution.is_some() && !self.resize_in_place;
+
+                // The stream restarts at the full display size, so pointer
+                // coordinates no longer need to be scaled.
+                if streaming_resolution.is_some() && !reattach_required {
+                    self.superscale = 1.0;
+                    self.attached.streaming_resolution = Some(protocol::Size {
+                        width: params.width,
+                        height: params.height,
+                    });
+                }
+
                 self.session_display_params = params;
                 let msg = protocol::SessionParametersChanged {
-                    display_params: Some(params.into()),
+                    display_params: Some(protocol::VirtualDisplayParameters {
+                        additional_outputs: self
+                            .additional_outputs
+                            .iter()
+                            .map(|o| (*o).into())
+                            .collect(),
+                        ..params.into()
+                    }),
                     supported_streaming_resolutions: super::generate_streaming_res(&params),
-                    reattach_required: reattach,
+                    streaming_resolution: streaming_resolution.filter(|_| !reattach_required),
+                    reattach_required,
                 };
 
                 self.send(msg);
-                if reattach {
+                if reattach_required {
                     return Err(AttachmentError::Finished);
                 }
             }
@@ -624,8 +822,8 @@ impl<'a> AttachmentHandler<'a> {
                 self.send(msg);
             }
             SessionEvent::PointerLocked(x, y) => {
-                let x = x / self.superscale;
-                let y = y / self.superscale;
+                let x = (x - self.origin.0) / self.superscale;
+                let y = (y - self.origin.1) / self.superscale;
 
                 if self.pointer_lock.replace((x, y)).is_none() {
                     self.send(protocol::LockPointer { x, y });
@@ -636,11 +834,65 @@ impl<'a> AttachmentHandler<'a> {
                     self.send(protocol::ReleasePointer {});
                 }
             }
+            SessionEvent::WindowList(windows) => {
+                // Track the position and focus of the streamed window, and
+                // end the attachment if it closed.
+                if let StreamTarget::Window(id) = self.target {
+                    let Some(window) = windows.iter().find(|w| w.id == id) else {
+                        debug!(window_id = id, "streamed window closed");
+                        return Err(AttachmentError::Finished);
+                    };
+
+                    self.origin = (window.x as f64, window.y as f64);
+                    self.window_focused = window.focused;
+                }
+
+                let list = windows
+                    .into_iter()
+                    .map(|w| protocol::window_list::Window {
+                        id: w.id,
+                        title: w.title.unwrap_or_default(),
+                        app_id: w.app_id.unwrap_or_default(),
+                        size: Some(protocol::Size {
+                            width: w.width,
+                            height: w.height,
+                        }),
+                        focused: w.focused,
+                    })
+                    .collect();
+
+                self.send(protocol::WindowList { list });
+            }
+            SessionEvent::WindowMetadataChanged { title, app_id } => {
+                self.send(protocol::WindowMetadataChanged {
+                    title: title.unwrap_or_default(),
+                    app_id: app_id.unwrap_or_default(),
+                });
+            }
         }
 
         Ok(())
     }
 
+    /// Focuses the streamed window, so that it receives input. Does nothing
+    /// if we're streaming an output.
+    fn focus_window(&mut self) {
+        if let StreamTarget::Window(id) = self.target {
+            if !self.window_focused {
+                self.handle
+                    .control
+                    .send(ControlMessage::WindowAction {
+                        id,
+                        action: compositor::WindowAction::Focus,
+                    })
+                    .ok();
+
+                // Avoid sending it repeatedly before the window list updates.
+                self.window_focused = true;
+            }
+        }
+    }
+
     fn send(&self, msg: impl Into<protocol::MessageType>) {
         let _ = self.ctx.outgoing.send(msg.into());
     }
@@ -2,13 +2,16 @@
 //
 // SPDX-License-Identifier: BUSL-1.1
 
-use std::time;
+use std::{sync::Arc, time};
 
 use simple_moving_average::SMA as _;
 use tracing::info;
 
+use crate::session::AttachmentInfo;
+
 pub struct AttachmentStats {
     app_id: String,
+    info: Arc<AttachmentInfo>,
     start: time::Instant,
     total_transfer: u64,
 
@@ -18,11 +21,12 @@ pub struct AttachmentStats {
 }
 
 impl AttachmentStats {
-    pub fn new(app_id: String) -> Self {
+    pub fn new(app_id: String, info: Arc<AttachmentInfo>) -> Self {
         let now = time::Instant::now();
 
         Self {
             app_id,
+            info,
             start: now,
             total_transfer: 0,
 
@@ -38,6 +42,7 @@ impl AttachmentStats {
             .add_sample((len as f64 * 8.0 / (1024.0 * 1024.0)) / duration.as_secs_f64());
 
         let avg = self.sma.get_average();
+        self.info.record_transfer(len, avg);
 
         if self.last_log.elapsed().as_secs() > 5 {
             self.last_log = time::Instant::now();
@@ -10,7 +10,7 @@ use crate::{
     color::VideoProfile,
     pixel_scale::PixelScale,
     session::{
-        control::{AudioStreamParams, DisplayParams, VideoStreamParams},
+        control::{AudioStreamParams, ContentType, DisplayParams, OutputParams, VideoStreamParams},
         GamepadLayout,
     },
 };
@@ -22,6 +22,9 @@ pub enum ValidationError {
 
 type Result<T> = std::result::Result<T, ValidationError>;
 
+/// The maximum number of outputs a session can have, besides the primary one.
+const MAX_ADDITIONAL_OUTPUTS: usize = 7;
+
 pub fn validate_display_params(
     params: Option<protocol::VirtualDisplayParameters>,
 ) -> Result<DisplayParams> {
@@ -43,6 +46,45 @@ pub fn validate_display_params(
     }
 }
 
+/// Validates any outputs beyond the primary one, which must not overlap with
+/// each other or with the primary output.
+pub fn validate_additional_outputs(
+    primary: &DisplayParams,
+    outputs: Vec<protocol::VirtualOutput>,
+) -> Result<Vec<OutputParams>> {
+    if outputs.len() > MAX_ADDITIONAL_OUTPUTS {
+        return Err(ValidationError::Unsupported(format!(
+            "at most {MAX_ADDITIONAL_OUTPUTS} additional outputs are supported"
+        )));
+    }
+
+    let mut validated: Vec<OutputParams> = vec![(*primary).into()];
+    for output in outputs {
+        let (width, height) = validate_resolution(output.resolution)?;
+        let ui_scale = validate_ui_scale(output.ui_scale)?;
+        let params = OutputParams {
+            width,
+            height,
+            ui_scale,
+            x: output.x,
+            y: output.y,
+        };
+
+        if params.x.checked_add(width).is_none() || params.y.checked_add(height).is_none() {
+            return Err(ValidationError::Invalid(
+                "output position out of range".into(),
+            ));
+        } else if validated.iter().any(|other| other.overlaps(&params)) {
+            return Err(ValidationError::Invalid("outputs must not overlap".into()));
+        }
+
+        validated.push(params);
+    }
+
+    // Skip the primary output.
+    Ok(validated.split_off(1))
+}
+
 pub fn validate_attachment(
     params: protocol::Attach,
 ) -> Result<(VideoStreamParams, AudioStreamParams)> {
@@ -62,6 +104,7 @@ pub fn validate_attachment(
             codec: video_codec,
             preset,
             profile: video_profile,
+            content_type: ContentType::None,
         },
         AudioStreamParams {
             sample_rate,
@@ -214,3 +257,96 @@ pub fn validate_gamepad_layout(layout: i32) -> Result<GamepadLayout> {
         Ok(_) => Ok(GamepadLayout::GenericDualStick), // TODO
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn primary() -> DisplayParams {
+        DisplayParams {
+            width: 1920,
+            height: 1080,
+            framerate: 60,
+            ui_scale: PixelScale::ONE,
+        }
+    }
+
+    fn output(x: u32, y: u32, width: u32, height: u32) -> protocol::VirtualOutput {
+        protocol::VirtualOutput {
+            resolution: Some(protocol::Size { width, height }),
+            ui_scale: Some(PixelScale::ONE.into()),
+            x,
+            y,
+        }
+    }
+
+    #[test]
+    fn additional_outputs() {
+        let outputs = validate_additional_outputs(
+            &primary(),
+            vec![output(1920, 0, 1280, 720), output(0, 1080, 1920, 1080)],
+        )
+        .ok()
+        .unwrap();
+
+        assert_eq!(outputs.len(), 2);
+        assert_eq!((outputs[0].x, outputs[0].y), (1920, 0));
+        assert_eq!((outputs[1].width, outputs[1].height), (1920, 1080));
+    }
+
+    #[test]
+    fn additional_outputs_overlap() {
+        // Overlaps the primary output.
+        assert!(matches!(
+            validate_additional_outputs(&primary(), vec![output(1918, 0, 1280, 720)]),
+            Err(ValidationError::Invalid(_))
+        ));
+
+        // Overlaps the previous additional output.
+        assert!(matches!(
+            validate_additional_outputs(
+                &primary(),
+                vec![output(1920, 0, 1280, 720), output(2000, 700, 1280, 720)]
+            ),
+            Err(ValidationError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn additional_outputs_out_of_range() {
+        assert!(matches!(
+            validate_additional_outputs(&primary(), vec![output(u32::MAX - 2, 0, 1280, 720)]),
+            Err(ValidationError::Invalid(_))
+        ));
+
+        assert!(matches!(
+            validate_additional_outputs(&primary(), vec![output(0, u32::MAX, 1280, 720)]),
+            Err(ValidationError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn additional_outputs_zero_size() {
+        assert!(matches!(
+            validate_additional_outputs(&primary(), vec![output(1920, 0, 0, 720)]),
+            Err(ValidationError::Invalid(_))
+        ));
+
+        assert!(matches!(
+            validate_additional_outputs(&primary(), vec![output(1920, 0, 1280, 0)]),
+            Err(ValidationError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn additional_outputs_limit() {
+        let outputs = (0..=MAX_ADDITIONAL_OUTPUTS as u32)
+            .map(|i| output(1920 * (i + 1), 0, 1920, 1080))
+            .collect();
+
+        assert!(matches!(
+            validate_additional_outputs(&primary(), outputs),
+            Err(ValidationError::Unsupported(_))
+        ));
+    }
+}
@@ -7,7 +7,7 @@ use either::Either;
 use mm_protocol as protocol;
 use tracing::{debug, error, instrument, trace_span};
 
-use crate::{config, waking_sender::WakingSender};
+use crate::{config, encoder::FrameTiming, session::EPOCH, waking_sender::WakingSender};
 
 /// A helper to write audio/video frames out as chunks to the client. Runs on
 /// the encoder thread, not on the server thread.
@@ -19,6 +19,7 @@ pub struct StreamWriter {
     chunk_size: usize,
     max_dgram_len: usize,
     fec_ratios: Vec<f32>,
+    frame_timing: bool,
 
     audio_stream_seq: u64,
     audio_seq: u64,
@@ -34,6 +35,7 @@ impl StreamWriter {
         config: &config::ServerConfig,
         outgoing: WakingSender<Vec<u8>>,
         max_dgram_len: usize,
+        frame_timing: bool,
     ) -> Self {
         // max_dgram_len is our overall MTU. The MM protocol header is 2-10 bytes,
         // and then we include seven varints (maximum 5 bytes each) and a bool of
@@ -41,7 +43,14 @@ impl StreamWriter {
         // headroom should cover the worst case. However, a little extra will
         // increase the chance that the packet is coalesced into an existing QUIC
         // packet.
-        let chunk_size = max_dgram_len - 128;
+        //
+        // Frame timing adds up to six more varints, which can be larger because
+        // they're microsecond timestamps.
+        let chunk_size = if frame_timing {
+            max_dgram_len - 192
+        } else {
+            max_dgram_len - 128
+        };
 
         Self {
             session_id,
@@ -51,6 +60,7 @@ impl StreamWriter {
             chunk_size,
             max_dgram_len,
             fec_ratios: config.video_fec_ratios.clone(),
+            frame_timing,
 
             // The first stream_seq is 1, but we increment immediately below.
             audio_stream_seq: 0,
@@ -67,6 +77,7 @@ impl StreamWriter {
         frame: Bytes,
         hierarchical_layer: u32,
         stream_restart: bool,
+        timing: &FrameTiming,
     ) -> (u64, u64) {
         if stream_restart {
             self.video_stream_seq += 1;
@@ -85,6 +96,23 @@ impl StreamWriter {
             .copied()
             .unwrap_or_default();
 
+        let timing = self.frame_timing.then(|| {
+            let micros = |ts: Option<std::time::Instant>| {
+                ts.map_or(0, |ts| {
+                    ts.saturating_duration_since(*EPOCH).as_micros() as u64
+                })
+            };
+
+            protocol::FrameTiming {
+                app_commit: micros(timing.app_commit),
+                composite_start: micros(timing.composite_start),
+                composite_end: micros(timing.composite_end),
+                encode_submit: micros(timing.encode_submit),
+                encode_complete: micros(timing.encode_complete),
+                send: EPOCH.elapsed().as_micros() as u64,
+            }
+        });
+
         for chunk in iter_chunks(frame, self.chunk_size, fec_ratio) {
             let msg = protocol::VideoChunk {
                 session_id: self.session_id,
@@ -97,6 +125,7 @@ impl StreamWriter {
                 num_chunks: chunk.num_chunks,
                 hierarchical_layer,
                 timestamp: pts,
+                timing,
 
                 fec_metadata: chunk.fec_metadata,
             };
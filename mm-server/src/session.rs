@@ -2,28 +2,45 @@
 //
 // SPDX-License-Identifier: BUSL-1.1
 
-use std::{path::PathBuf, sync::Arc, time};
+use std::{
+    collections::BTreeMap,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time,
+};
 
-use anyhow::{anyhow, bail};
+use anyhow::{anyhow, bail, Context as _};
 use crossbeam_channel as crossbeam;
 use mm_protocol as protocol;
 use pathsearch::find_executable_in_path;
 use tracing::{debug_span, info};
 
 use crate::{
-    codec::probe_codec, server::stream::StreamWriter, vulkan::VkContext,
+    codec::probe_codec,
+    config::AppConfig,
+    container::{Cgroup, ResourceUsage},
+    server::stream::StreamWriter,
+    vulkan::VkContext,
     waking_sender::WakingSender,
 };
 
 mod audio;
 pub mod compositor;
 pub mod control;
+mod dbus;
 mod handle;
 mod input;
 mod reactor;
+mod recording;
 mod video;
 
-use control::{AudioStreamParams, ControlMessage, DisplayParams, SessionEvent, VideoStreamParams};
+use control::{
+    AudioStreamParams, ControlMessage, DisplayParams, OutputParams, SessionEvent, StreamTarget,
+    VideoStreamParams,
+};
 pub use handle::SessionHandle;
 pub use input::GamepadLayout;
 use reactor::Reactor;
@@ -35,18 +52,25 @@ const ATTACH_TIMEOUT: time::Duration = time::Duration::from_secs(10);
 pub struct Session {
     pub id: u64,
     pub display_params: DisplayParams,
+    /// Outputs beyond the primary one. These are fixed at launch.
+    pub additional_outputs: Vec<OutputParams>,
     pub application_id: String,
+    /// The app config at launch time, which may have since been reloaded.
+    pub application_config: AppConfig,
     pub started: time::SystemTime,
     pub detached_since: Option<time::Instant>,
     pub permanent_gamepads: Vec<protocol::Gamepad>,
     pub defunct: bool,
+    pub activity: Arc<Activity>,
 
-    comp_thread_handle: std::thread::JoinHandle<anyhow::Result<()>>,
+    comp_thread_handle: std::thread::JoinHandle<anyhow::Result<i32>>,
     control_sender: WakingSender<ControlMessage>,
-    operator_attachment_id: Option<u64>,
+    // Operator attachments, by output or window.
+    operator_attachments: BTreeMap<StreamTarget, Arc<AttachmentInfo>>,
 
     pub bug_report_dir: Option<PathBuf>,
 
+    cgroup: Option<Arc<Cgroup>>,
     vk: Arc<VkContext>,
 }
 
@@ -55,17 +79,87 @@ pub struct Attachment {
     pub attachment_id: u64,
     pub events: crossbeam::Receiver<SessionEvent>,
     pub control: WakingSender<ControlMessage>,
+    pub info: Arc<AttachmentInfo>,
+}
+
+/// Live information about an attachment, shared between the attachment
+/// handler and the admin API.
+#[derive(Debug)]
+pub struct AttachmentInfo {
+    pub id: u64,
+    pub attached: time::SystemTime,
+    bytes_sent: AtomicU64,
+    // An f64, stored as bits.
+    bitrate_mbps: AtomicU64,
+}
+
+impl AttachmentInfo {
+    fn new(id: u64) -> Self {
+        Self {
+            id,
+            attached: time::SystemTime::now(),
+            bytes_sent: AtomicU64::new(0),
+            bitrate_mbps: AtomicU64::new(0),
+        }
+    }
+
+    pub fn record_transfer(&self, len: usize, bitrate_mbps: f64) {
+        self.bytes_sent.fetch_add(len as u64, Ordering::Relaxed);
+        self.bitrate_mbps
+            .store(bitrate_mbps.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn bytes_sent(&self) -> u64 {
+        self.bytes_sent.load(Ordering::Relaxed)
+    }
+
+    pub fn bitrate_mbps(&self) -> f64 {
+        f64::from_bits(self.bitrate_mbps.load(Ordering::Relaxed))
+    }
+}
+
+/// Tracks when the session was last in use, shared between the compositor
+/// and the server. Input from attached clients counts, as does an app
+/// inhibiting idle on a visible surface.
+#[derive(Debug)]
+pub struct Activity {
+    // Milliseconds since EPOCH.
+    last_active: AtomicU64,
+}
+
+impl Activity {
+    fn new() -> Self {
+        let this = Self {
+            last_active: AtomicU64::new(0),
+        };
+
+        this.touch();
+        this
+    }
+
+    pub fn touch(&self) {
+        self.last_active
+            .store(EPOCH.elapsed().as_millis() as u64, Ordering::Relaxed);
+    }
+
+    /// Returns how long it's been since the last activity.
+    pub fn idle_duration(&self) -> time::Duration {
+        let last_active = time::Duration::from_millis(self.last_active.load(Ordering::Relaxed));
+        EPOCH.elapsed().saturating_sub(last_active)
+    }
 }
 
 impl Session {
     /// Launches a standalone compositor and the application process. Blocks
     /// until both have started up and connected over a unix socket.
+    #[allow(clippy::too_many_arguments)]
     pub fn launch(
         vk: Arc<VkContext>,
         id: u64,
         application_id: &str,
-        application_config: &super::config::AppConfig,
+        application_config: &AppConfig,
         display_params: DisplayParams,
+        additional_outputs: Vec<OutputParams>,
         permanent_gamepads: Vec<protocol::Gamepad>,
         bug_report_dir: Option<PathBuf>,
     ) -> anyhow::Result<Self> {
@@ -78,11 +172,29 @@ impl Session {
         let vk_clone = vk.clone();
         let app_name = application_id.to_owned();
         let app_cfg = application_config.clone();
+        let outputs = additional_outputs.clone();
         let gamepads = permanent_gamepads
             .iter()
             .map(|pad| (pad.id, GamepadLayout::GenericDualStick)) // TODO layout.
             .collect();
 
+        // Only use a cgroup if limits are configured. The first one moves
+        // mmserver into a leaf cgroup, so we avoid doing that otherwise.
+        let cgroup = if application_config.resource_limits.is_empty() {
+            None
+        } else {
+            let cg = Cgroup::new(
+                &format!("session-{id}"),
+                &application_config.resource_limits,
+            )
+            .context("failed to apply resource limits")?;
+
+            Some(Arc::new(cg))
+        };
+
+        let activity = Arc::new(Activity::new());
+        let activity_clone = activity.clone();
+        let cgroup_clone = cgroup.clone();
         let bug_report_dir_clone = bug_report_dir.clone();
         let comp_thread_handle = std::thread::spawn(move || {
             tracy_client::set_thread_name!("compositor");
@@ -92,10 +204,14 @@ impl Session {
 
             Reactor::run(
                 vk_clone,
+                id,
                 app_cfg,
                 display_params,
+                outputs,
                 gamepads,
                 bug_report_dir_clone,
+                cgroup_clone,
+                activity_clone,
                 ready_send,
             )
         });
@@ -107,7 +223,7 @@ impl Session {
             Ok(s) => s,
             Err(_) => {
                 return match comp_thread_handle.join() {
-                    Ok(Ok(())) => Err(anyhow!("compositor thread exited unexpectedly")),
+                    Ok(Ok(_)) => Err(anyhow!("compositor thread exited unexpectedly")),
                     Ok(Err(e)) => Err(e),
                     Err(_) => Err(anyhow!("compositor thread panicked")),
                 }
@@ -117,15 +233,19 @@ impl Session {
         Ok(Self {
             id,
             application_id: application_id.to_string(),
+            application_config: application_config.clone(),
             display_params,
+            additional_outputs,
             permanent_gamepads,
             started: time::SystemTime::now(),
             defunct: false,
+            activity,
             detached_since: None,
-            operator_attachment_id: None,
+            operator_attachments: BTreeMap::new(),
             comp_thread_handle,
             control_sender,
             bug_report_dir,
+            cgroup,
             vk,
         })
     }
@@ -154,6 +274,7 @@ impl Session {
         &mut self,
         id: u64,
         operator: bool,
+        target: StreamTarget,
         video_params: VideoStreamParams,
         audio_params: AudioStreamParams,
         stream_writer: StreamWriter,
@@ -162,14 +283,17 @@ impl Session {
             return Err(anyhow!("session defunct"));
         } else if !operator {
             unimplemented!()
-        } else if self.operator_attachment_id.is_some() {
-            return Err(anyhow!("session already has an operator"));
+        } else if matches!(target, StreamTarget::Output(n) if n > self.additional_outputs.len()) {
+            return Err(anyhow!("no such output"));
+        } else if self.operator_attachments.contains_key(&target) {
+            return Err(anyhow!("stream target already has an operator"));
         }
 
         info!(
             session_id = self.id,
             attachment_id = id,
             operator,
+            ?target,
             "new attachment"
         );
 
@@ -179,6 +303,7 @@ impl Session {
             .control_sender
             .send(ControlMessage::Attach {
                 id,
+                target,
                 sender: events_send,
                 video_params,
                 audio_params,
@@ -196,7 +321,8 @@ impl Session {
             bail!("attachment rejected");
         }
 
-        self.operator_attachment_id = Some(id);
+        let info = Arc::new(AttachmentInfo::new(id));
+        self.operator_attachments.insert(target, info.clone());
         self.detached_since = None;
 
         Ok(Attachment {
@@ -204,6 +330,7 @@ impl Session {
             attachment_id: id,
             events: events_recv,
             control: self.control_sender.clone(),
+            info,
         })
     }
 
@@ -212,8 +339,12 @@ impl Session {
             return Err(anyhow!("session defunct"));
         }
 
-        self.operator_attachment_id = None;
-        self.detached_since = Some(time::Instant::now());
+        self.operator_attachments
+            .retain(|_, info| info.id != attachment.attachment_id);
+        if self.operator_attachments.is_empty() {
+            self.detached_since = Some(time::Instant::now());
+        }
+
         match self
             .control_sender
             .send(ControlMessage::Detach(attachment.attachment_id))
@@ -226,7 +357,71 @@ impl Session {
         }
     }
 
-    pub fn stop(self) -> anyhow::Result<()> {
+    /// Forcibly detaches a client. The session keeps running.
+    pub fn kick(&mut self, attachment_id: u64) -> anyhow::Result<()> {
+        if self.defunct {
+            return Err(anyhow!("session defunct"));
+        }
+
+        if !self
+            .operator_attachments
+            .values()
+            .any(|info| info.id == attachment_id)
+        {
+            bail!("attachment not found");
+        }
+
+        match self
+            .control_sender
+            .send(ControlMessage::Kick(attachment_id))
+        {
+            Ok(_) => Ok(()),
+            Err(crossbeam::SendError(_)) => {
+                self.defunct = true;
+                Err(anyhow!("compositor died"))
+            }
+        }
+    }
+
+    /// Forcibly detaches all clients, so that the session stops rendering.
+    /// The session keeps running until it times out.
+    pub fn suspend(&mut self) -> anyhow::Result<()> {
+        if self.defunct {
+            return Err(anyhow!("session defunct"));
+        }
+
+        for (_, info) in std::mem::take(&mut self.operator_attachments) {
+            if self
+                .control_sender
+                .send(ControlMessage::Suspend(info.id))
+                .is_err()
+            {
+                self.defunct = true;
+                bail!("compositor died");
+            }
+        }
+
+        self.detached_since = Some(time::Instant::now());
+        Ok(())
+    }
+
+    /// Returns the current resource usage of the session, if it's running in
+    /// a cgroup (that is, if it has resource limits).
+    pub fn resource_usage(&self) -> Option<ResourceUsage> {
+        self.cgroup.as_ref()?.usage().ok()
+    }
+
+    pub fn is_attached(&self) -> bool {
+        !self.operator_attachments.is_empty()
+    }
+
+    pub fn attachments(&self) -> impl Iterator<Item = &AttachmentInfo> {
+        self.operator_attachments.values().map(|info| info.as_ref())
+    }
+
+    /// Stops the session, waiting for the app to exit, and returns its exit
+    /// status.
+    pub fn stop(self) -> anyhow::Result<i32> {
         if let Err(crossbeam::TrySendError::Full(_)) =
             self.control_sender.try_send(ControlMessage::Stop)
         {
@@ -234,18 +429,48 @@ impl Session {
         }
 
         match self.comp_thread_handle.join() {
-            Ok(Ok(())) => Ok(()),
-            Ok(Err(e)) => Err(e),
+            Ok(res) => res,
             Err(v) => Err(anyhow!("compositor thread panicked: {:?}", v)),
         }
     }
 
-    pub fn supports_stream(&self, params: VideoStreamParams) -> bool {
-        if params.width != self.display_params.width || params.height != self.display_params.height
-        {
-            return false;
+    /// Returns the parameters of an output, where zero is the primary output.
+    pub fn output(&self, output: usize) -> Option<OutputParams> {
+        match output {
+            0 => Some(self.display_params.into()),
+            n => self.additional_outputs.get(n - 1).copied(),
+        }
+    }
+
+    pub fn supports_stream(&self, params: VideoStreamParams, target: StreamTarget) -> bool {
+        // Windows are resized to match the stream.
+        if let StreamTarget::Output(idx) = target {
+            let Some(output) = self.output(idx) else {
+                return false;
+            };
+
+            if params.width != output.width || params.height != output.height {
+                return false;
+            }
         }
 
         probe_codec(self.vk.clone(), params.codec)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn activity() {
+        let activity = Activity::new();
+        assert!(activity.idle_duration() < time::Duration::from_millis(50));
+
+        std::thread::sleep(time::Duration::from_millis(50));
+        assert!(activity.idle_duration() >= time::Duration::from_millis(50));
+
+        activity.touch();
+        assert!(activity.idle_duration() < time::Duration::from_millis(50));
+    }
+}
@@ -397,6 +397,7 @@ impl PulseServer {
                     // Check for underrun.
                     let Some(frames) = stream.buffer.drain(num_frames as usize) else {
                         error!(id, "buffer underrun for stream");
+                        crate::metrics::record_audio_underrun();
                         pulse::write_command_message(
                             &mut client.socket,
                             u32::MAX,
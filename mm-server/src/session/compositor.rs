@@ -2,40 +2,53 @@
 //
 // SPDX-License-Identifier: BUSL-1.1
 
-use std::{collections::BTreeMap, sync::Arc};
+use std::{collections::BTreeMap, sync::Arc, time};
 
 use protocols::*;
 use slotmap::SlotMap;
 use tracing::{debug, instrument, trace};
 use wayland_protocols::{
+    ext::idle_notify::v1::server::ext_idle_notifier_v1,
     wp::{
+        color_management::v1::server::{
+            wp_color_management_output_v1, wp_color_management_surface_feedback_v1,
+            wp_color_manager_v1,
+        },
+        content_type::v1::server::wp_content_type_manager_v1,
+        cursor_shape::v1::server::wp_cursor_shape_manager_v1,
         fractional_scale::v1::server::wp_fractional_scale_manager_v1,
+        idle_inhibit::zv1::server::zwp_idle_inhibit_manager_v1,
         linux_dmabuf::zv1::server::zwp_linux_dmabuf_v1,
         linux_drm_syncobj::v1::server::wp_linux_drm_syncobj_manager_v1,
         pointer_constraints::zv1::server::zwp_pointer_constraints_v1,
         presentation_time::server::wp_presentation,
         relative_pointer::zv1::server::zwp_relative_pointer_manager_v1,
+        tearing_control::v1::server::wp_tearing_control_manager_v1,
         text_input::zv3::server::zwp_text_input_manager_v3,
+        viewporter::server::wp_viewporter,
     },
     xdg::shell::server::xdg_wm_base,
     xwayland::shell::v1::server::xwayland_shell_v1,
 };
 use wayland_server::{
-    protocol::{self, wl_output, wl_shm},
+    protocol::{self, wl_shm},
     Resource as _,
 };
 
 use crate::{
+    color::{ColorSpace, HdrMetadata},
+    encoder::FrameTiming,
     session::{
         control::*,
         video::{self, TextureSync},
-        SessionHandle,
+        Activity, SessionHandle,
     },
-    vulkan::VkContext,
+    vulkan::{VkContext, VkTimelinePoint},
 };
 
 pub mod buffers;
 mod dispatch;
+mod idle;
 mod oneshot_render;
 mod output;
 mod protocols;
@@ -48,6 +61,7 @@ pub mod surface;
 pub mod xwayland;
 
 pub use seat::{ButtonState, KeyState};
+pub use stack::WindowAction;
 
 use super::EPOCH;
 
@@ -62,14 +76,55 @@ pub struct Compositor {
     in_flight_buffers: Vec<surface::ContentUpdate>,
     pending_presentation_feedback: Vec<surface::PendingPresentationFeedback>,
 
+    // Set if anything visible changed since the last frame was composited.
+    damaged: bool,
+    last_frame_done: Option<VkTimelinePoint>,
+
+    // The first app commit that went into the next frame, for latency
+    // measurement.
+    first_commit_ts: Option<time::Instant>,
+
+    // Set if the active surface committed new content. Used to drive
+    // rendering in variable refresh mode.
+    variable_refresh: bool,
+    active_surface_committed: bool,
+
+    // Set if a surface that allows tearing committed new content, in which
+    // case we render immediately.
+    async_surface_committed: bool,
+
     surface_stack: Vec<surface::SurfaceKey>,
     active_surface: Option<surface::SurfaceKey>,
 
-    output_proxies: Vec<wl_output::WlOutput>,
+    // The last window list sent to clients, and whether it needs to be
+    // rebuilt because a window was mapped, unmapped, raised, or renamed.
+    window_list: Vec<WindowInfo>,
+    windows_changed: bool,
+
+    // The content type of each streamed output or window, and whether it needs
+    // to be recomputed because the stack, focus, or a hint changed.
+    content_types: BTreeMap<StreamTarget, ContentType>,
+    content_types_changed: bool,
+
+    // The last title and app_id of the focused window sent to clients.
+    window_metadata: (Option<String>, Option<String>),
+
+    outputs: Vec<output::Output>,
+
+    // Set if the attached stream is HDR10, in which case we tell clients to
+    // prefer HDR content.
+    hdr_output: bool,
+    color_management_outputs: Vec<wp_color_management_output_v1::WpColorManagementOutputV1>,
+    color_management_feedback:
+        Vec<wp_color_management_surface_feedback_v1::WpColorManagementSurfaceFeedbackV1>,
 
     // TODO: one seat per operator
     pub default_seat: seat::Seat,
 
+    idle_inhibitors: Vec<idle::IdleInhibitor>,
+    idle_notifications: Vec<idle::IdleNotification>,
+    activity: Arc<Activity>,
+
     display_params: DisplayParams,
     session_handle: SessionHandle,
 
@@ -85,6 +140,9 @@ impl Compositor {
         vk: Arc<VkContext>,
         handle: SessionHandle,
         display_params: DisplayParams,
+        additional_outputs: &[OutputParams],
+        variable_refresh: bool,
+        activity: Arc<Activity>,
     ) -> anyhow::Result<Self> {
         let cached_dmabuf_feedback = buffers::CachedDmabufFeedback::new(vk.clone())?;
 
@@ -99,13 +157,39 @@ impl Compositor {
             in_flight_buffers: Vec::new(),
             pending_presentation_feedback: Vec::new(),
 
+            damaged: true,
+            last_frame_done: None,
+            first_commit_ts: None,
+
+            variable_refresh,
+            active_surface_committed: false,
+            async_surface_committed: false,
+
             surface_stack: Vec::new(),
             active_surface: None,
 
-            output_proxies: Vec::new(),
+            window_list: Vec::new(),
+            windows_changed: false,
+
+            content_types: BTreeMap::new(),
+            content_types_changed: false,
+            window_metadata: (None, None),
+
+            outputs: std::iter::once(display_params.into())
+                .chain(additional_outputs.iter().copied())
+                .map(output::Output::new)
+                .collect(),
+
+            hdr_output: false,
+            color_management_outputs: Vec::new(),
+            color_management_feedback: Vec::new(),
 
             default_seat: seat::Seat::default(),
 
+            idle_inhibitors: Vec::new(),
+            idle_notifications: Vec::new(),
+            activity,
+
             display_params,
             session_handle: handle.clone(),
 
@@ -122,6 +206,7 @@ impl Compositor {
         active: bool,
     ) -> anyhow::Result<()> {
         let now = EPOCH.elapsed().as_millis() as u32;
+        self.outputs[0].params = display_params.into();
 
         // Reconfigure all surfaces to be the right size.
         for surface in &self.surface_stack {
@@ -135,16 +220,18 @@ impl Compositor {
                 }
             });
 
-            surf.reconfigure(display_params, xwin);
+            surf.reconfigure(&self.outputs, xwin);
 
-            if display_params.width != self.display_params.width
-                || display_params.height != self.display_params.height
-                || display_params.ui_scale != self.display_params.ui_scale
+            let on_primary = surf.configuration.is_some_and(|conf| conf.output == 0);
+            if on_primary
+                && (display_params.width != self.display_params.width
+                    || display_params.height != self.display_params.height
+                    || display_params.ui_scale != self.display_params.ui_scale)
             {
                 // Try to trick the surface into thinking it's moving to a
                 // different monitor. This helps some games adjust to mode
                 // changes.
-                for wl_output in &self.output_proxies {
+                for wl_output in &self.outputs[0].proxies {
                     if wl_output.client() == surf.wl_surface.client() {
                         surf.wl_surface.leave(wl_output);
                         surf.wl_surface.enter(wl_output);
@@ -163,42 +250,178 @@ impl Compositor {
         self.update_focus_and_visibility(active)?;
         self.display_params = display_params;
         self.emit_output_params();
+        self.damaged = true;
+        self.windows_changed = true;
+        self.content_types_changed = true;
 
         Ok(())
     }
 
+    /// Sets whether the output is HDR, and notifies clients using
+    /// wp_color_management_v1 that the preferred image description changed.
+    pub fn set_hdr_output(&mut self, hdr: bool) {
+        if hdr == self.hdr_output {
+            return;
+        }
+
+        self.hdr_output = hdr;
+        let (identity, _) = self.preferred_image_description();
+
+        for output in &self.color_management_outputs {
+            output.image_description_changed();
+        }
+
+        for feedback in &self.color_management_feedback {
+            feedback.preferred_changed(identity);
+        }
+    }
+
+    /// Returns the image description clients should prefer for content,
+    /// along with its identity.
+    fn preferred_image_description(&self) -> (u32, surface::ImageDescription) {
+        // These don't collide with serials, which start at 1000.
+        if self.hdr_output {
+            (
+                2,
+                surface::ImageDescription {
+                    color_space: ColorSpace::Hdr10,
+                    hdr_metadata: Some(HdrMetadata::default()),
+                },
+            )
+        } else {
+            (1, surface::ImageDescription::default())
+        }
+    }
+
+    /// Composites visible surfaces for each output or window with an encode
+    /// pipeline, and submits the results for encoding. If nothing has been
+    /// damaged since the last frame, and no encoder needs a new frame,
+    /// compositing is skipped entirely. Returns true if any frame was
+    /// submitted.
+    ///
+    /// If `vsync` is false, the frame is rendered out of band for a surface
+    /// that allows tearing, and only outputs and windows showing such a
+    /// surface are composited.
     #[instrument(skip_all)]
     pub fn composite_frame(
         &mut self,
-        video_pipeline: &mut video::EncodePipeline,
-    ) -> anyhow::Result<()> {
+        video_pipelines: &mut BTreeMap<StreamTarget, video::EncodePipeline>,
+        vsync: bool,
+    ) -> anyhow::Result<bool> {
         let now = EPOCH.elapsed().as_millis() as u32;
+
+        let mut rendered = false;
+        let mut dropped = false;
+        for idx in 0..self.outputs.len() {
+            let visible = self.visible_surfaces(idx);
+            let origin = self.outputs[idx].params.origin();
+            if !vsync && !self.any_async(&visible) {
+                continue;
+            }
+
+            match video_pipelines.get_mut(&StreamTarget::Output(idx)) {
+                Some(pipeline) if self.damaged || pipeline.needs_frame() => {
+                    if self.composite_surfaces(idx, origin, &visible, pipeline, now)? {
+                        rendered = true;
+                    } else {
+                        dropped = true;
+                    }
+                }
+                Some(_) => {
+                    trace!(output = idx, "skipping frame, nothing damaged");
+                    self.skip_frame(idx, &visible, now);
+                }
+                None => {
+                    // Nobody is watching this output, but the app shouldn't
+                    // stall waiting for frame callbacks.
+                    self.skip_frame(idx, &visible, now);
+                }
+            }
+        }
+
+        // Streamed windows are composited on their own, whether or not
+        // they're visible on an output.
+        for (target, pipeline) in video_pipelines.iter_mut() {
+            let StreamTarget::Window(id) = *target else {
+                continue;
+            };
+
+            // The window may have just closed.
+            let Some((idx, origin, surfaces)) = self.window_surfaces(id) else {
+                continue;
+            };
+
+            if !vsync && !self.any_async(&surfaces) {
+                continue;
+            }
+
+            if !self.damaged && !pipeline.needs_frame() {
+                self.skip_frame(idx, &surfaces, now);
+            } else if self.composite_surfaces(idx, origin, &surfaces, pipeline, now)? {
+                rendered = true;
+            } else {
+                dropped = true;
+            }
+        }
+
+        if rendered {
+            self.first_commit_ts = None;
+            self.active_surface_committed = false;
+            self.async_surface_committed = false;
+
+            // If an output dropped the frame, or wasn't composited, it still
+            // needs the damage.
+            if !dropped && vsync {
+                self.damaged = false;
+            }
+        }
+
+        Ok(rendered)
+    }
+
+    /// Composites a set of surfaces, positioned relative to `origin`. The
+    /// output is used for presentation feedback. Returns false if the frame
+    /// was dropped.
+    fn composite_surfaces(
+        &mut self,
+        idx: usize,
+        origin: glam::UVec2,
+        visible: &[surface::SurfaceKey],
+        video_pipeline: &mut video::EncodePipeline,
+        now: u32,
+    ) -> anyhow::Result<bool> {
+        let composite_start = time::Instant::now();
         let ready = unsafe { video_pipeline.begin()? };
         if !ready {
-            debug!("dropped frame because of backpressure");
-            return Ok(());
+            debug!(output = idx, "dropped frame because of backpressure");
+            return Ok(false);
         }
 
-        // Iterate backwards to find the first fullscreen window.
-        let first_visible_idx = self
-            .surface_stack
-            .iter()
-            .rposition(|id| {
-                self.surfaces[*id]
-                    .configuration
-                    .map_or(true, |conf| conf.fullscreen)
-            })
-            .unwrap_or_default();
+        let mut presentation_feedback = Vec::with_capacity(visible.len());
 
-        let num_surfaces = self.surface_stack.len() - first_visible_idx;
-        let mut presentation_feedback = Vec::with_capacity(num_surfaces);
+        // Pass through HDR metadata from the topmost HDR surface.
+        let hdr_metadata = visible.iter().rev().find_map(|id| {
+            self.surfaces[*id]
+                .effective_image_description()
+                .hdr_metadata
+        });
+        video_pipeline.set_hdr_metadata(hdr_metadata);
 
-        for id in self.surface_stack[first_visible_idx..].iter() {
+        for id in visible {
             let surface = &mut self.surfaces[*id];
 
             let conf = surface
                 .configuration
                 .expect("mapped surface has no configuration");
+            let color_space = surface.effective_image_description().color_space;
+            let src = surface.texture_source_rect();
+
+            // Surfaces are positioned in the global space, but the pipeline
+            // draws relative to the output or window.
+            let conf = surface::SurfaceConfiguration {
+                topleft: conf.topleft.max(origin) - origin,
+                ..conf
+            };
 
             let content = surface
                 .content
@@ -218,25 +441,95 @@ impl Compositor {
                 _ => None,
             };
 
-            unsafe { content.tp_done = video_pipeline.composite_surface(buffer, sync, conf)? };
+            let tp_done =
+                unsafe { video_pipeline.composite_surface(buffer, sync, src, conf, color_space)? };
+
+            // Later points on the same timeline supersede earlier ones.
+            if let Some(tp) = tp_done {
+                content.tp_done.retain(|other| !other.same_timeline(&tp));
+                content.tp_done.push(tp);
+            }
             if let Some(callback) = surface.frame_callback.current.take().as_mut() {
                 callback.done(now);
             }
 
             if let Some(fb) = content.wp_presentation_feedback.take() {
-                presentation_feedback.push(fb);
+                presentation_feedback.push((fb, surface.presentation_kind()));
             }
 
-            trace!(?surface, ?conf, "compositing surface");
+            trace!(?surface, ?conf, output = idx, "compositing surface");
         }
 
-        let tp_render = unsafe { video_pipeline.end_and_submit()? };
-        for fb in presentation_feedback.drain(..) {
+        let timing = FrameTiming {
+            app_commit: self.first_commit_ts,
+            composite_start: Some(composite_start),
+            ..Default::default()
+        };
+
+        let tp_render = unsafe { video_pipeline.end_and_submit(timing)? };
+        for (fb, kind) in presentation_feedback.drain(..) {
             self.pending_presentation_feedback
-                .push(surface::PendingPresentationFeedback(fb, tp_render.clone()));
+                .push(surface::PendingPresentationFeedback(
+                    fb,
+                    tp_render.clone(),
+                    idx,
+                    kind,
+                ));
         }
 
-        Ok(())
+        self.last_frame_done = Some(tp_render);
+        Ok(true)
+    }
+
+    /// Discharges callbacks and feedback for surfaces as if the previous frame
+    /// had been rendered again.
+    fn skip_frame(&mut self, idx: usize, visible: &[surface::SurfaceKey], now: u32) {
+        for id in visible {
+            let surface = &mut self.surfaces[*id];
+            if let Some(callback) = surface.frame_callback.current.take() {
+                callback.done(now);
+            }
+
+            let Some(fb) = surface
+                .content
+                .as_mut()
+                .and_then(|content| content.wp_presentation_feedback.take())
+            else {
+                continue;
+            };
+
+            match &self.last_frame_done {
+                Some(tp) => {
+                    self.pending_presentation_feedback
+                        .push(surface::PendingPresentationFeedback(
+                            fb,
+                            tp.clone(),
+                            idx,
+                            surface.presentation_kind(),
+                        ))
+                }
+                None => fb.discarded(),
+            }
+        }
+    }
+
+    /// Returns true if the active surface has committed new content since the
+    /// last call, and resets the flag.
+    pub fn take_active_surface_commit(&mut self) -> bool {
+        std::mem::take(&mut self.active_surface_committed)
+    }
+
+    /// Returns true if any of the surfaces allows tearing.
+    fn any_async(&self, surfaces: &[surface::SurfaceKey]) -> bool {
+        surfaces
+            .iter()
+            .any(|id| self.surfaces[*id].async_presentation.current == Some(true))
+    }
+
+    /// Returns true if a visible surface that allows tearing has committed
+    /// new content since the last call, and resets the flag.
+    pub fn take_async_surface_commit(&mut self) -> bool {
+        std::mem::take(&mut self.async_surface_committed)
     }
 
     pub fn idle(&mut self, active: bool) -> anyhow::Result<()> {
@@ -258,6 +551,15 @@ impl Compositor {
         // Send presentation feedback.
         self.send_presentation_feedback()?;
 
+        // Tell clients whether the user is idle.
+        self.update_idle_notifications();
+
+        // Tell clients about new, closed or renamed windows.
+        if std::mem::take(&mut self.windows_changed) {
+            self.dispatch_window_list(false);
+            self.dispatch_window_metadata(false);
+        }
+
         Ok(())
     }
 }
@@ -277,21 +579,31 @@ impl wayland_server::backend::ClientData for ClientState {
     }
 }
 
-pub fn create_globals(dh: &wayland_server::DisplayHandle) {
+pub fn create_globals(dh: &wayland_server::DisplayHandle, num_outputs: usize) {
     create_global::<protocol::wl_compositor::WlCompositor>(dh, 6);
-    create_global::<protocol::wl_output::WlOutput>(dh, 4);
+    for idx in 0..num_outputs {
+        let _ = dh.create_global::<Compositor, protocol::wl_output::WlOutput, usize>(4, idx);
+    }
+
     create_global::<xdg_wm_base::XdgWmBase>(dh, 6);
     create_global::<wp_fractional_scale_manager_v1::WpFractionalScaleManagerV1>(dh, 1);
+    create_global::<wp_color_manager_v1::WpColorManagerV1>(dh, 1);
+    create_global::<wp_viewporter::WpViewporter>(dh, 1);
+    create_global::<wp_content_type_manager_v1::WpContentTypeManagerV1>(dh, 1);
 
     create_global::<protocol::wl_seat::WlSeat>(dh, 9);
     create_global::<protocol::wl_data_device_manager::WlDataDeviceManager>(dh, 3);
     create_global::<zwp_pointer_constraints_v1::ZwpPointerConstraintsV1>(dh, 1);
     create_global::<zwp_relative_pointer_manager_v1::ZwpRelativePointerManagerV1>(dh, 1);
+    create_global::<wp_cursor_shape_manager_v1::WpCursorShapeManagerV1>(dh, 1);
     create_global::<zwp_text_input_manager_v3::ZwpTextInputManagerV3>(dh, 1);
+    create_global::<zwp_idle_inhibit_manager_v1::ZwpIdleInhibitManagerV1>(dh, 1);
+    create_global::<ext_idle_notifier_v1::ExtIdleNotifierV1>(dh, 1);
 
     create_global::<wl_shm::WlShm>(dh, 1);
     create_global::<zwp_linux_dmabuf_v1::ZwpLinuxDmabufV1>(dh, 5);
     create_global::<wp_presentation::WpPresentation>(dh, 1);
+    create_global::<wp_tearing_control_manager_v1::WpTearingControlManagerV1>(dh, 1);
     create_global::<wp_linux_drm_syncobj_manager_v1::WpLinuxDrmSyncobjManagerV1>(dh, 1);
 
     create_global::<xwayland_shell_v1::XwaylandShellV1>(dh, 1);
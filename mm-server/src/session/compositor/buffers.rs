@@ -77,14 +77,20 @@ impl Compositor {
         // Check if any content updates have finished.
         let mut still_in_flight = Vec::new();
         for content in self.in_flight_buffers.drain(..) {
-            if let Some(tp) = &content.tp_done {
+            let mut in_use = false;
+            for tp in &content.tp_done {
                 if unsafe { !tp.poll()? } {
-                    // The frame using this content is still in-progress.
-                    still_in_flight.push(content);
-                    continue;
+                    in_use = true;
+                    break;
                 }
             }
 
+            if in_use {
+                // A frame using this content is still in-progress.
+                still_in_flight.push(content);
+                continue;
+            }
+
             if content.needs_release {
                 let buffer = self
                     .buffers
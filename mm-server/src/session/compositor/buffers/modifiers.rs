@@ -23,6 +23,30 @@ pub const SUPPORTED_DRM_FORMATS: &[(DrmFourcc, vk::Format, bool, usize)] = &[
     (DrmFourcc::Xrgb8888, vk::Format::B8G8R8A8_UNORM, true, 4),
     (DrmFourcc::Abgr8888, vk::Format::R8G8B8A8_UNORM, false, 4),
     (DrmFourcc::Xbgr8888, vk::Format::R8G8B8A8_UNORM, true, 4),
+    (
+        DrmFourcc::Argb2101010,
+        vk::Format::A2R10G10B10_UNORM_PACK32,
+        false,
+        4,
+    ),
+    (
+        DrmFourcc::Xrgb2101010,
+        vk::Format::A2R10G10B10_UNORM_PACK32,
+        true,
+        4,
+    ),
+    (
+        DrmFourcc::Abgr2101010,
+        vk::Format::A2B10G10R10_UNORM_PACK32,
+        false,
+        4,
+    ),
+    (
+        DrmFourcc::Xbgr2101010,
+        vk::Format::A2B10G10R10_UNORM_PACK32,
+        true,
+        4,
+    ),
     (
         DrmFourcc::Argb16161616f,
         vk::Format::R16G16B16A16_SFLOAT,
@@ -2,6 +2,7 @@
 //
 // SPDX-License-Identifier: BUSL-1.1
 
+mod ext_idle_notify;
 mod wl_buffer;
 mod wl_compositor;
 mod wl_data_device_manager;
@@ -9,13 +10,19 @@ mod wl_drm;
 mod wl_output;
 mod wl_seat;
 mod wl_shm;
+mod wp_color_management;
+mod wp_content_type;
+mod wp_cursor_shape;
 mod wp_fractional_scale;
+mod wp_idle_inhibit;
 mod wp_linux_dmabuf;
 mod wp_linux_drm_syncobj;
 mod wp_pointer_constraints;
 mod wp_presentation;
 mod wp_relative_pointer;
+mod wp_tearing_control;
 mod wp_text_input;
+mod wp_viewporter;
 mod xdg_shell;
 mod xwayland_shell;
 
@@ -0,0 +1,74 @@
+// Copyright 2024 Colin Marc <hi@colinmarc.com>
+//
+// SPDX-License-Identifier: BUSL-1.1
+
+use std::time;
+
+use wayland_protocols::ext::idle_notify::v1::server::{
+    ext_idle_notification_v1, ext_idle_notifier_v1,
+};
+
+use crate::session::compositor::{idle::IdleNotification, Compositor};
+
+impl wayland_server::GlobalDispatch<ext_idle_notifier_v1::ExtIdleNotifierV1, ()> for Compositor {
+    fn bind(
+        _state: &mut Self,
+        _handle: &wayland_server::DisplayHandle,
+        _client: &wayland_server::Client,
+        resource: wayland_server::New<ext_idle_notifier_v1::ExtIdleNotifierV1>,
+        _global_data: &(),
+        data_init: &mut wayland_server::DataInit<'_, Self>,
+    ) {
+        data_init.init(resource, ());
+    }
+}
+
+impl wayland_server::Dispatch<ext_idle_notifier_v1::ExtIdleNotifierV1, ()> for Compositor {
+    fn request(
+        state: &mut Self,
+        _client: &wayland_server::Client,
+        _resource: &ext_idle_notifier_v1::ExtIdleNotifierV1,
+        request: ext_idle_notifier_v1::Request,
+        _data: &(),
+        _dhandle: &wayland_server::DisplayHandle,
+        data_init: &mut wayland_server::DataInit<'_, Self>,
+    ) {
+        match request {
+            ext_idle_notifier_v1::Request::GetIdleNotification { id, timeout, .. } => {
+                // There's only one seat, and input from any client counts.
+                let ext_idle_notification = data_init.init(id, ());
+                state.idle_notifications.push(IdleNotification {
+                    ext_idle_notification,
+                    timeout: time::Duration::from_millis(timeout as u64),
+                    idled: false,
+                });
+            }
+            ext_idle_notifier_v1::Request::Destroy => (),
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl wayland_server::Dispatch<ext_idle_notification_v1::ExtIdleNotificationV1, ()> for Compositor {
+    fn request(
+        _state: &mut Self,
+        _client: &wayland_server::Client,
+        _resource: &ext_idle_notification_v1::ExtIdleNotificationV1,
+        _request: ext_idle_notification_v1::Request,
+        _data: &(),
+        _dhandle: &wayland_server::DisplayHandle,
+        _data_init: &mut wayland_server::DataInit<'_, Self>,
+    ) {
+    }
+
+    fn destroyed(
+        state: &mut Self,
+        _client: wayland_server::backend::ClientId,
+        resource: &ext_idle_notification_v1::ExtIdleNotificationV1,
+        _data: &(),
+    ) {
+        state
+            .idle_notifications
+            .retain(|notification| &notification.ext_idle_notification != resource);
+    }
+}
@@ -120,9 +120,15 @@ impl wayland_server::Dispatch<wl_surface::WlSurface, SurfaceKey> for Compositor
                     warn!(x, y, "ignoring nonzero buffer offset");
                 }
             }
-            // We ignore damage and don't do any related optimizations.
-            wl_surface::Request::DamageBuffer { .. } => (),
-            wl_surface::Request::Damage { .. } => (),
+            // We don't track damaged regions, just whether the surface was
+            // damaged at all.
+            wl_surface::Request::DamageBuffer { .. } | wl_surface::Request::Damage { .. } => {
+                state
+                    .surfaces
+                    .get_mut(*data)
+                    .expect("surface has no entry")
+                    .pending_damage = true;
+            }
             // We ignore input and opaque regions, because we don't support subcompositing.
             wl_surface::Request::SetOpaqueRegion { .. } => (),
             wl_surface::Request::SetInputRegion { .. } => (),
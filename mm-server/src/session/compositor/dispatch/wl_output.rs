@@ -6,29 +6,40 @@ use wayland_server::protocol::wl_output;
 
 use crate::session::compositor::{output::configure_output, Compositor};
 
-impl wayland_server::GlobalDispatch<wl_output::WlOutput, ()> for Compositor {
+// The data is the index of the output.
+impl wayland_server::GlobalDispatch<wl_output::WlOutput, usize> for Compositor {
     fn bind(
         state: &mut Self,
         _handle: &wayland_server::DisplayHandle,
         _client: &wayland_server::Client,
         resource: wayland_server::New<wl_output::WlOutput>,
-        _global_data: &(),
+        global_data: &usize,
         data_init: &mut wayland_server::DataInit<'_, Self>,
     ) {
-        let wl_output = data_init.init(resource, ());
-        configure_output(&wl_output, state.display_params);
+        let idx = *global_data;
+        let wl_output = data_init.init(resource, idx);
 
-        state.output_proxies.push(wl_output);
+        let Some(output) = state.outputs.get_mut(idx) else {
+            return;
+        };
+
+        configure_output(
+            &wl_output,
+            idx,
+            output.params,
+            state.display_params.framerate,
+        );
+        output.proxies.push(wl_output);
     }
 }
 
-impl wayland_server::Dispatch<wl_output::WlOutput, ()> for Compositor {
+impl wayland_server::Dispatch<wl_output::WlOutput, usize> for Compositor {
     fn request(
         _state: &mut Self,
         _client: &wayland_server::Client,
         _resource: &wl_output::WlOutput,
         _request: wl_output::Request,
-        _data: &(),
+        _data: &usize,
         _dhandle: &wayland_server::DisplayHandle,
         _data_init: &mut wayland_server::DataInit<'_, Self>,
     ) {
@@ -38,8 +49,10 @@ impl wayland_server::Dispatch<wl_output::WlOutput, ()> for Compositor {
         state: &mut Self,
         _client: wayland_server::backend::ClientId,
         resource: &wl_output::WlOutput,
-        _data: &(),
+        data: &usize,
     ) {
-        state.output_proxies.retain(|o| o == resource);
+        if let Some(output) = state.outputs.get_mut(*data) {
+            output.proxies.retain(|o| o != resource);
+        }
     }
 }
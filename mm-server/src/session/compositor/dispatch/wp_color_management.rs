@@ -0,0 +1,651 @@
+// Copyright 2024 Colin Marc <hi@colinmarc.com>
+//
+// SPDX-License-Identifier: BUSL-1.1
+
+use std::sync::Mutex;
+
+use tracing::debug;
+use wayland_protocols::wp::color_management::v1::server::{
+    wp_color_management_output_v1, wp_color_management_surface_feedback_v1,
+    wp_color_management_surface_v1, wp_color_manager_v1, wp_image_description_creator_icc_v1,
+    wp_image_description_creator_params_v1, wp_image_description_info_v1, wp_image_description_v1,
+};
+use wayland_server::{Resource as _, WEnum};
+
+use crate::{
+    color::{ColorSpace, HdrMetadata, Primaries, TransferFunction},
+    session::compositor::{
+        surface::{ImageDescription, SurfaceKey},
+        Compositor,
+    },
+};
+
+/// User data for wp_image_description_v1. The description is None if
+/// creation failed.
+pub struct ImageDescriptionData {
+    desc: Option<ImageDescription>,
+    // Only descriptions created by the compositor may be queried.
+    allow_info: bool,
+}
+
+#[derive(Default)]
+pub struct ParamsCreator {
+    tf: Option<TransferFunction>,
+    primaries: Option<Primaries>,
+    mastering_primaries: Option<([(u32, u32); 3], (u32, u32))>,
+    mastering_luminance: Option<(u32, u32)>,
+    max_cll: Option<u32>,
+    max_fall: Option<u32>,
+}
+
+impl wayland_server::GlobalDispatch<wp_color_manager_v1::WpColorManagerV1, ()> for Compositor {
+    fn bind(
+        _state: &mut Self,
+        _handle: &wayland_server::DisplayHandle,
+        _client: &wayland_server::Client,
+        resource: wayland_server::New<wp_color_manager_v1::WpColorManagerV1>,
+        _global_data: &(),
+        data_init: &mut wayland_server::DataInit<'_, Self>,
+    ) {
+        let wp_color_manager = data_init.init(resource, ());
+
+        wp_color_manager.supported_intent(wp_color_manager_v1::RenderIntent::Perceptual);
+        wp_color_manager.supported_feature(wp_color_manager_v1::Feature::Parametric);
+        wp_color_manager
+            .supported_feature(wp_color_manager_v1::Feature::SetMasteringDisplayPrimaries);
+        wp_color_manager.supported_feature(wp_color_manager_v1::Feature::WindowsScrgb);
+
+        wp_color_manager.supported_tf_named(wp_color_manager_v1::TransferFunction::Srgb);
+        wp_color_manager.supported_tf_named(wp_color_manager_v1::TransferFunction::ExtLinear);
+        wp_color_manager.supported_tf_named(wp_color_manager_v1::TransferFunction::St2084Pq);
+
+        wp_color_manager.supported_primaries_named(wp_color_manager_v1::Primaries::Srgb);
+        wp_color_manager.supported_primaries_named(wp_color_manager_v1::Primaries::Bt2020);
+
+        wp_color_manager.done();
+    }
+}
+
+impl wayland_server::Dispatch<wp_color_manager_v1::WpColorManagerV1, ()> for Compositor {
+    fn request(
+        state: &mut Self,
+        _client: &wayland_server::Client,
+        resource: &wp_color_manager_v1::WpColorManagerV1,
+        request: wp_color_manager_v1::Request,
+        _data: &(),
+        _dhandle: &wayland_server::DisplayHandle,
+        data_init: &mut wayland_server::DataInit<'_, Self>,
+    ) {
+        match request {
+            wp_color_manager_v1::Request::GetOutput { id, .. } => {
+                let wp_color_management_output = data_init.init(id, ());
+                state
+                    .color_management_outputs
+                    .push(wp_color_management_output);
+            }
+            wp_color_manager_v1::Request::GetSurface { id, surface } => {
+                if let Some(surface_key) = surface.data::<SurfaceKey>() {
+                    let wp_color_management_surface = data_init.init(id, *surface_key);
+
+                    let surface = state
+                        .surfaces
+                        .get_mut(*surface_key)
+                        .expect("surface has no entry");
+
+                    if surface.wp_color_management_surface.is_some() {
+                        resource.post_error(
+                            wp_color_manager_v1::Error::SurfaceExists,
+                            "A color management surface already exists for that wl_surface.",
+                        );
+                        return;
+                    }
+
+                    surface.wp_color_management_surface = Some(wp_color_management_surface);
+                }
+            }
+            wp_color_manager_v1::Request::GetSurfaceFeedback { id, surface } => {
+                if let Some(surface_key) = surface.data::<SurfaceKey>() {
+                    let wp_feedback = data_init.init(id, *surface_key);
+                    state.color_management_feedback.push(wp_feedback);
+                }
+            }
+            wp_color_manager_v1::Request::CreateIccCreator { obj } => {
+                data_init.init(obj, ());
+                resource.post_error(
+                    wp_color_manager_v1::Error::UnsupportedFeature,
+                    "ICC-based image descriptions are not supported.",
+                );
+            }
+            wp_color_manager_v1::Request::CreateParametricCreator { obj } => {
+                data_init.init(obj, Mutex::new(ParamsCreator::default()));
+            }
+            wp_color_manager_v1::Request::CreateWindowsScrgb { image_description } => {
+                let desc = ImageDescription {
+                    color_space: ColorSpace::LinearExtSrgb,
+                    hdr_metadata: None,
+                };
+
+                let wp_image_description = data_init.init(
+                    image_description,
+                    ImageDescriptionData {
+                        desc: Some(desc),
+                        allow_info: false,
+                    },
+                );
+
+                wp_image_description.ready(state.serial.next());
+            }
+            wp_color_manager_v1::Request::Destroy => (),
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl wayland_server::Dispatch<wp_color_management_output_v1::WpColorManagementOutputV1, ()>
+    for Compositor
+{
+    fn request(
+        state: &mut Self,
+        _client: &wayland_server::Client,
+        _resource: &wp_color_management_output_v1::WpColorManagementOutputV1,
+        request: wp_color_management_output_v1::Request,
+        _data: &(),
+        _dhandle: &wayland_server::DisplayHandle,
+        data_init: &mut wayland_server::DataInit<'_, Self>,
+    ) {
+        match request {
+            wp_color_management_output_v1::Request::GetImageDescription { image_description } => {
+                let (identity, desc) = state.preferred_image_description();
+                let wp_image_description = data_init.init(
+                    image_description,
+                    ImageDescriptionData {
+                        desc: Some(desc),
+                        allow_info: true,
+                    },
+                );
+
+                wp_image_description.ready(identity);
+            }
+            wp_color_management_output_v1::Request::Destroy => (),
+            _ => unreachable!(),
+        }
+    }
+
+    fn destroyed(
+        state: &mut Self,
+        _client: wayland_server::backend::ClientId,
+        resource: &wp_color_management_output_v1::WpColorManagementOutputV1,
+        _data: &(),
+    ) {
+        state
+            .color_management_outputs
+            .retain(|output| output != resource);
+    }
+}
+
+impl
+    wayland_server::Dispatch<wp_color_management_surface_v1::WpColorManagementSurfaceV1, SurfaceKey>
+    for Compositor
+{
+    fn request(
+        state: &mut Self,
+        _client: &wayland_server::Client,
+        resource: &wp_color_management_surface_v1::WpColorManagementSurfaceV1,
+        request: wp_color_management_surface_v1::Request,
+        surface_key: &SurfaceKey,
+        _dhandle: &wayland_server::DisplayHandle,
+        _data_init: &mut wayland_server::DataInit<'_, Self>,
+    ) {
+        let Some(surface) = state.surfaces.get_mut(*surface_key) else {
+            if !matches!(request, wp_color_management_surface_v1::Request::Destroy) {
+                resource.post_error(
+                    wp_color_management_surface_v1::Error::Inert,
+                    "The wl_surface was destroyed.",
+                );
+            }
+
+            return;
+        };
+
+        match request {
+            wp_color_management_surface_v1::Request::SetImageDescription {
+                image_description,
+                render_intent,
+            } => {
+                if !matches!(
+                    render_intent,
+                    WEnum::Value(wp_color_manager_v1::RenderIntent::Perceptual)
+                ) {
+                    resource.post_error(
+                        wp_color_management_surface_v1::Error::RenderIntent,
+                        "Unsupported rendering intent.",
+                    );
+                    return;
+                }
+
+                let Some(desc) = image_description
+                    .data::<ImageDescriptionData>()
+                    .and_then(|data| data.desc)
+                else {
+                    resource.post_error(
+                        wp_color_management_surface_v1::Error::ImageDescription,
+                        "The image description is not ready.",
+                    );
+                    return;
+                };
+
+                surface.image_description.pending = Some(desc);
+            }
+            wp_color_management_surface_v1::Request::UnsetImageDescription => {
+                surface.image_description.pending = Some(ImageDescription::default());
+            }
+            wp_color_management_surface_v1::Request::Destroy => (),
+            _ => unreachable!(),
+        }
+    }
+
+    fn destroyed(
+        state: &mut Self,
+        _client: wayland_server::backend::ClientId,
+        _resource: &wp_color_management_surface_v1::WpColorManagementSurfaceV1,
+        surface_key: &SurfaceKey,
+    ) {
+        if let Some(surface) = state.surfaces.get_mut(*surface_key) {
+            surface.wp_color_management_surface = None;
+            surface.image_description.pending = Some(ImageDescription::default());
+        }
+    }
+}
+
+impl
+    wayland_server::Dispatch<
+        wp_color_management_surface_feedback_v1::WpColorManagementSurfaceFeedbackV1,
+        SurfaceKey,
+    > for Compositor
+{
+    fn request(
+        state: &mut Self,
+        _client: &wayland_server::Client,
+        resource: &wp_color_management_surface_feedback_v1::WpColorManagementSurfaceFeedbackV1,
+        request: wp_color_management_surface_feedback_v1::Request,
+        surface_key: &SurfaceKey,
+        _dhandle: &wayland_server::DisplayHandle,
+        data_init: &mut wayland_server::DataInit<'_, Self>,
+    ) {
+        let image_description = match request {
+            wp_color_management_surface_feedback_v1::Request::GetPreferred {
+                image_description,
+            }
+            | wp_color_management_surface_feedback_v1::Request::GetPreferredParametric {
+                image_description,
+            } => image_description,
+            wp_color_management_surface_feedback_v1::Request::Destroy => return,
+            _ => unreachable!(),
+        };
+
+        if !state.surfaces.contains_key(*surface_key) {
+            data_init.init(
+                image_description,
+                ImageDescriptionData {
+                    desc: None,
+                    allow_info: false,
+                },
+            );
+
+            resource.post_error(
+                wp_color_management_surface_feedback_v1::Error::Inert,
+                "The wl_surface was destroyed.",
+            );
+            return;
+        }
+
+        let (identity, desc) = state.preferred_image_description();
+        let wp_image_description = data_init.init(
+            image_description,
+            ImageDescriptionData {
+                desc: Some(desc),
+                allow_info: true,
+            },
+        );
+
+        wp_image_description.ready(identity);
+    }
+
+    fn destroyed(
+        state: &mut Self,
+        _client: wayland_server::backend::ClientId,
+        resource: &wp_color_management_surface_feedback_v1::WpColorManagementSurfaceFeedbackV1,
+        _surface_key: &SurfaceKey,
+    ) {
+        state
+            .color_management_feedback
+            .retain(|feedback| feedback != resource);
+    }
+}
+
+impl
+    wayland_server::Dispatch<
+        wp_image_description_creator_icc_v1::WpImageDescriptionCreatorIccV1,
+        (),
+    > for Compositor
+{
+    fn request(
+        _state: &mut Self,
+        _client: &wayland_server::Client,
+        _resource: &wp_image_description_creator_icc_v1::WpImageDescriptionCreatorIccV1,
+        _request: wp_image_description_creator_icc_v1::Request,
+        _data: &(),
+        _dhandle: &wayland_server::DisplayHandle,
+        _data_init: &mut wayland_server::DataInit<'_, Self>,
+    ) {
+        // We never advertise support, and post an error on creation.
+    }
+}
+
+impl
+    wayland_server::Dispatch<
+        wp_image_description_creator_params_v1::WpImageDescriptionCreatorParamsV1,
+        Mutex<ParamsCreator>,
+    > for Compositor
+{
+    fn request(
+        state: &mut Self,
+        _client: &wayland_server::Client,
+        resource: &wp_image_description_creator_params_v1::WpImageDescriptionCreatorParamsV1,
+        request: wp_image_description_creator_params_v1::Request,
+        data: &Mutex<ParamsCreator>,
+        _dhandle: &wayland_server::DisplayHandle,
+        data_init: &mut wayland_server::DataInit<'_, Self>,
+    ) {
+        use wp_image_description_creator_params_v1::{Error, Request};
+
+        let mut creator = data.lock().unwrap();
+        let already_set = || resource.post_error(Error::AlreadySet, "Property already set.");
+
+        match request {
+            Request::SetTfNamed { tf } => {
+                if creator.tf.is_some() {
+                    return already_set();
+                }
+
+                creator.tf = Some(match tf {
+                    WEnum::Value(wp_color_manager_v1::TransferFunction::Srgb) => {
+                        TransferFunction::Srgb
+                    }
+                    WEnum::Value(wp_color_manager_v1::TransferFunction::ExtLinear) => {
+                        TransferFunction::Linear
+                    }
+                    WEnum::Value(wp_color_manager_v1::TransferFunction::St2084Pq) => {
+                        TransferFunction::Pq
+                    }
+                    _ => {
+                        resource.post_error(Error::InvalidTf, "Unsupported transfer function.");
+                        return;
+                    }
+                });
+            }
+            Request::SetPrimariesNamed { primaries } => {
+                if creator.primaries.is_some() {
+                    return already_set();
+                }
+
+                creator.primaries = Some(match primaries {
+                    WEnum::Value(wp_color_manager_v1::Primaries::Srgb) => Primaries::Srgb,
+                    WEnum::Value(wp_color_manager_v1::Primaries::Bt2020) => Primaries::Bt2020,
+                    _ => {
+                        resource.post_error(Error::InvalidPrimariesNamed, "Unsupported primaries.");
+                        return;
+                    }
+                });
+            }
+            Request::SetMasteringDisplayPrimaries {
+                r_x,
+                r_y,
+                g_x,
+                g_y,
+                b_x,
+                b_y,
+                w_x,
+                w_y,
+            } => {
+                if creator.mastering_primaries.is_some() {
+                    return already_set();
+                }
+
+                let c = |v: i32| v.max(0) as u32;
+                creator.mastering_primaries = Some((
+                    [(c(r_x), c(r_y)), (c(g_x), c(g_y)), (c(b_x), c(b_y))],
+                    (c(w_x), c(w_y)),
+                ));
+            }
+            Request::SetMasteringLuminance { min_lum, max_lum } => {
+                if creator.mastering_luminance.is_some() {
+                    return already_set();
+                }
+
+                // min_lum is in units of 0.0001 cd/m².
+                if u64::from(max_lum) * 10000 <= u64::from(min_lum) {
+                    resource.post_error(
+                        Error::InvalidLuminance,
+                        "Maximum luminance must be greater than minimum luminance.",
+                    );
+                    return;
+                }
+
+                creator.mastering_luminance = Some((min_lum, max_lum));
+            }
+            Request::SetMaxCll { max_cll } => {
+                if creator.max_cll.is_some() {
+                    return already_set();
+                }
+
+                creator.max_cll = Some(max_cll);
+            }
+            Request::SetMaxFall { max_fall } => {
+                if creator.max_fall.is_some() {
+                    return already_set();
+                }
+
+                creator.max_fall = Some(max_fall);
+            }
+            Request::SetTfPower { .. }
+            | Request::SetPrimaries { .. }
+            | Request::SetLuminances { .. } => {
+                resource.post_error(Error::UnsupportedFeature, "Unsupported feature.");
+            }
+            Request::Create { image_description } => {
+                let (Some(primaries), Some(tf)) = (creator.primaries, creator.tf) else {
+                    data_init.init(
+                        image_description,
+                        ImageDescriptionData {
+                            desc: None,
+                            allow_info: false,
+                        },
+                    );
+
+                    resource.post_error(
+                        Error::IncompleteSet,
+                        "Primaries and transfer function are required.",
+                    );
+                    return;
+                };
+
+                let desc = ColorSpace::from_primaries_and_tf(primaries, tf).map(|color_space| {
+                    let hdr_metadata =
+                        (color_space == ColorSpace::Hdr10).then(|| creator.hdr_metadata());
+
+                    ImageDescription {
+                        color_space,
+                        hdr_metadata,
+                    }
+                });
+
+                let wp_image_description = data_init.init(
+                    image_description,
+                    ImageDescriptionData {
+                        desc,
+                        allow_info: false,
+                    },
+                );
+
+                if desc.is_some() {
+                    wp_image_description.ready(state.serial.next());
+                } else {
+                    debug!(?primaries, ?tf, "unsupported image description");
+                    wp_image_description.failed(
+                        wp_image_description_v1::Cause::Unsupported,
+                        "Unsupported combination of primaries and transfer function.".to_string(),
+                    );
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl ParamsCreator {
+    fn hdr_metadata(&self) -> HdrMetadata {
+        let mut metadata = HdrMetadata::default();
+
+        if let Some((primaries, white_point)) = self.mastering_primaries {
+            metadata.mastering_primaries = primaries;
+            metadata.mastering_white_point = white_point;
+        }
+
+        if let Some((min, max)) = self.mastering_luminance {
+            metadata.min_mastering_luminance = min;
+            metadata.max_mastering_luminance = max;
+        }
+
+        // Zero means unknown.
+        if let Some(max_cll) = self.max_cll.filter(|v| *v > 0) {
+            metadata.max_cll = max_cll;
+        }
+
+        if let Some(max_fall) = self.max_fall.filter(|v| *v > 0) {
+            metadata.max_fall = max_fall;
+        }
+
+        metadata
+    }
+}
+
+impl wayland_server::Dispatch<wp_image_description_v1::WpImageDescriptionV1, ImageDescriptionData>
+    for Compositor
+{
+    fn request(
+        _state: &mut Self,
+        _client: &wayland_server::Client,
+        resource: &wp_image_description_v1::WpImageDescriptionV1,
+        request: wp_image_description_v1::Request,
+        data: &ImageDescriptionData,
+        _dhandle: &wayland_server::DisplayHandle,
+        data_init: &mut wayland_server::DataInit<'_, Self>,
+    ) {
+        match request {
+            wp_image_description_v1::Request::GetInformation { information } => {
+                let info = data_init.init(information, ());
+
+                let Some(desc) = data.desc else {
+                    resource.post_error(
+                        wp_image_description_v1::Error::NotReady,
+                        "The image description is not ready.",
+                    );
+                    return;
+                };
+
+                if !data.allow_info {
+                    resource.post_error(
+                        wp_image_description_v1::Error::NoInformation,
+                        "Information is not available for client-created image descriptions.",
+                    );
+                    return;
+                }
+
+                send_image_description_info(&info, desc);
+            }
+            wp_image_description_v1::Request::Destroy => (),
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl wayland_server::Dispatch<wp_image_description_info_v1::WpImageDescriptionInfoV1, ()>
+    for Compositor
+{
+    fn request(
+        _state: &mut Self,
+        _client: &wayland_server::Client,
+        _resource: &wp_image_description_info_v1::WpImageDescriptionInfoV1,
+        _request: wp_image_description_info_v1::Request,
+        _data: &(),
+        _dhandle: &wayland_server::DisplayHandle,
+        _data_init: &mut wayland_server::DataInit<'_, Self>,
+    ) {
+    }
+}
+
+const BT709_PRIMARIES: [(i32, i32); 3] =
+    [(640_000, 330_000), (300_000, 600_000), (150_000, 60_000)];
+const BT2020_PRIMARIES: [(i32, i32); 3] =
+    [(708_000, 292_000), (170_000, 797_000), (131_000, 46_000)];
+const D65_WHITE_POINT: (i32, i32) = (312_700, 329_000);
+
+fn send_image_description_info(
+    info: &wp_image_description_info_v1::WpImageDescriptionInfoV1,
+    desc: ImageDescription,
+) {
+    let (primaries, primaries_named, tf_named) = match desc.color_space {
+        ColorSpace::Srgb => (
+            BT709_PRIMARIES,
+            wp_color_manager_v1::Primaries::Srgb,
+            wp_color_manager_v1::TransferFunction::Srgb,
+        ),
+        ColorSpace::LinearExtSrgb => (
+            BT709_PRIMARIES,
+            wp_color_manager_v1::Primaries::Srgb,
+            wp_color_manager_v1::TransferFunction::ExtLinear,
+        ),
+        ColorSpace::Hdr10 => (
+            BT2020_PRIMARIES,
+            wp_color_manager_v1::Primaries::Bt2020,
+            wp_color_manager_v1::TransferFunction::St2084Pq,
+        ),
+    };
+
+    let [(r_x, r_y), (g_x, g_y), (b_x, b_y)] = primaries;
+    let (w_x, w_y) = D65_WHITE_POINT;
+    info.primaries(r_x, r_y, g_x, g_y, b_x, b_y, w_x, w_y);
+    info.primaries_named(primaries_named);
+    info.tf_named(tf_named);
+
+    // Luminances are given as (min, max, reference), where min is in units of
+    // 0.0001 cd/m².
+    match desc.color_space {
+        ColorSpace::Srgb | ColorSpace::LinearExtSrgb => {
+            info.luminances(2000, 80, 80);
+            info.target_primaries(r_x, r_y, g_x, g_y, b_x, b_y, w_x, w_y);
+            info.target_luminance(2000, 80);
+        }
+        ColorSpace::Hdr10 => {
+            let metadata = desc.hdr_metadata.unwrap_or_default();
+            info.luminances(50, 10000, 203);
+
+            let [(r_x, r_y), (g_x, g_y), (b_x, b_y)] = metadata.mastering_primaries;
+            let (w_x, w_y) = metadata.mastering_white_point;
+            info.target_primaries(
+                r_x as i32, r_y as i32, g_x as i32, g_y as i32, b_x as i32, b_y as i32, w_x as i32,
+                w_y as i32,
+            );
+
+            info.target_luminance(
+                metadata.min_mastering_luminance,
+                metadata.max_mastering_luminance,
+            );
+            info.target_max_cll(metadata.max_cll);
+            info.target_max_fall(metadata.max_fall);
+        }
+    }
+
+    info.done();
+}
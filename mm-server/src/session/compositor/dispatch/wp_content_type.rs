@@ -0,0 +1,99 @@
+// Copyright 2024 Colin Marc <hi@colinmarc.com>
+//
+// SPDX-License-Identifier: BUSL-1.1
+
+use wayland_protocols::wp::content_type::v1::server::{
+    wp_content_type_manager_v1, wp_content_type_v1,
+};
+use wayland_server::{Resource as _, WEnum};
+
+use crate::session::{
+    compositor::{surface::SurfaceKey, Compositor},
+    control::ContentType,
+};
+
+impl wayland_server::GlobalDispatch<wp_content_type_manager_v1::WpContentTypeManagerV1, ()>
+    for Compositor
+{
+    fn bind(
+        _state: &mut Self,
+        _handle: &wayland_server::DisplayHandle,
+        _client: &wayland_server::Client,
+        resource: wayland_server::New<wp_content_type_manager_v1::WpContentTypeManagerV1>,
+        _global_data: &(),
+        data_init: &mut wayland_server::DataInit<'_, Self>,
+    ) {
+        data_init.init(resource, ());
+    }
+}
+
+impl wayland_server::Dispatch<wp_content_type_manager_v1::WpContentTypeManagerV1, ()>
+    for Compositor
+{
+    fn request(
+        state: &mut Self,
+        _client: &wayland_server::Client,
+        resource: &wp_content_type_manager_v1::WpContentTypeManagerV1,
+        request: wp_content_type_manager_v1::Request,
+        _data: &(),
+        _dhandle: &wayland_server::DisplayHandle,
+        data_init: &mut wayland_server::DataInit<'_, Self>,
+    ) {
+        match request {
+            wp_content_type_manager_v1::Request::GetSurfaceContentType { id, surface } => {
+                if let Some(surface_key) = surface.data::<SurfaceKey>() {
+                    let wp_content_type = data_init.init(id, *surface_key);
+
+                    let surface = state
+                        .surfaces
+                        .get_mut(*surface_key)
+                        .expect("surface has no entry");
+
+                    if surface.wp_content_type.is_some() {
+                        resource.post_error(
+                            wp_content_type_manager_v1::Error::AlreadyConstructed,
+                            "wp_content_type object already exists for surface.",
+                        )
+                    }
+
+                    surface.wp_content_type = Some(wp_content_type);
+                }
+            }
+            wp_content_type_manager_v1::Request::Destroy => (),
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl wayland_server::Dispatch<wp_content_type_v1::WpContentTypeV1, SurfaceKey> for Compositor {
+    fn request(
+        state: &mut Self,
+        _client: &wayland_server::Client,
+        _resource: &wp_content_type_v1::WpContentTypeV1,
+        request: wp_content_type_v1::Request,
+        data: &SurfaceKey,
+        _dhandle: &wayland_server::DisplayHandle,
+        _data_init: &mut wayland_server::DataInit<'_, Self>,
+    ) {
+        let Some(surface) = state.surfaces.get_mut(*data) else {
+            return;
+        };
+
+        // The content type is double-buffered, and applied on commit.
+        match request {
+            wp_content_type_v1::Request::SetContentType { content_type } => {
+                surface.content_type.pending = Some(match content_type {
+                    WEnum::Value(wp_content_type_v1::Type::Photo) => ContentType::Photo,
+                    WEnum::Value(wp_content_type_v1::Type::Video) => ContentType::Video,
+                    WEnum::Value(wp_content_type_v1::Type::Game) => ContentType::Game,
+                    _ => ContentType::None,
+                });
+            }
+            wp_content_type_v1::Request::Destroy => {
+                surface.wp_content_type = None;
+                surface.content_type.pending = Some(ContentType::None);
+            }
+            _ => unreachable!(),
+        }
+    }
+}
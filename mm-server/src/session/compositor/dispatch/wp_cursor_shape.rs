@@ -0,0 +1,139 @@
+// Copyright 2024 Colin Marc <hi@colinmarc.com>
+//
+// SPDX-License-Identifier: BUSL-1.1
+
+use cursor_icon::CursorIcon;
+use wayland_protocols::wp::cursor_shape::v1::server::{
+    wp_cursor_shape_device_v1::{self, Shape},
+    wp_cursor_shape_manager_v1,
+};
+use wayland_server::{protocol::wl_pointer, Resource as _, WEnum};
+
+use crate::session::compositor::{seat::Cursor, Compositor};
+
+impl wayland_server::GlobalDispatch<wp_cursor_shape_manager_v1::WpCursorShapeManagerV1, ()>
+    for Compositor
+{
+    fn bind(
+        _state: &mut Self,
+        _handle: &wayland_server::DisplayHandle,
+        _client: &wayland_server::Client,
+        resource: wayland_server::New<wp_cursor_shape_manager_v1::WpCursorShapeManagerV1>,
+        _global_data: &(),
+        data_init: &mut wayland_server::DataInit<'_, Self>,
+    ) {
+        data_init.init(resource, ());
+    }
+}
+
+impl wayland_server::Dispatch<wp_cursor_shape_manager_v1::WpCursorShapeManagerV1, ()>
+    for Compositor
+{
+    fn request(
+        _state: &mut Self,
+        _client: &wayland_server::Client,
+        _resource: &wp_cursor_shape_manager_v1::WpCursorShapeManagerV1,
+        request: wp_cursor_shape_manager_v1::Request,
+        _data: &(),
+        _dhandle: &wayland_server::DisplayHandle,
+        data_init: &mut wayland_server::DataInit<'_, Self>,
+    ) {
+        match request {
+            wp_cursor_shape_manager_v1::Request::GetPointer {
+                cursor_shape_device,
+                pointer,
+            } => {
+                data_init.init(cursor_shape_device, Some(pointer));
+            }
+            wp_cursor_shape_manager_v1::Request::GetTabletToolV2 {
+                cursor_shape_device,
+                ..
+            } => {
+                // We don't advertise any tablets, so this is a no-op.
+                data_init.init(cursor_shape_device, None);
+            }
+            wp_cursor_shape_manager_v1::Request::Destroy => (),
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl
+    wayland_server::Dispatch<
+        wp_cursor_shape_device_v1::WpCursorShapeDeviceV1,
+        Option<wl_pointer::WlPointer>,
+    > for Compositor
+{
+    fn request(
+        state: &mut Self,
+        _client: &wayland_server::Client,
+        resource: &wp_cursor_shape_device_v1::WpCursorShapeDeviceV1,
+        request: wp_cursor_shape_device_v1::Request,
+        data: &Option<wl_pointer::WlPointer>,
+        _dhandle: &wayland_server::DisplayHandle,
+        _data_init: &mut wayland_server::DataInit<'_, Self>,
+    ) {
+        match request {
+            wp_cursor_shape_device_v1::Request::SetShape { shape, .. } => {
+                let Some(wl_pointer) = data else {
+                    return;
+                };
+
+                let icon = match shape {
+                    WEnum::Value(shape) => shape_to_icon(shape),
+                    WEnum::Unknown(_) => {
+                        resource.post_error(
+                            wp_cursor_shape_device_v1::Error::InvalidShape,
+                            "Unknown cursor shape.",
+                        );
+                        return;
+                    }
+                };
+
+                state.set_cursor(wl_pointer, Cursor::Named(icon));
+            }
+            wp_cursor_shape_device_v1::Request::Destroy => (),
+            _ => unreachable!(),
+        }
+    }
+}
+
+fn shape_to_icon(shape: Shape) -> CursorIcon {
+    match shape {
+        Shape::Default => CursorIcon::Default,
+        Shape::ContextMenu => CursorIcon::ContextMenu,
+        Shape::Help => CursorIcon::Help,
+        Shape::Pointer => CursorIcon::Pointer,
+        Shape::Progress => CursorIcon::Progress,
+        Shape::Wait => CursorIcon::Wait,
+        Shape::Cell => CursorIcon::Cell,
+        Shape::Crosshair => CursorIcon::Crosshair,
+        Shape::Text => CursorIcon::Text,
+        Shape::VerticalText => CursorIcon::VerticalText,
+        Shape::Alias => CursorIcon::Alias,
+        Shape::Copy => CursorIcon::Copy,
+        Shape::Move => CursorIcon::Move,
+        Shape::NoDrop => CursorIcon::NoDrop,
+        Shape::NotAllowed => CursorIcon::NotAllowed,
+        Shape::Grab => CursorIcon::Grab,
+        Shape::Grabbing => CursorIcon::Grabbing,
+        Shape::EResize => CursorIcon::EResize,
+        Shape::NResize => CursorIcon::NResize,
+        Shape::NeResize => CursorIcon::NeResize,
+        Shape::NwResize => CursorIcon::NwResize,
+        Shape::SResize => CursorIcon::SResize,
+        Shape::SeResize => CursorIcon::SeResize,
+        Shape::SwResize => CursorIcon::SwResize,
+        Shape::WResize => CursorIcon::WResize,
+        Shape::EwResize => CursorIcon::EwResize,
+        Shape::NsResize => CursorIcon::NsResize,
+        Shape::NeswResize => CursorIcon::NeswResize,
+        Shape::NwseResize => CursorIcon::NwseResize,
+        Shape::ColResize => CursorIcon::ColResize,
+        Shape::RowResize => CursorIcon::RowResize,
+        Shape::AllScroll => CursorIcon::AllScroll,
+        Shape::ZoomIn => CursorIcon::ZoomIn,
+        Shape::ZoomOut => CursorIcon::ZoomOut,
+        _ => CursorIcon::Default,
+    }
+}
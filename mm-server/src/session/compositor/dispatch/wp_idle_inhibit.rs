@@ -0,0 +1,77 @@
+// Copyright 2024 Colin Marc <hi@colinmarc.com>
+//
+// SPDX-License-Identifier: BUSL-1.1
+
+use wayland_protocols::wp::idle_inhibit::zv1::server::{
+    zwp_idle_inhibit_manager_v1, zwp_idle_inhibitor_v1,
+};
+use wayland_server::Resource as _;
+
+use crate::session::compositor::{idle::IdleInhibitor, surface::SurfaceKey, Compositor};
+
+impl wayland_server::GlobalDispatch<zwp_idle_inhibit_manager_v1::ZwpIdleInhibitManagerV1, ()>
+    for Compositor
+{
+    fn bind(
+        _state: &mut Self,
+        _handle: &wayland_server::DisplayHandle,
+        _client: &wayland_server::Client,
+        resource: wayland_server::New<zwp_idle_inhibit_manager_v1::ZwpIdleInhibitManagerV1>,
+        _global_data: &(),
+        data_init: &mut wayland_server::DataInit<'_, Self>,
+    ) {
+        data_init.init(resource, ());
+    }
+}
+
+impl wayland_server::Dispatch<zwp_idle_inhibit_manager_v1::ZwpIdleInhibitManagerV1, ()>
+    for Compositor
+{
+    fn request(
+        state: &mut Self,
+        _client: &wayland_server::Client,
+        _resource: &zwp_idle_inhibit_manager_v1::ZwpIdleInhibitManagerV1,
+        request: zwp_idle_inhibit_manager_v1::Request,
+        _data: &(),
+        _dhandle: &wayland_server::DisplayHandle,
+        data_init: &mut wayland_server::DataInit<'_, Self>,
+    ) {
+        match request {
+            zwp_idle_inhibit_manager_v1::Request::CreateInhibitor { id, surface } => {
+                let wp_idle_inhibitor = data_init.init(id, ());
+                if let Some(surface) = surface.data::<SurfaceKey>() {
+                    state.idle_inhibitors.push(IdleInhibitor {
+                        wp_idle_inhibitor,
+                        surface: *surface,
+                    });
+                }
+            }
+            zwp_idle_inhibit_manager_v1::Request::Destroy => (),
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl wayland_server::Dispatch<zwp_idle_inhibitor_v1::ZwpIdleInhibitorV1, ()> for Compositor {
+    fn request(
+        _state: &mut Self,
+        _client: &wayland_server::Client,
+        _resource: &zwp_idle_inhibitor_v1::ZwpIdleInhibitorV1,
+        _request: zwp_idle_inhibitor_v1::Request,
+        _data: &(),
+        _dhandle: &wayland_server::DisplayHandle,
+        _data_init: &mut wayland_server::DataInit<'_, Self>,
+    ) {
+    }
+
+    fn destroyed(
+        state: &mut Self,
+        _client: wayland_server::backend::ClientId,
+        resource: &zwp_idle_inhibitor_v1::ZwpIdleInhibitorV1,
+        _data: &(),
+    ) {
+        state
+            .idle_inhibitors
+            .retain(|inhibitor| &inhibitor.wp_idle_inhibitor != resource);
+    }
+}
@@ -0,0 +1,96 @@
+// Copyright 2024 Colin Marc <hi@colinmarc.com>
+//
+// SPDX-License-Identifier: BUSL-1.1
+
+use wayland_protocols::wp::tearing_control::v1::server::{
+    wp_tearing_control_manager_v1, wp_tearing_control_v1,
+};
+use wayland_server::{Resource as _, WEnum};
+
+use crate::session::compositor::{surface::SurfaceKey, Compositor};
+
+impl wayland_server::GlobalDispatch<wp_tearing_control_manager_v1::WpTearingControlManagerV1, ()>
+    for Compositor
+{
+    fn bind(
+        _state: &mut Self,
+        _handle: &wayland_server::DisplayHandle,
+        _client: &wayland_server::Client,
+        resource: wayland_server::New<wp_tearing_control_manager_v1::WpTearingControlManagerV1>,
+        _global_data: &(),
+        data_init: &mut wayland_server::DataInit<'_, Self>,
+    ) {
+        data_init.init(resource, ());
+    }
+}
+
+impl wayland_server::Dispatch<wp_tearing_control_manager_v1::WpTearingControlManagerV1, ()>
+    for Compositor
+{
+    fn request(
+        state: &mut Self,
+        _client: &wayland_server::Client,
+        resource: &wp_tearing_control_manager_v1::WpTearingControlManagerV1,
+        request: wp_tearing_control_manager_v1::Request,
+        _data: &(),
+        _dhandle: &wayland_server::DisplayHandle,
+        data_init: &mut wayland_server::DataInit<'_, Self>,
+    ) {
+        match request {
+            wp_tearing_control_manager_v1::Request::GetTearingControl { id, surface } => {
+                if let Some(surface_key) = surface.data::<SurfaceKey>() {
+                    let wp_tearing_control = data_init.init(id, *surface_key);
+
+                    let surface = state
+                        .surfaces
+                        .get_mut(*surface_key)
+                        .expect("surface has no entry");
+
+                    if surface.wp_tearing_control.is_some() {
+                        resource.post_error(
+                            wp_tearing_control_manager_v1::Error::TearingControlExists,
+                            "wp_tearing_control object already exists for surface.",
+                        )
+                    }
+
+                    surface.wp_tearing_control = Some(wp_tearing_control);
+                }
+            }
+            wp_tearing_control_manager_v1::Request::Destroy => (),
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl wayland_server::Dispatch<wp_tearing_control_v1::WpTearingControlV1, SurfaceKey>
+    for Compositor
+{
+    fn request(
+        state: &mut Self,
+        _client: &wayland_server::Client,
+        _resource: &wp_tearing_control_v1::WpTearingControlV1,
+        request: wp_tearing_control_v1::Request,
+        data: &SurfaceKey,
+        _dhandle: &wayland_server::DisplayHandle,
+        _data_init: &mut wayland_server::DataInit<'_, Self>,
+    ) {
+        let Some(surface) = state.surfaces.get_mut(*data) else {
+            return;
+        };
+
+        // The hint is double-buffered, and applied on commit.
+        match request {
+            wp_tearing_control_v1::Request::SetPresentationHint { hint } => {
+                surface.async_presentation.pending = Some(matches!(
+                    hint,
+                    WEnum::Value(wp_tearing_control_v1::PresentationHint::Async)
+                ));
+            }
+            wp_tearing_control_v1::Request::Destroy => {
+                surface.wp_tearing_control = None;
+                surface.async_presentation.pending = Some(false);
+            }
+            _ => unreachable!(),
+        }
+    }
+}
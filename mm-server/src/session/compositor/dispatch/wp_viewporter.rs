@@ -0,0 +1,130 @@
+// Copyright 2024 Colin Marc <hi@colinmarc.com>
+//
+// SPDX-License-Identifier: BUSL-1.1
+
+use wayland_protocols::wp::viewporter::server::{wp_viewport, wp_viewporter};
+use wayland_server::Resource as _;
+
+use crate::session::compositor::{
+    surface::{SurfaceKey, Viewport, ViewportSource},
+    Compositor,
+};
+
+impl wayland_server::GlobalDispatch<wp_viewporter::WpViewporter, ()> for Compositor {
+    fn bind(
+        _state: &mut Self,
+        _handle: &wayland_server::DisplayHandle,
+        _client: &wayland_server::Client,
+        resource: wayland_server::New<wp_viewporter::WpViewporter>,
+        _global_data: &(),
+        data_init: &mut wayland_server::DataInit<'_, Self>,
+    ) {
+        data_init.init(resource, ());
+    }
+}
+
+impl wayland_server::Dispatch<wp_viewporter::WpViewporter, ()> for Compositor {
+    fn request(
+        state: &mut Self,
+        _client: &wayland_server::Client,
+        resource: &wp_viewporter::WpViewporter,
+        request: wp_viewporter::Request,
+        _data: &(),
+        _dhandle: &wayland_server::DisplayHandle,
+        data_init: &mut wayland_server::DataInit<'_, Self>,
+    ) {
+        match request {
+            wp_viewporter::Request::GetViewport { id, surface } => {
+                if let Some(surface_key) = surface.data::<SurfaceKey>() {
+                    let wp_viewport = data_init.init(id, *surface_key);
+
+                    let surface = state
+                        .surfaces
+                        .get_mut(*surface_key)
+                        .expect("surface has no entry");
+
+                    if surface.wp_viewport.is_some() {
+                        resource.post_error(
+                            wp_viewporter::Error::ViewportExists,
+                            "wp_viewport object already exists for surface.",
+                        )
+                    }
+
+                    surface.wp_viewport = Some(wp_viewport);
+                }
+            }
+            wp_viewporter::Request::Destroy => (),
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl wayland_server::Dispatch<wp_viewport::WpViewport, SurfaceKey> for Compositor {
+    fn request(
+        state: &mut Self,
+        _client: &wayland_server::Client,
+        resource: &wp_viewport::WpViewport,
+        request: wp_viewport::Request,
+        data: &SurfaceKey,
+        _dhandle: &wayland_server::DisplayHandle,
+        _data_init: &mut wayland_server::DataInit<'_, Self>,
+    ) {
+        let Some(surface) = state.surfaces.get_mut(*data) else {
+            if !matches!(request, wp_viewport::Request::Destroy) {
+                resource.post_error(
+                    wp_viewport::Error::NoSurface,
+                    "The wl_surface was destroyed.",
+                );
+            }
+
+            return;
+        };
+
+        // Viewport state is double-buffered, so edits start from whatever is
+        // already pending.
+        let mut viewport = surface
+            .viewport
+            .pending
+            .or(surface.viewport.current)
+            .unwrap_or_default();
+
+        match request {
+            wp_viewport::Request::SetSource {
+                x,
+                y,
+                width,
+                height,
+            } => {
+                if x == -1.0 && y == -1.0 && width == -1.0 && height == -1.0 {
+                    viewport.source = None;
+                } else if x < 0.0 || y < 0.0 || width <= 0.0 || height <= 0.0 {
+                    resource.post_error(wp_viewport::Error::BadValue, "Invalid source rectangle.");
+                    return;
+                } else {
+                    viewport.source = Some(ViewportSource {
+                        pos: (x, y).into(),
+                        size: (width, height).into(),
+                    });
+                }
+            }
+            wp_viewport::Request::SetDestination { width, height } => {
+                if width == -1 && height == -1 {
+                    viewport.destination = None;
+                } else if width <= 0 || height <= 0 {
+                    resource.post_error(wp_viewport::Error::BadValue, "Invalid destination size.");
+                    return;
+                } else {
+                    viewport.destination = Some((width as u32, height as u32).into());
+                }
+            }
+            wp_viewport::Request::Destroy => {
+                // The viewport is removed on the next commit.
+                surface.wp_viewport = None;
+                viewport = Viewport::default();
+            }
+            _ => unreachable!(),
+        }
+
+        surface.viewport.pending = Some(viewport);
+    }
+}
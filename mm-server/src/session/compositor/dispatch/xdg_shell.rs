@@ -190,6 +190,7 @@ impl wayland_server::Dispatch<xdg_toplevel::XdgToplevel, SurfaceKey> for Composi
                     .get_mut(*data)
                     .expect("surface has no entry")
                     .title = Some(title);
+                state.windows_changed = true;
             }
             xdg_toplevel::Request::SetAppId { app_id } => {
                 state
@@ -197,6 +198,7 @@ impl wayland_server::Dispatch<xdg_toplevel::XdgToplevel, SurfaceKey> for Composi
                     .get_mut(*data)
                     .expect("surface has no entry")
                     .app_id = Some(app_id);
+                state.windows_changed = true;
             }
             xdg_toplevel::Request::ShowWindowMenu { .. } => (),
             xdg_toplevel::Request::Move { .. } => (),
@@ -205,7 +207,22 @@ impl wayland_server::Dispatch<xdg_toplevel::XdgToplevel, SurfaceKey> for Composi
             xdg_toplevel::Request::SetMinSize { .. } => (),
             xdg_toplevel::Request::SetMaximized => (),
             xdg_toplevel::Request::UnsetMaximized => (),
-            xdg_toplevel::Request::SetFullscreen { .. } => (),
+            xdg_toplevel::Request::SetFullscreen { output } => {
+                // Toplevels are always fullscreen, but they can pick which
+                // output to be fullscreen on.
+                let Some(idx) = output.as_ref().and_then(|o| o.data::<usize>()).copied() else {
+                    return;
+                };
+
+                let surface = state.surfaces.get_mut(*data).expect("surface has no entry");
+                if surface.preferred_output != idx {
+                    surface.preferred_output = idx;
+                    if surface.configuration.is_some() {
+                        surface.reconfigure(&state.outputs, None);
+                        state.damaged = true;
+                    }
+                }
+            }
             xdg_toplevel::Request::UnsetFullscreen => (),
             xdg_toplevel::Request::SetMinimized => (),
             xdg_toplevel::Request::Destroy => (),
@@ -0,0 +1,83 @@
+// Copyright 2024 Colin Marc <hi@colinmarc.com>
+//
+// SPDX-License-Identifier: BUSL-1.1
+
+use std::time;
+
+use tracing::trace;
+use wayland_protocols::{
+    ext::idle_notify::v1::server::ext_idle_notification_v1,
+    wp::idle_inhibit::zv1::server::zwp_idle_inhibitor_v1,
+};
+
+use crate::session::compositor::{
+    surface::{SurfaceKey, Visibility},
+    Compositor,
+};
+
+/// An app asking us not to consider the user idle while a surface is
+/// visible, e.g. a video player.
+pub struct IdleInhibitor {
+    pub wp_idle_inhibitor: zwp_idle_inhibitor_v1::ZwpIdleInhibitorV1,
+    pub surface: SurfaceKey,
+}
+
+/// An app waiting to be told when the user goes idle.
+pub struct IdleNotification {
+    pub ext_idle_notification: ext_idle_notification_v1::ExtIdleNotificationV1,
+    pub timeout: time::Duration,
+    pub idled: bool,
+}
+
+impl Compositor {
+    /// Records user input (or a new attachment), resetting the idle timer.
+    pub fn record_activity(&mut self) {
+        self.activity.touch();
+    }
+
+    /// Returns true if any inhibitor is attached to a visible surface.
+    fn idle_inhibited(&self) -> bool {
+        self.idle_inhibitors.iter().any(|inhibitor| {
+            self.surface_stack.contains(&inhibitor.surface)
+                && self.surfaces[inhibitor.surface]
+                    .configuration
+                    .is_some_and(|conf| conf.visibility != Visibility::Occluded)
+        })
+    }
+
+    /// Sends idled or resumed events to apps, based on how long it's been
+    /// since the last activity.
+    pub fn update_idle_notifications(&mut self) {
+        if self.idle_inhibited() {
+            self.activity.touch();
+        }
+
+        let idle_duration = self.activity.idle_duration();
+        for notification in &mut self.idle_notifications {
+            let idle = idle_duration >= notification.timeout;
+            if idle == notification.idled {
+                continue;
+            }
+
+            trace!(?idle_duration, idle, "sending idle notification");
+            if idle {
+                notification.ext_idle_notification.idled();
+            } else {
+                notification.ext_idle_notification.resumed();
+            }
+
+            notification.idled = idle;
+        }
+    }
+
+    /// Returns how long until the next idle notification is due, if any app
+    /// is waiting for one.
+    pub fn time_until_idle_notification(&self) -> Option<time::Duration> {
+        let idle_duration = self.activity.idle_duration();
+        self.idle_notifications
+            .iter()
+            .filter(|notification| !notification.idled)
+            .map(|notification| notification.timeout.saturating_sub(idle_duration))
+            .min()
+    }
+}
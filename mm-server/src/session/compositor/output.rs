@@ -4,27 +4,64 @@
 
 use wayland_server::{protocol::wl_output, Resource as _};
 
-use crate::session::compositor::{Compositor, DisplayParams};
+use crate::session::{compositor::Compositor, control::OutputParams};
+
+/// A virtual output. The first output is the primary one, and is always at
+/// the origin.
+pub struct Output {
+    pub params: OutputParams,
+    pub proxies: Vec<wl_output::WlOutput>,
+}
+
+impl Output {
+    pub fn new(params: OutputParams) -> Self {
+        Self {
+            params,
+            proxies: Vec::new(),
+        }
+    }
+}
+
+/// Returns the index of the output containing the point, or the primary
+/// output if there isn't one.
+pub fn output_at(outputs: &[Output], point: glam::UVec2) -> usize {
+    outputs
+        .iter()
+        .position(|output| output.params.contains(point))
+        .unwrap_or_default()
+}
 
 impl Compositor {
     pub fn emit_output_params(&mut self) {
-        let params = self.display_params;
-        for proxy in &self.output_proxies {
-            configure_output(proxy, params);
+        let framerate = self.display_params.framerate;
+        for (idx, output) in self.outputs.iter().enumerate() {
+            for proxy in &output.proxies {
+                configure_output(proxy, idx, output.params, framerate);
+            }
         }
     }
 }
 
-pub fn configure_output(output: &wl_output::WlOutput, params: DisplayParams) {
+pub fn configure_output(
+    output: &wl_output::WlOutput,
+    idx: usize,
+    params: OutputParams,
+    framerate: u32,
+) {
     let version = output.version();
     if version >= 4 {
-        output.name("MM".to_string());
+        if idx == 0 {
+            output.name("MM".to_string());
+        } else {
+            output.name(format!("MM-{}", idx + 1));
+        }
+
         output.description("Magic Mirror Virtual Display".to_string());
     }
 
     output.geometry(
-        0,
-        0,
+        params.x as i32,
+        params.y as i32,
         params.width as i32,
         params.height as i32,
         wl_output::Subpixel::None,
@@ -37,7 +74,7 @@ pub fn configure_output(output: &wl_output::WlOutput, params: DisplayParams) {
         wl_output::Mode::Current | wl_output::Mode::Preferred,
         params.width as i32,
         params.height as i32,
-        params.framerate as i32 * 1000,
+        framerate as i32 * 1000,
     );
 
     if version >= 2 {
@@ -68,6 +68,8 @@ pub enum Cursor {
     #[default]
     Unset,
     Hidden,
+    /// A named cursor, set using wp_cursor_shape_v1.
+    Named(cursor_icon::CursorIcon),
     Surface {
         surface: SurfaceKey,
         needs_render: bool,
@@ -795,6 +797,12 @@ impl Compositor {
                 hotspot_x: 0,
                 hotspot_y: 0,
             }),
+            Cursor::Named(icon) => self.session_handle.dispatch(SessionEvent::CursorUpdate {
+                image: None,
+                icon: Some(*icon),
+                hotspot_x: 0,
+                hotspot_y: 0,
+            }),
         }
     }
 
@@ -2,15 +2,28 @@
 //
 // SPDX-License-Identifier: BUSL-1.1
 
+use slotmap::{Key as _, KeyData};
 use tracing::{debug, trace};
 use wayland_server::Resource as _;
 
-use crate::session::compositor::{
-    buffers::BufferKey,
-    surface::{self, SurfaceKey, SurfaceRole},
-    Compositor,
+use crate::session::{
+    compositor::{
+        buffers::BufferKey,
+        output,
+        surface::{self, SurfaceKey, SurfaceRole},
+        Compositor,
+    },
+    control::{ContentType, SessionEvent, StreamTarget, WindowInfo},
 };
 
+/// An action requested by a client for a toplevel window.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum WindowAction {
+    Focus,
+    Raise,
+    Close,
+}
+
 impl Compositor {
     /// Displays the surface, if it has not yet been displayed.
     pub fn map_surface(&mut self, id: SurfaceKey, buffer_id: BufferKey) {
@@ -33,8 +46,8 @@ impl Compositor {
             );
         }
 
-        for wl_output in self
-            .output_proxies
+        for wl_output in self.outputs[config.output]
+            .proxies
             .iter()
             .filter(|wl_output| wl_output.id().same_client_as(&surface.wl_surface.id()))
         {
@@ -43,6 +56,9 @@ impl Compositor {
 
         trace!(?surface, "surface mapped");
         self.surface_stack.push(id);
+        self.damaged = true;
+        self.windows_changed = true;
+        self.content_types_changed = true;
     }
 
     /// Removes any configuration and attached buffer from a surface. This
@@ -58,6 +74,9 @@ impl Compositor {
         surface.sent_configuration = None;
 
         self.surface_stack.retain(|v| *v != id);
+        self.damaged = true;
+        self.windows_changed = true;
+        self.content_types_changed = true;
     }
 
     /// Raises an X11 window to the top.
@@ -72,6 +91,201 @@ impl Compositor {
         }
     }
 
+    /// Handles a window action from a client. The id is the one sent in the
+    /// window list.
+    pub fn handle_window_action(&mut self, id: u64, action: WindowAction) -> anyhow::Result<()> {
+        let surface_id: SurfaceKey = KeyData::from_ffi(id).into();
+        let pos = self
+            .surface_stack
+            .iter()
+            .rposition(|id| *id == surface_id)
+            .filter(|_| self.is_toplevel(surface_id));
+
+        let Some(pos) = pos else {
+            debug!(id, ?action, "ignoring action for missing window");
+            return Ok(());
+        };
+
+        match action {
+            // Focus always follows the top of the stack.
+            WindowAction::Focus | WindowAction::Raise => self.raise_surface_at(pos),
+            WindowAction::Close => match &self.surfaces[surface_id].role.current {
+                Some(SurfaceRole::XdgToplevel { xdg_toplevel, .. }) => xdg_toplevel.close(),
+                Some(SurfaceRole::XWayland { serial }) => {
+                    let xwm = self.xwm.as_ref().unwrap();
+                    if let Some(xwin) = xwm.xwindow_for_serial(*serial) {
+                        xwm.close_window(xwin.id)?;
+                    }
+                }
+                _ => (),
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Sends the list of toplevel windows to clients, if it changed since it
+    /// was last sent (or unconditionally, if `force` is set).
+    pub fn dispatch_window_list(&mut self, force: bool) {
+        let list = self
+            .surface_stack
+            .iter()
+            .filter(|id| self.is_toplevel(**id))
+            .filter_map(|id| {
+                let surf = &self.surfaces[*id];
+                let conf = surf.configuration?;
+
+                Some(WindowInfo {
+                    id: id.data().as_ffi(),
+                    title: surf.title.clone(),
+                    app_id: surf.app_id.clone(),
+                    x: conf.topleft.x,
+                    y: conf.topleft.y,
+                    width: conf.size.x,
+                    height: conf.size.y,
+                    focused: self.active_surface == Some(*id),
+                })
+            })
+            .collect::<Vec<_>>();
+
+        if force || list != self.window_list {
+            trace!(?list, "window list changed");
+            self.session_handle
+                .dispatch(SessionEvent::WindowList(list.clone()));
+            self.window_list = list;
+        }
+    }
+
+    /// Returns true if the surface is a window that clients can act on, as
+    /// opposed to a menu or tooltip.
+    fn is_toplevel(&self, id: SurfaceKey) -> bool {
+        match self.surfaces.get(id).and_then(|s| s.role.current.as_ref()) {
+            Some(SurfaceRole::XdgToplevel { .. }) => true,
+            Some(SurfaceRole::XWayland { serial }) => self
+                .xwm
+                .as_ref()
+                .and_then(|xwm| xwm.xwindow_for_serial(*serial))
+                .is_some_and(|xwin| !xwin.override_redirect),
+            _ => false,
+        }
+    }
+
+    /// Sends the title and app_id of the focused window to clients, if either
+    /// changed since they were last sent (or unconditionally, if `force` is
+    /// set).
+    pub fn dispatch_window_metadata(&mut self, force: bool) {
+        let metadata = self
+            .active_surface
+            .and_then(|id| self.surfaces.get(id))
+            .map(|surf| (surf.title.clone(), surf.app_id.clone()))
+            .unwrap_or_default();
+
+        if force || metadata != self.window_metadata {
+            trace!(?metadata, "window metadata changed");
+            let (title, app_id) = metadata.clone();
+            self.session_handle
+                .dispatch(SessionEvent::WindowMetadataChanged { title, app_id });
+            self.window_metadata = metadata;
+        }
+    }
+
+    /// Starts or stops streaming a toplevel window on its own. While it's
+    /// streamed, the window is sized to match the stream, and is never marked
+    /// as occluded. Returns false if there's no such window.
+    pub fn set_window_stream_size(&mut self, id: u64, size: Option<glam::UVec2>) -> bool {
+        let surface_id: SurfaceKey = KeyData::from_ffi(id).into();
+        if !self.surface_stack.contains(&surface_id) || !self.is_toplevel(surface_id) {
+            return false;
+        }
+
+        let surf = &mut self.surfaces[surface_id];
+        surf.stream_size = size;
+
+        let xwin = match &surf.role.current {
+            Some(SurfaceRole::XWayland { serial }) => self
+                .xwm
+                .as_ref()
+                .and_then(|xwm| xwm.xwindow_for_serial(*serial)),
+            _ => None,
+        };
+
+        surf.reconfigure(&self.outputs, xwin);
+        if let Some(conf) = surf.configuration.as_mut() {
+            if size.is_some() && conf.visibility == surface::Visibility::Occluded {
+                conf.visibility = surface::Visibility::Visible;
+            }
+        }
+
+        self.damaged = true;
+        true
+    }
+
+    /// Returns the surfaces that make up a streamed window, bottom to top,
+    /// along with the output the window is on and its position. That's the
+    /// window itself, plus any unmanaged X11 windows (like menus) stacked
+    /// directly above it.
+    ///
+    /// xdg_popup surfaces aren't included, because the compositor doesn't
+    /// support them yet; popups are dismissed as soon as they're grabbed.
+    pub fn window_surfaces(&self, id: u64) -> Option<(usize, glam::UVec2, Vec<SurfaceKey>)> {
+        let surface_id: SurfaceKey = KeyData::from_ffi(id).into();
+        let pos = self.surface_stack.iter().position(|id| *id == surface_id)?;
+        let conf = self.surfaces[surface_id].configuration?;
+
+        let popups = self.surface_stack[pos + 1..]
+            .iter()
+            .copied()
+            .take_while(|id| !self.is_toplevel(*id));
+
+        let surfaces = std::iter::once(surface_id).chain(popups).collect();
+        Some((conf.output, conf.topleft, surfaces))
+    }
+
+    /// Returns the content type hinted by the topmost window on an output, or
+    /// by a streamed window. Popups and menus don't count. The result is cached
+    /// until the stack, focus, or a hint changes.
+    pub fn content_type(&mut self, target: StreamTarget) -> ContentType {
+        if std::mem::take(&mut self.content_types_changed) {
+            self.content_types.clear();
+        }
+
+        if let Some(content_type) = self.content_types.get(&target) {
+            return *content_type;
+        }
+
+        let surface = match target {
+            StreamTarget::Output(idx) => self
+                .visible_surfaces(idx)
+                .into_iter()
+                .rev()
+                .find(|id| self.is_toplevel(*id)),
+            StreamTarget::Window(id) => self
+                .window_surfaces(id)
+                .and_then(|(_, _, surfaces)| surfaces.first().copied()),
+        };
+
+        let content_type = surface
+            .and_then(|id| self.surfaces[id].content_type.current)
+            .unwrap_or_default();
+
+        self.content_types.insert(target, content_type);
+        content_type
+    }
+
+    /// Returns true if the surface is a window that should be listed for
+    /// clients: an xdg_toplevel, or a managed X11 window.
+    fn is_toplevel(&self, id: SurfaceKey) -> bool {
+        match &self.surfaces[id].role.current {
+            Some(SurfaceRole::XdgToplevel { .. }) => true,
+            Some(SurfaceRole::XWayland { serial }) => self
+                .xwm
+                .as_ref()
+                .and_then(|xwm| xwm.xwindow_for_serial(*serial))
+                .is_some_and(|xwin| !xwin.override_redirect),
+            _ => false,
+        }
+    }
+
     fn raise_surface_at(&mut self, position: usize) {
         let id = self.surface_stack.remove(position);
 
@@ -80,6 +294,9 @@ impl Compositor {
         }
 
         self.surface_stack.push(id);
+        self.damaged = true;
+        self.windows_changed = true;
+        self.content_types_changed = true;
     }
 
     /// Updates focus and surface configurations based on any changes made to
@@ -95,14 +312,29 @@ impl Compositor {
             return Ok(());
         }
 
-        // Mark the old active surface as occluded.
-        if let Some(conf) = self
+        self.windows_changed = true;
+        self.content_types_changed = true;
+
+        // Mark the old active surface as occluded, unless focus moved to a
+        // different output, in which case it's still visible.
+        let new_focus_output = top_surface
+            .and_then(|id| self.surfaces[id].configuration)
+            .map(|conf| conf.output);
+        // Streamed windows stay visible, too.
+        if let Some(surf) = self
             .active_surface
             .take()
             .and_then(|id| self.surfaces.get_mut(id))
-            .and_then(|surf| surf.configuration.as_mut())
         {
-            conf.visibility = surface::Visibility::Occluded;
+            let streamed = surf.stream_size.is_some();
+            if let Some(conf) = surf.configuration.as_mut() {
+                conf.visibility =
+                    if streamed || new_focus_output.is_some_and(|output| output != conf.output) {
+                        surface::Visibility::Visible
+                    } else {
+                        surface::Visibility::Occluded
+                    };
+            }
         }
 
         if let Some(focus) = top_surface {
@@ -114,6 +346,7 @@ impl Compositor {
                 .as_mut()
                 .expect("mapped surface with no configuration");
             let is_fullscreen = conf.fullscreen;
+            let focus_output = conf.output;
             conf.visibility = surface::Visibility::Active;
 
             self.active_surface = Some(focus);
@@ -154,6 +387,9 @@ impl Compositor {
                         .configuration
                         .as_mut()
                         .expect("mapped surface with no configuration");
+                    if conf.output != focus_output {
+                        continue;
+                    }
 
                     conf.visibility = surface::Visibility::Visible;
                     if conf.fullscreen {
@@ -179,8 +415,14 @@ impl Compositor {
     ) -> Option<(SurfaceKey, glam::DVec2)> {
         let coords = coords.into();
 
+        // Fullscreen surfaces catch input anywhere, so make sure we only
+        // consider surfaces on the output under the pointer.
+        let output = output::output_at(&self.outputs, coords.max(glam::DVec2::ZERO).as_uvec2());
         for id in self.surface_stack.iter().rev() {
             let surf = &self.surfaces[*id];
+            if surf_output(surf) != output {
+                continue;
+            }
 
             if let Some(surface_coords) = surf.surface_coords(coords.round().as_uvec2()) {
                 return Some((*id, surface_coords));
@@ -190,6 +432,29 @@ impl Compositor {
         None
     }
 
+    /// Returns the visible surfaces on an output, from the topmost fullscreen
+    /// surface up.
+    pub fn visible_surfaces(&self, output: usize) -> Vec<SurfaceKey> {
+        let on_output = self
+            .surface_stack
+            .iter()
+            .copied()
+            .filter(|id| surf_output(&self.surfaces[*id]) == output)
+            .collect::<Vec<_>>();
+
+        // Iterate backwards to find the first fullscreen window.
+        let first_visible_idx = on_output
+            .iter()
+            .rposition(|id| {
+                self.surfaces[*id]
+                    .configuration
+                    .map_or(true, |conf| conf.fullscreen)
+            })
+            .unwrap_or_default();
+
+        on_output[first_visible_idx..].to_vec()
+    }
+
     /// Returns true if all visible surfaces have settled (with no configure
     /// pending) and have content.
     pub fn surfaces_ready(&self) -> bool {
@@ -197,15 +462,8 @@ impl Compositor {
             return false;
         }
 
-        // Iterate backwards to find the first fullscreen window.
-        let first_visible_idx = self.surface_stack.iter().rposition(|id| {
-            self.surfaces[*id]
-                .configuration
-                .is_some_and(|conf| conf.fullscreen)
-        });
-
-        for id in &self.surface_stack[first_visible_idx.unwrap_or_default()..] {
-            let surf = &self.surfaces[*id];
+        for id in (0..self.outputs.len()).flat_map(|idx| self.visible_surfaces(idx)) {
+            let surf = &self.surfaces[id];
             if surf.content.is_none() || surf.pending_configure.is_some() {
                 debug!(
                     ?surf,
@@ -220,3 +478,7 @@ impl Compositor {
         true
     }
 }
+
+fn surf_output(surf: &surface::Surface) -> usize {
+    surf.configuration.map_or(0, |conf| conf.output)
+}
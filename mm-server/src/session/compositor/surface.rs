@@ -7,9 +7,12 @@ use std::time;
 use tracing::{debug, trace, warn};
 use wayland_protocols::{
     wp::{
+        color_management::v1::server::wp_color_management_surface_v1,
+        content_type::v1::server::wp_content_type_v1,
         fractional_scale::v1::server::wp_fractional_scale_v1,
         linux_drm_syncobj::v1::server::wp_linux_drm_syncobj_surface_v1,
         presentation_time::server::wp_presentation_feedback,
+        tearing_control::v1::server::wp_tearing_control_v1, viewporter::server::wp_viewport,
     },
     xdg::shell::server::{xdg_surface, xdg_toplevel},
 };
@@ -20,10 +23,14 @@ use wayland_server::{
 
 use super::buffers::SyncobjTimelinePoint;
 use crate::{
+    color::{ColorSpace, HdrMetadata},
     pixel_scale::PixelScale,
-    session::compositor::{
-        buffers::{BufferBacking, BufferKey},
-        xwayland, Compositor, DisplayParams,
+    session::{
+        compositor::{
+            buffers::{BufferBacking, BufferKey},
+            output, xwayland, Compositor,
+        },
+        control::ContentType,
     },
     vulkan::VkTimelinePoint,
 };
@@ -37,15 +44,30 @@ pub struct Surface {
     pub wp_fractional_scale: Option<wp_fractional_scale_v1::WpFractionalScaleV1>,
 
     pub pending_buffer: Option<PendingBuffer>,
+    pub pending_damage: bool,
     pub pending_feedback: Option<wp_presentation_feedback::WpPresentationFeedback>,
     pub frame_callback: DoubleBuffered<wl_callback::WlCallback>,
     pub buffer_scale: DoubleBuffered<PixelScale>,
     pub content: Option<ContentUpdate>,
 
+    pub wp_viewport: Option<wp_viewport::WpViewport>,
+    pub viewport: DoubleBuffered<Viewport>,
+
     pub wp_syncobj_surface: Option<wp_linux_drm_syncobj_surface_v1::WpLinuxDrmSyncobjSurfaceV1>,
     pub pending_acquire_point: Option<SyncobjTimelinePoint>,
     pub pending_release_point: Option<SyncobjTimelinePoint>,
 
+    pub wp_color_management_surface:
+        Option<wp_color_management_surface_v1::WpColorManagementSurfaceV1>,
+    pub image_description: DoubleBuffered<ImageDescription>,
+
+    pub wp_content_type: Option<wp_content_type_v1::WpContentTypeV1>,
+    pub content_type: DoubleBuffered<ContentType>,
+
+    pub wp_tearing_control: Option<wp_tearing_control_v1::WpTearingControlV1>,
+    /// Set if the app prefers tearing to waiting for the next frame.
+    pub async_presentation: DoubleBuffered<bool>,
+
     pub role: DoubleBuffered<SurfaceRole>,
     pub sent_configuration: Option<SurfaceConfiguration>,
     pub configuration: Option<SurfaceConfiguration>,
@@ -53,6 +75,12 @@ pub struct Surface {
 
     pub title: Option<String>,
     pub app_id: Option<String>,
+
+    /// The output an xdg_toplevel asked to be fullscreened on.
+    pub preferred_output: usize,
+    /// Set if the window is being streamed on its own, in which case it's
+    /// sized to match the stream instead of the output.
+    pub stream_size: Option<glam::UVec2>,
 }
 
 impl Surface {
@@ -62,15 +90,28 @@ impl Surface {
             wp_fractional_scale: None,
 
             pending_buffer: None,
+            pending_damage: false,
             pending_feedback: None,
             frame_callback: DoubleBuffered::default(),
             buffer_scale: DoubleBuffered::default(),
             content: None,
 
+            wp_viewport: None,
+            viewport: DoubleBuffered::default(),
+
             wp_syncobj_surface: None,
             pending_acquire_point: None,
             pending_release_point: None,
 
+            wp_color_management_surface: None,
+            image_description: DoubleBuffered::default(),
+
+            wp_content_type: None,
+            content_type: DoubleBuffered::default(),
+
+            wp_tearing_control: None,
+            async_presentation: DoubleBuffered::default(),
+
             role: DoubleBuffered::default(),
             sent_configuration: None,
             configuration: None,
@@ -78,10 +119,13 @@ impl Surface {
 
             title: None,
             app_id: None,
+
+            preferred_output: 0,
+            stream_size: None,
         }
     }
 
-    pub fn reconfigure(&mut self, params: DisplayParams, xwin: Option<&xwayland::XWindow>) {
+    pub fn reconfigure(&mut self, outputs: &[output::Output], xwin: Option<&xwayland::XWindow>) {
         // Keep current visibility, or start new windows visible.
         let visibility = self
             .configuration
@@ -89,13 +133,19 @@ impl Surface {
 
         let conf = match self.role.current {
             None | Some(SurfaceRole::Cursor) => None,
-            Some(SurfaceRole::XdgToplevel { .. }) => Some(SurfaceConfiguration {
-                topleft: glam::UVec2::ZERO,
-                size: (params.width, params.height).into(),
-                scale: params.ui_scale,
-                visibility,
-                fullscreen: true,
-            }),
+            Some(SurfaceRole::XdgToplevel { .. }) => {
+                let idx = self.preferred_output.min(outputs.len() - 1);
+                let params = outputs[idx].params;
+
+                Some(SurfaceConfiguration {
+                    output: idx,
+                    topleft: params.origin(),
+                    size: self.stream_size.unwrap_or(params.size()),
+                    scale: params.ui_scale,
+                    visibility,
+                    fullscreen: self.stream_size.is_none(),
+                })
+            }
             Some(SurfaceRole::XWayland { .. }) => {
                 match xwin {
                     None => None,
@@ -107,19 +157,26 @@ impl Surface {
                         override_redirect,
                         ..
                     }) if *override_redirect => Some(SurfaceConfiguration {
+                        output: output::output_at(outputs, (*x, *y).into()),
                         topleft: (*x, *y).into(),
                         size: (*width, *height).into(),
                         scale: PixelScale::ONE,
                         visibility,
                         fullscreen: false,
                     }),
-                    Some(_) => {
+                    Some(xwayland::XWindow { x, y, .. }) => {
+                        // Fullscreen the window on whichever output it was
+                        // placed on.
+                        let idx = output::output_at(outputs, (*x, *y).into());
+                        let params = outputs[idx].params;
+
                         Some(SurfaceConfiguration {
-                            topleft: glam::UVec2::ZERO,
-                            size: (params.width, params.height).into(),
+                            output: idx,
+                            topleft: params.origin(),
+                            size: self.stream_size.unwrap_or(params.size()),
                             scale: PixelScale::ONE, // XWayland always uses scale one.
                             visibility,
-                            fullscreen: true,
+                            fullscreen: self.stream_size.is_none(),
                         })
                     }
                 }
@@ -133,10 +190,7 @@ impl Surface {
     /// wayland-specific logical surface coordinates.
     pub fn surface_coords(&self, coords: impl Into<glam::DVec2>) -> Option<glam::DVec2> {
         let conf = self.configuration?;
-        let buffer_size = self
-            .content
-            .as_ref()
-            .map(|content| content.dimensions.as_dvec2())?;
+        let surface_size = self.surface_size()?;
 
         let coords = coords.into();
         let topleft = conf.topleft.as_dvec2();
@@ -149,20 +203,94 @@ impl Surface {
                 && coords.y < bottomright.y)
         {
             let offset_coords = coords - conf.topleft.as_dvec2();
-
-            let buffer_coords = offset_coords * (buffer_size / conf.size.as_dvec2());
-            Some(buffer_vector_to_surface(
-                buffer_coords,
-                self.effective_scale(),
-            ))
+            Some(offset_coords * (surface_size / conf.size.as_dvec2()))
         } else {
             None
         }
     }
 
+    /// The size of the surface in logical coordinates. Without a viewport,
+    /// that's the buffer size divided by the buffer scale.
+    pub fn surface_size(&self) -> Option<glam::DVec2> {
+        let viewport = self.viewport.current.unwrap_or_default();
+        if let Some(destination) = viewport.destination {
+            return Some(destination.as_dvec2());
+        } else if let Some(source) = viewport.source {
+            return Some(source.size);
+        }
+
+        self.buffer_surface_size()
+    }
+
+    /// The part of the buffer that should be displayed, as a position and
+    /// size in texture coordinates.
+    pub fn texture_source_rect(&self) -> (glam::Vec2, glam::Vec2) {
+        match (
+            self.viewport.current.and_then(|vp| vp.source),
+            self.buffer_surface_size(),
+        ) {
+            (Some(source), Some(size)) => (
+                (source.pos / size).as_vec2(),
+                (source.size / size).as_vec2(),
+            ),
+            _ => (glam::Vec2::ZERO, glam::Vec2::ONE),
+        }
+    }
+
+    /// Checks the current viewport against the current buffer.
+    fn validate_viewport(&self) -> Result<(), (wp_viewport::Error, &'static str)> {
+        let Some(viewport) = self.viewport.current else {
+            return Ok(());
+        };
+
+        if let Some(source) = viewport.source {
+            if viewport.destination.is_none() && source.size != source.size.round() {
+                return Err((
+                    wp_viewport::Error::BadSize,
+                    "Source size must be integer if no destination is set.",
+                ));
+            }
+
+            if let Some(size) = self.buffer_surface_size() {
+                let bottomright = source.pos + source.size;
+                if bottomright.x > size.x || bottomright.y > size.y {
+                    return Err((
+                        wp_viewport::Error::OutOfBuffer,
+                        "Source rectangle extends outside of the buffer.",
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The buffer size, in logical coordinates.
+    fn buffer_surface_size(&self) -> Option<glam::DVec2> {
+        let content = self.content.as_ref()?;
+        Some(buffer_vector_to_surface(
+            content.dimensions,
+            self.effective_scale(),
+        ))
+    }
+
     pub fn effective_scale(&self) -> PixelScale {
         self.buffer_scale.current.unwrap_or_default()
     }
+
+    pub fn effective_image_description(&self) -> ImageDescription {
+        self.image_description.current.unwrap_or_default()
+    }
+
+    /// Surfaces that allow tearing are presented without waiting for the
+    /// (simulated) vblank.
+    pub fn presentation_kind(&self) -> wp_presentation_feedback::Kind {
+        if self.async_presentation.current == Some(true) {
+            wp_presentation_feedback::Kind::empty()
+        } else {
+            wp_presentation_feedback::Kind::Vsync
+        }
+    }
 }
 
 impl std::fmt::Debug for Surface {
@@ -258,8 +386,10 @@ pub enum Visibility {
 /// The configuration to be sent to the surface.
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub struct SurfaceConfiguration {
-    // x, y, width, and height are in the "physical" coordinate space. x and y
-    // are not relevant to xdg_shell surfaces.
+    // The output the surface is displayed on.
+    pub output: usize,
+    // x, y, width, and height are in the "physical" coordinate space, shared
+    // between all outputs. x and y are not relevant to xdg_shell surfaces.
     pub topleft: glam::UVec2,
     pub size: glam::UVec2,
     pub scale: PixelScale,
@@ -269,6 +399,40 @@ pub struct SurfaceConfiguration {
 
 impl SurfaceConfiguration {}
 
+/// Cropping and scaling for a surface, set using the wp_viewporter protocol.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct Viewport {
+    pub source: Option<ViewportSource>,
+    pub destination: Option<glam::UVec2>,
+}
+
+// The values are validated, so they're never NaN.
+impl Eq for Viewport {}
+
+/// A source rectangle, in logical coordinates.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ViewportSource {
+    pub pos: glam::DVec2,
+    pub size: glam::DVec2,
+}
+
+/// The color space of a surface's content, set using the
+/// wp_color_management_v1 protocol.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct ImageDescription {
+    pub color_space: ColorSpace,
+    pub hdr_metadata: Option<HdrMetadata>,
+}
+
+impl Default for ImageDescription {
+    fn default() -> Self {
+        Self {
+            color_space: ColorSpace::Srgb,
+            hdr_metadata: None,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub enum PendingBuffer {
     Attach(BufferKey),
@@ -285,9 +449,10 @@ pub struct ContentUpdate {
     /// Used for explicit sync.
     pub explicit_sync: Option<(SyncobjTimelinePoint, SyncobjTimelinePoint)>,
 
-    /// If the content update is in use, this timeline point indicates when it
-    /// will be free.
-    pub tp_done: Option<VkTimelinePoint>,
+    /// If the content update is in use, these timeline points indicate when it
+    /// will be free. There's one for each pipeline that used it, since the
+    /// same surface can be streamed more than once.
+    pub tp_done: Vec<VkTimelinePoint>,
 
     /// The real dimensions of the buffer. This is how surface coordinates are
     /// determined in wayland.
@@ -295,9 +460,13 @@ pub struct ContentUpdate {
     pub wp_presentation_feedback: Option<wp_presentation_feedback::WpPresentationFeedback>,
 }
 
+/// Presentation feedback, the point when the frame is done, the output the
+/// frame was presented on, and how it was presented.
 pub struct PendingPresentationFeedback(
     pub wp_presentation_feedback::WpPresentationFeedback,
     pub VkTimelinePoint,
+    pub usize,
+    pub wp_presentation_feedback::Kind,
 );
 
 pub struct CommitError(pub xdg_surface::Error, pub String);
@@ -305,12 +474,12 @@ pub struct CommitError(pub xdg_surface::Error, pub String);
 impl Compositor {
     /// Handles wl_surface.commit.
     pub fn surface_commit(&mut self, id: SurfaceKey) -> Result<(), CommitError> {
-        let display_params = self.display_params;
         let surface = &mut self.surfaces[id];
 
         // Buffer swap happens first. We handle it a bit differently because
         // buffers can be removed, not just overwritten.
         let mut feedback = surface.pending_feedback.take();
+        let mut damaged = std::mem::take(&mut surface.pending_damage);
         match surface.pending_buffer.take() {
             Some(PendingBuffer::Detach) => {
                 self.unmap_surface(id);
@@ -402,11 +571,21 @@ impl Compositor {
                     needs_release = false;
                 }
 
+                // Treat the whole surface as damaged if the client didn't tell
+                // us otherwise, but the buffer is new or resized.
+                if surface
+                    .content
+                    .as_ref()
+                    .map_or(true, |c| c.dimensions != buffer.dimensions())
+                {
+                    damaged = true;
+                }
+
                 let old_content = surface.content.replace(ContentUpdate {
                     buffer: buffer_id,
                     needs_release,
                     explicit_sync,
-                    tp_done: None,
+                    tp_done: Vec::new(),
                     dimensions: buffer.dimensions(),
                     wp_presentation_feedback: feedback,
                 });
@@ -430,15 +609,61 @@ impl Compositor {
                     None
                 };
 
-                surface.reconfigure(display_params, xwin);
+                surface.reconfigure(&self.outputs, xwin);
             }
             _ => (),
         }
 
         surface.buffer_scale.promote();
         surface.frame_callback.promote();
+        if !matches!(surface.content_type.promote(), CommitResult::NoChange) {
+            self.content_types_changed = true;
+        }
+        surface.async_presentation.promote();
+
+        // A new viewport changes which part of the buffer is composited.
+        if !matches!(surface.viewport.promote(), CommitResult::NoChange) {
+            damaged = true;
+        }
+
+        if let Some(wp_viewport) = &surface.wp_viewport {
+            if let Err((code, msg)) = surface.validate_viewport() {
+                wp_viewport.post_error(code, msg);
+            }
+        }
+
+        // A new color space changes how the content is composited.
+        if !matches!(surface.image_description.promote(), CommitResult::NoChange) {
+            damaged = true;
+        }
 
-        trace!(?surface, "surface commit");
+        trace!(?surface, damaged, "surface commit");
+
+        // If the surface is visible, we need to composite a new frame. Unmapped
+        // surfaces are picked up when they're mapped.
+        if damaged
+            && surface
+                .configuration
+                .is_some_and(|conf| conf.visibility != Visibility::Occluded)
+        {
+            self.damaged = true;
+            self.first_commit_ts.get_or_insert_with(time::Instant::now);
+        }
+
+        if damaged && self.active_surface == Some(id) {
+            self.active_surface_committed = true;
+        }
+
+        // Surfaces that allow tearing get composited right away, instead of
+        // waiting for the next frame.
+        if damaged
+            && surface.async_presentation.current == Some(true)
+            && surface
+                .configuration
+                .is_some_and(|conf| conf.visibility != Visibility::Occluded)
+        {
+            self.async_surface_committed = true;
+        }
 
         // Map the surface, if we've fulfilled all requirements.
         let is_mappable = match surface.role.current {
@@ -592,18 +817,26 @@ impl Compositor {
         let tv_sec_lo = (time.tv_sec & 0xFFFFFFFF) as u32;
         let tv_nsec = time.tv_nsec as u32;
 
-        let framerate = self.display_params.framerate;
-        let refresh = time::Duration::from_secs_f64(1.0 / framerate as f64).as_nanos() as u32;
+        // In variable refresh mode, there's no fixed refresh interval, which
+        // the protocol indicates with zero.
+        let refresh = if self.variable_refresh {
+            0
+        } else {
+            let framerate = self.display_params.framerate;
+            time::Duration::from_secs_f64(1.0 / framerate as f64).as_nanos() as u32
+        };
 
         let mut still_pending = Vec::with_capacity(self.pending_presentation_feedback.len());
-        for PendingPresentationFeedback(fb, tp) in self.pending_presentation_feedback.drain(..) {
+        for PendingPresentationFeedback(fb, tp, output, kind) in
+            self.pending_presentation_feedback.drain(..)
+        {
             if unsafe { !tp.poll()? } {
-                still_pending.push(PendingPresentationFeedback(fb, tp));
+                still_pending.push(PendingPresentationFeedback(fb, tp, output, kind));
                 continue;
             }
 
-            for wl_output in self
-                .output_proxies
+            for wl_output in self.outputs[output]
+                .proxies
                 .iter()
                 .filter(|wl_output| wl_output.id().same_client_as(&fb.id()))
             {
@@ -611,13 +844,9 @@ impl Compositor {
             }
 
             fb.presented(
-                tv_sec_hi,
-                tv_sec_lo,
-                tv_nsec,
-                refresh,
-                0, // seq_hi
+                tv_sec_hi, tv_sec_lo, tv_nsec, refresh, 0, // seq_hi
                 0, // seq_lo
-                wp_presentation_feedback::Kind::empty(),
+                kind,
             );
         }
 
@@ -24,6 +24,7 @@ use x11rb::{
 use crate::{
     pixel_scale::PixelScale,
     session::compositor::{
+        output,
         surface::{self, SurfaceConfiguration},
         Compositor,
     },
@@ -40,6 +41,7 @@ x11rb::atom_manager! {
         WM_HINTS,
         WM_PROTOCOLS,
         WM_TAKE_FOCUS,
+        WM_DELETE_WINDOW,
         WM_CHANGE_STATE,
         _NET_WM_NAME,
         _NET_WM_MOVERESIZE,
@@ -376,6 +378,33 @@ impl Xwm {
         self.conn.flush()?;
         Ok(())
     }
+
+    /// Asks a window to close, or disconnects the client if the window
+    /// doesn't support WM_DELETE_WINDOW.
+    pub fn close_window(&self, window: u32) -> anyhow::Result<()> {
+        let Some(xwin) = self.xwindows.get(&window) else {
+            return Ok(());
+        };
+
+        if xwin.protocols.contains(&self.atoms.WM_DELETE_WINDOW) {
+            trace!(?xwin, "sending WM_DELETE_WINDOW");
+
+            let event = xproto::ClientMessageEvent::new(
+                32,
+                xwin.id,
+                self.atoms.WM_PROTOCOLS,
+                [self.atoms.WM_DELETE_WINDOW, x11rb::CURRENT_TIME, 0, 0, 0],
+            );
+            self.conn
+                .send_event(false, xwin.id, xproto::EventMask::NO_EVENT, event)?;
+        } else {
+            trace!(?xwin, "killing client");
+            self.conn.kill_client(xwin.id)?;
+        }
+
+        self.conn.flush()?;
+        Ok(())
+    }
 }
 
 impl Compositor {
@@ -407,12 +436,10 @@ impl Compositor {
             return;
         };
 
-        let display_params = self.display_params;
-
         let surf = &mut self.surfaces[*surface_id];
         surf.title = xwin.title.clone();
         surf.app_id = xwin.app_id.clone();
-        surf.reconfigure(display_params, Some(xwin));
+        surf.reconfigure(&self.outputs, Some(xwin));
 
         if let Some(surface::ContentUpdate { buffer, .. }) = surf.content {
             self.map_surface(*surface_id, buffer);
@@ -422,7 +449,6 @@ impl Compositor {
 
 fn handle_event(state: &mut Compositor, ev: protocol::Event) -> anyhow::Result<()> {
     trace!(?ev, "x11 event");
-    let display_params = state.display_params;
     let xwm = state.xwm.as_mut().unwrap();
 
     use protocol::Event::*;
@@ -440,7 +466,7 @@ fn handle_event(state: &mut Compositor, ev: protocol::Event) -> anyhow::Result<(
             )?;
             xwm.conn.flush()?;
 
-            let title = fetch_string_property(&xwm.conn, msg.window, xwm.atoms._NET_WM_NAME)?;
+            let title = fetch_title(&xwm.conn, &xwm.atoms, msg.window)?;
             let app_id = fetch_class(&xwm.conn, msg.window)?;
             let hints = fetch_hints(&xwm.conn, msg.window)?;
             let protocols = fetch_protocols(&xwm.conn, xwm.atoms.WM_PROTOCOLS, msg.window)?;
@@ -579,6 +605,7 @@ fn handle_event(state: &mut Compositor, ev: protocol::Event) -> anyhow::Result<(
                 }
 
                 let conf = SurfaceConfiguration {
+                    output: output::output_at(&state.outputs, (xwin.x, xwin.y).into()),
                     topleft: (xwin.x, xwin.y).into(),
                     size: (xwin.width, xwin.height).into(),
                     scale: PixelScale::ONE,
@@ -613,7 +640,9 @@ fn handle_event(state: &mut Compositor, ev: protocol::Event) -> anyhow::Result<(
                     .and_then(|serial| state.xwayland_surface_lookup.get(&serial))
                     .and_then(|id| state.surfaces.get_mut(*id))
                 {
-                    surf.reconfigure(display_params, Some(xwin));
+                    surf.reconfigure(&state.outputs, Some(xwin));
+                    state.damaged = true;
+                    state.windows_changed = true;
                 }
             }
         }
@@ -752,13 +781,33 @@ fn handle_event(state: &mut Compositor, ev: protocol::Event) -> anyhow::Result<(
 
             if let Some(xwin) = xwm.xwindows.get_mut(&msg.window) {
                 match msg.atom {
-                    v if v == xwm.atoms._NET_WM_NAME => {
-                        xwin.title = fetch_string_property(&xwm.conn, msg.window, v)?;
+                    v if v == xwm.atoms._NET_WM_NAME
+                        || v == u32::from(xproto::AtomEnum::WM_NAME) =>
+                    {
+                        xwin.title = fetch_title(&xwm.conn, &xwm.atoms, msg.window)?;
                         trace!(?xwin, "title changed");
+
+                        if let Some(surf) = xwin
+                            .serial
+                            .and_then(|serial| state.xwayland_surface_lookup.get(&serial))
+                            .and_then(|id| state.surfaces.get_mut(*id))
+                        {
+                            surf.title = xwin.title.clone();
+                            state.windows_changed = true;
+                        }
                     }
                     v if v == u32::from(xproto::AtomEnum::WM_CLASS) => {
                         xwin.app_id = fetch_class(&xwm.conn, msg.window)?;
                         trace!(?xwin, class = xwin.app_id, "class changed");
+
+                        if let Some(surf) = xwin
+                            .serial
+                            .and_then(|serial| state.xwayland_surface_lookup.get(&serial))
+                            .and_then(|id| state.surfaces.get_mut(*id))
+                        {
+                            surf.app_id = xwin.app_id.clone();
+                            state.windows_changed = true;
+                        }
                     }
                     v if v == xwm.atoms.WM_HINTS => {
                         let hints = fetch_hints(&xwm.conn, msg.window)?;
@@ -769,6 +818,7 @@ fn handle_event(state: &mut Compositor, ev: protocol::Event) -> anyhow::Result<(
                         let protocols =
                             fetch_protocols(&xwm.conn, xwm.atoms.WM_PROTOCOLS, msg.window)?;
                         trace!(?xwin, ?protocols, "protocols changed");
+                        xwin.protocols = protocols;
                     }
                     _ => (),
                 }
@@ -808,6 +858,18 @@ fn fetch_string_property(
     }
 }
 
+/// Fetches the window title, preferring _NET_WM_NAME to WM_NAME.
+fn fetch_title(
+    conn: &X11Connection,
+    atoms: &Atoms,
+    window: xproto::Window,
+) -> Result<Option<String>, ConnectionError> {
+    match fetch_string_property(conn, window, atoms._NET_WM_NAME)? {
+        Some(title) => Ok(Some(title)),
+        None => fetch_string_property(conn, window, xproto::AtomEnum::WM_NAME),
+    }
+}
+
 fn fetch_class(
     conn: &X11Connection,
     window: xproto::Window,
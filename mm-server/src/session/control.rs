@@ -9,7 +9,7 @@ use crate::{
     color::VideoProfile,
     pixel_scale::PixelScale,
     server::stream::StreamWriter,
-    session::compositor::{self, ButtonState},
+    session::compositor::{self, ButtonState, WindowAction},
 };
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
@@ -20,6 +20,70 @@ pub struct DisplayParams {
     pub ui_scale: PixelScale,
 }
 
+/// The configuration of a single virtual output. The primary output is
+/// described by [DisplayParams], and is always at the origin.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct OutputParams {
+    pub width: u32,
+    pub height: u32,
+    pub ui_scale: PixelScale,
+    /// The position of the output, in pixels.
+    pub x: u32,
+    pub y: u32,
+}
+
+impl OutputParams {
+    pub fn origin(&self) -> glam::UVec2 {
+        (self.x, self.y).into()
+    }
+
+    pub fn size(&self) -> glam::UVec2 {
+        (self.width, self.height).into()
+    }
+
+    pub fn contains(&self, point: glam::UVec2) -> bool {
+        let bottomright = self.origin() + self.size();
+        point.cmpge(self.origin()).all() && point.cmplt(bottomright).all()
+    }
+
+    pub fn overlaps(&self, other: &OutputParams) -> bool {
+        let (a0, a1) = (self.origin(), self.origin() + self.size());
+        let (b0, b1) = (other.origin(), other.origin() + other.size());
+        a0.cmplt(b1).all() && b0.cmplt(a1).all()
+    }
+}
+
+impl From<DisplayParams> for OutputParams {
+    fn from(params: DisplayParams) -> Self {
+        Self {
+            width: params.width,
+            height: params.height,
+            ui_scale: params.ui_scale,
+            x: 0,
+            y: 0,
+        }
+    }
+}
+
+/// What an attachment streams: an entire output, or a single toplevel window
+/// (by the id sent in the window list).
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum StreamTarget {
+    Output(usize),
+    Window(u64),
+}
+
+impl StreamTarget {
+    pub const PRIMARY: Self = Self::Output(0);
+
+    /// Whether attachments to this target can receive the audio stream.
+    /// Additional outputs don't, and only one attachment receives it at a time,
+    /// to avoid playing the same audio twice.
+    pub fn receives_audio(&self) -> bool {
+        matches!(self, Self::Output(0) | Self::Window(_))
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct VideoStreamParams {
     pub width: u32,
@@ -27,6 +91,19 @@ pub struct VideoStreamParams {
     pub codec: VideoCodec,
     pub preset: u32,
     pub profile: VideoProfile,
+    /// Set by the compositor, based on the focused window.
+    pub content_type: ContentType,
+}
+
+/// The kind of content being streamed, as hinted by the app using
+/// wp_content_type_v1. Used to tune the encoder.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum ContentType {
+    #[default]
+    None,
+    Photo,
+    Video,
+    Game,
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
@@ -36,10 +113,25 @@ pub struct AudioStreamParams {
     pub codec: AudioCodec,
 }
 
+/// A toplevel window, as described to clients.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WindowInfo {
+    pub id: u64,
+    pub title: Option<String>,
+    pub app_id: Option<String>,
+    /// The position of the window, in the space shared by all outputs.
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    pub focused: bool,
+}
+
 pub enum ControlMessage {
     Stop,
     Attach {
         id: u64,
+        target: StreamTarget,
         sender: Sender<SessionEvent>,
         video_params: VideoStreamParams,
         audio_params: AudioStreamParams,
@@ -47,8 +139,17 @@ pub enum ControlMessage {
         ready: oneshot::Sender<()>,
     },
     Detach(u64),
-    RefreshVideo,
+    Kick(u64),
+    /// Detaches a client because the session is idle.
+    Suspend(u64),
+    RefreshVideo(StreamTarget),
     UpdateDisplayParams(DisplayParams),
+    /// Resizes a streamed window, along with its video stream.
+    ResizeWindowStream {
+        window_id: u64,
+        width: u32,
+        height: u32,
+    },
     KeyboardInput {
         key_code: u32,
         state: compositor::KeyState,
@@ -83,13 +184,16 @@ pub enum ControlMessage {
         button_code: u32,
         state: ButtonState,
     },
+    WindowAction {
+        id: u64,
+        action: WindowAction,
+    },
 }
 
 #[derive(Debug, Clone)]
 pub enum SessionEvent {
     DisplayParamsChanged {
         params: DisplayParams,
-        reattach: bool,
     },
     VideoFrame {
         stream_seq: u64,
@@ -109,5 +213,52 @@ pub enum SessionEvent {
     },
     PointerLocked(f64, f64),
     PointerReleased,
-    Shutdown,
+    WindowList(Vec<WindowInfo>),
+    WindowMetadataChanged {
+        title: Option<String>,
+        app_id: Option<String>,
+    },
+    Kicked,
+    /// The attachment was ended because the session went idle.
+    Suspended,
+    /// The session ended. If the app exited on its own, this includes its exit
+    /// status.
+    Shutdown {
+        exit_status: Option<i32>,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn output(x: u32, y: u32, width: u32, height: u32) -> OutputParams {
+        OutputParams {
+            width,
+            height,
+            ui_scale: PixelScale::ONE,
+            x,
+            y,
+        }
+    }
+
+    #[test]
+    fn overlaps() {
+        let a = output(0, 0, 1920, 1080);
+
+        assert!(a.overlaps(&a));
+        assert!(a.overlaps(&output(100, 100, 200, 200)));
+        assert!(a.overlaps(&output(1918, 1078, 100, 100)));
+        assert!(output(100, 100, 200, 200).overlaps(&a));
+
+        // Adjacent outputs don't overlap.
+        assert!(!a.overlaps(&output(1920, 0, 1280, 720)));
+        assert!(!a.overlaps(&output(0, 1080, 1920, 1080)));
+        assert!(!a.overlaps(&output(1920, 1080, 100, 100)));
+        assert!(!output(1920, 0, 1280, 720).overlaps(&a));
+
+        // Overlapping in only one dimension isn't enough.
+        assert!(!a.overlaps(&output(100, 2000, 100, 100)));
+        assert!(!a.overlaps(&output(2000, 100, 100, 100)));
+    }
 }
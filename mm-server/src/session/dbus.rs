@@ -0,0 +1,105 @@
+// Copyright 2024 Colin Marc <hi@colinmarc.com>
+//
+// SPDX-License-Identifier: BUSL-1.1
+
+use std::{
+    io::{BufRead as _, BufReader, Write as _},
+    path::Path,
+    process::{Child, Command, Stdio},
+    time,
+};
+
+use anyhow::{anyhow, bail, Context as _};
+use pathsearch::find_executable_in_path;
+use rustix::event::{poll, PollFd, PollFlags};
+use tracing::debug;
+
+const READY_TIMEOUT: time::Duration = time::Duration::from_secs(5);
+
+/// A private D-Bus session bus for a single session, run by dbus-daemon on
+/// the host. The bus doesn't support service activation, so apps can't use it
+/// to start anything outside the container.
+pub struct SessionBus {
+    child: Child,
+    _config: mktemp::Temp,
+}
+
+impl SessionBus {
+    /// Starts a bus listening at the given path, and waits for it to be ready.
+    pub fn spawn(socket_path: &Path) -> anyhow::Result<Self> {
+        let exe = find_executable_in_path("dbus-daemon").ok_or(anyhow!("dbus-daemon not found"))?;
+
+        let config = mktemp::Temp::new_file()?;
+        let mut f = std::fs::File::create(&config)?;
+        write!(
+            f,
+            r#"<!DOCTYPE busconfig PUBLIC "-//freedesktop//DTD D-Bus Bus Configuration 1.0//EN"
+ "http://www.freedesktop.org/standards/dbus/1.0/busconfig.dtd">
+<busconfig>
+  <type>session</type>
+  <listen>unix:path={}</listen>
+  <auth>EXTERNAL</auth>
+  <policy context="default">
+    <allow send_destination="*"/>
+    <allow own="*"/>
+  </policy>
+</busconfig>
+"#,
+            socket_path.display()
+        )?;
+        drop(f);
+
+        let mut child = Command::new(exe)
+            .arg("--nofork")
+            .arg("--nopidfile")
+            .arg("--print-address=1")
+            .arg(format!("--config-file={}", config.display()))
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .spawn()
+            .context("failed to spawn dbus-daemon")?;
+
+        // dbus-daemon prints the address once it's listening, or exits.
+        let stdout = child.stdout.take().unwrap();
+        if !wait_readable(&stdout, READY_TIMEOUT)? {
+            let _ = child.kill();
+            let _ = child.wait();
+            bail!("timed out waiting for dbus-daemon");
+        }
+
+        let mut address = String::new();
+        BufReader::new(stdout).read_line(&mut address)?;
+        if address.trim().is_empty() {
+            let _ = child.kill();
+            let status = child.wait()?;
+            bail!("dbus-daemon exited: {status}");
+        }
+
+        debug!(address = address.trim(), "started session bus");
+        Ok(Self {
+            child,
+            _config: config,
+        })
+    }
+}
+
+/// Waits for the fd to become readable (or hang up), returning false on
+/// timeout.
+fn wait_readable(fd: impl std::os::fd::AsFd, timeout: time::Duration) -> anyhow::Result<bool> {
+    let mut pollfd = [PollFd::new(&fd, PollFlags::IN)];
+    let timespec = timeout.try_into().expect("invalid duration");
+    loop {
+        match poll(&mut pollfd, Some(&timespec)) {
+            Ok(n) => return Ok(n > 0),
+            Err(rustix::io::Errno::INTR) => continue,
+            Err(e) => return Err(e).context("poll"),
+        }
+    }
+}
+
+impl Drop for SessionBus {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
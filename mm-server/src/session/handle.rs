@@ -7,16 +7,25 @@ use std::{collections::BTreeMap, sync::Arc};
 use crossbeam_channel as crossbeam;
 use parking_lot::Mutex;
 
-use super::control::SessionEvent;
-use crate::server::stream::StreamWriter;
+use super::{
+    control::{AudioStreamParams, SessionEvent, StreamTarget, VideoStreamParams},
+    recording::Recorder,
+};
+use crate::{encoder::FrameTiming, server::stream::StreamWriter};
 
 struct Client {
+    target: StreamTarget,
+    audio_params: AudioStreamParams,
     events: crossbeam::Sender<SessionEvent>,
     writer: StreamWriter,
 }
 
 struct Inner {
     attachments: BTreeMap<u64, Client>,
+    /// The one attachment that receives audio, so that a client streaming
+    /// multiple windows doesn't play it more than once.
+    audio_client: Option<u64>,
+    recorder: Option<Recorder>,
 }
 
 #[derive(Clone)]
@@ -27,29 +36,85 @@ impl SessionHandle {
         Self(
             Arc::new(Mutex::new(Inner {
                 attachments: BTreeMap::new(),
+                audio_client: None,
+                recorder: None,
             })),
             waker,
         )
     }
 
+    /// Adds a client. Returns true if the client should receive the audio
+    /// stream, in which case the stream should be restarted.
     pub fn insert_client(
         &self,
         id: u64,
+        target: StreamTarget,
+        audio_params: AudioStreamParams,
         events: crossbeam::Sender<SessionEvent>,
         writer: StreamWriter,
-    ) {
-        self.0
-            .lock()
+    ) -> bool {
+        let inner = &mut *self.0.lock();
+        inner.attachments.insert(
+            id,
+            Client {
+                target,
+                audio_params,
+                events,
+                writer,
+            },
+        );
+
+        if inner.audio_client.is_none() && target.receives_audio() {
+            inner.audio_client = Some(id);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// If nobody is receiving audio, picks a new client to receive it,
+    /// preferring the primary output. Returns the parameters to restart the
+    /// audio stream with.
+    pub fn reassign_audio(&self) -> Option<AudioStreamParams> {
+        let inner = &mut *self.0.lock();
+        if inner.audio_client.is_some() {
+            return None;
+        }
+
+        let (id, client) = inner
             .attachments
-            .insert(id, Client { events, writer });
+            .iter()
+            .filter(|(_, c)| c.target.receives_audio())
+            .min_by_key(|(_, c)| c.target != StreamTarget::PRIMARY)?;
+
+        inner.audio_client = Some(*id);
+        Some(client.audio_params)
     }
 
-    pub fn remove_client(&self, id: u64) {
-        self.0.lock().attachments.remove(&id);
+    pub fn set_recorder(&self, recorder: Recorder) {
+        self.0.lock().recorder = Some(recorder);
     }
 
-    pub fn remove_all(&self) {
-        self.0.lock().attachments.clear();
+    pub fn is_recording(&self) -> bool {
+        self.0.lock().recorder.is_some()
+    }
+
+    /// Starts a new recording file, if recording is enabled. Should be called
+    /// whenever the encoders are restarted.
+    pub fn start_recording_segment(&self, video: VideoStreamParams, audio: AudioStreamParams) {
+        if let Some(recorder) = &self.0.lock().recorder {
+            recorder.new_segment(video, audio);
+        }
+    }
+
+    /// Removes a client, returning what it was streaming.
+    pub fn remove_client(&self, id: u64) -> Option<StreamTarget> {
+        let inner = &mut *self.0.lock();
+        if inner.audio_client == Some(id) {
+            inner.audio_client = None;
+        }
+
+        inner.attachments.remove(&id).map(|client| client.target)
     }
 
     pub fn dispatch(&self, event: SessionEvent) {
@@ -60,34 +125,55 @@ impl SessionHandle {
     }
 
     pub fn dispatch_audio_frame(&self, pts: u64, frame: bytes::Bytes, stream_restart: bool) {
-        let attachments = &mut self.0.lock().attachments;
-        for (_, client) in attachments.iter_mut() {
-            let (stream_seq, seq) =
-                client
-                    .writer
-                    .write_audio_frame(pts, frame.clone(), stream_restart);
-            let _ = client.events.send(SessionEvent::AudioFrame {
-                _stream_seq: stream_seq,
-                seq,
-                frame: frame.clone(),
-            });
+        let inner = &mut *self.0.lock();
+        if let Some(recorder) = &inner.recorder {
+            recorder.write_audio_frame(pts, frame.clone());
         }
+
+        let Some(client) = inner
+            .audio_client
+            .and_then(|id| inner.attachments.get_mut(&id))
+        else {
+            return;
+        };
+
+        let (stream_seq, seq) = client
+            .writer
+            .write_audio_frame(pts, frame.clone(), stream_restart);
+        let _ = client.events.send(SessionEvent::AudioFrame {
+            _stream_seq: stream_seq,
+            seq,
+            frame,
+        });
     }
 
     pub fn dispatch_video_frame(
         &self,
+        target: StreamTarget,
         pts: u64,
         frame: bytes::Bytes,
         hierarchical_layer: u32,
         stream_restart: bool,
+        timing: FrameTiming,
     ) {
-        let attachments = &mut self.0.lock().attachments;
-        for (_, client) in attachments.iter_mut() {
+        let inner = &mut *self.0.lock();
+        if let Some(recorder) = inner
+            .recorder
+            .as_ref()
+            .filter(|_| target == StreamTarget::PRIMARY)
+        {
+            // A stream restart always starts with a keyframe.
+            recorder.write_video_frame(pts, frame.clone(), stream_restart);
+        }
+
+        let attachments = &mut inner.attachments;
+        for (_, client) in attachments.iter_mut().filter(|(_, c)| c.target == target) {
             let (stream_seq, seq) = client.writer.write_video_frame(
                 pts,
                 frame.clone(),
                 hierarchical_layer,
                 stream_restart,
+                &timing,
             );
 
             let _ = client.events.send(SessionEvent::VideoFrame {
@@ -102,14 +188,43 @@ impl SessionHandle {
         self.1.wake()
     }
 
-    pub fn kick_clients(&self) {
-        let attachments = &mut self.0.lock().attachments;
-        for (_, client) in std::mem::take(attachments) {
-            let _ = client.events.send(SessionEvent::Shutdown);
+    pub fn kick_clients(&self, exit_status: Option<i32>) {
+        let inner = &mut *self.0.lock();
+        inner.audio_client = None;
+        for (_, client) in std::mem::take(&mut inner.attachments) {
+            let _ = client.events.send(SessionEvent::Shutdown { exit_status });
+        }
+    }
+
+    pub fn kick_client(&self, id: u64) {
+        self.end_client(id, SessionEvent::Kicked);
+    }
+
+    pub fn suspend_client(&self, id: u64) {
+        self.end_client(id, SessionEvent::Suspended);
+    }
+
+    fn end_client(&self, id: u64, event: SessionEvent) {
+        let inner = &mut *self.0.lock();
+        if inner.audio_client == Some(id) {
+            inner.audio_client = None;
+        }
+
+        if let Some(client) = inner.attachments.remove(&id) {
+            let _ = client.events.send(event);
         }
     }
 
     pub fn num_attachments(&self) -> usize {
         self.0.lock().attachments.len()
     }
+
+    pub fn num_attachments_for(&self, target: StreamTarget) -> usize {
+        self.0
+            .lock()
+            .attachments
+            .values()
+            .filter(|client| client.target == target)
+            .count()
+    }
 }
@@ -12,17 +12,22 @@ use std::{
 use anyhow::{bail, Context as _};
 use crossbeam_channel as crossbeam;
 use lazy_static::lazy_static;
-use tracing::{debug, trace, trace_span};
+use tracing::{debug, trace, trace_span, warn};
 
 use super::{
     audio,
-    compositor::{self, xwayland, Compositor},
-    control::{AudioStreamParams, ControlMessage, DisplayParams, SessionEvent, VideoStreamParams},
-    input, video, GamepadLayout, SessionHandle,
+    compositor::{self, xwayland, Compositor, WindowAction},
+    control::{
+        AudioStreamParams, ContentType, ControlMessage, DisplayParams, OutputParams, SessionEvent,
+        StreamTarget, VideoStreamParams,
+    },
+    dbus, input, recording, video, Activity, GamepadLayout, SessionHandle,
 };
 use crate::{
+    codec::{probe_codec, AudioCodec, VideoCodec},
+    color::VideoProfile,
     config::AppConfig,
-    container::{Container, ContainerHandle},
+    container::{Cgroup, Container, ContainerHandle},
     pixel_scale::PixelScale,
     server::stream::StreamWriter,
     vulkan::VkContext,
@@ -35,6 +40,17 @@ lazy_static! {
 
 const READY_TIMEOUT: std::time::Duration = time::Duration::from_secs(30);
 
+/// How many frames a new content type has to stick around before we retune
+/// the encoder for it.
+const CONTENT_TYPE_SETTLE_FRAMES: u32 = 30;
+
+/// Used for recording when no client is attached.
+const RECORDING_AUDIO_PARAMS: AudioStreamParams = AudioStreamParams {
+    sample_rate: 48000,
+    channels: 2,
+    codec: AudioCodec::Opus,
+};
+
 const DISPLAY: mio::Token = mio::Token(0);
 const ACCEPT: mio::Token = mio::Token(1);
 const CHILD: mio::Token = mio::Token(2);
@@ -61,10 +77,14 @@ pub struct Reactor {
 
     display_params: DisplayParams,
     new_display_params: Option<DisplayParams>,
+    additional_outputs: Vec<OutputParams>,
 
     audio_pipeline: audio::EncodePipeline,
-    video_pipeline: Option<video::EncodePipeline>,
-    new_video_stream_params: Option<VideoStreamParams>,
+    audio_params: AudioStreamParams,
+    video_pipelines: BTreeMap<StreamTarget, video::EncodePipeline>,
+    new_video_stream_params: BTreeMap<StreamTarget, VideoStreamParams>,
+    // Content type changes that haven't settled yet, with a frame count.
+    pending_content_types: BTreeMap<StreamTarget, (ContentType, u32)>,
 
     input_manager: input::InputDeviceManager,
     gamepads: BTreeMap<u64, input::GamepadHandle>,
@@ -72,25 +92,34 @@ pub struct Reactor {
     xwayland: Option<xwayland::XWayland>,
     xwayland_debug_log: Option<File>,
 
+    _session_bus: Option<dbus::SessionBus>,
+
     pending_attachments: Vec<ControlMessage>,
 
     ready_once: Option<oneshot::Sender<WakingSender<ControlMessage>>>,
     timer: mio_timerfd::TimerFd,
+    last_frame: time::Instant,
     sleeping: bool,
     shutting_down: bool,
+    stop_deadline: Option<time::Instant>,
 
     vk: Arc<VkContext>,
 }
 
 impl Reactor {
+    #[allow(clippy::too_many_arguments)]
     pub fn run(
         vk: Arc<VkContext>,
+        session_id: u64,
         app_config: AppConfig,
         display_params: DisplayParams,
+        additional_outputs: Vec<OutputParams>,
         permanent_gamepads: Vec<(u64, GamepadLayout)>,
         bug_report_dir: Option<PathBuf>,
+        cgroup: Option<Arc<Cgroup>>,
+        activity: Arc<Activity>,
         ready_send: oneshot::Sender<WakingSender<ControlMessage>>,
-    ) -> anyhow::Result<()> {
+    ) -> anyhow::Result<i32> {
         let mut display = wayland_server::Display::new().context("failed to create display")?;
 
         let ui_scale = if app_config.force_1x_scale {
@@ -99,27 +128,70 @@ impl Reactor {
             display_params.ui_scale
         };
 
+        let additional_outputs = additional_outputs
+            .into_iter()
+            .map(|output| OutputParams {
+                ui_scale: if app_config.force_1x_scale {
+                    PixelScale::ONE
+                } else {
+                    output.ui_scale
+                },
+                ..output
+            })
+            .collect::<Vec<_>>();
+
         trace!(
             %ui_scale,
             width = display_params.width,
             height = display_params.height,
+            ?additional_outputs,
             "configuring virtual display"
         );
 
         // Create wayland globals.
         let dh = display.handle();
-        compositor::create_globals(&dh);
-
-        let mut container = Container::new(
-            app_config.command.clone(),
-            app_config.home_isolation_mode.clone(),
-        )
+        compositor::create_globals(&dh, additional_outputs.len() + 1);
+
+        let mut container = match &app_config.rootfs {
+            Some(rootfs) => Container::with_rootfs(
+                app_config.command.clone(),
+                app_config.home_isolation_mode.clone(),
+                rootfs,
+            ),
+            None => Container::new(
+                app_config.command.clone(),
+                app_config.home_isolation_mode.clone(),
+            ),
+        }
         .context("initializing container")?;
 
         for (k, v) in &app_config.env {
             container.set_env(k, v);
         }
 
+        container.set_network_mode(app_config.network_mode);
+        container.set_denied_syscalls(&app_config.denied_syscalls);
+
+        for mount in &app_config.bind_mounts {
+            if mount.read_only {
+                container.bind_mount_readonly(&mount.source, &mount.destination);
+            } else {
+                container.bind_mount(&mount.source, &mount.destination);
+            }
+        }
+
+        for path in &app_config.tmpfs_paths {
+            container.mount_tmpfs(path)?;
+        }
+
+        for path in &app_config.masked_paths {
+            container.mask_path(path)?;
+        }
+
+        if let Some(cgroup) = cgroup {
+            container.set_cgroup(cgroup);
+        }
+
         let poll = mio::Poll::new()?;
         let waker = Arc::new(mio::Waker::new(poll.registry(), WAKER)?);
         let handle = SessionHandle::new(waker.clone());
@@ -144,6 +216,11 @@ impl Reactor {
             mio::Interest::READABLE,
         )?;
 
+        // Set up session recording, if enabled.
+        if let Some(dir) = &app_config.recording_dir {
+            handle.set_recorder(recording::Recorder::new(dir, session_id)?);
+        }
+
         // Set up the pulse audio server.
         let audio_pipeline =
             audio::EncodePipeline::new(handle.clone(), container.extern_run_path())?;
@@ -156,6 +233,9 @@ impl Reactor {
                 ui_scale, // Overridden by force_1x_scale.
                 ..display_params
             },
+            &additional_outputs,
+            app_config.variable_refresh,
+            activity,
         )?;
 
         // Set up input emulation (this is just for gamepads).
@@ -227,6 +307,25 @@ impl Reactor {
         // Shadow pipewire, just in case.
         container.set_env("PIPEWIRE_REMOTE", "(null)");
 
+        // Give the app its own session bus, so that it doesn't try to use
+        // (or autolaunch) one on the host.
+        let session_bus = if app_config.session_bus {
+            match dbus::SessionBus::spawn(&container.extern_run_path().join("bus")) {
+                Ok(bus) => {
+                    let address =
+                        format!("unix:path={}/bus", container.intern_run_path().display());
+                    container.set_env("DBUS_SESSION_BUS_ADDRESS", address);
+                    Some(bus)
+                }
+                Err(e) => {
+                    warn!("failed to start session bus: {:#}", e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
         let child = match container.spawn() {
             Ok(ch) => ch,
             Err(e) => {
@@ -298,10 +397,13 @@ impl Reactor {
 
             display_params,
             new_display_params: None,
+            additional_outputs,
 
             audio_pipeline,
-            video_pipeline: None,
-            new_video_stream_params: None,
+            audio_params: RECORDING_AUDIO_PARAMS,
+            video_pipelines: BTreeMap::new(),
+            new_video_stream_params: BTreeMap::new(),
+            pending_content_types: BTreeMap::new(),
 
             input_manager,
             gamepads,
@@ -311,10 +413,14 @@ impl Reactor {
             xwayland,
             xwayland_debug_log,
 
+            _session_bus: session_bus,
+
             ready_once: Some(ready_send),
             timer,
+            last_frame: time::Instant::now(),
             sleeping: false,
             shutting_down: false,
+            stop_deadline: None,
 
             vk,
         };
@@ -326,7 +432,7 @@ impl Reactor {
         &mut self,
         mut child_pipe: mio::unix::pipe::Receiver,
         mut xwayland_pipe: Option<mio::unix::pipe::Receiver>,
-    ) -> Result<(), anyhow::Error> {
+    ) -> Result<i32, anyhow::Error> {
         let mut events = mio::Events::with_capacity(64);
 
         let (control_send, control_recv) = crossbeam::unbounded();
@@ -337,7 +443,16 @@ impl Reactor {
         let mut xwayland_output = xwayland_pipe.as_mut().map(BufReader::new);
 
         loop {
-            trace_span!("poll").in_scope(|| self.poll.poll(&mut events, None))?;
+            // If we're waiting for the app to exit gracefully, wake up in time
+            // to kill it. Otherwise, wake up in time to tell apps the user
+            // went idle.
+            let timeout = match self.stop_deadline {
+                Some(d) => Some(d.saturating_duration_since(time::Instant::now())),
+                None if !self.shutting_down => self.compositor.time_until_idle_notification(),
+                None => None,
+            };
+
+            trace_span!("poll").in_scope(|| self.poll.poll(&mut events, timeout))?;
 
             for event in events.iter() {
                 match event.token() {
@@ -352,14 +467,14 @@ impl Reactor {
                         }
                     }
                     CHILD if event.is_read_closed() => {
-                        self.child.wait()?;
-                        self.session_handle.kick_clients();
+                        let exit_status = self.child.wait()?;
+                        self.session_handle.kick_clients(Some(exit_status));
 
                         if self.ready_once.is_some() {
                             // The client exited immediately, which is an error.
-                            bail!("client exited without doing anything");
+                            bail!("client exited without doing anything (status {exit_status})");
                         } else {
-                            return Ok(());
+                            return Ok(exit_status);
                         }
                     }
                     CHILD if event.is_readable() => {
@@ -368,13 +483,20 @@ impl Reactor {
                     WAKER => loop {
                         match control_recv.try_recv() {
                             Ok(ControlMessage::Stop) => {
-                                self.session_handle.kick_clients();
+                                self.session_handle.kick_clients(None);
                                 self.shutting_down = true;
                                 trace!("shutting down");
 
-                                // Usually, TERM doesn't work, because the
-                                // process is PID 1 in the container.
-                                self.child.signal(rustix::process::Signal::KILL)?;
+                                // Give the app a chance to exit cleanly. Init
+                                // forwards TERM to the app; KILL takes down
+                                // the whole container.
+                                let timeout = self.app_config.stop_timeout;
+                                if timeout.is_zero() {
+                                    self.child.signal(rustix::process::Signal::KILL)?;
+                                } else {
+                                    self.child.signal(rustix::process::Signal::TERM)?;
+                                    self.stop_deadline = Some(time::Instant::now() + timeout);
+                                }
                             }
                             Ok(msg) => self.handle_control_message(msg)?,
                             Err(crossbeam::TryRecvError::Empty) => break,
@@ -412,7 +534,10 @@ impl Reactor {
                         }
                     }
                     XWAYLAND if event.is_read_closed() => {
-                        self.xwayland.as_mut().unwrap().child.wait()?;
+                        let exit_status = self.xwayland.as_mut().unwrap().child.wait()?;
+                        if exit_status != 0 {
+                            bail!("Xwayland exited with status {exit_status}");
+                        }
                     }
                     XWAYLAND if event.is_readable() => {
                         dump_child_output(
@@ -434,14 +559,35 @@ impl Reactor {
                                 ))?;
                         }
 
-                        self.frame()?;
+                        self.frame(true)?;
                     }
                     _ => unreachable!(),
                 }
             }
 
+            // Surfaces that allow tearing are rendered as soon as they commit,
+            // without waiting for the simulated vblank. In variable refresh
+            // mode, the same goes for the active surface.
+            if !self.sleeping && !self.shutting_down {
+                if self.compositor.take_async_surface_commit() {
+                    self.early_frame(false)?;
+                }
+
+                if self.app_config.variable_refresh && self.compositor.take_active_surface_commit()
+                {
+                    self.early_frame(true)?;
+                }
+            }
+
             if !self.shutting_down {
                 self.idle()?;
+            } else if self
+                .stop_deadline
+                .is_some_and(|d| time::Instant::now() >= d)
+            {
+                debug!("app didn't exit in time, killing it");
+                self.stop_deadline = None;
+                self.child.signal(rustix::process::Signal::KILL)?;
             }
 
             // Check that we haven't timed out waiting for the client to start up.
@@ -477,6 +623,7 @@ impl Reactor {
             for attach_msg in pending_attachments {
                 if let ControlMessage::Attach {
                     id,
+                    target,
                     sender,
                     video_params,
                     audio_params,
@@ -484,9 +631,35 @@ impl Reactor {
                     ready,
                 } = attach_msg
                 {
+                    // Resize the window to match the stream. If it's gone,
+                    // dropping the ready channel rejects the attachment.
+                    if let StreamTarget::Window(window_id) = target {
+                        let size = (video_params.width, video_params.height).into();
+                        if !self
+                            .compositor
+                            .set_window_stream_size(window_id, Some(size))
+                        {
+                            debug!(id, window_id, "rejecting attachment to missing window");
+                            continue;
+                        }
+                    }
+
+                    // Attaching counts as activity, so that the session
+                    // isn't immediately considered idle.
+                    self.compositor.record_activity();
+
                     // Check if the caller is still waiting.
                     if ready.send(()).is_ok() {
-                        self.attach(id, sender, video_params, audio_params, stream_writer)?;
+                        self.attach(
+                            id,
+                            target,
+                            sender,
+                            video_params,
+                            audio_params,
+                            stream_writer,
+                        )?;
+                    } else if let StreamTarget::Window(window_id) = target {
+                        self.compositor.set_window_stream_size(window_id, None);
                     }
                 } else {
                     unreachable!()
@@ -508,8 +681,12 @@ impl Reactor {
         Ok(())
     }
 
+    /// Returns true if we're rendering frames. Otherwise, the session sleeps.
+    /// A recording session never sleeps.
     fn active(&self) -> bool {
-        self.session_handle.num_attachments() > 0 || !self.pending_attachments.is_empty()
+        self.session_handle.num_attachments() > 0
+            || !self.pending_attachments.is_empty()
+            || self.session_handle.is_recording()
     }
 
     fn update_display_params(&mut self, params: DisplayParams) -> anyhow::Result<()> {
@@ -539,32 +716,23 @@ impl Reactor {
                 "resizing output",
             );
 
-            // If the size or framerate is different, force the client to reattach.
-            // TODO: if we support multiple attachments, or attachments that
-            // differ in resolution from the render res, we need to check for
-            // that here. For now, it's safe to just kill the attachment streams.
-            let force_reattach = size_changed || framerate_changed;
-
             self.compositor.update_display_params(
                 DisplayParams {
                     ui_scale: new_ui_scale,
                     ..params
                 },
-                // If we're forcing clients to reattach, the attachment is about
-                // end, so configure the surfaces as inactive.
-                !force_reattach,
+                self.active(),
             )?;
 
             self.session_handle
-                .dispatch(SessionEvent::DisplayParamsChanged {
-                    params,
-                    reattach: force_reattach,
-                });
+                .dispatch(SessionEvent::DisplayParamsChanged { params });
 
-            if force_reattach {
+            if size_changed || framerate_changed {
                 // Clear any pending attachments which don't match the new output.
+                // Attachments to other outputs or windows are unaffected.
                 self.pending_attachments.retain(|pending| {
                     let ControlMessage::Attach {
+                        target,
                         video_params: VideoStreamParams { width, height, .. },
                         ..
                     } = pending
@@ -572,23 +740,35 @@ impl Reactor {
                         unreachable!()
                     };
 
-                    *width == params.width && *height == params.height
+                    *target != StreamTarget::PRIMARY
+                        || (*width == params.width && *height == params.height)
                 });
 
-                // Clear any current attachments.
-                self.session_handle.remove_all();
-                self.audio_pipeline.stop_stream();
+                // Once the app has caught up, restart all encoders if the
+                // framerate changed, or resize the primary output's encoder in
+                // place. Attached clients stay attached, and see a new video
+                // stream starting with a keyframe.
+                if framerate_changed {
+                    for (target, pipeline) in std::mem::take(&mut self.video_pipelines) {
+                        self.new_video_stream_params
+                            .insert(target, pipeline.streaming_params());
+                    }
+                } else if let Some(pipeline) = self.video_pipelines.get(&StreamTarget::PRIMARY) {
+                    self.new_video_stream_params
+                        .insert(StreamTarget::PRIMARY, pipeline.streaming_params());
+                }
 
-                self.video_pipeline = None;
-                self.new_video_stream_params = None;
+                if let Some(stream_params) =
+                    self.new_video_stream_params.get_mut(&StreamTarget::PRIMARY)
+                {
+                    stream_params.width = params.width;
+                    stream_params.height = params.height;
+                }
             }
         } else if params.ui_scale != old.ui_scale {
             // Synthesize a param change if we are forcing 1x scale.
             self.session_handle
-                .dispatch(SessionEvent::DisplayParamsChanged {
-                    params,
-                    reattach: false,
-                });
+                .dispatch(SessionEvent::DisplayParamsChanged { params });
         }
 
         self.display_params = DisplayParams {
@@ -599,58 +779,258 @@ impl Reactor {
         Ok(())
     }
 
-    fn frame(&mut self) -> anyhow::Result<()> {
+    /// Renders a frame without waiting for the timer. We still don't render
+    /// faster than the framerate; if we rendered too recently, the next timer
+    /// tick catches the frame instead.
+    fn early_frame(&mut self, vsync: bool) -> anyhow::Result<()> {
+        let interval = time::Duration::from_secs_f64(1.0 / self.display_params.framerate as f64);
+        if self.last_frame.elapsed() >= interval && self.frame(vsync)? {
+            // Restart the timer, so that it fires one interval after the
+            // frame we just rendered.
+            self.timer.set_timeout_interval(&interval)?;
+        }
+
+        Ok(())
+    }
+
+    /// Composites and encodes a frame, if needed. Returns true if a frame was
+    /// rendered. `vsync` is false for frames rendered immediately for a
+    /// surface that allows tearing.
+    fn frame(&mut self, vsync: bool) -> anyhow::Result<bool> {
         #[cfg(feature = "tracy")]
         tracy_client::frame_mark();
 
-        if self.session_handle.num_attachments() == 0 {
-            return Ok(());
+        let recording = self.session_handle.is_recording();
+        if self.session_handle.num_attachments() == 0 && !recording {
+            return Ok(false);
         }
 
         if !self.compositor.surfaces_ready() {
-            return Ok(());
+            return Ok(false);
         }
 
-        if let Some(params) = self.new_video_stream_params.take() {
-            self.video_pipeline = Some(video::EncodePipeline::new(
+        // If we're recording with no client attached, we have to start the
+        // encoders ourselves. Only the primary output is recorded.
+        if recording
+            && !self.video_pipelines.contains_key(&StreamTarget::PRIMARY)
+            && !self
+                .new_video_stream_params
+                .contains_key(&StreamTarget::PRIMARY)
+        {
+            let codec = if probe_codec(self.vk.clone(), VideoCodec::H265) {
+                VideoCodec::H265
+            } else {
+                VideoCodec::H264
+            };
+
+            self.new_video_stream_params.insert(
+                StreamTarget::PRIMARY,
+                VideoStreamParams {
+                    width: self.display_params.width,
+                    height: self.display_params.height,
+                    codec,
+                    preset: 6,
+                    profile: VideoProfile::Hd,
+                    content_type: ContentType::None,
+                },
+            );
+
+            self.restart_audio_stream(RECORDING_AUDIO_PARAMS)?;
+        }
+
+        // Retune encoders if the app changed the content type, e.g. by focusing
+        // a video player. If the rate control is all that changes, we can do
+        // that in place; otherwise, the encoder has to be restarted, which
+        // costs a keyframe. Either way, we wait for the new content type to
+        // settle first, so that an app flipping back and forth doesn't cause
+        // churn.
+        let mut restart = Vec::new();
+        for (target, pipeline) in self.video_pipelines.iter_mut() {
+            let content_type = self.compositor.content_type(*target);
+            if pipeline.streaming_params().content_type == content_type {
+                self.pending_content_types.remove(target);
+                continue;
+            }
+
+            let (pending, frames) = self
+                .pending_content_types
+                .entry(*target)
+                .or_insert((content_type, 0));
+            if *pending != content_type {
+                *pending = content_type;
+                *frames = 0;
+            }
+
+            *frames += 1;
+            if *frames < CONTENT_TYPE_SETTLE_FRAMES {
+                continue;
+            }
+
+            self.pending_content_types.remove(target);
+            if pipeline.set_content_type(content_type) {
+                debug!(
+                    ?target,
+                    ?content_type,
+                    "content type changed, updated rate control"
+                );
+            } else {
+                restart.push(*target);
+            }
+        }
+
+        for target in restart {
+            let pipeline = self.video_pipelines.remove(&target).unwrap();
+            debug!(?target, "content type changed, restarting encoder");
+            self.new_video_stream_params
+                .insert(target, pipeline.streaming_params());
+        }
+
+        for (target, mut params) in std::mem::take(&mut self.new_video_stream_params) {
+            // A resized encoder keeps its tuning, which is updated above.
+            params.content_type = match self.video_pipelines.get(&target) {
+                Some(pipeline) => pipeline.streaming_params().content_type,
+                None => self.compositor.content_type(target),
+            };
+
+            if target == StreamTarget::PRIMARY {
+                self.session_handle
+                    .start_recording_segment(params, self.audio_params);
+            }
+
+            let display_params = self.stream_display_params(target, params);
+            if let Some(pipeline) = self.video_pipelines.get_mut(&target) {
+                pipeline.resize(display_params, params)?;
+                continue;
+            }
+
+            let pipeline = video::EncodePipeline::new(
                 self.vk.clone(),
                 self.session_handle.clone(),
-                self.display_params,
+                target,
+                display_params,
                 params,
-            )?);
+            )?;
+
+            self.video_pipelines.insert(target, pipeline);
         }
 
-        let Some(video_pipeline) = &mut self.video_pipeline else {
-            return Ok(());
-        };
+        if self.video_pipelines.is_empty() {
+            return Ok(false);
+        }
 
         // Composite visible surfaces.
-        self.compositor.composite_frame(video_pipeline)?;
+        let rendered = self
+            .compositor
+            .composite_frame(&mut self.video_pipelines, vsync)?;
+        if rendered {
+            self.last_frame = time::Instant::now();
+        }
 
         // Render the cursor, if needed.
         self.compositor.render_cursor()?;
 
+        Ok(rendered)
+    }
+
+    /// Returns the display params for an output or window, for the purposes
+    /// of encoding. Everything shares the same framerate.
+    fn stream_display_params(
+        &self,
+        target: StreamTarget,
+        params: VideoStreamParams,
+    ) -> DisplayParams {
+        match target {
+            StreamTarget::Output(0) => self.display_params,
+            StreamTarget::Output(n) => {
+                let output = self.additional_outputs[n - 1];
+                DisplayParams {
+                    width: output.width,
+                    height: output.height,
+                    ui_scale: output.ui_scale,
+                    ..self.display_params
+                }
+            }
+            // Windows are sized to match the stream.
+            StreamTarget::Window(_) => DisplayParams {
+                width: params.width,
+                height: params.height,
+                ..self.display_params
+            },
+        }
+    }
+
+    /// Stops encoding for an output or window. A streamed window goes back to
+    /// its usual size.
+    fn stop_video_stream(&mut self, target: StreamTarget) {
+        self.video_pipelines.remove(&target);
+        self.new_video_stream_params.remove(&target);
+        self.pending_content_types.remove(&target);
+
+        if let StreamTarget::Window(id) = target {
+            self.compositor.set_window_stream_size(id, None);
+        }
+    }
+
+    fn stop_all_video_streams(&mut self) {
+        let targets = self
+            .video_pipelines
+            .keys()
+            .chain(self.new_video_stream_params.keys())
+            .copied()
+            .collect::<Vec<_>>();
+
+        for target in targets {
+            self.stop_video_stream(target);
+        }
+    }
+
+    fn restart_audio_stream(&mut self, params: AudioStreamParams) -> anyhow::Result<()> {
+        self.audio_pipeline.stop_stream();
+        self.audio_pipeline.restart_stream(params)?;
+        self.audio_params = params;
         Ok(())
     }
 
     fn attach(
         &mut self,
         id: u64,
+        target: StreamTarget,
         sender: crossbeam::Sender<SessionEvent>,
         video_params: VideoStreamParams,
         audio_params: AudioStreamParams,
         stream_writer: StreamWriter,
     ) -> anyhow::Result<()> {
-        if self.session_handle.num_attachments() > 0 {
+        if self.session_handle.num_attachments_for(target) > 0 {
             unimplemented!();
         }
 
-        self.session_handle.insert_client(id, sender, stream_writer);
-        self.new_video_stream_params = Some(video_params);
-        self.audio_pipeline.restart_stream(audio_params)?;
+        let receives_audio =
+            self.session_handle
+                .insert_client(id, target, audio_params, sender, stream_writer);
+        self.new_video_stream_params.insert(target, video_params);
+
+        // The primary output determines HDR mode.
+        if target == StreamTarget::PRIMARY {
+            self.compositor
+                .set_hdr_output(video_params.profile == VideoProfile::Hdr10);
+        }
+
+        if receives_audio {
+            // The audio stream may already be running for a recording.
+            self.restart_audio_stream(audio_params)?;
+        }
+
+        // Bring a streamed window to the front.
+        if let StreamTarget::Window(window_id) = target {
+            self.compositor
+                .handle_window_action(window_id, WindowAction::Focus)?;
+        }
+
         self.compositor.update_focus_and_visibility(true)?;
 
         self.compositor.dispatch_cursor();
+        self.compositor.dispatch_window_list(true);
+        self.compositor.dispatch_window_metadata(true);
         if let Some(coords) = self.compositor.default_seat.pointer_locked() {
             let (x, y) = coords.into();
             self.session_handle
@@ -674,7 +1054,7 @@ impl Reactor {
 
         match msg {
             ControlMessage::Detach(id) => {
-                self.session_handle.remove_client(id);
+                let target = self.session_handle.remove_client(id);
                 self.pending_attachments.retain(|msg| {
                     let ControlMessage::Attach { id: pending_id, .. } = msg else {
                         unreachable!();
@@ -685,12 +1065,34 @@ impl Reactor {
 
                 if !self.active() {
                     self.audio_pipeline.stop_stream();
-                    self.video_pipeline = None;
+                    self.stop_all_video_streams();
                     self.compositor.update_focus_and_visibility(false)?;
+                } else if let Some(target) = target {
+                    // Stop encoding the output or window, unless it's being
+                    // recorded.
+                    let recorded =
+                        target == StreamTarget::PRIMARY && self.session_handle.is_recording();
+                    if !recorded && self.session_handle.num_attachments_for(target) == 0 {
+                        self.stop_video_stream(target);
+                    }
+
+                    // Hand the audio to another attachment, if the detached
+                    // client was the one receiving it.
+                    if let Some(audio_params) = self.session_handle.reassign_audio() {
+                        self.restart_audio_stream(audio_params)?;
+                    }
                 }
             }
-            ControlMessage::RefreshVideo => {
-                if let Some(video) = &mut self.video_pipeline {
+            ControlMessage::Kick(id) => {
+                // The client will detach in response.
+                self.session_handle.kick_client(id);
+            }
+            ControlMessage::Suspend(id) => {
+                // Same as above, with a different error for the client.
+                self.session_handle.suspend_client(id);
+            }
+            ControlMessage::RefreshVideo(target) => {
+                if let Some(video) = self.video_pipelines.get_mut(&target) {
                     video.request_refresh();
                 }
             }
@@ -698,14 +1100,48 @@ impl Reactor {
                 // Updates once per render.
                 self.new_display_params = Some(params);
             }
+            ControlMessage::ResizeWindowStream {
+                window_id,
+                width,
+                height,
+            } => {
+                let target = StreamTarget::Window(window_id);
+                let params = self
+                    .new_video_stream_params
+                    .get(&target)
+                    .copied()
+                    .or_else(|| {
+                        self.video_pipelines
+                            .get(&target)
+                            .map(|p| p.streaming_params())
+                    });
+
+                // The encoder is resized in place once the app has caught up.
+                if let Some(params) = params {
+                    self.compositor
+                        .set_window_stream_size(window_id, Some((width, height).into()));
+                    self.new_video_stream_params.insert(
+                        target,
+                        VideoStreamParams {
+                            width,
+                            height,
+                            ..params
+                        },
+                    );
+                }
+            }
+            ControlMessage::PointerEntered | ControlMessage::PointerLeft => {
+                self.compositor.handle_input_event(msg)
+            }
             ControlMessage::KeyboardInput { .. }
             | ControlMessage::PointerInput { .. }
             | ControlMessage::PointerMotion { .. }
             | ControlMessage::RelativePointerMotion { .. }
             | ControlMessage::PointerAxis(_, _)
-            | ControlMessage::PointerAxisDiscrete(_, _)
-            | ControlMessage::PointerEntered
-            | ControlMessage::PointerLeft => self.compositor.handle_input_event(msg),
+            | ControlMessage::PointerAxisDiscrete(_, _) => {
+                self.compositor.record_activity();
+                self.compositor.handle_input_event(msg)
+            }
             ControlMessage::GamepadAvailable(id) => {
                 use std::collections::btree_map::Entry;
                 if let Entry::Vacant(e) = self.gamepads.entry(id) {
@@ -730,6 +1166,7 @@ impl Reactor {
                 axis_code,
                 value,
             } => {
+                self.compositor.record_activity();
                 if let Some(gamepad) = self.gamepads.get_mut(&id) {
                     gamepad.axis(axis_code, value);
                 }
@@ -739,6 +1176,7 @@ impl Reactor {
                 trigger_code,
                 value,
             } => {
+                self.compositor.record_activity();
                 if let Some(gamepad) = self.gamepads.get_mut(&id) {
                     gamepad.trigger(trigger_code, value);
                 }
@@ -748,10 +1186,14 @@ impl Reactor {
                 button_code,
                 state,
             } => {
+                self.compositor.record_activity();
                 if let Some(gamepad) = self.gamepads.get_mut(&id) {
                     gamepad.input(button_code, state);
                 }
             }
+            ControlMessage::WindowAction { id, action } => {
+                self.compositor.handle_window_action(id, action)?;
+            }
             // Handled above.
             ControlMessage::Stop | ControlMessage::Attach { .. } => unreachable!(),
         }
@@ -0,0 +1,225 @@
+// Copyright 2024 Colin Marc <hi@colinmarc.com>
+//
+// SPDX-License-Identifier: BUSL-1.1
+
+use std::{
+    fs::{File, OpenOptions},
+    io::BufWriter,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
+    time,
+};
+
+use anyhow::Context as _;
+use bytes::Bytes;
+use crossbeam_channel as crossbeam;
+use tracing::{debug, error, info, warn};
+
+use super::control::{AudioStreamParams, VideoStreamParams};
+
+mod mkv;
+
+/// How many frames can be waiting to be written before we start dropping them.
+/// This is a few seconds of audio and video.
+const MAX_QUEUED_FRAMES: usize = 512;
+
+enum RecorderMessage {
+    NewSegment(VideoStreamParams, AudioStreamParams),
+    Video {
+        pts: u64,
+        frame: Bytes,
+        keyframe: bool,
+    },
+    Audio {
+        pts: u64,
+        frame: Bytes,
+    },
+}
+
+/// Records encoded audio and video to Matroska files on disk. A new file is
+/// started whenever the encoder is restarted with new parameters.
+pub struct Recorder {
+    sender: Option<crossbeam::Sender<RecorderMessage>>,
+    thread_handle: Option<std::thread::JoinHandle<()>>,
+    // Set if a video frame was dropped because the disk couldn't keep up. The
+    // following frames are dropped too, until the next keyframe.
+    dropping_video: AtomicBool,
+}
+
+impl Recorder {
+    pub fn new(dir: impl AsRef<Path>, session_id: u64) -> anyhow::Result<Self> {
+        let dir = dir.as_ref().to_owned();
+        std::fs::create_dir_all(&dir).context(format!(
+            "failed to create recording directory ({})",
+            dir.display()
+        ))?;
+
+        let (sender, receiver) = crossbeam::bounded(MAX_QUEUED_FRAMES);
+        let thread_handle = std::thread::Builder::new()
+            .name("recorder".into())
+            .spawn(move || record(dir, session_id, receiver))?;
+
+        Ok(Self {
+            sender: Some(sender),
+            thread_handle: Some(thread_handle),
+            dropping_video: AtomicBool::new(false),
+        })
+    }
+
+    /// Finishes the current file. The next file starts with the next keyframe.
+    pub fn new_segment(&self, video_params: VideoStreamParams, audio_params: AudioStreamParams) {
+        // This one can't be dropped, so we block if we have to.
+        if let Some(sender) = &self.sender {
+            let _ = sender.send(RecorderMessage::NewSegment(video_params, audio_params));
+        }
+    }
+
+    /// Queues a video frame to be written. If the recorder is falling behind,
+    /// frames are dropped until the next keyframe, since the frames in between
+    /// can't be decoded anyway.
+    pub fn write_video_frame(&self, pts: u64, frame: Bytes, keyframe: bool) {
+        if !keyframe && self.dropping_video.load(Ordering::Relaxed) {
+            return;
+        }
+
+        let sent = self.try_send(RecorderMessage::Video {
+            pts,
+            frame,
+            keyframe,
+        });
+
+        if !sent && !self.dropping_video.swap(true, Ordering::Relaxed) {
+            warn!("recording can't keep up, dropping video until the next keyframe");
+        } else if sent && keyframe {
+            self.dropping_video.store(false, Ordering::Relaxed);
+        }
+    }
+
+    pub fn write_audio_frame(&self, pts: u64, frame: Bytes) {
+        if !self.try_send(RecorderMessage::Audio { pts, frame }) {
+            debug!("recording can't keep up, dropped audio frame");
+        }
+    }
+
+    /// Sends a message to the writer thread without blocking. Returns false if
+    /// the queue is full.
+    fn try_send(&self, msg: RecorderMessage) -> bool {
+        match &self.sender {
+            Some(sender) => !matches!(sender.try_send(msg), Err(crossbeam::TrySendError::Full(_))),
+            None => true,
+        }
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        // Hang up, so that the thread finishes the current file and exits.
+        self.sender.take();
+
+        if let Some(handle) = self.thread_handle.take() {
+            if handle.join().is_err() {
+                error!("recorder thread panicked");
+            }
+        }
+    }
+}
+
+struct Segment {
+    video_params: VideoStreamParams,
+    audio_params: AudioStreamParams,
+    writer: Option<(PathBuf, mkv::MkvWriter<BufWriter<File>>)>,
+}
+
+fn record(dir: PathBuf, session_id: u64, receiver: crossbeam::Receiver<RecorderMessage>) {
+    let mut segment: Option<Segment> = None;
+    let mut segment_num = 0;
+
+    let started = time::SystemTime::now()
+        .duration_since(time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+
+    while let Ok(msg) = receiver.recv() {
+        let res = match msg {
+            RecorderMessage::NewSegment(video_params, audio_params) => {
+                finish_segment(segment.take());
+                segment = Some(Segment {
+                    video_params,
+                    audio_params,
+                    writer: None,
+                });
+
+                Ok(())
+            }
+            RecorderMessage::Video {
+                pts,
+                frame,
+                keyframe,
+            } => {
+                let Some(segment) = segment.as_mut() else {
+                    continue;
+                };
+
+                // Files must start with a keyframe, which contains the
+                // parameter sets we need for the header.
+                if segment.writer.is_none() && keyframe {
+                    segment_num += 1;
+                    let path = dir.join(format!("{started}-{session_id}-{segment_num}.mkv"));
+                    match start_file(&path, segment, &frame) {
+                        Ok(w) => {
+                            info!(path = ?path, "recording started");
+                            segment.writer = Some((path, w));
+                        }
+                        Err(err) => error!(?path, "failed to start recording: {err:#}"),
+                    }
+                }
+
+                match &mut segment.writer {
+                    Some((_, w)) => w.write_video(pts, &frame, keyframe),
+                    None => Ok(()),
+                }
+            }
+            RecorderMessage::Audio { pts, frame } => {
+                match segment.as_mut().and_then(|s| s.writer.as_mut()) {
+                    Some((_, w)) => w.write_audio(pts, &frame),
+                    None => Ok(()),
+                }
+            }
+        };
+
+        if let Err(err) = res {
+            error!("error writing recording: {err:#}");
+
+            // Stop writing to the file until the next segment.
+            if let Some(segment) = segment.as_mut() {
+                segment.writer = None;
+            }
+        }
+    }
+
+    finish_segment(segment);
+}
+
+fn start_file(
+    path: &Path,
+    segment: &Segment,
+    keyframe: &[u8],
+) -> anyhow::Result<mkv::MkvWriter<BufWriter<File>>> {
+    let file = OpenOptions::new().write(true).create_new(true).open(path)?;
+    mkv::MkvWriter::new(
+        BufWriter::new(file),
+        segment.video_params,
+        keyframe,
+        segment.audio_params.sample_rate,
+        segment.audio_params.channels,
+    )
+}
+
+fn finish_segment(segment: Option<Segment>) {
+    if let Some((path, w)) = segment.and_then(|s| s.writer) {
+        match w.finish() {
+            Ok(_) => debug!(?path, "recording finished"),
+            Err(err) => error!(?path, "failed to finish recording: {err:#}"),
+        }
+    }
+}
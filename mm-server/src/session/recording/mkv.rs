@@ -0,0 +1,516 @@
+// Copyright 2024 Colin Marc <hi@colinmarc.com>
+//
+// SPDX-License-Identifier: BUSL-1.1
+
+//! A minimal, append-only Matroska muxer. The segment is written with an
+//! unknown size, like a live stream, so that files are playable even if the
+//! recording is interrupted.
+
+use std::io::Write;
+
+use bytes::{BufMut as _, Bytes, BytesMut};
+
+use crate::{codec::VideoCodec, color::VideoProfile, session::control::VideoStreamParams};
+
+const EBML: u32 = 0x1A45DFA3;
+const EBML_VERSION: u32 = 0x4286;
+const EBML_READ_VERSION: u32 = 0x42F7;
+const EBML_MAX_ID_LENGTH: u32 = 0x42F2;
+const EBML_MAX_SIZE_LENGTH: u32 = 0x42F3;
+const DOC_TYPE: u32 = 0x4282;
+const DOC_TYPE_VERSION: u32 = 0x4287;
+const DOC_TYPE_READ_VERSION: u32 = 0x4285;
+
+const SEGMENT: u32 = 0x18538067;
+const INFO: u32 = 0x1549A966;
+const TIMESTAMP_SCALE: u32 = 0x2AD7B1;
+const MUXING_APP: u32 = 0x4D80;
+const WRITING_APP: u32 = 0x5741;
+
+const TRACKS: u32 = 0x1654AE6B;
+const TRACK_ENTRY: u32 = 0xAE;
+const TRACK_NUMBER: u32 = 0xD7;
+const TRACK_UID: u32 = 0x73C5;
+const TRACK_TYPE: u32 = 0x83;
+const FLAG_LACING: u32 = 0x9C;
+const CODEC_ID: u32 = 0x86;
+const CODEC_PRIVATE: u32 = 0x63A2;
+const SEEK_PRE_ROLL: u32 = 0x56BB;
+const VIDEO: u32 = 0xE0;
+const PIXEL_WIDTH: u32 = 0xB0;
+const PIXEL_HEIGHT: u32 = 0xBA;
+const COLOUR: u32 = 0x55B0;
+const MATRIX_COEFFICIENTS: u32 = 0x55B1;
+const BITS_PER_CHANNEL: u32 = 0x55B2;
+const RANGE: u32 = 0x55B9;
+const TRANSFER_CHARACTERISTICS: u32 = 0x55BA;
+const PRIMARIES: u32 = 0x55BB;
+const AUDIO: u32 = 0xE1;
+const SAMPLING_FREQUENCY: u32 = 0xB5;
+const CHANNELS: u32 = 0x9F;
+
+const CLUSTER: u32 = 0x1F43B675;
+const CLUSTER_TIMESTAMP: u32 = 0xE7;
+const SIMPLE_BLOCK: u32 = 0xA3;
+
+const VIDEO_TRACK: u64 = 1;
+const AUDIO_TRACK: u64 = 2;
+
+/// Block timestamps are signed 16-bit offsets from the cluster timestamp.
+const MAX_CLUSTER_DURATION_MS: u64 = 30_000;
+
+/// Writes a Matroska file with one video and one Opus audio track.
+/// Timestamps are in milliseconds.
+pub struct MkvWriter<W: Write> {
+    w: W,
+    video_codec: VideoCodec,
+    cluster: BytesMut,
+    cluster_ts: Option<u64>,
+}
+
+impl<W: Write> MkvWriter<W> {
+    /// Writes the file header. The parameter sets (SPS, PPS, and so on) are
+    /// taken from the first keyframe of the stream, in Annex B format.
+    pub fn new(
+        mut w: W,
+        params: VideoStreamParams,
+        keyframe: &[u8],
+        audio_sample_rate: u32,
+        audio_channels: u32,
+    ) -> anyhow::Result<Self> {
+        let (codec_id, codec_private) = match params.codec {
+            VideoCodec::H264 => ("V_MPEG4/ISO/AVC", avc_decoder_config(keyframe)?),
+            VideoCodec::H265 => (
+                "V_MPEGH/ISO/HEVC",
+                hevc_decoder_config(keyframe, params.profile)?,
+            ),
+            VideoCodec::Av1 => anyhow::bail!("recording AV1 is not supported"),
+        };
+
+        let mut header = BytesMut::new();
+        element(
+            &mut header,
+            EBML,
+            &[
+                uint(EBML_VERSION, 1),
+                uint(EBML_READ_VERSION, 1),
+                uint(EBML_MAX_ID_LENGTH, 4),
+                uint(EBML_MAX_SIZE_LENGTH, 8),
+                string(DOC_TYPE, "matroska"),
+                uint(DOC_TYPE_VERSION, 4),
+                uint(DOC_TYPE_READ_VERSION, 2),
+            ]
+            .concat(),
+        );
+
+        // The segment has an unknown size.
+        put_id(&mut header, SEGMENT);
+        header.put_slice(&[0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF]);
+
+        let app = concat!("mmserver ", env!("CARGO_PKG_VERSION"));
+        element(
+            &mut header,
+            INFO,
+            &[
+                uint(TIMESTAMP_SCALE, 1_000_000),
+                string(MUXING_APP, app),
+                string(WRITING_APP, app),
+            ]
+            .concat(),
+        );
+
+        let mut video = vec![
+            uint(PIXEL_WIDTH, params.width as u64),
+            uint(PIXEL_HEIGHT, params.height as u64),
+        ];
+
+        // Values are from ISO/IEC 23091-4.
+        let colour = match params.profile {
+            VideoProfile::Hd => [uint(BITS_PER_CHANNEL, 8), uint(MATRIX_COEFFICIENTS, 1)],
+            VideoProfile::Hdr10 => [uint(BITS_PER_CHANNEL, 10), uint(MATRIX_COEFFICIENTS, 9)],
+        };
+
+        let (tc, primaries) = match params.profile {
+            VideoProfile::Hd => (1, 1),
+            VideoProfile::Hdr10 => (16, 9),
+        };
+
+        video.push(master(
+            COLOUR,
+            &[
+                colour.concat(),
+                uint(RANGE, 1),
+                uint(TRANSFER_CHARACTERISTICS, tc),
+                uint(PRIMARIES, primaries),
+            ]
+            .concat(),
+        ));
+
+        let video_track = [
+            uint(TRACK_NUMBER, VIDEO_TRACK),
+            uint(TRACK_UID, VIDEO_TRACK),
+            uint(TRACK_TYPE, 1),
+            uint(FLAG_LACING, 0),
+            string(CODEC_ID, codec_id),
+            binary(CODEC_PRIVATE, &codec_private),
+            master(VIDEO, &video.concat()),
+        ]
+        .concat();
+
+        let audio_track = [
+            uint(TRACK_NUMBER, AUDIO_TRACK),
+            uint(TRACK_UID, AUDIO_TRACK),
+            uint(TRACK_TYPE, 2),
+            uint(FLAG_LACING, 0),
+            string(CODEC_ID, "A_OPUS"),
+            binary(CODEC_PRIVATE, &opus_head(audio_sample_rate, audio_channels)),
+            uint(SEEK_PRE_ROLL, 80_000_000),
+            master(
+                AUDIO,
+                &[
+                    float(SAMPLING_FREQUENCY, audio_sample_rate as f64),
+                    uint(CHANNELS, audio_channels as u64),
+                ]
+                .concat(),
+            ),
+        ]
+        .concat();
+
+        element(
+            &mut header,
+            TRACKS,
+            &[
+                master(TRACK_ENTRY, &video_track),
+                master(TRACK_ENTRY, &audio_track),
+            ]
+            .concat(),
+        );
+
+        w.write_all(&header)?;
+
+        Ok(Self {
+            w,
+            video_codec: params.codec,
+            cluster: BytesMut::new(),
+            cluster_ts: None,
+        })
+    }
+
+    /// Writes an Annex B video frame.
+    pub fn write_video(&mut self, pts: u64, frame: &[u8], keyframe: bool) -> anyhow::Result<()> {
+        // Start a new cluster on every keyframe, for seeking.
+        if keyframe {
+            self.flush_cluster()?;
+        }
+
+        let data = annexb_to_length_prefixed(frame, self.video_codec);
+        self.write_block(VIDEO_TRACK, pts, &data, keyframe)
+    }
+
+    /// Writes an Opus packet.
+    pub fn write_audio(&mut self, pts: u64, packet: &[u8]) -> anyhow::Result<()> {
+        self.write_block(AUDIO_TRACK, pts, packet, true)
+    }
+
+    /// Writes any buffered blocks to the underlying writer.
+    pub fn finish(mut self) -> anyhow::Result<W> {
+        self.flush_cluster()?;
+        self.w.flush()?;
+        Ok(self.w)
+    }
+
+    fn write_block(
+        &mut self,
+        track: u64,
+        pts: u64,
+        data: &[u8],
+        keyframe: bool,
+    ) -> anyhow::Result<()> {
+        // Audio and video are encoded on different threads, so blocks may
+        // arrive slightly out of order. Small negative offsets are fine.
+        if self.cluster_ts.is_some_and(|ts| {
+            let offset = pts as i64 - ts as i64;
+            offset < i16::MIN as i64 || offset > MAX_CLUSTER_DURATION_MS as i64
+        }) {
+            self.flush_cluster()?;
+        }
+
+        let cluster_ts = *self.cluster_ts.get_or_insert(pts);
+        let offset = (pts as i64 - cluster_ts as i64) as i16;
+
+        let mut block = BytesMut::with_capacity(data.len() + 4);
+        put_vint(&mut block, track);
+        block.put_i16(offset);
+        block.put_u8(if keyframe { 0x80 } else { 0 });
+        block.put_slice(data);
+
+        element(&mut self.cluster, SIMPLE_BLOCK, &block);
+        Ok(())
+    }
+
+    fn flush_cluster(&mut self) -> anyhow::Result<()> {
+        let Some(ts) = self.cluster_ts.take() else {
+            return Ok(());
+        };
+
+        let mut body = BytesMut::from(&uint(CLUSTER_TIMESTAMP, ts)[..]);
+        body.put_slice(&self.cluster.split());
+
+        let mut cluster = BytesMut::with_capacity(body.len() + 12);
+        element(&mut cluster, CLUSTER, &body);
+        self.w.write_all(&cluster)?;
+
+        Ok(())
+    }
+}
+
+fn put_id(buf: &mut BytesMut, id: u32) {
+    let bytes = id.to_be_bytes();
+    let skip = bytes.iter().position(|b| *b != 0).unwrap_or(3);
+    buf.put_slice(&bytes[skip..]);
+}
+
+/// Writes a variable-length integer, using the shortest encoding.
+fn put_vint(buf: &mut BytesMut, v: u64) {
+    let mut len = 1;
+
+    // All ones is reserved to mean "unknown size".
+    while len < 8 && v >= (1 << (7 * len)) - 1 {
+        len += 1;
+    }
+
+    let marked = v | (1 << (7 * len));
+    buf.put_slice(&marked.to_be_bytes()[8 - len..]);
+}
+
+fn element(buf: &mut BytesMut, id: u32, data: &[u8]) {
+    put_id(buf, id);
+    put_vint(buf, data.len() as u64);
+    buf.put_slice(data);
+}
+
+fn master(id: u32, children: &[u8]) -> Vec<u8> {
+    binary(id, children)
+}
+
+fn binary(id: u32, data: &[u8]) -> Vec<u8> {
+    let mut buf = BytesMut::new();
+    element(&mut buf, id, data);
+    buf.to_vec()
+}
+
+fn uint(id: u32, v: u64) -> Vec<u8> {
+    let bytes = v.to_be_bytes();
+    let skip = bytes.iter().position(|b| *b != 0).unwrap_or(7);
+    binary(id, &bytes[skip..])
+}
+
+fn float(id: u32, v: f64) -> Vec<u8> {
+    binary(id, &v.to_be_bytes())
+}
+
+fn string(id: u32, s: &str) -> Vec<u8> {
+    binary(id, s.as_bytes())
+}
+
+/// Generates an OpusHead structure, as described in RFC 7845.
+fn opus_head(sample_rate: u32, channels: u32) -> Vec<u8> {
+    let mut buf = BytesMut::new();
+    buf.put_slice(b"OpusHead");
+    buf.put_u8(1); // Version.
+    buf.put_u8(channels as u8);
+    buf.put_u16_le(0); // Pre-skip.
+    buf.put_u32_le(sample_rate);
+    buf.put_i16_le(0); // Output gain.
+    buf.put_u8(0); // Channel mapping family.
+    buf.to_vec()
+}
+
+/// Splits an Annex B bitstream into NAL units, without start codes.
+fn split_annexb(buf: &[u8]) -> impl Iterator<Item = &[u8]> {
+    let mut starts = Vec::new();
+    let mut i = 0;
+    while i + 3 <= buf.len() {
+        if buf[i] == 0 && buf[i + 1] == 0 && buf[i + 2] == 1 {
+            starts.push(i + 3);
+            i += 3;
+        } else {
+            i += 1;
+        }
+    }
+
+    let ends = starts
+        .iter()
+        .skip(1)
+        .map(|start| {
+            // Trim the start code, and the leading zero of a four-byte start
+            // code.
+            let mut end = start - 3;
+            while end > 0 && buf[end - 1] == 0 {
+                end -= 1;
+            }
+
+            end
+        })
+        .chain(std::iter::once(buf.len()))
+        .collect::<Vec<_>>();
+
+    starts
+        .into_iter()
+        .zip(ends)
+        .map(move |(start, end)| &buf[start..end.max(start)])
+}
+
+/// Returns the type of a NAL unit.
+fn nal_type(nal: &[u8], codec: VideoCodec) -> Option<u8> {
+    let header = *nal.first()?;
+    match codec {
+        VideoCodec::H264 => Some(header & 0x1F),
+        _ => Some((header >> 1) & 0x3F),
+    }
+}
+
+fn annexb_to_length_prefixed(frame: &[u8], codec: VideoCodec) -> Bytes {
+    let mut buf = BytesMut::with_capacity(frame.len() + 16);
+    for nal in split_annexb(frame) {
+        // Access unit delimiters aren't allowed in the length-prefixed format.
+        match (codec, nal_type(nal, codec)) {
+            (VideoCodec::H264, Some(9)) | (VideoCodec::H265, Some(35)) => continue,
+            _ => (),
+        }
+
+        buf.put_u32(nal.len() as u32);
+        buf.put_slice(nal);
+    }
+
+    buf.freeze()
+}
+
+fn find_nal(frame: &[u8], codec: VideoCodec, ty: u8) -> anyhow::Result<&[u8]> {
+    split_annexb(frame)
+        .find(|nal| nal_type(nal, codec) == Some(ty))
+        .ok_or_else(|| anyhow::anyhow!("missing NAL unit of type {ty}"))
+}
+
+/// Generates an AVCDecoderConfigurationRecord, as described in ISO/IEC
+/// 14496-15.
+fn avc_decoder_config(keyframe: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let sps = find_nal(keyframe, VideoCodec::H264, 7)?;
+    let pps = find_nal(keyframe, VideoCodec::H264, 8)?;
+    if sps.len() < 4 {
+        anyhow::bail!("invalid SPS");
+    }
+
+    let mut buf = BytesMut::new();
+    buf.put_u8(1); // configurationVersion
+    buf.put_slice(&sps[1..4]); // profile, compatibility, level
+    buf.put_u8(0xFF); // lengthSizeMinusOne = 3
+    buf.put_u8(0xE1); // One SPS.
+    buf.put_u16(sps.len() as u16);
+    buf.put_slice(sps);
+    buf.put_u8(1); // One PPS.
+    buf.put_u16(pps.len() as u16);
+    buf.put_slice(pps);
+
+    Ok(buf.to_vec())
+}
+
+/// Generates an HEVCDecoderConfigurationRecord, as described in ISO/IEC
+/// 14496-15.
+fn hevc_decoder_config(keyframe: &[u8], profile: VideoProfile) -> anyhow::Result<Vec<u8>> {
+    let vps = find_nal(keyframe, VideoCodec::H265, 32)?;
+    let sps = find_nal(keyframe, VideoCodec::H265, 33)?;
+    let pps = find_nal(keyframe, VideoCodec::H265, 34)?;
+
+    // The profile_tier_level structure is byte-aligned at the start of the
+    // SPS, after the two-byte NAL header.
+    let rbsp = remove_emulation_prevention(&sps[2..]);
+    if rbsp.len() < 13 {
+        anyhow::bail!("invalid SPS");
+    }
+
+    let max_sub_layers = ((rbsp[0] >> 1) & 0x7) + 1;
+    let temporal_id_nested = rbsp[0] & 1;
+
+    let bit_depth_minus8 = match profile {
+        VideoProfile::Hd => 0,
+        VideoProfile::Hdr10 => 2,
+    };
+
+    let mut buf = BytesMut::new();
+    buf.put_u8(1); // configurationVersion
+    buf.put_slice(&rbsp[1..13]); // Profile, compatibility and constraint flags, level.
+    buf.put_u16(0xF000); // min_spatial_segmentation_idc
+    buf.put_u8(0xFC); // parallelismType
+    buf.put_u8(0xFC | 1); // chroma_format_idc = 4:2:0
+    buf.put_u8(0xF8 | bit_depth_minus8);
+    buf.put_u8(0xF8 | bit_depth_minus8);
+    buf.put_u16(0); // avgFrameRate
+    buf.put_u8((max_sub_layers << 3) | (temporal_id_nested << 2) | 3);
+
+    buf.put_u8(3); // numOfArrays
+    for (ty, nal) in [(32, vps), (33, sps), (34, pps)] {
+        buf.put_u8(0x80 | ty); // array_completeness = 1
+        buf.put_u16(1);
+        buf.put_u16(nal.len() as u16);
+        buf.put_slice(nal);
+    }
+
+    Ok(buf.to_vec())
+}
+
+fn remove_emulation_prevention(buf: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(buf.len());
+    let mut zeroes = 0;
+    for &b in buf {
+        if zeroes >= 2 && b == 3 {
+            zeroes = 0;
+            continue;
+        }
+
+        out.push(b);
+        zeroes = if b == 0 { zeroes + 1 } else { 0 };
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_vint() {
+        let encode = |v| {
+            let mut buf = BytesMut::new();
+            put_vint(&mut buf, v);
+            buf.to_vec()
+        };
+
+        assert_eq!(encode(0), vec![0x80]);
+        assert_eq!(encode(126), vec![0xFE]);
+        assert_eq!(encode(127), vec![0x40, 0x7F]);
+        assert_eq!(encode(500), vec![0x41, 0xF4]);
+    }
+
+    #[test]
+    fn test_split_annexb() {
+        let buf = [
+            0, 0, 0, 1, 0x67, 1, 2, 0, 0, 1, 0x68, 3, 0, 0, 0, 1, 0x65, 4, 5,
+        ];
+        let nals = split_annexb(&buf).collect::<Vec<_>>();
+
+        assert_eq!(
+            nals,
+            vec![&[0x67, 1, 2][..], &[0x68, 3][..], &[0x65, 4, 5][..]]
+        );
+    }
+
+    #[test]
+    fn test_remove_emulation_prevention() {
+        assert_eq!(
+            remove_emulation_prevention(&[0, 0, 3, 1, 0, 0, 3, 0, 5]),
+            vec![0, 0, 1, 0, 0, 0, 5]
+        );
+    }
+}
@@ -14,16 +14,17 @@ use tracing::{instrument, trace, trace_span, warn};
 
 use super::{
     compositor::{self, buffers::SyncobjTimelinePoint},
-    DisplayParams, SessionHandle, VideoStreamParams,
+    control::ContentType,
+    DisplayParams, SessionHandle, StreamTarget, VideoStreamParams,
 };
 use crate::{
-    color::ColorSpace,
+    color::{ColorSpace, HdrMetadata},
     encoder::{self},
     session::EPOCH,
     vulkan::*,
 };
 
-struct Sink(SessionHandle);
+struct Sink(SessionHandle, StreamTarget);
 
 impl encoder::Sink for Sink {
     fn write_frame(
@@ -32,10 +33,11 @@ impl encoder::Sink for Sink {
         frame: bytes::Bytes,
         hierarchical_layer: u32,
         is_keyframe: bool,
+        timing: encoder::FrameTiming,
     ) {
         let pts = (ts - *EPOCH).as_millis() as u64;
         self.0
-            .dispatch_video_frame(pts, frame, hierarchical_layer, is_keyframe);
+            .dispatch_video_frame(self.1, pts, frame, hierarchical_layer, is_keyframe, timing);
 
         // Wake the compositor, so it can release buffers and send presentation
         // feedback.
@@ -45,7 +47,13 @@ impl encoder::Sink for Sink {
 
 pub struct SwapFrame {
     convert_ds: vk::DescriptorSet, // Should be dropped first.
-    draws: Vec<(vk::ImageView, glam::Vec2, glam::Vec2)>,
+    draws: Vec<(
+        vk::ImageView,
+        (glam::Vec2, glam::Vec2),
+        glam::Vec2,
+        glam::Vec2,
+        ColorSpace,
+    )>,
     texture_semas: Vec<vk::Semaphore>, // Reused each frame.
     texture_semas_used: usize,
 
@@ -87,6 +95,9 @@ pub struct EncodePipeline {
     swap: [SwapFrame; 2],
     swap_idx: usize,
 
+    // Set until the first frame is submitted, or when a refresh is requested.
+    needs_frame: bool,
+
     vk: Arc<VkContext>,
 }
 
@@ -95,6 +106,7 @@ impl EncodePipeline {
     pub fn new(
         vk: Arc<VkContext>,
         compositor_handle: SessionHandle,
+        target: StreamTarget,
         display_params: DisplayParams,
         streaming_params: VideoStreamParams,
     ) -> anyhow::Result<Self> {
@@ -111,7 +123,7 @@ impl EncodePipeline {
             unimplemented!()
         }
 
-        let sink = Sink(compositor_handle);
+        let sink = Sink(compositor_handle, target);
         let mut encoder =
             encoder::Encoder::new(vk.clone(), streaming_params, display_params.framerate, sink)?;
 
@@ -137,6 +149,8 @@ impl EncodePipeline {
             swap,
             swap_idx: 0,
 
+            needs_frame: true,
+
             vk,
         })
     }
@@ -218,7 +232,10 @@ impl EncodePipeline {
         &mut self,
         texture: &compositor::buffers::Buffer,
         sync: Option<TextureSync>,
+        // In texture coordinates.
+        src: (glam::Vec2, glam::Vec2),
         dest: compositor::surface::SurfaceConfiguration,
+        color_space: ColorSpace,
     ) -> anyhow::Result<Option<VkTimelinePoint>> {
         let device = &self.vk.device;
         let frame = &mut self.swap[self.swap_idx];
@@ -354,7 +371,9 @@ impl EncodePipeline {
         let dst_size = dest.size.as_vec2() / display_size.as_vec2() * 2.0;
 
         // Draw.
-        frame.draws.push((view, dst_pos, dst_size));
+        frame
+            .draws
+            .push((view, src, dst_pos, dst_size, color_space));
 
         Ok(release)
     }
@@ -362,7 +381,10 @@ impl EncodePipeline {
     /// End the current frame and submit it to the GPU. Returns the timeline
     /// point indicating when rendering and encoding have both completed.
     #[instrument(skip_all)]
-    pub unsafe fn end_and_submit(&mut self) -> anyhow::Result<VkTimelinePoint> {
+    pub unsafe fn end_and_submit(
+        &mut self,
+        mut timing: encoder::FrameTiming,
+    ) -> anyhow::Result<VkTimelinePoint> {
         let device = &self.vk.device;
         let frame = &mut self.swap[self.swap_idx];
 
@@ -371,9 +393,16 @@ impl EncodePipeline {
         self.composite_pipeline
             .begin_compositing(frame.render_cb, &frame.blend_image);
 
-        for (view, dst_pos, dst_size) in frame.draws.drain(..) {
-            self.composite_pipeline
-                .composite_surface(frame.render_cb, view, dst_pos, dst_size)?;
+        for (view, (src_pos, src_size), dst_pos, dst_size, color_space) in frame.draws.drain(..) {
+            self.composite_pipeline.composite_surface(
+                frame.render_cb,
+                view,
+                src_pos,
+                src_size,
+                dst_pos,
+                dst_size,
+                color_space,
+            )?;
         }
 
         self.composite_pipeline.end_compositing(frame.render_cb);
@@ -530,11 +559,14 @@ impl EncodePipeline {
             device.queue_submit2(self.vk.graphics_queue.queue, &submits, vk::Fence::null())
         })?;
 
+        timing.composite_end = Some(std::time::Instant::now());
+
         // Trigger encode.
         self.encoder.submit_encode(
             &frame.encode_image,
             frame.tp_render_done.clone(),
             frame.tp_clear.clone(),
+            timing,
         )?;
 
         // Wait for uploads to finish before returning, so that writes to the
@@ -544,12 +576,88 @@ impl EncodePipeline {
 
         let swap_len = self.swap.len();
         self.swap_idx = (self.swap_idx + 1) % swap_len;
+        self.needs_frame = false;
 
         Ok(tp_clear)
     }
 
+    pub fn streaming_params(&self) -> VideoStreamParams {
+        self.streaming_params
+    }
+
+    /// Changes the resolution of the pipeline, without restarting the
+    /// encoder. The next frame is a keyframe.
+    #[instrument(level = "trace", skip_all)]
+    pub fn resize(
+        &mut self,
+        display_params: DisplayParams,
+        streaming_params: VideoStreamParams,
+    ) -> anyhow::Result<()> {
+        if streaming_params.width != display_params.width
+            || streaming_params.height != display_params.height
+        {
+            // Superres is not implemented yet.
+            unimplemented!()
+        }
+
+        // The swap frames may still be in use.
+        unsafe { self.vk.device.device_wait_idle()? };
+
+        self.encoder
+            .resize(streaming_params.width, streaming_params.height)?;
+
+        let swap = [
+            new_swapframe(
+                self.vk.clone(),
+                self.encoder.create_input_image()?,
+                &self.convert_pipeline,
+            )?,
+            new_swapframe(
+                self.vk.clone(),
+                self.encoder.create_input_image()?,
+                &self.convert_pipeline,
+            )?,
+        ];
+
+        for frame in std::mem::replace(&mut self.swap, swap) {
+            unsafe { destroy_swapframe(&self.vk, &frame) };
+        }
+
+        self.display_params = display_params;
+        self.streaming_params = streaming_params;
+        self.swap_idx = 0;
+        self.needs_frame = true;
+
+        Ok(())
+    }
+
     pub fn request_refresh(&mut self) {
-        self.encoder.request_refresh()
+        self.encoder.request_refresh();
+        self.needs_frame = true;
+    }
+
+    /// Sets the static HDR metadata for the stream. The metadata is attached
+    /// to the next keyframe, and ignored for SDR streams.
+    pub fn set_hdr_metadata(&mut self, metadata: Option<HdrMetadata>) {
+        self.encoder.set_hdr_metadata(metadata);
+    }
+
+    /// Retunes the encoder for a new content type, without restarting the
+    /// stream. Returns false if the pipeline needs to be recreated instead.
+    pub fn set_content_type(&mut self, content_type: ContentType) -> bool {
+        if !self.encoder.set_content_type(content_type) {
+            return false;
+        }
+
+        self.streaming_params.content_type = content_type;
+        true
+    }
+
+    /// Returns true if a frame must be encoded, regardless of whether the
+    /// content changed. This is the case for the first frame, and when a
+    /// keyframe was requested.
+    pub fn needs_frame(&self) -> bool {
+        self.needs_frame
     }
 }
 
@@ -566,24 +674,36 @@ impl Drop for EncodePipeline {
             device.device_wait_idle().unwrap();
 
             for frame in self.swap.iter() {
-                device.free_command_buffers(
-                    self.vk.graphics_queue.command_pool,
-                    &[frame.staging_cb, frame.render_cb],
-                );
+                destroy_swapframe(&self.vk, frame);
+            }
+        }
+    }
+}
 
-                for view in &frame.plane_views {
-                    device.destroy_image_view(*view, None);
-                }
+/// Frees the resources of a swap frame that is no longer in use by the GPU.
+/// The images are freed when the frame is dropped.
+unsafe fn destroy_swapframe(vk: &VkContext, frame: &SwapFrame) {
+    let device = &vk.device;
 
-                for sema in &frame.texture_semas {
-                    device.destroy_semaphore(*sema, None);
-                }
+    device
+        .free_descriptor_sets(vk.descriptor_pool, &[frame.convert_ds])
+        .unwrap();
 
-                device.destroy_query_pool(frame.render_ts_pool.pool, None);
-                device.destroy_query_pool(frame.staging_ts_pool.pool, None);
-            }
-        }
+    device.free_command_buffers(
+        vk.graphics_queue.command_pool,
+        &[frame.staging_cb, frame.render_cb],
+    );
+
+    for view in &frame.plane_views {
+        device.destroy_image_view(*view, None);
     }
+
+    for sema in &frame.texture_semas {
+        device.destroy_semaphore(*sema, None);
+    }
+
+    device.destroy_query_pool(frame.render_ts_pool.pool, None);
+    device.destroy_query_pool(frame.staging_ts_pool.pool, None);
 }
 
 fn new_swapframe(
@@ -250,17 +250,20 @@ impl CompositePipeline {
         &self,
         cb: vk::CommandBuffer,
         view: vk::ImageView,
+        // In texture coordinates.
+        src_pos: glam::Vec2,
+        src_size: glam::Vec2,
         // In clip coordinates.
         // TODO: mat3 transform
         dst_pos: glam::Vec2,
         dst_size: glam::Vec2,
+        color_space: ColorSpace,
     ) -> anyhow::Result<()> {
         let device = &self.vk.device;
 
-        let color_space = ColorSpace::Srgb; // TODO
         let pc = SurfacePC {
-            src_pos: glam::Vec2::ZERO,
-            src_size: glam::Vec2::ONE,
+            src_pos,
+            src_size,
             dst_pos,
             dst_size,
             color_space: color_space.into(),
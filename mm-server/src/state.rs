@@ -4,11 +4,12 @@
 
 use std::sync::Arc;
 
+use crossbeam_channel::Sender;
 use hashbrown::HashMap;
 use parking_lot::Mutex;
 use tracing::{error, info};
 
-use crate::config::Config;
+use crate::config::{Config, ConfigSource, IdleAction};
 use crate::{session::Session, vulkan::VkContext};
 
 pub type SharedState = Arc<Mutex<ServerState>>;
@@ -20,18 +21,68 @@ pub struct ServerState {
     pub session_seq: usize,
     pub id_generator: tiny_id::ShortCodeGenerator<char>,
     pub cfg: Config,
+    pub cfg_source: ConfigSource,
     pub vk: Arc<VkContext>,
+
+    reaper: Sender<Session>,
+}
+
+/// Re-reads the configuration from disk and swaps it in, if it's valid. Running
+/// sessions keep the app config they were launched with. Server settings can't
+/// be changed at runtime, so only the app definitions are updated.
+pub fn reload_config(state: &SharedState) -> anyhow::Result<()> {
+    // Don't hold the lock while reading files.
+    let source = state.lock().cfg_source.clone();
+    let mut cfg = source.load()?;
+
+    let mut guard = state.lock();
+    cfg.server = guard.cfg.server.clone();
+    cfg.bug_report_dir = guard.cfg.bug_report_dir.clone();
+
+    let old = &guard.cfg.apps;
+    for (id, app) in &cfg.apps {
+        match old.get(id) {
+            None => info!(app = id, "app added"),
+            Some(old_app) if old_app != app => info!(app = id, "app updated"),
+            _ => (),
+        }
+    }
+
+    for id in old.keys().filter(|id| !cfg.apps.contains_key(*id)) {
+        info!(app = id, "app removed");
+    }
+
+    guard.cfg = cfg;
+    Ok(())
 }
 
 impl ServerState {
-    pub fn new(vk: Arc<VkContext>, cfg: Config) -> Self {
-        Self {
+    pub fn new(vk: Arc<VkContext>, cfg: Config, cfg_source: ConfigSource) -> anyhow::Result<Self> {
+        // Stopping a session blocks until the compositor exits, which can take
+        // a while. Since we usually hold the lock when ending a session, the
+        // actual stopping happens on a separate thread.
+        let (reaper, ended) = crossbeam_channel::unbounded::<Session>();
+        std::thread::Builder::new()
+            .name("session reaper".into())
+            .spawn(move || {
+                for s in ended {
+                    let id = s.id;
+                    match s.stop() {
+                        Ok(exit_status) => info!(session_id = id, exit_status, "session ended"),
+                        Err(e) => error!(session_id = id, "session ended with error: {:#}", e),
+                    }
+                }
+            })?;
+
+        Ok(Self {
             vk,
             cfg,
+            cfg_source,
             sessions: HashMap::new(),
             session_seq: 0,
             id_generator: tiny_id::ShortCodeGenerator::new_numeric(6),
-        }
+            reaper,
+        })
     }
 
     pub fn generate_session_id(&mut self) -> (usize, u64) {
@@ -41,6 +92,18 @@ impl ServerState {
         (seq, self.id_generator.next_int())
     }
 
+    /// Removes a session and stops it in the background. Returns false if the
+    /// session doesn't exist.
+    pub fn end_session(&mut self, id: u64) -> bool {
+        match self.sessions.remove(&id) {
+            Some(s) => {
+                let _ = self.reaper.send(s);
+                true
+            }
+            None => false,
+        }
+    }
+
     /// Run periodic cleanup, e.g. ending defunct sessions.
     pub fn tick(&mut self) -> anyhow::Result<()> {
         self.sessions
@@ -50,22 +113,40 @@ impl ServerState {
                     return true;
                 }
 
-                let session_timeout = self.cfg.apps[&s.application_id].session_timeout;
+                let session_timeout = s.application_config.session_timeout;
                 if s.detached_since
                     .zip(session_timeout)
                     .is_some_and(|(t, timeout)| t.elapsed() > timeout)
                 {
                     info!("cleaning up idle session {}", s.id);
-                    true
-                } else {
-                    false
+                    return true;
                 }
-            })
-            .for_each(|(_, s)| match s.stop() {
-                Ok(()) => {}
-                Err(e) => {
-                    error!("session ended with error: {:#}", e);
+
+                // Sessions can also be forgotten about while attached.
+                let idle_timeout = s.application_config.idle_timeout;
+                if !s.is_attached()
+                    || idle_timeout.is_none_or(|timeout| s.activity.idle_duration() < timeout)
+                {
+                    return false;
+                }
+
+                match s.application_config.idle_action {
+                    IdleAction::End => {
+                        info!("ending attached session {} after no input", s.id);
+                        true
+                    }
+                    IdleAction::Suspend => {
+                        info!("suspending attached session {} after no input", s.id);
+                        if let Err(e) = s.suspend() {
+                            error!("failed to suspend session: {:#}", e);
+                        }
+
+                        false
+                    }
                 }
+            })
+            .for_each(|(_, s)| {
+                let _ = self.reaper.send(s);
             });
         Ok(())
     }
@@ -105,6 +105,10 @@ impl VkTimelinePoint {
         VkTimelineSemaphore(self.0.clone())
     }
 
+    pub fn same_timeline(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+
     #[instrument(skip_all)]
     pub unsafe fn wait(&self) -> anyhow::Result<()> {
         let device = &self.0.vk.device;